@@ -1,20 +1,85 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{
 	atomic::{AtomicBool, Ordering},
 	Arc, Condvar, Mutex,
 };
 
-use gelatin::image::imageops::{
-	flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place, rotate270, rotate90,
+use gelatin::{
+	cgmath::Vector2,
+	image::{
+		imageops::{
+			self, crop_imm, flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place,
+			rotate270, rotate90, FilterType,
+		},
+		RgbaImage,
+	},
 };
 
 use crate::image_cache::image_loader::{
 	complex_load_image, ImageLoaderError, LoadResult, Orientation,
 };
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// A rectangle within the displayed image. The coordinates are fractions of the width and the
+/// height of the image, so that the region doesn't depend on the resolution that the image was
+/// decoded at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageRegion {
+	pub left: f32,
+	pub top: f32,
+	pub right: f32,
+	pub bottom: f32,
+}
+
+impl ImageRegion {
+	pub fn from_corners(a: Vector2<f32>, b: Vector2<f32>) -> ImageRegion {
+		ImageRegion {
+			left: a.x.min(b.x),
+			top: a.y.min(b.y),
+			right: a.x.max(b.x),
+			bottom: a.y.max(b.y),
+		}
+	}
+
+	/// Returns the position and the size of the region in pixels, within an image that has
+	/// the given dimensions. The result is at least one pixel wide and high.
+	pub fn to_pixels(self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+		let to_px = |fraction: f32, size: u32| ((fraction * size as f32).round() as u32).min(size);
+		let x = to_px(self.left, width).min(width.saturating_sub(1));
+		let y = to_px(self.top, height).min(height.saturating_sub(1));
+		let w = to_px(self.right, width).saturating_sub(x).max(1);
+		let h = to_px(self.bottom, height).saturating_sub(y).max(1);
+		(x, y, w, h)
+	}
+}
+
+/// How the copied images are placed on the clipboard
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClipboardOptions {
+	/// Images with a longer side than this are downscaled before copying, because some
+	/// clipboard managers freeze when they receive gigantic images
+	pub max_image_size: Option<u32>,
+	/// Also offers the image as JPEG on Windows, where some applications only paste that
+	pub offer_jpeg: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CopyRequest {
+	File {
+		path: PathBuf,
+		/// When set, only this region of the image is copied. The region is relative to the
+		/// image after the orientation of the file and then the view orientation are applied.
+		region: Option<(Orientation, ImageRegion)>,
+	},
+	/// Pixels that are already in memory, like a capture of the rendered view
+	Image(Arc<RgbaImage>),
+	Text(String),
+	/// Placed on the clipboard as a list of files, like the ones copied in a file manager
+	FileList(Vec<PathBuf>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum ClipboardState {
-	Pending(PathBuf),
+	Pending(CopyRequest),
 	Succeeded,
 	Failed,
 }
@@ -23,6 +88,7 @@ struct ClipboardRequestHandle {
 	run_thread: AtomicBool,
 	condvar: Condvar,
 	state: Mutex<ClipboardState>,
+	options: Mutex<ClipboardOptions>,
 }
 
 pub struct ClipboardHandler {
@@ -38,6 +104,7 @@ impl ClipboardHandler {
 			run_thread: AtomicBool::new(true),
 			condvar: Condvar::new(),
 			state: Mutex::new(prev_state),
+			options: Mutex::new(ClipboardOptions::default()),
 		});
 		let handle = {
 			let request_handle = request_handle.clone();
@@ -49,13 +116,54 @@ impl ClipboardHandler {
 		ClipboardHandler { request_handle, thread_handle: Some(handle) }
 	}
 
+	/// Applies to the requests made after this
+	pub fn set_options(&self, options: ClipboardOptions) {
+		*self.request_handle.options.lock().unwrap() = options;
+	}
+
 	pub fn request_copy(&mut self, target: PathBuf) -> bool {
+		self.start_request(CopyRequest::File { path: target, region: None })
+	}
+
+	/// Copies a part of the image. `view_orientation` is the orientation that the image is
+	/// displayed with on top of the orientation stored in the file.
+	pub fn request_copy_region(
+		&mut self,
+		target: PathBuf,
+		view_orientation: Orientation,
+		region: ImageRegion,
+	) -> bool {
+		let region = Some((view_orientation, region));
+		self.start_request(CopyRequest::File { path: target, region })
+	}
+
+	pub fn request_copy_image(&mut self, image: RgbaImage) -> bool {
+		self.start_request(CopyRequest::Image(Arc::new(image)))
+	}
+
+	pub fn request_copy_text(&mut self, text: String) -> bool {
+		self.start_request(CopyRequest::Text(text))
+	}
+
+	/// Copies the absolute path of the file as text
+	pub fn request_copy_path(&mut self, path: &Path) -> bool {
+		let text = absolute_path(path).to_string_lossy().into_owned();
+		self.request_copy_text(text)
+	}
+
+	/// Copies the file itself, which is offered as `text/uri-list` on Linux, `CF_HDROP` on
+	/// Windows and a file URL on macOS. Unlike the pixels, this refers to the original file.
+	pub fn request_copy_file(&mut self, path: &Path) -> bool {
+		self.start_request(CopyRequest::FileList(vec![absolute_path(path)]))
+	}
+
+	fn start_request(&mut self, request: CopyRequest) -> bool {
 		{
 			let mut state = self.request_handle.state.lock().unwrap();
 			if let ClipboardState::Pending(..) = &*state {
 				return false;
 			} else {
-				*state = ClipboardState::Pending(target);
+				*state = ClipboardState::Pending(request);
 			}
 		}
 		// Notify the condvar after releasing the mutex
@@ -84,12 +192,12 @@ impl ClipboardHandler {
 			eprintln!("The clipboard could not be created, error was: {}", e);
 		}
 		while request_handle.run_thread.load(Ordering::Acquire) {
-			let request_path;
+			let request;
 			{
 				let mut state_guard = request_handle.state.lock().unwrap();
 				'wait_for_request: loop {
-					if let ClipboardState::Pending(path) = state_guard.clone() {
-						request_path = path;
+					if let ClipboardState::Pending(pending) = state_guard.clone() {
+						request = pending;
 						break 'wait_for_request;
 					} else {
 						if !request_handle.run_thread.load(Ordering::Acquire) {
@@ -106,57 +214,244 @@ impl ClipboardHandler {
 					}
 				}
 			}
-			let result = complex_load_image(&request_path, false, 0, |frame| {
-				if let LoadResult::Frame { mut image, orientation, .. } = frame {
-					if let Ok(clipboard) = &mut clipboard {
-						// Note: the imageops functions use clockwise rotation whereas the
-						// `Orientation` type describes counter-clockwise rotation.
-						image = match orientation {
-							Orientation::Deg0 => image,
-							Orientation::Deg0HorFlip => {
-								flip_horizontal_in_place(&mut image);
-								image
-							}
-							Orientation::Deg90 => rotate270(&image),
-							Orientation::Deg90VerFlip => {
-								let mut result = rotate270(&image);
-								flip_vertical_in_place(&mut result);
-								result
-							}
-							Orientation::Deg180 => {
-								rotate180_in_place(&mut image);
-								image
+			let options = *request_handle.options.lock().unwrap();
+			let result = match request {
+				CopyRequest::File { path, region } => {
+					complex_load_image(&path, false, 0, None, |frame| {
+						if let LoadResult::Frame { mut image, orientation, .. } = frame {
+							match region {
+								Some((view_orientation, region)) => {
+									let orientation = orientation.then(view_orientation);
+									image = apply_orientation(image, orientation);
+									let (x, y, w, h) =
+										region.to_pixels(image.width(), image.height());
+									image = crop_imm(&image, x, y, w, h).to_image();
+								}
+								None => image = apply_orientation(image, orientation),
 							}
-							Orientation::Deg180HorFlip => {
-								// This is identical to just a vertical flip with no rotation.
-								flip_vertical_in_place(&mut image);
-								image
+							if set_clipboard_image(&mut clipboard, image, options) {
+								return Ok(());
 							}
-							Orientation::Deg270 => rotate90(&image),
-							Orientation::Deg270VerFlip => {
-								let mut result = rotate90(&image);
-								flip_vertical_in_place(&mut result);
-								result
-							}
-						};
-						let (w, h) = image.dimensions();
-						let cb_image = arboard::ImageData {
-							width: w as usize,
-							height: h as usize,
-							bytes: image.into_raw().into(),
-						};
-						if let Err(e) = clipboard.set_image(cb_image) {
-							eprintln!("Could not set the clipboard image, error was: {}", e);
-						} else {
-							return Ok(());
 						}
-					}
+						Err(ImageLoaderError::unsupported("Could not set the clipboard image."))
+					})
+					.is_ok()
 				}
-				Err(ImageLoaderError { description: "Could not set the clipboard image.".into() })
-			});
+				CopyRequest::Image(image) => {
+					let image = Arc::try_unwrap(image).unwrap_or_else(|image| (*image).clone());
+					set_clipboard_image(&mut clipboard, image, options)
+				}
+				CopyRequest::Text(text) => set_clipboard_text(&mut clipboard, text),
+				CopyRequest::FileList(paths) => set_clipboard_file_list(&mut clipboard, &paths),
+			};
 			let mut state = request_handle.state.lock().unwrap();
-			*state =
-				if result.is_ok() { ClipboardState::Succeeded } else { ClipboardState::Failed };
+			*state = if result { ClipboardState::Succeeded } else { ClipboardState::Failed };
+		}
+	}
+}
+
+/// Returns true if the image was placed on the clipboard. The image is offered as PNG on every
+/// platform; on Windows also as a DIB, and optionally as JPEG.
+fn set_clipboard_image(
+	clipboard: &mut Result<arboard::Clipboard, arboard::Error>,
+	image: RgbaImage,
+	options: ClipboardOptions,
+) -> bool {
+	let clipboard = match clipboard {
+		Ok(clipboard) => clipboard,
+		Err(_) => return false,
+	};
+	let image = match options.max_image_size {
+		Some(max_size) => limit_size(image, max_size),
+		None => image,
+	};
+	// Arboard only offers a DIB on Windows, so the other formats are added after it
+	#[cfg(windows)]
+	let encoded_formats = windows_clipboard::encode_formats(&image, options.offer_jpeg);
+	let (w, h) = image.dimensions();
+	let cb_image = arboard::ImageData {
+		width: w as usize,
+		height: h as usize,
+		bytes: image.into_raw().into(),
+	};
+	if let Err(e) = clipboard.set_image(cb_image) {
+		eprintln!("Could not set the clipboard image, error was: {}", e);
+		return false;
+	}
+	#[cfg(windows)]
+	windows_clipboard::add_formats(&encoded_formats);
+	true
+}
+
+/// Downscales the image so that neither of its sides is longer than `max_size`
+fn limit_size(image: RgbaImage, max_size: u32) -> RgbaImage {
+	let (w, h) = image.dimensions();
+	let max_size = max_size.max(1);
+	if w <= max_size && h <= max_size {
+		return image;
+	}
+	let scale = max_size as f64 / w.max(h) as f64;
+	let new_w = ((w as f64 * scale).round() as u32).clamp(1, max_size);
+	let new_h = ((h as f64 * scale).round() as u32).clamp(1, max_size);
+	imageops::resize(&image, new_w, new_h, FilterType::Triangle)
+}
+
+#[cfg(windows)]
+mod windows_clipboard {
+	use std::io::Cursor;
+
+	use gelatin::image::{DynamicImage, ImageFormat, RgbaImage};
+	use windows_sys::Win32::System::{
+		DataExchange::{CloseClipboard, OpenClipboard, RegisterClipboardFormatW, SetClipboardData},
+		Memory::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+	};
+
+	/// Returns the names of the registered clipboard formats along with the encoded image
+	pub fn encode_formats(image: &RgbaImage, offer_jpeg: bool) -> Vec<(&'static str, Vec<u8>)> {
+		let mut formats = Vec::new();
+		let mut png = Vec::new();
+		match image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+			Ok(()) => formats.push(("PNG", png)),
+			Err(e) => eprintln!("Could not encode the clipboard image as PNG: {}", e),
+		}
+		if offer_jpeg {
+			// JPEG has no alpha channel
+			let rgb = DynamicImage::ImageRgba8(image.clone()).into_rgb8();
+			let mut jpeg = Vec::new();
+			match rgb.write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg) {
+				Ok(()) => formats.push(("JFIF", jpeg)),
+				Err(e) => eprintln!("Could not encode the clipboard image as JPEG: {}", e),
+			}
+		}
+		formats
+	}
+
+	/// Adds the formats to the ones already on the clipboard
+	pub fn add_formats(formats: &[(&'static str, Vec<u8>)]) {
+		unsafe {
+			if OpenClipboard(0) == 0 {
+				eprintln!("Could not open the clipboard to add the image formats");
+				return;
+			}
+			for (name, bytes) in formats {
+				let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+				let format = RegisterClipboardFormatW(name.as_ptr());
+				if format == 0 {
+					continue;
+				}
+				let memory = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+				if memory as isize == 0 {
+					continue;
+				}
+				let target = GlobalLock(memory);
+				if target.is_null() {
+					GlobalFree(memory);
+					continue;
+				}
+				std::ptr::copy_nonoverlapping(bytes.as_ptr(), target as *mut u8, bytes.len());
+				GlobalUnlock(memory);
+				// The clipboard owns the memory if this succeeds
+				if SetClipboardData(format, memory as _) as isize == 0 {
+					GlobalFree(memory);
+				}
+			}
+			CloseClipboard();
+		}
+	}
+}
+
+/// Formats an absolute path as a `file://` URI, percent-encoding the bytes that may not appear
+/// in one unencoded
+pub fn file_uri(path: &Path) -> String {
+	let mut path = path.to_string_lossy().into_owned();
+	if cfg!(windows) {
+		path = path.replace('\\', "/");
+	}
+	let mut uri = String::from("file://");
+	if !path.starts_with('/') {
+		// Windows paths start with the drive letter
+		uri.push('/');
+	}
+	for byte in path.bytes() {
+		if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+			uri.push(byte as char);
+		} else {
+			uri.push_str(&format!("%{:02X}", byte));
+		}
+	}
+	uri
+}
+
+/// Returns true if the text was placed on the clipboard
+fn set_clipboard_text(
+	clipboard: &mut Result<arboard::Clipboard, arboard::Error>,
+	text: String,
+) -> bool {
+	let clipboard = match clipboard {
+		Ok(clipboard) => clipboard,
+		Err(_) => return false,
+	};
+	if let Err(e) = clipboard.set_text(text) {
+		eprintln!("Could not set the clipboard text, error was: {}", e);
+		false
+	} else {
+		true
+	}
+}
+
+/// Returns true if the files were placed on the clipboard
+fn set_clipboard_file_list(
+	clipboard: &mut Result<arboard::Clipboard, arboard::Error>,
+	paths: &[PathBuf],
+) -> bool {
+	let clipboard = match clipboard {
+		Ok(clipboard) => clipboard,
+		Err(_) => return false,
+	};
+	if let Err(e) = clipboard.set().file_list(paths) {
+		eprintln!("Could not set the clipboard file list, error was: {}", e);
+		false
+	} else {
+		true
+	}
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+	match std::env::current_dir() {
+		Ok(current_dir) if path.is_relative() => current_dir.join(path),
+		_ => path.to_owned(),
+	}
+}
+
+pub fn apply_orientation(mut image: RgbaImage, orientation: Orientation) -> RgbaImage {
+	// Note: the imageops functions use clockwise rotation whereas the
+	// `Orientation` type describes counter-clockwise rotation.
+	match orientation {
+		Orientation::Deg0 => image,
+		Orientation::Deg0HorFlip => {
+			flip_horizontal_in_place(&mut image);
+			image
+		}
+		Orientation::Deg90 => rotate270(&image),
+		Orientation::Deg90VerFlip => {
+			let mut result = rotate270(&image);
+			flip_vertical_in_place(&mut result);
+			result
+		}
+		Orientation::Deg180 => {
+			rotate180_in_place(&mut image);
+			image
+		}
+		Orientation::Deg180HorFlip => {
+			// This is identical to just a vertical flip with no rotation.
+			flip_vertical_in_place(&mut image);
+			image
+		}
+		Orientation::Deg270 => rotate90(&image),
+		Orientation::Deg270VerFlip => {
+			let mut result = rotate90(&image);
+			flip_vertical_in_place(&mut result);
+			result
 		}
 	}
 }
@@ -1,10 +1,25 @@
 use crate::Version;
 use clap::{parser::ValueSource, value_parser, Arg, Command};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_COLUMNS: u32 = 6;
+
+pub struct ContactSheetArgs {
+	pub folder: PathBuf,
+	pub output: PathBuf,
+	pub columns: u32,
+}
 
 pub struct Args {
 	pub file_path: Option<String>,
 	pub displayed_folders: Option<u32>,
+	pub filter: Option<String>,
+	pub self_test: bool,
+	pub exit_after_last: bool,
+	pub software_render: bool,
+	pub contact_sheet: Option<ContactSheetArgs>,
+	pub info: Option<PathBuf>,
+	pub json: bool,
 }
 
 /// Parses the command-line arguments and returns the file path
@@ -45,7 +60,98 @@ pub fn parse_args(config_path: &Path, cache_path: &Path) -> Args {
 				.num_args(0)
 				.conflicts_with("FOLDER_COUNT"),
 		)
-		.arg(Arg::new("PATH").help("The file path of the image").index(1))
+		.arg(
+			Arg::new("FILTER")
+				.long("filter")
+				.help(
+					"Only show the files whose names match one of the patterns separated by \
+					semicolons, like \"*.png;render_*.jpg\"",
+				)
+				.num_args(1),
+		)
+		.arg(
+			Arg::new("exit-after-last")
+				.long("exit-after-last")
+				.help("Exit when the presentation reaches the last image")
+				.num_args(0),
+		)
+		.arg(
+			Arg::new("software-render")
+				.long("software-render")
+				.help(
+					"Render on the CPU instead of the GPU, for when the graphics driver is \
+					broken. Not available on Windows",
+				)
+				.num_args(0),
+		)
+		.arg(
+			Arg::new("self-test")
+				.long("self-test")
+				.help(
+					"Display generated test images, check that navigation, zooming and \
+					animations work, then exit with a non-zero status code on failure",
+				)
+				.num_args(0)
+				.conflicts_with("PATH"),
+		)
+		.arg(
+			Arg::new("CONTACT_SHEET")
+				.long("contact-sheet")
+				.value_name("FOLDER")
+				.help(
+					"Save the images of the folder in a grid into the image given with --output, \
+					then exit without opening a window",
+				)
+				.num_args(1)
+				.value_parser(value_parser!(PathBuf))
+				.requires("OUTPUT")
+				.conflicts_with_all(["PATH", "self-test"]),
+		)
+		.arg(
+			Arg::new("OUTPUT")
+				.long("output")
+				.short('o')
+				.value_name("FILE")
+				.help(
+					"The file that the contact sheet is saved to. Its format is chosen by its \
+					extension",
+				)
+				.num_args(1)
+				.value_parser(value_parser!(PathBuf))
+				.requires("CONTACT_SHEET"),
+		)
+		.arg(
+			Arg::new("COLUMNS")
+				.long("columns")
+				.help("The number of images in each row of the contact sheet, 6 by default")
+				.num_args(1)
+				.value_parser(value_parser!(u32).range(1..))
+				.requires("CONTACT_SHEET"),
+		)
+		.arg(
+			Arg::new("INFO")
+				.long("info")
+				.value_name("FILE")
+				.help(
+					"Print the format, the dimensions, the number of frames, the Exif orientation \
+					and the color profile of the image, then exit without opening a window",
+				)
+				.num_args(1)
+				.value_parser(value_parser!(PathBuf))
+				.conflicts_with_all(["PATH", "self-test", "CONTACT_SHEET"]),
+		)
+		.arg(
+			Arg::new("json")
+				.long("json")
+				.help("Print the information of --info as JSON")
+				.num_args(0)
+				.requires("INFO"),
+		)
+		.arg(
+			Arg::new("PATH")
+				.help("The file path of the image, or - to read the image from the standard input")
+				.index(1),
+		)
 		.get_matches();
 
 	let file_path = matches.get_one::<String>("PATH").cloned();
@@ -54,10 +160,40 @@ pub fn parse_args(config_path: &Path, cache_path: &Path) -> Args {
 	let displayed_folders = if is_absolute {
 		// Subtract one because we later want to add one to this value, and we don't want
 		// an overflow
-		Some(std::u32::MAX - 1)
+		Some(u32::MAX - 1)
 	} else {
 		matches.get_one::<u32>("FOLDER_COUNT").copied()
 	};
 
-	Args { file_path, displayed_folders }
+	let filter = matches.get_one::<String>("FILTER").cloned();
+
+	let self_test = matches.value_source("self-test") == Some(ValueSource::CommandLine);
+
+	let exit_after_last = matches.value_source("exit-after-last") == Some(ValueSource::CommandLine);
+
+	let software_render = matches.value_source("software-render") == Some(ValueSource::CommandLine);
+
+	let contact_sheet =
+		matches.get_one::<PathBuf>("CONTACT_SHEET").map(|folder| ContactSheetArgs {
+			folder: folder.clone(),
+			// Required by the contact sheet
+			output: matches.get_one::<PathBuf>("OUTPUT").cloned().unwrap_or_default(),
+			columns: matches.get_one::<u32>("COLUMNS").copied().unwrap_or(DEFAULT_COLUMNS),
+		});
+
+	let info = matches.get_one::<PathBuf>("INFO").cloned();
+
+	let json = matches.value_source("json") == Some(ValueSource::CommandLine);
+
+	Args {
+		file_path,
+		displayed_folders,
+		filter,
+		self_test,
+		exit_after_last,
+		software_render,
+		contact_sheet,
+		info,
+		json,
+	}
 }
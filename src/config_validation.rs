@@ -0,0 +1,318 @@
+//! Looks for mistakes in the config file that would otherwise go unnoticed, because misspelled
+//! options are ignored and a value of the wrong type makes the whole file fall back to the
+//! defaults. The warnings point at the line of the mistake where it can be found.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::configuration::Configuration;
+use crate::input_handling::{binding_problem, find_action};
+
+/// The options of the sections of the config. An option that's added to `Configuration` has to
+/// be added here too, otherwise it's reported as unknown.
+const SECTIONS: &[(&str, &[&str])] = &[
+	("updates", &["check_updates"]),
+	("title", &["displayed_folders", "show_program_name"]),
+	(
+		"image",
+		&[
+			"antialiasing",
+			"max_decode_megapixels",
+			"background",
+			"background_color",
+			"reading_progress",
+			"wheel_zoom_anchor",
+			"fit_stretches",
+			"display_transform",
+			"display_lut",
+		],
+	),
+	(
+		"window",
+		&[
+			"start_fullscreen",
+			"start_maximized",
+			"show_bottom_bar",
+			"autohide_bottom_bar",
+			"show_osd",
+			"osd_secs",
+			"theme",
+			"use_last_window_area",
+			"win_w",
+			"win_h",
+			"win_x",
+			"win_y",
+			"single_instance",
+			"restore_last_image",
+			"restore_last_zoom",
+			"always_on_top",
+			"borderless",
+			"opacity",
+			"reference_opacity",
+			"reference_click_through",
+			"ui_scale",
+			"deep_color",
+		],
+	),
+	("power", &["power_saving"]),
+	("cache", &["max_memory_mb", "threads", "save_thumbnails", "sync_load_max_kb"]),
+	("mouse", &["invert_scroll_zoom", "horizontal_scroll_navigation"]),
+	("navigation", &["extra_extensions"]),
+	("files", &["confirm_delete"]),
+	("clipboard", &["max_image_size", "jpeg"]),
+	("quick_sort", &["move_to", "copy_to", "advance_after_copy"]),
+	("export", &["image_path", "view_path", "jpeg_quality"]),
+	("metadata", &["tag_keys", "min_rating", "required_tags"]),
+	(
+		"playback",
+		&[
+			"present_interval_secs",
+			"present_end",
+			"folder_end",
+			"folder_end",
+			"panorama_tour_speed",
+			"ken_burns",
+			"nav_repeat_delay_ms",
+			"nav_repeat_interval_ms",
+			"sort_order",
+			"filter",
+		],
+	),
+	// Listed even without the "video" feature, which only makes the section have no effect
+	("video", &["ffmpeg_path", "ffprobe_path", "max_loop_seconds"]),
+];
+
+/// The options of each table in the `commands` and the `open_with` arrays
+const COMMAND_OPTIONS: &[&str] = &["input", "program", "args", "envs"];
+const OPEN_WITH_OPTIONS: &[&str] = &["name", "program", "args", "envs"];
+const ENV_VAR_OPTIONS: &[&str] = &["name", "value"];
+
+/// At most this many warnings are listed in the toast, the rest are only printed
+const MAX_SHOWN_WARNINGS: usize = 3;
+
+pub struct ConfigWarning {
+	/// Starting from 1. `None` if the mistake couldn't be found in the text.
+	pub line: Option<usize>,
+	pub message: String,
+}
+
+impl fmt::Display for ConfigWarning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.line {
+			Some(line) => write!(f, "line {}: {}", line, self.message),
+			None => write!(f, "{}", self.message),
+		}
+	}
+}
+
+/// Returns no warnings if the file can't be read, for example because it doesn't exist yet
+pub fn validate_file(path: &Path) -> Vec<ConfigWarning> {
+	match fs::read_to_string(path) {
+		Ok(text) => validate(&text),
+		Err(_) => Vec::new(),
+	}
+}
+
+pub fn validate(text: &str) -> Vec<ConfigWarning> {
+	let mut validator = Validator { text, warnings: Vec::new() };
+	let table = match text.parse::<toml::Table>() {
+		Ok(table) => table,
+		Err(e) => {
+			validator.parse_error(&e);
+			return validator.warnings;
+		}
+	};
+	validator.check_options(&table);
+	match toml::from_str::<Configuration>(text) {
+		Ok(config) => {
+			validator.check_bindings(&config);
+			validator.check_values(&config);
+		}
+		Err(e) => validator.parse_error(&e),
+	}
+	validator.warnings.sort_by_key(|warning| warning.line);
+	validator.warnings
+}
+
+pub fn print_warnings(path: &Path, warnings: &[ConfigWarning]) {
+	for warning in warnings {
+		eprintln!("Problem in the config file {:?}, {}", path, warning);
+	}
+}
+
+/// The text of the toast that lists the warnings, `None` if there are none
+pub fn summary(warnings: &[ConfigWarning]) -> Option<String> {
+	if warnings.is_empty() {
+		return None;
+	}
+	let mut text = String::from("Problems in the config file:");
+	for warning in warnings.iter().take(MAX_SHOWN_WARNINGS) {
+		text.push_str(&format!("\n{}", warning));
+	}
+	if warnings.len() > MAX_SHOWN_WARNINGS {
+		text.push_str(&format!("\nand {} more", warnings.len() - MAX_SHOWN_WARNINGS));
+	}
+	Some(text)
+}
+
+struct Validator<'a> {
+	text: &'a str,
+	warnings: Vec<ConfigWarning>,
+}
+
+impl<'a> Validator<'a> {
+	fn warn(&mut self, line: Option<usize>, message: String) {
+		self.warnings.push(ConfigWarning { line, message });
+	}
+
+	fn parse_error(&mut self, error: &toml::de::Error) {
+		let start = error.span().map(|span| span.start);
+		let line = start.and_then(|start| self.text.get(..start));
+		let line = line.map(|before| before.matches('\n').count() + 1);
+		self.warn(line, error.message().to_owned());
+	}
+
+	fn check_options(&mut self, table: &toml::Table) {
+		for (name, value) in table {
+			match name.as_str() {
+				// The names of the actions are checked with the bindings
+				"bindings" => (),
+				// A value of the wrong type is reported when the config is deserialized
+				"trusted_folders" => (),
+				"commands" => self.check_array(name, value, COMMAND_OPTIONS),
+				"open_with" => self.check_array(name, value, OPEN_WITH_OPTIONS),
+				_ => match SECTIONS.iter().find(|(section, _)| *section == name.as_str()) {
+					Some((_, options)) => self.check_table(name, value, options),
+					None if value.is_table() => {
+						let line = self.line_of(name, None);
+						self.warn(line, format!("Unknown section [{}]", name));
+					}
+					None => {
+						let line = self.line_of("", Some(name));
+						self.warn(line, format!("Unknown option `{}`", name));
+					}
+				},
+			}
+		}
+	}
+
+	fn check_array(&mut self, section: &str, value: &toml::Value, options: &[&str]) {
+		let tables = value.as_array().into_iter().flatten().filter_map(|value| value.as_table());
+		for table in tables {
+			self.check_table_keys(section, table, options);
+			let env_vars = table.get("envs").and_then(|envs| envs.as_array());
+			for env_var in env_vars.into_iter().flatten().filter_map(|var| var.as_table()) {
+				self.check_table_keys(section, env_var, ENV_VAR_OPTIONS);
+			}
+		}
+	}
+
+	fn check_table(&mut self, section: &str, value: &toml::Value, options: &[&str]) {
+		// A value of the wrong type is reported when the config is deserialized
+		if let Some(table) = value.as_table() {
+			self.check_table_keys(section, table, options);
+		}
+	}
+
+	fn check_table_keys(&mut self, section: &str, table: &toml::Table, options: &[&str]) {
+		for name in table.keys().filter(|name| !options.contains(&name.as_str())) {
+			let line = self.line_of(section, Some(name));
+			self.warn(line, format!("Unknown option `{}` in [{}]", name, section));
+		}
+	}
+
+	fn check_bindings(&mut self, config: &Configuration) {
+		for (action_name, keys) in config.bindings.iter().flatten() {
+			if find_action(action_name).is_none() {
+				let line = self.line_of("bindings", Some(action_name));
+				self.warn(line, format!("Unknown action `{}` in [bindings]", action_name));
+			}
+			self.check_keys(keys);
+		}
+		for command in config.commands.iter().flatten() {
+			self.check_keys(&command.input);
+		}
+		if let Some(quick_sort) = &config.quick_sort {
+			let targets = quick_sort.move_to.iter().chain(quick_sort.copy_to.iter());
+			let keys: Vec<_> = targets.flat_map(|targets| targets.keys()).collect();
+			self.check_keys(&keys);
+		}
+		let tag_keys = config.metadata.as_ref().and_then(|s| s.tag_keys.as_ref());
+		if let Some(tag_keys) = tag_keys {
+			let keys: Vec<_> = tag_keys.keys().collect();
+			self.check_keys(&keys);
+		}
+	}
+
+	fn check_keys<S: AsRef<str>>(&mut self, keys: &[S]) {
+		for key in keys {
+			let key = key.as_ref();
+			if let Some(problem) = binding_problem(key) {
+				let line = self.line_containing(&format!("{:?}", key));
+				self.warn(line, format!("The binding {}", problem));
+			}
+		}
+	}
+
+	fn check_values(&mut self, config: &Configuration) {
+		let antialiasing = config.image.as_ref().and_then(|s| s.antialiasing.as_deref());
+		if let Some(antialiasing) = antialiasing {
+			if !["auto", "always", "never", "previous"].contains(&antialiasing) {
+				let message = format!(
+					"`antialiasing` is {:?}, but it can be \"auto\", \"always\", \"never\" or \
+					\"previous\"",
+					antialiasing
+				);
+				self.warn(self.line_of("image", Some("antialiasing")), message);
+			}
+		}
+		if let Some(window) = &config.window {
+			let opacities =
+				[("opacity", window.opacity), ("reference_opacity", window.reference_opacity)];
+			for &(name, opacity) in opacities.iter() {
+				if opacity.is_some_and(|opacity| !(0.0..=1.0).contains(&opacity)) {
+					let message = format!("`{}` has to be between 0 and 1", name);
+					self.warn(self.line_of("window", Some(name)), message);
+				}
+			}
+		}
+		let interval = config.playback.as_ref().and_then(|s| s.present_interval_secs);
+		if interval.is_some_and(|interval| interval <= 0.0) {
+			let message = "`present_interval_secs` has to be more than 0".to_owned();
+			self.warn(self.line_of("playback", Some("present_interval_secs")), message);
+		}
+		let jpeg_quality = config.export.as_ref().and_then(|s| s.jpeg_quality);
+		if jpeg_quality.is_some_and(|quality| !(1..=100).contains(&quality)) {
+			let message = "`jpeg_quality` has to be between 1 and 100".to_owned();
+			self.warn(self.line_of("export", Some("jpeg_quality")), message);
+		}
+	}
+
+	/// The line of the option in the section, or the header of the section if `option` is
+	/// `None`. The options before the first section are in the section "". The first match is
+	/// returned if the section is an array of tables.
+	fn line_of(&self, section: &str, option: Option<&str>) -> Option<usize> {
+		let mut current_section = "";
+		for (index, line) in self.text.lines().enumerate() {
+			let line = line.trim();
+			if line.starts_with('[') {
+				let header = line.trim_start_matches('[').split(']').next().unwrap_or("");
+				current_section = header.trim();
+				if option.is_none() && current_section == section {
+					return Some(index + 1);
+				}
+				continue;
+			}
+			let name = line.split('=').next().unwrap_or("").trim().trim_matches('"');
+			if current_section == section && Some(name) == option {
+				return Some(index + 1);
+			}
+		}
+		None
+	}
+
+	fn line_containing(&self, needle: &str) -> Option<usize> {
+		self.text.lines().position(|line| line.contains(needle)).map(|index| index + 1)
+	}
+}
@@ -0,0 +1,118 @@
+//! Reads the config file again when it's saved, so that the changes take effect without restarting
+//! Emulsion. The key bindings, the commands and the title are looked up in the config whenever
+//! they are used. The options that are applied once, like the theme or the presentation interval,
+//! are applied again by the subscribers.
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use gelatin::winit::event_loop::EventLoopProxy;
+use log::debug;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config_validation::{self, ConfigWarning};
+use crate::configuration::Configuration;
+
+/// Sent to the subscribers when the config file was saved
+pub enum ConfigChange<'a> {
+	Reloaded {
+		previous: &'a Configuration,
+		config: &'a Configuration,
+		/// The mistakes that were found in the new config
+		warnings: &'a [ConfigWarning],
+	},
+	/// The file couldn't be read, so the previous config stays in effect
+	Failed(&'a str),
+}
+
+type Subscriber = Box<dyn FnMut(&ConfigChange)>;
+
+pub struct ConfigWatcher {
+	config: Rc<RefCell<Configuration>>,
+	path: PathBuf,
+	/// Stops watching when dropped. `None` if the watcher couldn't be created.
+	_watcher: Option<RecommendedWatcher>,
+	/// Set by the watcher thread when the config file changes
+	changed: Arc<AtomicBool>,
+	subscribers: Vec<Subscriber>,
+}
+
+impl ConfigWatcher {
+	/// Sends an event through `proxy` when the file at `path` changes, after which `update`
+	/// should be called
+	pub fn new(
+		config: Rc<RefCell<Configuration>>,
+		path: PathBuf,
+		proxy: EventLoopProxy<()>,
+	) -> Self {
+		let changed = Arc::new(AtomicBool::new(false));
+		let watcher = create_watcher(&path, changed.clone(), proxy);
+		ConfigWatcher { config, path, _watcher: watcher, changed, subscribers: Vec::new() }
+	}
+
+	/// The callback is called while the watcher is borrowed, so it must not subscribe. It may
+	/// borrow the config though.
+	pub fn subscribe<F: FnMut(&ConfigChange) + 'static>(&mut self, callback: F) {
+		self.subscribers.push(Box::new(callback));
+	}
+
+	/// Reads the config file again if it changed since the last call, and tells the subscribers
+	pub fn update(&mut self) {
+		if !self.changed.swap(false, Ordering::SeqCst) {
+			return;
+		}
+		let previous = self.config.borrow().clone();
+		let result = self.config.borrow_mut().reload(&self.path);
+		let warnings = config_validation::validate_file(&self.path);
+		config_validation::print_warnings(&self.path, &warnings);
+		let config = self.config.borrow();
+		let change = match &result {
+			Ok(()) => {
+				debug!("Reloaded config: {:#?}", *config);
+				ConfigChange::Reloaded { previous: &previous, config: &config, warnings: &warnings }
+			}
+			Err(e) => ConfigChange::Failed(e),
+		};
+		for subscriber in self.subscribers.iter_mut() {
+			subscriber(&change);
+		}
+	}
+}
+
+/// The folder of the config file is watched instead of the file itself, because many editors
+/// save by replacing the file with a new one
+fn create_watcher(
+	path: &Path,
+	changed: Arc<AtomicBool>,
+	proxy: EventLoopProxy<()>,
+) -> Option<RecommendedWatcher> {
+	let file_name: OsString = path.file_name()?.to_owned();
+	let folder = path.parent()?;
+	let result = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+		let event = match event {
+			Ok(event) if !event.kind.is_access() => event,
+			_ => return,
+		};
+		let is_config = |path: &PathBuf| path.file_name() == Some(file_name.as_os_str());
+		// A save often consists of several events, one wake-up is enough for them
+		if event.paths.iter().any(is_config) && !changed.swap(true, Ordering::SeqCst) {
+			let _ = proxy.send_event(());
+		}
+	});
+	let mut watcher = match result {
+		Ok(watcher) => watcher,
+		Err(e) => {
+			debug!("Could not create the config watcher: {}", e);
+			return None;
+		}
+	};
+	if let Err(e) = watcher.watch(folder, RecursiveMode::NonRecursive) {
+		// The folder is created when the config is first saved
+		debug!("Could not watch the config folder {:?}: {}", folder, e);
+	}
+	Some(watcher)
+}
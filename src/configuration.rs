@@ -10,7 +10,8 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
@@ -30,6 +31,24 @@ impl Theme {
 	}
 }
 
+/// The `theme` of the `[window]` section
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeSetting {
+	Light,
+	Dark,
+	/// Follows the light or dark preference of the operating system
+	Auto,
+}
+impl From<Theme> for ThemeSetting {
+	fn from(theme: Theme) -> Self {
+		match theme {
+			Theme::Light => ThemeSetting::Light,
+			Theme::Dark => ThemeSetting::Dark,
+		}
+	}
+}
+
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Antialias {
@@ -39,15 +58,267 @@ pub enum Antialias {
 	Never,
 }
 
+/// Whether Emulsion should reduce prefetching, decoder threads and animation frame rates
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSaving {
+	/// Save power when the computer is running on battery
+	#[default]
+	Auto,
+	Always,
+	Never,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigPowerSection {
+	pub power_saving: Option<PowerSaving>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigCacheSection {
+	/// The memory that the loaded images may take up. The default is an eighth of the RAM.
+	pub max_memory_mb: Option<u64>,
+	/// The number of threads that decode the images
+	pub threads: Option<u32>,
+	/// Save thumbnails of the viewed images into the thumbnail folder that file managers use
+	pub save_thumbnails: Option<bool>,
+	/// The image that is opened at startup is loaded before the window is first drawn if its
+	/// file is at most this many kilobytes. Zero turns this off.
+	pub sync_load_max_kb: Option<u64>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigNavigationSection {
+	/// The files with these extensions are also navigated to, in addition to the files that
+	/// are known to be supported
+	pub extra_extensions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigFilesSection {
+	/// Deleting an image has to be confirmed by pressing the delete key again
+	pub confirm_delete: Option<bool>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigClipboardSection {
+	/// Copied images with a longer side than this many pixels are downscaled first
+	pub max_image_size: Option<u32>,
+	/// Also offers the copied images as JPEG, on Windows
+	pub jpeg: Option<bool>,
+}
+
+/// Where the exported images are saved. See the `export` module for the patterns.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigExportSection {
+	/// The path pattern of the exported images, like `"${folder}/exports/${name}.jpg"`
+	pub image_path: Option<String>,
+	/// The path pattern of the exported views
+	pub view_path: Option<String>,
+	/// From 1 to 100
+	pub jpeg_quality: Option<u8>,
+}
+
+/// Keys that move or copy the current image into a folder, for sorting photos
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigQuickSortSection {
+	/// The folder that each key moves the image into, like `"1" = "/photos/keep"`
+	pub move_to: Option<BTreeMap<String, PathBuf>>,
+	/// The folder that each key copies the image into
+	pub copy_to: Option<BTreeMap<String, PathBuf>>,
+	/// Show the next image after copying. Moving always shows the next image.
+	pub advance_after_copy: Option<bool>,
+}
+
+/// Star ratings and tags, which are stored in XMP sidecar files next to the images
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigMetadataSection {
+	/// The tag that each key adds or removes, like `"Alt+1" = "family"`
+	pub tag_keys: Option<BTreeMap<String, String>>,
+	/// Only the images with at least this many stars are shown
+	pub min_rating: Option<u8>,
+	/// Only the images that have all of these tags are shown
+	pub required_tags: Option<Vec<String>>,
+}
+
+/// How video clips are shown, if Emulsion was built with the `video` feature
+#[cfg(feature = "video")]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigVideoSection {
+	/// The `ffmpeg` program that decodes the videos, if it's not on the `PATH`
+	pub ffmpeg_path: Option<PathBuf>,
+	/// The `ffprobe` program that reads the size and the length of the videos
+	pub ffprobe_path: Option<PathBuf>,
+	/// Clips up to this long are played in a loop. Only the first frame of longer videos is
+	/// shown.
+	pub max_loop_seconds: Option<u32>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct ConfigMouseSection {
+	/// Scrolling up zooms out instead of zooming in
+	pub invert_scroll_zoom: Option<bool>,
+	/// Scrolling horizontally, or tilting the wheel, moves to the next or previous image
+	pub horizontal_scroll_navigation: Option<bool>,
+}
+
+/// How the colors of the image are turned into the values that are sent to the display
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayTransform {
+	#[default]
+	Srgb,
+	/// A display with the 2.4 gamma of BT.1886, like the ones used for reviewing video
+	Rec709,
+	/// The 3D LUT given by `display_lut`
+	Lut,
+}
+
+/// What is drawn behind the transparent parts of the image
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+	/// A checkerboard pattern in the shade of the theme
+	#[default]
+	Checkerboard,
+	/// The solid shade of the theme
+	Theme,
+	/// The solid color specified by `background_color`
+	Custom,
+}
+impl Background {
+	pub fn next(self) -> Self {
+		match self {
+			Background::Checkerboard => Background::Theme,
+			Background::Theme => Background::Custom,
+			Background::Custom => Background::Checkerboard,
+		}
+	}
+}
+
+/// The order of the images in a folder
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+	/// By file name, with the numbers in the names compared by their value
+	#[default]
+	Name,
+	/// The oldest first
+	ModifiedTime,
+	/// The smallest first
+	FileSize,
+	/// A random order that stays the same while the window is open
+	Random,
+}
+impl SortOrder {
+	pub fn next(self) -> Self {
+		match self {
+			SortOrder::Name => SortOrder::ModifiedTime,
+			SortOrder::ModifiedTime => SortOrder::FileSize,
+			SortOrder::FileSize => SortOrder::Random,
+			SortOrder::Random => SortOrder::Name,
+		}
+	}
+}
+
+/// Where the position of the view in tall images is remembered, so that returning to the image
+/// continues where the user left off
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingProgress {
+	Off,
+	/// Until Emulsion is closed
+	#[default]
+	Session,
+	/// In the cache, along with the zoom of the image
+	Cache,
+}
+
+/// The point that stays in place when zooming with the mouse wheel
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoomAnchor {
+	/// The point of the image under the cursor
+	#[default]
+	Cursor,
+	/// The center of the window
+	Center,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheGraphicsSection {
+	/// The number of launches in a row that didn't get to open the window, which usually means
+	/// that the graphics driver crashed while the OpenGL context was created
+	pub failed_starts: u32,
+}
+
 #[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct CacheImageSection {
 	pub fit_stretches: bool,
 	pub antialiasing: Antialias,
 }
 
+/// What was shown when Emulsion was closed the last time
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheSessionSection {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_file: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub zoom: Option<ViewZoom>,
+}
+
+/// The number of images whose view is remembered. The least recently viewed ones are forgotten
+/// first.
+const MAX_VIEW_ENTRIES: usize = 1000;
+/// The number of folders that are remembered as recent
+const MAX_RECENT_FOLDERS: usize = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewZoom {
+	Fit,
+	FitStretch,
+	/// The size of an image pixel in physical pixels
+	Fixed(f32),
+}
+
+/// How an image was displayed the last time that it was viewed
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheViewEntry {
+	pub path: String,
+	/// The Exif orientation value of the displayed orientation
+	pub orientation: u16,
+	pub zoom: ViewZoom,
+	/// The fraction of the height of a tall image that was at the center of the view
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub reading_position: Option<f32>,
+}
+
+/// A folder that images were viewed in
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheRecentFolder {
+	pub folder: String,
+	/// The image that was viewed last in the folder
+	pub file: String,
+}
+
 #[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
 pub struct ConfigImageSection {
 	pub antialiasing: Option<String>,
+	/// Images with more pixels than this are not decoded unless the user asks for a downscaled
+	/// version. Zero disables the limit.
+	pub max_decode_megapixels: Option<u32>,
+	pub background: Option<Background>,
+	/// The red, green and blue components of the custom background
+	pub background_color: Option<[u8; 3]>,
+	pub reading_progress: Option<ReadingProgress>,
+	pub wheel_zoom_anchor: Option<ZoomAnchor>,
+	/// Whether small images are stretched to fit the window at start. The last used fitting
+	/// mode is used if this is not set.
+	pub fit_stretches: Option<bool>,
+	pub display_transform: Option<DisplayTransform>,
+	/// The `.cube` file that is used when `display_transform` is "lut"
+	pub display_lut: Option<PathBuf>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
@@ -65,17 +336,96 @@ impl Default for CacheWindowSection {
 	}
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct ConfigWindowSection {
 	pub start_fullscreen: Option<bool>,
 	pub start_maximized: Option<bool>,
 	pub show_bottom_bar: Option<bool>,
-	pub theme: Option<Theme>,
+	/// The bottom bar is only shown while the cursor is near the bottom edge of the window.
+	/// It's always hidden in fullscreen.
+	pub autohide_bottom_bar: Option<bool>,
+	/// Briefly shows the name and the index of the image in a corner when the image changes
+	pub show_osd: Option<bool>,
+	/// How long the name of the image is shown for
+	pub osd_secs: Option<f32>,
+	pub theme: Option<ThemeSetting>,
 	pub use_last_window_area: Option<bool>,
 	pub win_w: Option<u32>,
 	pub win_h: Option<u32>,
 	pub win_x: Option<i32>,
 	pub win_y: Option<i32>,
+	/// Opening an image while Emulsion is running shows it in the running instance
+	pub single_instance: Option<bool>,
+	/// Starting Emulsion without an image opens the image that was shown when it was closed
+	pub restore_last_image: Option<bool>,
+	/// The restored image is shown with the zoom that it had when Emulsion was closed
+	pub restore_last_zoom: Option<bool>,
+	pub always_on_top: Option<bool>,
+	/// Hides the title bar and the borders of the window
+	pub borderless: Option<bool>,
+	/// Between 0 and 1. The opacity can only be changed while Emulsion is running if this is
+	/// set, because the window has to be created as translucent.
+	pub opacity: Option<f32>,
+	/// The opacity of the window in reference mode, if `opacity` is set
+	pub reference_opacity: Option<f32>,
+	/// The mouse clicks go through the window to the one below it in reference mode
+	pub reference_click_through: Option<bool>,
+	/// Draws the bottom bar, the text and the other widgets larger or smaller, on top of the
+	/// scaling of the operating system. Between 0.5 and 4.
+	pub ui_scale: Option<f32>,
+	/// Asks for a surface with 10 bits per channel, so that 16-bit PNG and TIFF images are
+	/// shown without banding on displays that support it
+	pub deep_color: Option<bool>,
+}
+
+/// What happens when the presentation reaches the last image of the folder
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentEnd {
+	/// Continue with the first image
+	#[default]
+	Loop,
+	Stop,
+	/// Close Emulsion
+	Exit,
+	/// Stop and show how many images were presented
+	Summary,
+}
+
+/// What happens when moving past the last or the first image of the folder
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderEnd {
+	/// Continue with the image on the other end
+	#[default]
+	Wrap,
+	/// Stay at the last or the first image
+	Stop,
+	/// Turn around, after which the next image key moves backwards until the other end is
+	/// reached
+	PingPong,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Deserialize)]
+pub struct ConfigPlaybackSection {
+	/// The time that each image is shown for in presentation mode
+	pub present_interval_secs: Option<f32>,
+	pub present_end: Option<PresentEnd>,
+	/// Applies to the presentation as well if `present_end` is "loop"
+	pub folder_end: Option<FolderEnd>,
+	/// How fast the panorama tour moves, in widths of the window per second
+	pub panorama_tour_speed: Option<f32>,
+	/// Slowly zooms and pans across each image of the presentation
+	pub ken_burns: Option<bool>,
+	/// How long the next or previous image key has to be held before it starts repeating
+	pub nav_repeat_delay_ms: Option<u64>,
+	/// The time between two images while the key is held. Zero leaves repeating to the
+	/// key repeat of the operating system.
+	pub nav_repeat_interval_ms: Option<u64>,
+	pub sort_order: Option<SortOrder>,
+	/// Only the files whose names match one of these patterns are shown, for example
+	/// `*.png;render_*.jpg`
+	pub filter: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize)]
@@ -115,13 +465,23 @@ struct IncompleteCache {
 	pub window: Option<CacheWindowSection>,
 	pub updates: Option<CacheUpdateSection>,
 	pub image: Option<CacheImageSection>,
+	pub graphics: Option<CacheGraphicsSection>,
+	pub session: Option<CacheSessionSection>,
+	pub views: Option<Vec<CacheViewEntry>>,
+	pub recent_folders: Option<Vec<CacheRecentFolder>>,
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize)]
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
 pub struct Cache {
 	pub window: CacheWindowSection,
 	pub updates: CacheUpdateSection,
 	pub image: CacheImageSection,
+	pub graphics: CacheGraphicsSection,
+	pub session: CacheSessionSection,
+	/// The most recently viewed image is the last one
+	pub views: Vec<CacheViewEntry>,
+	/// The most recently viewed folder is the last one
+	pub recent_folders: Vec<CacheRecentFolder>,
 }
 impl From<IncompleteCache> for Cache {
 	fn from(cache: IncompleteCache) -> Self {
@@ -129,6 +489,10 @@ impl From<IncompleteCache> for Cache {
 			window: cache.window.unwrap_or_default(),
 			updates: cache.updates.unwrap_or_default(),
 			image: cache.image.unwrap_or_default(),
+			graphics: cache.graphics.unwrap_or_default(),
+			session: cache.session.unwrap_or_default(),
+			views: cache.views.unwrap_or_default(),
+			recent_folders: cache.recent_folders.unwrap_or_default(),
 		}
 	}
 }
@@ -145,6 +509,45 @@ impl Cache {
 		self.window.dark = theme == Theme::Dark;
 	}
 
+	pub fn view(&self, path: &Path) -> Option<&CacheViewEntry> {
+		let path = path.to_string_lossy();
+		self.views.iter().rev().find(|entry| entry.path == path)
+	}
+
+	pub fn set_view(
+		&mut self,
+		path: &Path,
+		orientation: u16,
+		zoom: ViewZoom,
+		reading_position: Option<f32>,
+	) {
+		let path = path.to_string_lossy().into_owned();
+		self.views.retain(|entry| entry.path != path);
+		if self.views.len() >= MAX_VIEW_ENTRIES {
+			self.views.remove(0);
+		}
+		self.views.push(CacheViewEntry { path, orientation, zoom, reading_position });
+	}
+
+	pub fn forget_view(&mut self, path: &Path) {
+		let path = path.to_string_lossy();
+		self.views.retain(|entry| entry.path != path);
+	}
+
+	/// Remembers the folder of the image as the most recent folder, along with the image
+	pub fn set_recent_folder(&mut self, file_path: &Path) {
+		let folder = match file_path.parent() {
+			Some(folder) => folder.to_string_lossy().into_owned(),
+			None => return,
+		};
+		let file = file_path.to_string_lossy().into_owned();
+		self.recent_folders.retain(|entry| entry.folder != folder);
+		if self.recent_folders.len() >= MAX_RECENT_FOLDERS {
+			self.recent_folders.remove(0);
+		}
+		self.recent_folders.push(CacheRecentFolder { folder, file });
+	}
+
 	pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Cache, String> {
 		let file_path = file_path.as_ref();
 		let cfg_str = fs::read_to_string(file_path)
@@ -177,6 +580,18 @@ pub struct Command {
 	pub envs: Option<Vec<EnvVar>>,
 }
 
+/// A program that the "Open with" list offers to open the shown image with
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct OpenWithProgram {
+	/// Shown in the list
+	pub name: String,
+	pub program: String,
+	/// `${img}` and `${folder}` are replaced like in the commands. The path of the image is the
+	/// only argument if these are not given.
+	pub args: Option<Vec<String>>,
+	pub envs: Option<Vec<EnvVar>>,
+}
+
 #[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
 pub struct TitleSection {
 	pub displayed_folders: Option<u32>,
@@ -215,22 +630,214 @@ impl TitleSection {
 	}
 }
 
+/// The name of the config file that can be placed in a folder to add options that only apply while
+/// browsing that folder
+pub const FOLDER_CONFIG_FILE_NAME: &str = ".emulsion.toml";
+
+/// The options of the config file of a folder. These take precedence over the global config.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Deserialize)]
+pub struct FolderConfiguration {
+	/// Added to the commands of the global config. A global command is disabled in the folder if
+	/// a command of the folder has any of its inputs. Only used in the `trusted_folders` of the
+	/// global config, because a downloaded folder could run any program otherwise.
+	pub commands: Option<Vec<Command>>,
+	/// Used instead of the `sort_order` of the global config
+	pub sort_order: Option<SortOrder>,
+	/// Used instead of the `filter` of the global config
+	pub filter: Option<String>,
+}
+impl FolderConfiguration {
+	/// Returns the default if the folder has no config file
+	pub fn load(folder: &Path) -> Result<FolderConfiguration, String> {
+		let file_path = folder.join(FOLDER_CONFIG_FILE_NAME);
+		let cfg_str = match fs::read_to_string(&file_path) {
+			Ok(cfg_str) => cfg_str,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+			Err(_) => return Err(format!("Could not read config from {:?}", file_path)),
+		};
+		toml::from_str(&cfg_str).map_err(|e| format!("Error in {:?}: {}", file_path, e))
+	}
+}
+
+/// Compares key bindings regardless of the letter case and the order of the modifiers
+pub fn same_input(a: &str, b: &str) -> bool {
+	let parts = |input: &str| {
+		let mut parts: Vec<_> = input.split('+').map(|s| s.trim().to_lowercase()).collect();
+		parts.sort_unstable();
+		parts
+	};
+	parts(a) == parts(b)
+}
+
+/// The options given on the command line. Only the options that were given take precedence over
+/// the config file, the rest of the config file stays in effect.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct CmdLineOverrides {
+	pub displayed_folders: Option<u32>,
+	pub filter: Option<String>,
+	pub software_render: bool,
+	/// Closes Emulsion when the presentation reaches the last image
+	pub exit_after_last: bool,
+}
+
+/// The options of the sections also have to be listed in `config_validation`, so that they
+/// aren't reported as unknown
 #[derive(Debug, Default, Clone, Deserialize)]
 pub struct Configuration {
 	pub bindings: Option<BTreeMap<String, Vec<String>>>,
 	pub commands: Option<Vec<Command>>,
+	/// The folders whose `.emulsion.toml` may define commands, including their subfolders
+	pub trusted_folders: Option<Vec<PathBuf>>,
+	pub open_with: Option<Vec<OpenWithProgram>>,
 	pub updates: Option<ConfigUpdateSection>,
 	pub title: Option<TitleSection>,
 	pub image: Option<ConfigImageSection>,
 	pub window: Option<ConfigWindowSection>,
+	pub power: Option<ConfigPowerSection>,
+	pub cache: Option<ConfigCacheSection>,
+	pub mouse: Option<ConfigMouseSection>,
+	pub navigation: Option<ConfigNavigationSection>,
+	pub files: Option<ConfigFilesSection>,
+	pub clipboard: Option<ConfigClipboardSection>,
+	pub quick_sort: Option<ConfigQuickSortSection>,
+	pub export: Option<ConfigExportSection>,
+	pub metadata: Option<ConfigMetadataSection>,
+	pub playback: Option<ConfigPlaybackSection>,
+	#[cfg(feature = "video")]
+	pub video: Option<ConfigVideoSection>,
+	/// Kept apart from the sections, so that they still apply if the config file is read again
+	#[serde(skip)]
+	pub overrides: CmdLineOverrides,
+	/// Where the config is saved when it's changed in Emulsion
+	#[serde(skip)]
+	pub file_path: PathBuf,
 }
 impl Configuration {
+	/// The `[title]` section with the command line options applied. The title should be
+	/// formatted with this on every update, so that a changed config takes effect right away.
+	pub fn title_section(&self) -> TitleSection {
+		let mut title = self.title.clone().unwrap_or_default();
+		if let Some(displayed_folders) = self.overrides.displayed_folders {
+			title.displayed_folders = Some(displayed_folders);
+		}
+		title
+	}
+
+	/// What happens at the end of the presentation, with the command line option applied
+	pub fn present_end(&self) -> PresentEnd {
+		if self.overrides.exit_after_last {
+			return PresentEnd::Exit;
+		}
+		self.playback.as_ref().and_then(|s| s.present_end).unwrap_or_default()
+	}
+
+	pub fn folder_end(&self) -> FolderEnd {
+		self.playback.as_ref().and_then(|s| s.folder_end).unwrap_or_default()
+	}
+
+	/// The patterns of the files shown in the folder. The command line takes precedence over the
+	/// folder config, which takes precedence over the global config.
+	pub fn file_filter<'a>(&'a self, folder_config: &'a FolderConfiguration) -> Option<&'a str> {
+		let global_filter = self.playback.as_ref().and_then(|s| s.filter.as_ref());
+		let filter = self.overrides.filter.as_ref().or(folder_config.filter.as_ref());
+		filter.or(global_filter).map(|filter| filter.as_str())
+	}
+
+	/// True if the folder is in one of the `trusted_folders`. The paths are compared after
+	/// resolving the symbolic links and the `..` components.
+	pub fn is_folder_trusted(&self, folder: &Path) -> bool {
+		let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+		let folder = canonical(folder);
+		let mut trusted_folders = self.trusted_folders.iter().flatten();
+		trusted_folders.any(|trusted| folder.starts_with(canonical(trusted)))
+	}
+
+	/// The commands that apply while browsing the folder, the ones from the folder config first
+	pub fn folder_commands<'a>(
+		&'a self,
+		folder_config: &'a FolderConfiguration,
+	) -> Vec<&'a Command> {
+		let folder_commands = folder_config.commands.iter().flatten();
+		let overridden = |command: &Command| {
+			folder_commands.clone().any(|folder_command| {
+				let mut folder_inputs = folder_command.input.iter();
+				folder_inputs.any(|a| command.input.iter().any(|b| same_input(a, b)))
+			})
+		};
+		let global_commands = self.commands.iter().flatten().filter(|c| !overridden(c));
+		folder_commands.clone().chain(global_commands).collect()
+	}
+
 	pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Configuration, String> {
 		let file_path = file_path.as_ref();
 		let cfg_str = fs::read_to_string(file_path)
 			.map_err(|_| format!("Could not read config from {:?}", file_path))?;
-		let result = toml::from_str(cfg_str.as_ref()).map_err(|e| format!("{}", e))?;
+		let mut result: Configuration =
+			toml::from_str(cfg_str.as_ref()).map_err(|e| format!("{}", e))?;
 		//println!("Read config from file:\n{:#?}", result);
+		result.file_path = file_path.to_owned();
 		Ok(result)
 	}
+
+	/// Binds the action to the keys, or removes its binding so that its default keys are used
+	/// if `keys` is `None`. The binding is written into the config file too.
+	pub fn save_binding(
+		&mut self,
+		action_name: &str,
+		keys: Option<Vec<String>>,
+	) -> Result<(), String> {
+		let bindings = self.bindings.get_or_insert_with(Default::default);
+		match &keys {
+			Some(keys) => bindings.insert(action_name.to_owned(), keys.clone()),
+			None => bindings.remove(action_name),
+		};
+		let keys = keys.map(|keys| keys.iter().collect::<toml_edit::Array>().into());
+		save_options(&self.file_path, vec![("bindings", action_name, keys)])
+	}
+
+	/// Reads the config file again, keeping the command line options. The config stays as it
+	/// was if the file can't be read.
+	pub fn reload<P: AsRef<Path>>(&mut self, file_path: P) -> Result<(), String> {
+		let mut config = Configuration::load(file_path)?;
+		config.overrides = self.overrides.clone();
+		*self = config;
+		Ok(())
+	}
+}
+
+/// Sets options in the config file, or removes the ones that are `None`. Everything else in the
+/// file is kept as it was, including the comments. The file is created if it doesn't exist.
+pub fn save_options(
+	file_path: &Path,
+	options: Vec<(&str, &str, Option<toml_edit::Value>)>,
+) -> Result<(), String> {
+	let mut config = match fs::read_to_string(file_path) {
+		Ok(text) => text.parse::<toml_edit::DocumentMut>().map_err(|e| e.to_string())?,
+		Err(_) => toml_edit::DocumentMut::new(),
+	};
+	for (section, key, value) in options {
+		let section = config.entry(section).or_insert_with(toml_edit::table);
+		let section = match section.as_table_like_mut() {
+			Some(section) => section,
+			None => continue,
+		};
+		match (section.get_mut(key).and_then(|item| item.as_value_mut()), value) {
+			// Keeps the comment after the value
+			(Some(old_value), Some(mut value)) => {
+				*value.decor_mut() = old_value.decor().clone();
+				*old_value = value;
+			}
+			(None, Some(value)) => {
+				section.insert(key, toml_edit::Item::Value(value));
+			}
+			(_, None) => {
+				section.remove(key);
+			}
+		}
+	}
+	if let Some(folder) = file_path.parent() {
+		fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+	}
+	fs::write(file_path, config.to_string())
+		.map_err(|_| format!("Could not write to config file {:?}", file_path))
 }
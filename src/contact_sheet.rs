@@ -0,0 +1,121 @@
+//! The `--contact-sheet` mode, which saves the images of a folder in a grid into a single image
+//! without opening a window.
+//!
+//! The images are decoded the same way as when they are viewed, so every supported format can
+//! be on the sheet. Only the first frame of the animations and videos is shown. The name of each
+//! file is written under its image.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gelatin::image::{imageops, Rgba, RgbaImage};
+
+use crate::cmd_line::ContactSheetArgs;
+use crate::configuration::Configuration;
+use crate::export::{self, DEFAULT_JPEG_QUALITY};
+use crate::image_cache::image_loader::{self, Orientation};
+use crate::text_rendering::{render_text, TextStyle};
+
+/// The images are scaled to fit into a square of this size, in pixels
+const CELL_SIZE: u32 = 256;
+/// The space between the cells and around the edges of the sheet
+const GAP: u32 = 12;
+/// The space under each image that its name is written into
+const LABEL_HEIGHT: u32 = 40;
+/// Longer names are shortened, because they wouldn't fit under the image anyway
+const MAX_LABEL_CHARS: usize = 32;
+const BACKGROUND: [u8; 4] = [32, 32, 32, 255];
+
+/// Returns the exit code of the process
+pub fn run(args: &ContactSheetArgs, config: &Configuration) -> i32 {
+	let extensions = config.navigation.as_ref().and_then(|s| s.extra_extensions.as_ref());
+	if let Some(extensions) = extensions {
+		image_loader::set_extra_extensions(extensions);
+	}
+	let paths = match image_paths(&args.folder) {
+		Ok(paths) => paths,
+		Err(e) => {
+			eprintln!("Could not read the folder {:?}: {}", args.folder, e);
+			return 1;
+		}
+	};
+	let mut cells = Vec::with_capacity(paths.len());
+	for path in paths.iter() {
+		match export::decode(path, Orientation::Deg0) {
+			Ok(image) => cells.push((path, thumbnail(&image))),
+			Err(e) => eprintln!("Skipping {:?}, because it could not be decoded: {}", path, e),
+		}
+	}
+	if cells.is_empty() {
+		eprintln!("There are no images in {:?}", args.folder);
+		return 1;
+	}
+	let columns = args.columns.max(1).min(cells.len() as u32);
+	let rows = (cells.len() as u32).div_ceil(columns);
+	let width = GAP + columns * (CELL_SIZE + GAP);
+	let height = GAP + rows * (CELL_SIZE + LABEL_HEIGHT + GAP);
+	let mut sheet = RgbaImage::from_pixel(width, height, Rgba(BACKGROUND));
+	for (index, (path, image)) in cells.iter().enumerate() {
+		let column = index as u32 % columns;
+		let row = index as u32 / columns;
+		let x = GAP + column * (CELL_SIZE + GAP);
+		let y = GAP + row * (CELL_SIZE + LABEL_HEIGHT + GAP);
+		// Centered in the cell
+		let image_x = x + (CELL_SIZE - image.width()) / 2;
+		let image_y = y + (CELL_SIZE - image.height()) / 2;
+		imageops::overlay(&mut sheet, image, image_x as i64, image_y as i64);
+		if let Some(label) = label(path) {
+			let label = imageops::crop_imm(&label, 0, 0, CELL_SIZE, LABEL_HEIGHT).to_image();
+			let label_x = x + (CELL_SIZE - label.width()) / 2;
+			imageops::overlay(&mut sheet, &label, label_x as i64, (y + CELL_SIZE) as i64);
+		}
+	}
+	if let Err(e) = export::save(&sheet, &args.output, DEFAULT_JPEG_QUALITY) {
+		eprintln!("Could not save the contact sheet to {:?}: {}", args.output, e);
+		return 1;
+	}
+	println!("Saved {} images into {:?}", cells.len(), args.output);
+	0
+}
+
+/// The supported files of the folder, ordered by name like the viewer orders them by default
+fn image_paths(folder: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut paths = Vec::new();
+	for entry in fs::read_dir(folder)? {
+		let path = entry?.path();
+		if path.is_file() && image_loader::is_file_supported(&path) {
+			paths.push(path);
+		}
+	}
+	paths.sort_by(|a, b| {
+		lexical_sort::natural_lexical_cmp(
+			&a.file_name().unwrap_or_default().to_string_lossy(),
+			&b.file_name().unwrap_or_default().to_string_lossy(),
+		)
+	});
+	Ok(paths)
+}
+
+/// Scales the image to fit into a cell. Smaller images are kept at their size.
+fn thumbnail(image: &RgbaImage) -> RgbaImage {
+	let (w, h) = image.dimensions();
+	if w <= CELL_SIZE && h <= CELL_SIZE {
+		return image.clone();
+	}
+	let scale = CELL_SIZE as f32 / w.max(h) as f32;
+	let new_w = ((w as f32 * scale).round() as u32).clamp(1, CELL_SIZE);
+	let new_h = ((h as f32 * scale).round() as u32).clamp(1, CELL_SIZE);
+	imageops::thumbnail(image, new_w, new_h)
+}
+
+fn label(path: &Path) -> Option<RgbaImage> {
+	let name = path.file_name()?.to_string_lossy();
+	let name = if name.chars().count() > MAX_LABEL_CHARS {
+		let start: String = name.chars().take(MAX_LABEL_CHARS - 1).collect();
+		format!("{}…", start)
+	} else {
+		name.into_owned()
+	};
+	let style = TextStyle { font_size: 6.0, background: BACKGROUND, ..TextStyle::default() };
+	Some(render_text(&name, &style)?.image)
+}
@@ -0,0 +1,264 @@
+//! Moves files to the trash, remembering the last one so that its deletion can be undone.
+//!
+//! Trashing can take a long time on network drives, so it runs on a background thread. The
+//! requests are carried out one after the other, and their results are collected with `poll`.
+//!
+//! On macOS the trash can't be listed, so the file is trashed through `NSFileManager`, which
+//! tells where the file ended up in the trash. It's moved back from there when restoring.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+struct DeletedFile {
+	original_path: PathBuf,
+	/// `None` if the system didn't tell
+	#[cfg(target_os = "macos")]
+	trashed_path: Option<PathBuf>,
+}
+
+enum Request {
+	Delete(PathBuf),
+	Undo,
+}
+
+/// What the background thread is asked to do
+enum Operation {
+	Delete(PathBuf),
+	Undo(DeletedFile),
+}
+
+enum OperationResult {
+	Deleted { path: PathBuf, result: Result<DeletedFile, String> },
+	Restored { deleted: DeletedFile, result: Result<(), String> },
+}
+
+/// What the background thread is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+	Deleting,
+	Restoring,
+}
+
+/// The outcome of a request, returned by `poll`
+pub enum Completion {
+	Deleted(PathBuf),
+	DeleteFailed(PathBuf, String),
+	Restored(PathBuf),
+	RestoreFailed(String),
+}
+
+pub struct Deletions {
+	last: Option<DeletedFile>,
+	queue: VecDeque<Request>,
+	in_progress: Option<Activity>,
+	/// The file that the background thread is moving to the trash
+	deleting: Option<PathBuf>,
+	/// `None` only while dropping, which makes the background thread exit
+	operations: Option<Sender<Operation>>,
+	results: Receiver<OperationResult>,
+	worker: Option<JoinHandle<()>>,
+	/// Not yet returned by `poll`
+	completions: Vec<Completion>,
+}
+
+impl Deletions {
+	pub fn new() -> Self {
+		let (operations, operation_rx) = channel();
+		let (result_tx, results) = channel();
+		let worker = thread::spawn(move || {
+			for operation in operation_rx {
+				if result_tx.send(run_operation(operation)).is_err() {
+					return;
+				}
+			}
+		});
+		Deletions {
+			last: None,
+			queue: VecDeque::new(),
+			in_progress: None,
+			deleting: None,
+			operations: Some(operations),
+			results,
+			worker: Some(worker),
+			completions: Vec::new(),
+		}
+	}
+
+	/// Does nothing if the file is already being deleted, because the file stays shown until
+	/// then and the delete key may be pressed again
+	pub fn delete(&mut self, path: &Path) {
+		let queued =
+			|request: &Request| matches!(request, Request::Delete(queued) if queued == path);
+		if self.deleting.as_deref() == Some(path) || self.queue.iter().any(queued) {
+			return;
+		}
+		self.queue.push_back(Request::Delete(path.to_owned()));
+		self.dispatch();
+	}
+
+	/// Restores the last deleted file once the earlier requests are done
+	pub fn undo(&mut self) {
+		self.queue.push_back(Request::Undo);
+		self.dispatch();
+	}
+
+	/// Returns `None` when no file is being deleted or restored
+	pub fn activity(&self) -> Option<Activity> {
+		self.in_progress
+	}
+
+	/// Returns the outcome of the requests that finished since the last call
+	pub fn poll(&mut self) -> Vec<Completion> {
+		if let Ok(result) = self.results.try_recv() {
+			self.in_progress = None;
+			let completion = self.complete(result);
+			self.completions.push(completion);
+		}
+		self.dispatch();
+		std::mem::take(&mut self.completions)
+	}
+
+	fn complete(&mut self, result: OperationResult) -> Completion {
+		self.deleting = None;
+		match result {
+			OperationResult::Deleted { path, result: Ok(deleted) } => {
+				self.last = Some(deleted);
+				Completion::Deleted(path)
+			}
+			OperationResult::Deleted { path, result: Err(e) } => Completion::DeleteFailed(path, e),
+			OperationResult::Restored { deleted, result: Ok(()) } => {
+				Completion::Restored(deleted.original_path)
+			}
+			OperationResult::Restored { deleted, result: Err(e) } => {
+				// It can be tried again
+				self.last = Some(deleted);
+				Completion::RestoreFailed(e)
+			}
+		}
+	}
+
+	/// Gives the next request to the background thread if it's free
+	fn dispatch(&mut self) {
+		let operations = match &self.operations {
+			Some(operations) => operations,
+			None => return,
+		};
+		while self.in_progress.is_none() {
+			let (operation, activity) = match self.queue.pop_front() {
+				Some(Request::Delete(path)) => {
+					self.deleting = Some(path.clone());
+					(Operation::Delete(path), Activity::Deleting)
+				}
+				Some(Request::Undo) => match self.last.take() {
+					Some(deleted) => (Operation::Undo(deleted), Activity::Restoring),
+					None => {
+						let completion = Completion::RestoreFailed("Nothing to restore".into());
+						self.completions.push(completion);
+						continue;
+					}
+				},
+				None => break,
+			};
+			if operations.send(operation).is_err() {
+				break;
+			}
+			self.in_progress = Some(activity);
+		}
+	}
+}
+
+impl Default for Deletions {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Drop for Deletions {
+	fn drop(&mut self) {
+		// The background thread exits once it's done with the operation it was given
+		self.operations = None;
+		if let Some(worker) = self.worker.take() {
+			let _ = worker.join();
+		}
+		while let Ok(result) = self.results.try_recv() {
+			self.complete(result);
+		}
+		// Finish the remaining requests on this thread, because they were confirmed already
+		while let Some(request) = self.queue.pop_front() {
+			let operation = match request {
+				Request::Delete(path) => Operation::Delete(path),
+				Request::Undo => match self.last.take() {
+					Some(deleted) => Operation::Undo(deleted),
+					None => continue,
+				},
+			};
+			let result = run_operation(operation);
+			self.complete(result);
+		}
+	}
+}
+
+fn run_operation(operation: Operation) -> OperationResult {
+	match operation {
+		Operation::Delete(path) => {
+			let result = move_to_trash(&path);
+			OperationResult::Deleted { path, result }
+		}
+		Operation::Undo(deleted) => {
+			let result = if deleted.original_path.exists() {
+				Err(format!("{:?} already exists", deleted.original_path))
+			} else {
+				restore(&deleted)
+			};
+			OperationResult::Restored { deleted, result }
+		}
+	}
+}
+
+#[cfg(not(target_os = "macos"))]
+fn move_to_trash(path: &Path) -> Result<DeletedFile, String> {
+	trash::delete(path).map_err(|e| format!("{:?}", e))?;
+	Ok(DeletedFile { original_path: path.to_owned() })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn restore(deleted: &DeletedFile) -> Result<(), String> {
+	use trash::os_limited;
+
+	let items = os_limited::list().map_err(|e| format!("{:?}", e))?;
+	let item = items
+		.into_iter()
+		.filter(|item| item.original_path() == deleted.original_path)
+		.max_by_key(|item| item.time_deleted);
+	match item {
+		Some(item) => os_limited::restore_all([item]).map_err(|e| format!("{:?}", e)),
+		None => Err(format!("{:?} is not in the trash anymore", deleted.original_path)),
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn move_to_trash(path: &Path) -> Result<DeletedFile, String> {
+	use objc2_foundation::{NSFileManager, NSURL};
+
+	let url = NSURL::from_file_path(path).ok_or_else(|| format!("{:?} is not a file", path))?;
+	let mut trashed_url = None;
+	NSFileManager::defaultManager()
+		.trashItemAtURL_resultingItemURL_error(&url, Some(&mut trashed_url))
+		.map_err(|e| e.to_string())?;
+	let trashed_path = trashed_url.and_then(|url| url.to_file_path());
+	Ok(DeletedFile { original_path: path.to_owned(), trashed_path })
+}
+
+#[cfg(target_os = "macos")]
+fn restore(deleted: &DeletedFile) -> Result<(), String> {
+	use objc2_foundation::{NSFileManager, NSURL};
+
+	let not_found = || format!("{:?} was not found in the trash", deleted.original_path);
+	let trashed_path = deleted.trashed_path.as_ref().ok_or_else(not_found)?;
+	let from = NSURL::from_file_path(trashed_path).ok_or_else(not_found)?;
+	let to = NSURL::from_file_path(&deleted.original_path)
+		.ok_or_else(|| format!("{:?} is not a file", deleted.original_path))?;
+	NSFileManager::defaultManager().moveItemAtURL_toURL_error(&from, &to).map_err(|e| e.to_string())
+}
@@ -0,0 +1,27 @@
+//! Native file dialogs. These are only available with the `dialogs` feature.
+
+use std::path::{Path, PathBuf};
+
+use crate::image_cache::image_loader::supported_extensions;
+
+/// Shows a dialog for choosing an image. Returns `None` if the dialog was cancelled.
+pub fn pick_image(start_dir: Option<&Path>) -> Option<PathBuf> {
+	let dialog = rfd::FileDialog::new()
+		.set_title("Open image")
+		.add_filter("Images", &supported_extensions())
+		.add_filter("All files", &["*"]);
+	with_start_dir(dialog, start_dir).pick_file()
+}
+
+/// Shows a dialog for choosing a folder. Returns `None` if the dialog was cancelled.
+pub fn pick_folder(start_dir: Option<&Path>) -> Option<PathBuf> {
+	let dialog = rfd::FileDialog::new().set_title("Open folder");
+	with_start_dir(dialog, start_dir).pick_folder()
+}
+
+fn with_start_dir(dialog: rfd::FileDialog, start_dir: Option<&Path>) -> rfd::FileDialog {
+	match start_dir {
+		Some(dir) => dialog.set_directory(dir),
+		None => dialog,
+	}
+}
@@ -0,0 +1,289 @@
+//! Finds the ICC profile of the monitor that the window is on, so that the images can be
+//! converted to the color space of that monitor.
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gelatin::winit::{monitor::MonitorHandle, window::Window};
+
+use crate::image_cache::color_management;
+
+/// How often the result of a query is looked for while it's running
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long the first query is waited for, before the first image is loaded without the profile
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Finds out the color profile of a monitor. Returns `None` if the monitor doesn't have a color
+/// profile or if it could not be queried.
+type ProfileQuery = Box<dyn FnOnce() -> Option<Vec<u8>> + Send>;
+
+/// The profile is asked from colord first, which knows the profiles of the outputs on both X11
+/// and Wayland. Its devices are matched to the monitor by the name of the connector, like
+/// "DP-1".
+///
+/// Other X11 color managers publish the profile of each monitor in the `_ICC_PROFILE` property
+/// of the root window, `_ICC_PROFILE_1` for the second monitor, etc. That is only looked at if
+/// the window is on X11, because Wayland has no such property.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn profile_query(window: &Window, monitor: &MonitorHandle) -> ProfileQuery {
+	use gelatin::winit::raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
+
+	let is_x11 = matches!(
+		window.display_handle().map(|handle| handle.as_raw()),
+		Ok(RawDisplayHandle::Xlib(_)) | Ok(RawDisplayHandle::Xcb(_))
+	);
+	let index = window.available_monitors().position(|m| m == *monitor);
+	let name = monitor.name();
+	Box::new(move || {
+		let profile = name.and_then(|name| colord::display_profile(&name));
+		match index {
+			Some(index) if is_x11 => profile.or_else(|| root_window_profile(index)),
+			_ => profile,
+		}
+	})
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn root_window_profile(index: usize) -> Option<Vec<u8>> {
+	use x11rb::{
+		connection::Connection,
+		protocol::xproto::{AtomEnum, ConnectionExt},
+	};
+
+	let (connection, screen) = x11rb::connect(None).ok()?;
+	let root = connection.setup().roots.get(screen)?.root;
+	let name =
+		if index == 0 { "_ICC_PROFILE".to_owned() } else { format!("_ICC_PROFILE_{}", index) };
+	// Only looks up the atom, it doesn't exist if no profile was ever published
+	let atom = connection.intern_atom(true, name.as_bytes()).ok()?.reply().ok()?.atom;
+	if atom == x11rb::NONE {
+		return None;
+	}
+	let property = connection
+		.get_property(false, root, atom, AtomEnum::ANY, 0, u32::MAX)
+		.ok()?
+		.reply()
+		.ok()?;
+	Some(property.value).filter(|profile| !profile.is_empty())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod colord {
+	use std::{collections::HashMap, convert::TryFrom};
+
+	use zbus::{
+		blocking::{proxy::Builder, Connection, Proxy},
+		proxy::CacheProperties,
+		zvariant::OwnedObjectPath,
+	};
+
+	const SERVICE: &str = "org.freedesktop.ColorManager";
+	const DEVICE_INTERFACE: &str = "org.freedesktop.ColorManager.Device";
+	const PROFILE_INTERFACE: &str = "org.freedesktop.ColorManager.Profile";
+	/// The metadata of a display device that holds the name of its connector
+	const OUTPUT_NAME_KEY: &str = "XRANDR_name";
+
+	/// Returns the default profile of the display connected to `output_name`. Returns `None` if
+	/// colord isn't running, if it doesn't know the display or if the display has no profile.
+	pub fn display_profile(output_name: &str) -> Option<Vec<u8>> {
+		let connection = Connection::system().ok()?;
+		let manager_path = OwnedObjectPath::try_from("/org/freedesktop/ColorManager").ok()?;
+		let manager = proxy(&connection, manager_path, SERVICE)?;
+		let devices: Vec<OwnedObjectPath> = manager.call("GetDevicesByKind", &("display",)).ok()?;
+		for device in devices {
+			let device = proxy(&connection, device, DEVICE_INTERFACE)?;
+			let metadata: HashMap<String, String> = device.get_property("Metadata").ok()?;
+			if metadata.get(OUTPUT_NAME_KEY).map(String::as_str) != Some(output_name) {
+				continue;
+			}
+			// The first profile is the default one
+			let profiles: Vec<OwnedObjectPath> = device.get_property("Profiles").ok()?;
+			let profile = proxy(&connection, profiles.into_iter().next()?, PROFILE_INTERFACE)?;
+			let file_name: String = profile.get_property("Filename").ok()?;
+			return std::fs::read(file_name).ok();
+		}
+		None
+	}
+
+	fn proxy<'a>(
+		connection: &Connection,
+		path: OwnedObjectPath,
+		interface: &'static str,
+	) -> Option<Proxy<'a>> {
+		Builder::new(connection)
+			.destination(SERVICE)
+			.ok()?
+			.path(path)
+			.ok()?
+			.interface(interface)
+			.ok()?
+			.cache_properties(CacheProperties::No)
+			.build()
+			.ok()
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn profile_query(_window: &Window, monitor: &MonitorHandle) -> ProfileQuery {
+	use gelatin::winit::platform::macos::MonitorHandleExtMacOS;
+
+	let display = monitor.native_id();
+	Box::new(move || display_profile(display))
+}
+
+#[cfg(target_os = "macos")]
+fn display_profile(display: u32) -> Option<Vec<u8>> {
+	use std::ffi::c_void;
+
+	#[link(name = "CoreGraphics", kind = "framework")]
+	extern "C" {
+		fn CGDisplayCopyColorSpace(display: u32) -> *const c_void;
+		fn CGColorSpaceCopyICCData(space: *const c_void) -> *const c_void;
+	}
+	#[link(name = "CoreFoundation", kind = "framework")]
+	extern "C" {
+		fn CFDataGetLength(data: *const c_void) -> isize;
+		fn CFDataGetBytePtr(data: *const c_void) -> *const u8;
+		fn CFRelease(object: *const c_void);
+	}
+
+	unsafe {
+		let color_space = CGDisplayCopyColorSpace(display);
+		if color_space.is_null() {
+			return None;
+		}
+		let data = CGColorSpaceCopyICCData(color_space);
+		CFRelease(color_space);
+		if data.is_null() {
+			return None;
+		}
+		let len = CFDataGetLength(data) as usize;
+		let profile = std::slice::from_raw_parts(CFDataGetBytePtr(data), len).to_vec();
+		CFRelease(data);
+		Some(profile)
+	}
+}
+
+#[cfg(windows)]
+fn profile_query(_window: &Window, monitor: &MonitorHandle) -> ProfileQuery {
+	use gelatin::winit::platform::windows::MonitorHandleExtWindows;
+
+	let device_name = monitor.native_id();
+	Box::new(move || display_profile(&device_name))
+}
+
+#[cfg(windows)]
+fn display_profile(device_name: &str) -> Option<Vec<u8>> {
+	use windows_sys::Win32::{
+		Graphics::Gdi::{CreateDCW, DeleteDC},
+		UI::ColorSystem::GetICMProfileW,
+	};
+
+	let device_name: Vec<u16> = device_name.encode_utf16().chain(Some(0)).collect();
+	let mut path = [0u16; 260];
+	let mut path_len = path.len() as u32;
+	let found = unsafe {
+		let dc = CreateDCW(
+			device_name.as_ptr(),
+			device_name.as_ptr(),
+			std::ptr::null(),
+			std::ptr::null(),
+		);
+		if dc == 0 {
+			return None;
+		}
+		let found = GetICMProfileW(dc, &mut path_len, path.as_mut_ptr());
+		DeleteDC(dc);
+		found != 0
+	};
+	if !found {
+		return None;
+	}
+	let path_end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+	let path = String::from_utf16_lossy(&path[..path_end]);
+	std::fs::read(path).ok()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn profile_query(_window: &Window, _monitor: &MonitorHandle) -> ProfileQuery {
+	Box::new(|| None)
+}
+
+/// Keeps track of which monitor the window is on, and updates the display profile used by the
+/// color management when the window is moved to a different monitor.
+///
+/// Finding out the profile may have to wait for colord to start, so it's done on a thread of its
+/// own and `receive_profile` only picks up the result.
+#[derive(Default)]
+pub struct DisplayProfileTracker {
+	monitor: Option<MonitorHandle>,
+	/// The result of the query for the current monitor while it's running
+	query: Option<Receiver<Option<Vec<u8>>>>,
+}
+
+impl DisplayProfileTracker {
+	/// Waits for the profile of the monitor that the window is on, so that it's already used when
+	/// the first image is loaded. If that takes too long, the profile is received like after
+	/// moving the window.
+	pub fn new(window: &Window) -> Self {
+		let mut tracker = DisplayProfileTracker::default();
+		tracker.update(window);
+		let result = match &tracker.query {
+			Some(query) => query.recv_timeout(STARTUP_TIMEOUT),
+			None => return tracker,
+		};
+		match result {
+			Ok(profile) => {
+				tracker.query = None;
+				color_management::set_display_profile(profile);
+			}
+			Err(RecvTimeoutError::Timeout) => (),
+			Err(RecvTimeoutError::Disconnected) => tracker.query = None,
+		}
+		tracker
+	}
+
+	/// Starts finding out the profile of the monitor if the window is on a different monitor
+	/// than before. The query of the previous monitor is abandoned.
+	pub fn update(&mut self, window: &Window) {
+		let monitor = window.current_monitor();
+		if monitor == self.monitor {
+			return;
+		}
+		let query: ProfileQuery = match &monitor {
+			Some(monitor) => profile_query(window, monitor),
+			None => Box::new(|| None),
+		};
+		let (sender, receiver) = channel();
+		thread::spawn(move || {
+			let _ = sender.send(query());
+		});
+		self.monitor = monitor;
+		self.query = Some(receiver);
+	}
+
+	/// Returns `true` if the profile of the monitor was found out and it's different from the
+	/// display profile used so far, meaning that the images have to be loaded again.
+	pub fn receive_profile(&mut self) -> bool {
+		let result = match &self.query {
+			Some(query) => query.try_recv(),
+			None => return false,
+		};
+		match result {
+			Ok(profile) => {
+				self.query = None;
+				color_management::set_display_profile(profile)
+			}
+			Err(TryRecvError::Empty) => false,
+			Err(TryRecvError::Disconnected) => {
+				self.query = None;
+				false
+			}
+		}
+	}
+
+	pub fn next_check_time(&self) -> Option<Instant> {
+		self.query.as_ref().map(|_| Instant::now() + POLL_INTERVAL)
+	}
+}
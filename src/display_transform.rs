@@ -0,0 +1,166 @@
+//! The transform between the colors of the image and the values that are sent to the display.
+//! This covers the common case of reviewing renders and footage through the view LUT of a
+//! project, without a full color management system.
+//!
+//! 3D LUTs are read from `.cube` files in the format that Resolve and most other tools write.
+//! The LUT gets the sRGB encoded colors of the image, and its output is sent to the display as
+//! it is.
+
+use std::fs;
+use std::path::Path;
+
+use gelatin::glium::texture::{
+	ClientFormat, MipmapsOption, RawImage3d, Texture3d, UncompressedFloatFormat,
+};
+
+use crate::configuration::DisplayTransform;
+
+/// LUTs with more entries per axis than this are rejected
+const MAX_LUT_SIZE: usize = 256;
+
+struct CubeLut {
+	/// The number of entries along each axis
+	size: usize,
+	domain_min: [f32; 3],
+	domain_max: [f32; 3],
+	/// RGB triplets where red changes the fastest and blue the slowest
+	data: Vec<f32>,
+}
+
+impl CubeLut {
+	/// Maps every color to itself. Used when no LUT is configured, so that the shader always
+	/// has a LUT texture to sample.
+	fn identity() -> Self {
+		let mut data = Vec::with_capacity(8 * 3);
+		for b in 0..2 {
+			for g in 0..2 {
+				for r in 0..2 {
+					data.extend_from_slice(&[r as f32, g as f32, b as f32]);
+				}
+			}
+		}
+		CubeLut { size: 2, domain_min: [0.0; 3], domain_max: [1.0; 3], data }
+	}
+
+	fn load(path: &Path) -> Result<Self, String> {
+		let text =
+			fs::read_to_string(path).map_err(|e| format!("Could not read {:?}: {}", path, e))?;
+		Self::parse(&text).map_err(|e| format!("Could not parse {:?}: {}", path, e))
+	}
+
+	fn parse(text: &str) -> Result<Self, String> {
+		let mut size = None;
+		let mut domain_min = [0.0; 3];
+		let mut domain_max = [1.0; 3];
+		let mut data = Vec::new();
+		for (line_index, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let line_error = |message: &str| format!("{} on line {}", message, line_index + 1);
+			let mut words = line.split_whitespace();
+			let keyword = words.next().unwrap_or_default();
+			match keyword {
+				"TITLE" => (),
+				"LUT_1D_SIZE" => return Err("1D LUTs are not supported".into()),
+				"LUT_3D_SIZE" => {
+					let value = words.next().and_then(|word| word.parse::<usize>().ok());
+					match value {
+						Some(value) if (2..=MAX_LUT_SIZE).contains(&value) => size = Some(value),
+						_ => return Err(line_error("Invalid LUT size")),
+					}
+				}
+				"DOMAIN_MIN" => {
+					domain_min =
+						parse_triplet(words).ok_or_else(|| line_error("Invalid domain"))?;
+				}
+				"DOMAIN_MAX" => {
+					domain_max =
+						parse_triplet(words).ok_or_else(|| line_error("Invalid domain"))?;
+				}
+				_ => {
+					let entry = parse_triplet(line.split_whitespace());
+					data.extend_from_slice(&entry.ok_or_else(|| line_error("Invalid entry"))?);
+				}
+			}
+		}
+		let size = size.ok_or("The LUT_3D_SIZE is missing")?;
+		let entry_count = size * size * size;
+		if data.len() != entry_count * 3 {
+			return Err(format!("Expected {} entries, found {}", entry_count, data.len() / 3));
+		}
+		if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+			return Err("The domain of the LUT is empty".into());
+		}
+		Ok(CubeLut { size, domain_min, domain_max, data })
+	}
+
+	pub fn upload(&self, display: &gelatin::Display) -> Result<DisplayLut, String> {
+		let size = self.size as u32;
+		let raw_image = RawImage3d {
+			data: self.data.as_slice().into(),
+			width: size,
+			height: size,
+			depth: size,
+			format: ClientFormat::F32F32F32,
+		};
+		let format = UncompressedFloatFormat::F16F16F16;
+		let texture = Texture3d::with_format(display, raw_image, format, MipmapsOption::NoMipmap)
+			.map_err(|e| format!("Could not create the LUT texture: {}", e))?;
+		Ok(DisplayLut {
+			texture,
+			size: self.size as f32,
+			domain_min: self.domain_min,
+			domain_max: self.domain_max,
+		})
+	}
+}
+
+/// A LUT that was uploaded for the fragment shaders
+pub struct DisplayLut {
+	pub texture: Texture3d,
+	pub size: f32,
+	pub domain_min: [f32; 3],
+	pub domain_max: [f32; 3],
+}
+
+/// Loads the LUT if the transform needs one. Falls back to sRGB if the LUT can't be loaded.
+pub fn load(
+	display: &gelatin::Display,
+	transform: DisplayTransform,
+	lut_path: Option<&Path>,
+) -> (DisplayTransform, DisplayLut) {
+	let identity = || CubeLut::identity().upload(display).unwrap();
+	if transform != DisplayTransform::Lut {
+		return (transform, identity());
+	}
+	let lut = match lut_path {
+		Some(lut_path) => CubeLut::load(lut_path).and_then(|lut| lut.upload(display)),
+		None => Err("Set `display_lut` in the [image] section of the config to use a LUT".into()),
+	};
+	match lut {
+		Ok(lut) => (transform, lut),
+		Err(e) => {
+			eprintln!("{}", e);
+			(DisplayTransform::Srgb, identity())
+		}
+	}
+}
+
+fn parse_triplet<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+	let mut triplet = [0.0; 3];
+	for value in triplet.iter_mut() {
+		*value = words.next()?.parse().ok()?;
+	}
+	Some(triplet)
+}
+
+/// The value of the `display_transform` uniform of the fragment shaders
+pub fn shader_mode(transform: DisplayTransform) -> i32 {
+	match transform {
+		DisplayTransform::Srgb => 0,
+		DisplayTransform::Rec709 => 1,
+		DisplayTransform::Lut => 2,
+	}
+}
@@ -0,0 +1,134 @@
+//! Saves the shown image, or the part of it that is visible in the window, into a new file.
+//!
+//! The new file is named by a pattern from the config, in which `${folder}`, `${name}` and
+//! `${ext}` are replaced by the folder, the name without the extension, and the extension of
+//! the shown image. Relative paths are relative to the folder of the shown image. The format
+//! is chosen by the extension of the new file.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use gelatin::image::{self, codecs::jpeg::JpegEncoder, RgbaImage};
+
+use crate::clipboard_handler::apply_orientation;
+use crate::file_operations::unique_path;
+use crate::image_cache::image_loader::{complex_load_image, LoadResult, Orientation};
+use crate::parallel_action::ParallelAction;
+
+pub const DEFAULT_IMAGE_PATTERN: &str = "${name}-export.png";
+pub const DEFAULT_VIEW_PATTERN: &str = "${name}-view.png";
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+pub enum ExportSource {
+	/// The file is decoded again. The image is saved with `view_orientation` applied on top of
+	/// the orientation stored in the file.
+	File { path: PathBuf, view_orientation: Orientation },
+	/// A capture of the rendered view
+	Capture(RgbaImage),
+}
+
+pub struct ExportRequest {
+	pub source: ExportSource,
+	pub target: PathBuf,
+	pub jpeg_quality: u8,
+}
+
+/// Encodes the exported images on a background thread, one at a time
+pub struct Exporter {
+	worker: ParallelAction<ExportRequest, Result<PathBuf, String>>,
+	busy: bool,
+}
+
+impl Exporter {
+	pub fn new() -> Self {
+		Exporter { worker: ParallelAction::new(run_export), busy: false }
+	}
+
+	/// Returns false if the previous export hasn't finished yet
+	pub fn export(&mut self, request: ExportRequest) -> bool {
+		if self.busy {
+			return false;
+		}
+		self.worker.give_input(request);
+		self.busy = true;
+		true
+	}
+
+	pub fn is_busy(&self) -> bool {
+		self.busy
+	}
+
+	/// Returns the path of the new file, or the error, once the export finished
+	pub fn poll(&mut self) -> Option<Result<PathBuf, String>> {
+		let result = self.worker.try_get_output()?;
+		self.busy = false;
+		Some(result)
+	}
+}
+
+impl Default for Exporter {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Returns the path that the export of the image is saved to. A number is added to the name if
+/// the path is already taken.
+pub fn target_path(pattern: &str, image: &Path) -> PathBuf {
+	let folder = match image.parent() {
+		Some(folder) if !folder.as_os_str().is_empty() => folder,
+		_ => Path::new("."),
+	};
+	let name = image.file_stem().unwrap_or_default().to_string_lossy();
+	let ext = image.extension().unwrap_or_default().to_string_lossy();
+	let path = pattern
+		.replace("${folder}", &folder.to_string_lossy())
+		.replace("${name}", &name)
+		.replace("${ext}", &ext);
+	let path = folder.join(path);
+	match (path.parent(), path.file_name()) {
+		(Some(parent), Some(file_name)) => unique_path(parent, Path::new(file_name)),
+		_ => path,
+	}
+}
+
+fn run_export(request: ExportRequest) -> Result<PathBuf, String> {
+	let image = match request.source {
+		ExportSource::File { path, view_orientation } => decode(&path, view_orientation)?,
+		ExportSource::Capture(image) => image,
+	};
+	save(&image, &request.target, request.jpeg_quality)?;
+	Ok(request.target)
+}
+
+pub fn decode(path: &Path, view_orientation: Orientation) -> Result<RgbaImage, String> {
+	let mut decoded = None;
+	let result = complex_load_image(path, false, 0, None, |frame| {
+		if let LoadResult::Frame { image, orientation, .. } = frame {
+			decoded = Some(apply_orientation(image, orientation.then(view_orientation)));
+		}
+		Ok(())
+	});
+	if let Err(e) = result {
+		return Err(e.to_string());
+	}
+	decoded.ok_or_else(|| format!("{:?} has no image to export", path))
+}
+
+/// The format is chosen by the extension of `path`
+pub fn save(image: &RgbaImage, path: &Path, jpeg_quality: u8) -> Result<(), String> {
+	if let Some(folder) = path.parent() {
+		fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+	}
+	let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+	if extension == "jpg" || extension == "jpeg" {
+		// JPEG has no alpha channel
+		let image = image::DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+		let file = File::create(path).map_err(|e| e.to_string())?;
+		let encoder = JpegEncoder::new_with_quality(BufWriter::new(file), jpeg_quality.min(100));
+		image.write_with_encoder(encoder).map_err(|e| e.to_string())
+	} else {
+		image.save(path).map_err(|e| e.to_string())
+	}
+}
@@ -0,0 +1,271 @@
+//! The `--info` mode, which prints what Emulsion knows about an image file and exits. It's meant
+//! for scripts and for bug reports, so it can print JSON as well.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+use gelatin::image::{self, ImageDecoder, ImageFormat};
+use serde::{Serialize, Serializer};
+
+use crate::export;
+use crate::image_cache::image_loader::{
+	self, detect_format, detect_orientation, ImgFormat, Orientation,
+};
+
+#[derive(Serialize)]
+struct FileInfo {
+	format: String,
+	width: u32,
+	height: u32,
+	/// `None` if it can't be known without playing the file, like for videos
+	#[serde(rename = "frames")]
+	frame_count: Option<usize>,
+	/// `None` if the file has no Exif data
+	#[serde(serialize_with = "serialize_orientation")]
+	orientation: Option<Orientation>,
+	color_profile: Option<ColorProfile>,
+}
+
+#[derive(Serialize)]
+struct ColorProfile {
+	/// `None` if the profile has no readable description
+	description: Option<String>,
+	/// Like "RGB" or "GRAY"
+	color_space: String,
+	size: usize,
+}
+
+/// Returns the exit code of the process
+pub fn run(path: &Path, json: bool) -> i32 {
+	match read_info(path) {
+		Ok(info) if json => {
+			let info = JsonInfo { path: path.to_string_lossy(), info: &info };
+			match serde_json::to_string(&info) {
+				Ok(json) => {
+					println!("{}", json);
+					0
+				}
+				Err(e) => {
+					eprintln!("Could not print the info of {:?}: {}", path, e);
+					1
+				}
+			}
+		}
+		Ok(info) => {
+			print!("{}", to_text(path, &info));
+			0
+		}
+		Err(e) => {
+			eprintln!("Could not read {:?}: {}", path, e);
+			1
+		}
+	}
+}
+
+fn read_info(path: &Path) -> image_loader::Result<FileInfo> {
+	let format = detect_format(path)?;
+	let orientation = detect_orientation(path).ok();
+	let info = match format {
+		ImgFormat::Image(image_format) => {
+			let mut reader = image::io::Reader::new(BufReader::new(fs::File::open(path)?));
+			reader.set_format(image_format);
+			let mut decoder = reader.into_decoder()?;
+			let (width, height) = decoder.dimensions();
+			let icc_profile = decoder.icc_profile().ok().flatten();
+			FileInfo {
+				format: format!("{:?}", image_format).to_uppercase(),
+				width,
+				height,
+				frame_count: Some(frame_count(path, image_format)?),
+				orientation,
+				color_profile: icc_profile.map(|profile| read_color_profile(&profile)),
+			}
+		}
+		_ => {
+			// The dimensions of the other formats are only known after decoding them
+			let image = export::decode(path, Orientation::Deg0)
+				.map_err(image_loader::ImageLoaderError::unsupported)?;
+			#[cfg(feature = "video")]
+			let frame_count = if let ImgFormat::Video = format { None } else { Some(1) };
+			#[cfg(not(feature = "video"))]
+			let frame_count = Some(1);
+			FileInfo {
+				format: format_name(&format).to_owned(),
+				width: image.width(),
+				height: image.height(),
+				frame_count,
+				orientation,
+				color_profile: None,
+			}
+		}
+	};
+	Ok(info)
+}
+
+/// Counts the frames from their headers, without decoding them
+fn frame_count(path: &Path, image_format: ImageFormat) -> image_loader::Result<usize> {
+	let reader = BufReader::new(fs::File::open(path)?);
+	match image_format {
+		ImageFormat::Gif => {
+			let mut options = gif::DecodeOptions::new();
+			options.skip_frame_decoding(true);
+			let mut decoder = options.read_info(reader).map_err(gif_error)?;
+			let mut count = 0;
+			// Only copies the compressed data of the frames
+			while decoder.read_next_frame().map_err(gif_error)?.is_some() {
+				count += 1;
+			}
+			Ok(count)
+		}
+		ImageFormat::Png => {
+			let decoder = png::Decoder::new(reader)
+				.read_info()
+				.map_err(|e| image_loader::ImageLoaderError::decoder("PNG", e))?;
+			// The animation control chunk comes before the image data, so this only reads the
+			// header chunks
+			let animation = decoder.info().animation_control();
+			Ok(animation.map_or(1, |animation| animation.num_frames as usize))
+		}
+		_ => Ok(1),
+	}
+}
+
+fn gif_error(error: gif::DecodingError) -> image_loader::ImageLoaderError {
+	image_loader::ImageLoaderError::decoder("GIF", error)
+}
+
+fn format_name(format: &ImgFormat) -> &'static str {
+	match format {
+		ImgFormat::Image(_) => "image",
+		ImgFormat::Svg => "SVG",
+		#[cfg(feature = "jxl")]
+		ImgFormat::Jxl => "JPEG XL",
+		#[cfg(feature = "raw")]
+		ImgFormat::Raw => "camera RAW",
+		#[cfg(feature = "textures")]
+		ImgFormat::Texture => "texture",
+		#[cfg(feature = "layered")]
+		ImgFormat::Layered => "layered image",
+		#[cfg(feature = "video")]
+		ImgFormat::Video => "video",
+	}
+}
+
+/// The meaning of the Exif orientation values, as described by the Exif standard
+fn orientation_description(orientation: Orientation) -> &'static str {
+	match orientation.to_exif() {
+		1 => "normal",
+		2 => "mirrored horizontally",
+		3 => "rotated 180°",
+		4 => "mirrored vertically",
+		5 => "mirrored horizontally and rotated 270° clockwise",
+		6 => "rotated 90° clockwise",
+		7 => "mirrored horizontally and rotated 90° clockwise",
+		_ => "rotated 270° clockwise",
+	}
+}
+
+/// Reads the header and the description tag of an ICC profile
+fn read_color_profile(profile: &[u8]) -> ColorProfile {
+	let color_space = profile.get(16..20).map(String::from_utf8_lossy).unwrap_or_default();
+	ColorProfile {
+		description: icc_description(profile),
+		color_space: color_space.trim().to_owned(),
+		size: profile.len(),
+	}
+}
+
+fn icc_description(profile: &[u8]) -> Option<String> {
+	let read_u32 = |offset: usize| -> Option<usize> {
+		let bytes = profile.get(offset..offset + 4)?;
+		Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+	};
+	// The tag table follows the 128 byte header, each entry is the signature, the offset and
+	// the size of the tag
+	let tag_count = read_u32(128)?;
+	let entry = (0..tag_count)
+		.map(|index| 132 + index * 12)
+		.take_while(|&entry| entry + 12 <= profile.len())
+		.find(|&entry| profile.get(entry..entry + 4) == Some(&b"desc"[..]))?;
+	let offset = read_u32(entry + 4)?;
+	let tag = profile.get(offset..offset + read_u32(entry + 8)?)?;
+	let text = match tag.get(0..4)? {
+		// ICC version 2 stores ASCII text after its length
+		b"desc" => {
+			let length = read_u32(offset + 8)?;
+			String::from_utf8_lossy(tag.get(12..12 + length)?).into_owned()
+		}
+		// Version 4 stores UTF-16 text for several languages, the first one is used
+		b"mluc" => {
+			let length = read_u32(offset + 20)?;
+			let text_offset = read_u32(offset + 24)?;
+			let bytes = tag.get(text_offset..text_offset + length)?;
+			let units: Vec<u16> =
+				bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+			String::from_utf16_lossy(&units)
+		}
+		_ => return None,
+	};
+	let text = text.trim_end_matches('\0').trim();
+	if text.is_empty() {
+		None
+	} else {
+		Some(text.to_owned())
+	}
+}
+
+fn to_text(path: &Path, info: &FileInfo) -> String {
+	let mut text = String::new();
+	let _ = writeln!(text, "File: {}", path.display());
+	let _ = writeln!(text, "Format: {}", info.format);
+	let _ = writeln!(text, "Dimensions: {}x{}", info.width, info.height);
+	if let Some(frame_count) = info.frame_count {
+		let _ = writeln!(text, "Frames: {}", frame_count);
+	}
+	match info.orientation {
+		Some(orientation) => {
+			let description = orientation_description(orientation);
+			let _ = writeln!(text, "Orientation: {} ({})", orientation.to_exif(), description);
+		}
+		None => text.push_str("Orientation: no Exif data\n"),
+	}
+	match &info.color_profile {
+		Some(profile) => {
+			let description = profile.description.as_deref().unwrap_or("no description");
+			let _ = writeln!(
+				text,
+				"Color profile: {} ({}, {} bytes)",
+				description, profile.color_space, profile.size
+			);
+		}
+		None => text.push_str("Color profile: none\n"),
+	}
+	text
+}
+
+/// The JSON output also includes the path, before the other fields
+#[derive(Serialize)]
+struct JsonInfo<'a> {
+	path: Cow<'a, str>,
+	#[serde(flatten)]
+	info: &'a FileInfo,
+}
+
+fn serialize_orientation<S: Serializer>(
+	orientation: &Option<Orientation>,
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	#[derive(Serialize)]
+	struct ExifOrientation {
+		exif: u16,
+		description: &'static str,
+	}
+	let orientation = orientation.map(|orientation| ExifOrientation {
+		exif: orientation.to_exif(),
+		description: orientation_description(orientation),
+	});
+	orientation.serialize(serializer)
+}
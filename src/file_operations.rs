@@ -0,0 +1,69 @@
+//! Moves and copies images into other folders, for sorting them with a single key.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Moves the file into the folder and returns its new path. The folder is created if it
+/// doesn't exist, and the file is renamed if the folder already has a file with its name.
+pub fn move_to_folder(path: &Path, folder: &Path) -> io::Result<PathBuf> {
+	let target = prepare_target(path, folder)?;
+	if fs::rename(path, &target).is_err() {
+		// Renaming doesn't work across file systems
+		fs::copy(path, &target)?;
+		if let Err(e) = fs::remove_file(path) {
+			let _ = fs::remove_file(&target);
+			return Err(e);
+		}
+	}
+	Ok(target)
+}
+
+/// Copies the file into the folder and returns the path of the copy. The folder is created if
+/// it doesn't exist, and the copy is renamed if the folder already has a file with its name.
+pub fn copy_to_folder(path: &Path, folder: &Path) -> io::Result<PathBuf> {
+	let target = prepare_target(path, folder)?;
+	fs::copy(path, &target)?;
+	Ok(target)
+}
+
+fn prepare_target(path: &Path, folder: &Path) -> io::Result<PathBuf> {
+	let file_name = match path.file_name() {
+		Some(file_name) => file_name,
+		None => {
+			let message = format!("{:?} is not a file", path);
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+		}
+	};
+	let parent = path.parent().and_then(|parent| parent.canonicalize().ok());
+	if parent.is_some() && parent == folder.canonicalize().ok() {
+		let message = format!("{:?} is already in {:?}", path, folder);
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+	}
+	fs::create_dir_all(folder)?;
+	Ok(unique_path(folder, Path::new(file_name)))
+}
+
+/// Returns a path in the folder with the file name that isn't taken yet. The name gets a
+/// number like "photo (2).jpg" if the file name itself is taken.
+pub fn unique_path(folder: &Path, file_name: &Path) -> PathBuf {
+	let path = folder.join(file_name);
+	if !path.exists() {
+		return path;
+	}
+	let stem = file_name.file_stem().unwrap_or_default();
+	for number in 2.. {
+		let mut name = OsString::from(stem);
+		name.push(format!(" ({})", number));
+		if let Some(extension) = file_name.extension() {
+			name.push(".");
+			name.push(extension);
+		}
+		let path = folder.join(name);
+		if !path.exists() {
+			return path;
+		}
+	}
+	unreachable!()
+}
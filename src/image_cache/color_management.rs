@@ -0,0 +1,85 @@
+//! Converts images with an embedded ICC profile to sRGB, which is the color space that the
+//! textures are displayed in. Without this, wide-gamut photos (e.g. Display P3 or Adobe RGB)
+//! look washed out.
+//!
+//! If the monitor that the window is on has a color profile, the textures are further converted
+//! from sRGB to the color space of the monitor.
+
+use std::sync::{Arc, Mutex};
+
+use gelatin::image::RgbaImage;
+use lazy_static::lazy_static;
+
+use super::parallel_decode;
+
+lazy_static! {
+	/// The ICC profile of the monitor that the window is on. `None` means that the monitor is
+	/// treated as an sRGB display.
+	static ref DISPLAY_PROFILE: Mutex<Option<Arc<Vec<u8>>>> = Mutex::new(None);
+}
+
+/// Converts the pixels of `image` from the color space described by `icc_profile` to sRGB.
+/// If the profile cannot be parsed, the image is left unchanged.
+pub fn convert_to_srgb(icc_profile: &[u8], image: &mut RgbaImage) {
+	let input = match qcms::Profile::new_from_slice(icc_profile, false) {
+		Some(profile) => profile,
+		None => {
+			log::warn!("Could not parse the embedded ICC profile, ignoring it");
+			return;
+		}
+	};
+	transform(&input, qcms::Profile::new_sRGB(), image);
+}
+
+/// Sets the ICC profile of the monitor that the window is on. Returns `true` if this is
+/// different from the previous profile, meaning that the images have to be converted again.
+pub fn set_display_profile(icc_profile: Option<Vec<u8>>) -> bool {
+	let icc_profile = icc_profile.filter(|icc_profile| {
+		let valid = qcms::Profile::new_from_slice(icc_profile, false).is_some();
+		if !valid {
+			log::warn!("Could not parse the ICC profile of the display, ignoring it");
+		}
+		valid
+	});
+	let mut display_profile = DISPLAY_PROFILE.lock().unwrap();
+	let changed = display_profile.as_ref().map(|p| p.as_slice()) != icc_profile.as_deref();
+	*display_profile = icc_profile.map(Arc::new);
+	changed
+}
+
+/// Converts the pixels of `image` from sRGB to the color space of the display. The image is
+/// left unchanged if the display doesn't have a color profile.
+pub fn convert_to_display(image: &mut RgbaImage) {
+	let display_profile = match DISPLAY_PROFILE.lock().unwrap().clone() {
+		Some(display_profile) => display_profile,
+		None => return,
+	};
+	if let Some(output) = qcms::Profile::new_from_slice(&display_profile, false) {
+		transform(&qcms::Profile::new_sRGB(), output, image);
+	}
+}
+
+/// Converts the pixels of `image` from the color space of the display back to sRGB, which gives
+/// the colors that the image had before `convert_to_display`.
+pub fn convert_from_display(image: &mut RgbaImage) {
+	let display_profile = match DISPLAY_PROFILE.lock().unwrap().clone() {
+		Some(display_profile) => display_profile,
+		None => return,
+	};
+	if let Some(input) = qcms::Profile::new_from_slice(&display_profile, false) {
+		transform(&input, qcms::Profile::new_sRGB(), image);
+	}
+}
+
+fn transform(input: &qcms::Profile, mut output: Box<qcms::Profile>, image: &mut RgbaImage) {
+	output.precache_output_transform();
+	let transform =
+		qcms::Transform::new(input, &output, qcms::DataType::RGBA8, qcms::Intent::Perceptual);
+	match transform {
+		Some(transform) => {
+			let row_len = image.width() as usize * 4;
+			parallel_decode::for_each_strip(image, row_len, |_, strip| transform.apply(strip));
+		}
+		None => log::warn!("Could not create a color transform from the ICC profile"),
+	}
+}
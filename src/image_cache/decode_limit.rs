@@ -0,0 +1,84 @@
+//! Protects against running out of memory when opening extremely large images (or
+//! decompression bombs) by refusing to decode images above a configurable pixel count,
+//! unless the user agreed to open a downscaled version instead.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+pub const DEFAULT_MAX_MEGAPIXELS: u32 = 200;
+/// Images that have to be scaled down are decoded with at most this many times the pixels of the
+/// decode limit before they are scaled down
+pub const MAX_DOWNSCALE_DECODE_FACTOR: u64 = 4;
+
+struct DecodeLimitState {
+	/// `None` means that there's no limit
+	max_pixels: Option<u64>,
+	/// Set when the user chose to always downscale oversized images during this session
+	always_downscale: bool,
+	/// Images for which the user allowed opening a downscaled version
+	downscale_allowed: HashSet<PathBuf>,
+	/// Images that were not decoded because they are too large, with their dimensions
+	oversized: HashMap<PathBuf, (u32, u32)>,
+}
+
+lazy_static! {
+	static ref STATE: Mutex<DecodeLimitState> = Mutex::new(DecodeLimitState {
+		max_pixels: Some(DEFAULT_MAX_MEGAPIXELS as u64 * 1_000_000),
+		always_downscale: false,
+		downscale_allowed: HashSet::new(),
+		oversized: HashMap::new(),
+	});
+}
+
+pub enum DecodeDecision {
+	Full,
+	/// The image has to be scaled down so that it has at most this many pixels
+	Downscale {
+		max_pixels: u64,
+	},
+	Refuse,
+}
+
+/// A value of zero disables the limit
+pub fn set_max_megapixels(megapixels: u32) {
+	let max_pixels = if megapixels > 0 { Some(megapixels as u64 * 1_000_000) } else { None };
+	STATE.lock().unwrap().max_pixels = max_pixels;
+}
+
+pub fn max_megapixels() -> Option<u32> {
+	STATE.lock().unwrap().max_pixels.map(|p| (p / 1_000_000) as u32)
+}
+
+pub fn set_always_downscale() {
+	STATE.lock().unwrap().always_downscale = true;
+}
+
+pub fn allow_downscale(path: &Path) {
+	STATE.lock().unwrap().downscale_allowed.insert(path.to_owned());
+}
+
+/// Returns the dimensions of the image if it was refused because of its size.
+pub fn oversized_dimensions(path: &Path) -> Option<(u32, u32)> {
+	STATE.lock().unwrap().oversized.get(path).copied()
+}
+
+pub fn decide(path: &Path, width: u32, height: u32) -> DecodeDecision {
+	let mut state = STATE.lock().unwrap();
+	let max_pixels = match state.max_pixels {
+		Some(max_pixels) => max_pixels,
+		None => return DecodeDecision::Full,
+	};
+	if width as u64 * height as u64 <= max_pixels {
+		return DecodeDecision::Full;
+	}
+	if state.always_downscale || state.downscale_allowed.contains(path) {
+		state.oversized.remove(path);
+		DecodeDecision::Downscale { max_pixels }
+	} else {
+		state.oversized.insert(path.to_owned(), (width, height));
+		DecodeDecision::Refuse
+	}
+}
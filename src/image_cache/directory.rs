@@ -1,38 +1,41 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
-use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
+use gelatin::winit::event_loop::EventLoopProxy;
 use log::debug;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 
+use super::file_filter::FileFilter;
 use super::image_loader::is_file_supported;
+use super::sidecar::MetadataFilter;
+use crate::configuration::SortOrder;
 use crate::parallel_action::ParallelAction;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
+	#[error("The directory is still being filtered for images")]
 	WaitingOnFolderFilter,
+	/// The folder was deleted or unmounted
+	#[error("The folder {0:?} is no longer available")]
+	FolderUnavailable(PathBuf),
+	#[error("The file {0:?} doesn't match the filter")]
+	FilteredOut(PathBuf),
+	#[error("IO error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Other error: {0}")]
 	Other(String),
 }
-impl fmt::Display for Error {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
-			Error::WaitingOnFolderFilter => {
-				f.write_str("The directory is still being filtered for images")
-			}
-			Error::Other(s) => f.write_fmt(format_args!("Other error: {}", s)),
-		}
-	}
-}
-impl std::error::Error for Error {}
-impl From<std::io::Error> for Error {
-	fn from(e: std::io::Error) -> Self {
-		Error::Other(format!("{}", e))
-	}
-}
 impl From<Error> for std::io::Error {
 	fn from(value: Error) -> Self {
-		use std::io::ErrorKind;
-		std::io::Error::new(ErrorKind::Other, format!("directory::Error: {}", value))
+		std::io::Error::other(format!("directory::Error: {}", value))
 	}
 }
 
@@ -92,6 +95,29 @@ pub struct Directory {
 
 	//filter_state: Arc<Mutex<FilterState>>,
 	filter_action: ParallelAction<Vec<DirItem>, Vec<usize>>,
+
+	sort_order: SortOrder,
+	/// Decides the random order, so that it doesn't change when the folder is collected again
+	random_seed: u64,
+	/// The files that don't match are left out of `files`
+	file_filter: FileFilter,
+	/// The files whose rating or tags don't match are left out of `files`
+	metadata_filter: MetadataFilter,
+
+	/// Created when the first folder is opened. `None` if the watcher couldn't be created, in
+	/// which case the folder is only refreshed when the window is focused.
+	watcher: Option<RecommendedWatcher>,
+	watched_path: Option<PathBuf>,
+	/// The watcher thread wakes up the event loop through this when `changed` is set
+	proxy: EventLoopProxy<()>,
+	/// Set by the watcher thread when a file in the watched folder is created, deleted,
+	/// renamed, or modified
+	changed: Arc<AtomicBool>,
+	/// The files that were renamed by other programs, noticed by the watcher thread. The old
+	/// path comes first.
+	renamed: Arc<Mutex<Vec<(PathBuf, PathBuf)>>>,
+	/// Incremented whenever the files are collected
+	generation: u64,
 }
 
 fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
@@ -105,7 +131,9 @@ fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
 }
 
 impl Directory {
-	pub fn new() -> Self {
+	/// An event is sent through `proxy` when the contents of the watched folder change, after
+	/// which `take_changed` returns true
+	pub fn new(proxy: EventLoopProxy<()>) -> Self {
 		Directory {
 			path: PathBuf::new(),
 			files: Vec::new(),
@@ -115,12 +143,23 @@ impl Directory {
 			curr_image_idx: 0,
 			current_req_id: 0,
 			filter_action: ParallelAction::new(get_action()),
+			sort_order: SortOrder::default(),
+			random_seed: rand::random(),
+			file_filter: FileFilter::default(),
+			metadata_filter: MetadataFilter::default(),
+			watcher: None,
+			watched_path: None,
+			proxy,
+			changed: Arc::new(AtomicBool::new(false)),
+			renamed: Arc::new(Mutex::new(Vec::new())),
+			generation: 0,
 		}
 	}
 
 	pub fn change_directory(&mut self, path: &Path) -> Result<()> {
 		if self.path != path {
 			path.clone_into(&mut self.path);
+			self.watch_directory();
 			self.collect_directory()
 		} else {
 			Ok(())
@@ -140,6 +179,10 @@ impl Directory {
 			}
 		}
 
+		let file_path = path.join(filename);
+		if !self.file_filter.matches(filename) || !self.metadata_filter.matches(&file_path) {
+			return Err(Error::FilteredOut(file_path));
+		}
 		Err(Error::Other(format!("Could not find file {:?} in directory {:?}", filename, path)))
 	}
 
@@ -169,6 +212,19 @@ impl Directory {
 		Err(Error::Other("Could not find image index".to_string()))
 	}
 
+	/// Returns true if there's no image after the current one, so `jump_to_next` would continue
+	/// with the first image
+	pub fn is_at_last_image(&self) -> bool {
+		let mut after = self.files.iter().skip(self.curr_file_idx + 1);
+		!after.any(|file| is_file_supported(&file.path))
+	}
+
+	/// Returns true if there's no image before the current one
+	pub fn is_at_first_image(&self) -> bool {
+		let mut before = self.files.iter().take(self.curr_file_idx);
+		!before.any(|file| is_file_supported(&file.path))
+	}
+
 	pub fn jump_to_prev(&mut self) {
 		let skip = (self.files.len() - 1) - self.curr_file_idx;
 		let iter =
@@ -219,7 +275,74 @@ impl Directory {
 		}
 	}
 
+	/// Collects the folder again in the new order. The current image stays the same.
+	pub fn set_sort_order(&mut self, sort_order: SortOrder) -> Result<()> {
+		if self.sort_order == sort_order {
+			return Ok(());
+		}
+		self.sort_order = sort_order;
+		if self.path.as_os_str().is_empty() {
+			// No folder was opened yet
+			return Ok(());
+		}
+		self.update_directory()
+	}
+
+	/// Collects the folder again with only the files that match the filter. The current image
+	/// stays the same if it matches.
+	pub fn set_file_filter(&mut self, file_filter: FileFilter) -> Result<()> {
+		if self.file_filter == file_filter {
+			return Ok(());
+		}
+		self.file_filter = file_filter;
+		if self.path.as_os_str().is_empty() {
+			return Ok(());
+		}
+		self.update_directory()
+	}
+
+	/// Collects the folder again with only the images whose rating and tags match the filter.
+	/// The current image stays the same if it matches.
+	pub fn set_metadata_filter(&mut self, metadata_filter: MetadataFilter) -> Result<()> {
+		if self.metadata_filter == metadata_filter {
+			return Ok(());
+		}
+		self.metadata_filter = metadata_filter;
+		if self.path.as_os_str().is_empty() {
+			return Ok(());
+		}
+		self.update_directory()
+	}
+
+	/// Renames a file of the folder on the disk. The file keeps its request id, so that it's not
+	/// loaded again, and it stays the current file if it was. Returns the new path.
+	pub fn rename_file(&mut self, path: &Path, new_name: &OsStr) -> Result<PathBuf> {
+		if Path::new(new_name).file_name() != Some(new_name) {
+			return Err(Error::Other(format!("{:?} is not a valid file name", new_name)));
+		}
+		let new_path = path.with_file_name(new_name);
+		if new_path.exists() {
+			return Err(Error::Other(format!("{:?} already exists", new_path)));
+		}
+		fs::rename(path, &new_path)?;
+		for item in self.files.iter_mut().filter(|item| item.path == path) {
+			item.path = new_path.clone();
+		}
+		Ok(new_path)
+	}
+
+	/// Returns true if the contents of the folder changed since the last call
+	pub fn take_changed(&self) -> bool {
+		self.changed.swap(false, Ordering::SeqCst)
+	}
+
+	/// Changes whenever the files of the folder are collected
+	pub fn generation(&self) -> u64 {
+		self.generation
+	}
+
 	pub fn update_directory(&mut self) -> Result<()> {
+		self.follow_renames();
 		let curr_filename = self.curr_filename();
 		let curr_filename = curr_filename.as_deref();
 		let curr_index = self.curr_file_idx;
@@ -250,6 +373,16 @@ impl Directory {
 				return Ok(());
 			}
 		}
+		// The last files were removed, so the nearest image is before them
+		for (index, desc) in self.files.iter().enumerate().take(curr_index).rev() {
+			if is_file_supported(&desc.path) {
+				debug!("Previous supported file found. Index {:?}, name {:?}.", index, desc.path);
+				self.curr_file_idx = index;
+				self.set_image_index_from_file_index();
+				self.check_filter_ready();
+				return Ok(());
+			}
+		}
 		if self.files.len() <= self.curr_file_idx && !self.files.is_empty() {
 			self.curr_file_idx = 0;
 		}
@@ -257,14 +390,62 @@ impl Directory {
 		Ok(())
 	}
 
+	/// Gives the files that other programs renamed within the folder their new names, so that
+	/// they keep their request ids and the current file stays current. The files that were
+	/// moved out of the folder are dropped when the folder is collected.
+	fn follow_renames(&mut self) {
+		let renamed = mem::take(&mut *self.renamed.lock().unwrap());
+		for (from, to) in renamed {
+			let new_name = match to.file_name() {
+				Some(new_name) if from.parent() == to.parent() => new_name,
+				_ => continue,
+			};
+			let renamed_items =
+				self.files.iter_mut().filter(|item| item.path.file_name() == from.file_name());
+			for item in renamed_items {
+				debug!("Following the renamed file {:?} to {:?}", item.path, new_name);
+				item.path.set_file_name(new_name);
+			}
+		}
+	}
+
 	pub fn collect_directory(&mut self) -> Result<()> {
-		let mut dir_files: Vec<_> = fs::read_dir(&self.path)?
+		let entries = match fs::read_dir(&self.path) {
+			Ok(entries) => entries,
+			Err(e) if self.path.is_dir() => return Err(e.into()),
+			Err(_) => {
+				// Forget the files, so that nothing tries to load them anymore
+				self.filter_action.give_input(Vec::new());
+				self.img_i_to_file_i.clear();
+				self.file_i_to_img_i.clear();
+				self.files.clear();
+				self.curr_file_idx = 0;
+				return Err(Error::FolderUnavailable(self.path.clone()));
+			}
+		};
+		// The files that were already in the folder keep their request ids, so that their textures
+		// are not thrown away
+		let prev_req_ids: HashMap<_, _> =
+			self.files.iter().map(|item| (item.path.clone(), item.request_id)).collect();
+		let mut dir_files: Vec<_> = entries
 			.filter_map(|x| match x {
 				Ok(entry) => match entry.file_type() {
 					Ok(file_type) => {
-						if file_type.is_file() || file_type.is_symlink() {
-							self.current_req_id += 1;
-							Some(DirItem { path: entry.path(), request_id: self.current_req_id })
+						let file_name = entry.file_name();
+						let is_file = file_type.is_file() || file_type.is_symlink();
+						if is_file && self.file_filter.matches(&file_name) {
+							let path = entry.path();
+							if !self.metadata_filter.matches(&path) {
+								return None;
+							}
+							let request_id = match prev_req_ids.get(&path) {
+								Some(&request_id) => request_id,
+								None => {
+									self.current_req_id += 1;
+									self.current_req_id
+								}
+							};
+							Some(DirItem { path, request_id })
 						} else {
 							None
 						}
@@ -275,12 +456,7 @@ impl Directory {
 			})
 			.collect();
 
-		dir_files.sort_unstable_by(|a, b| {
-			lexical_sort::natural_lexical_cmp(
-				&a.path.file_name().unwrap().to_string_lossy(),
-				&b.path.file_name().unwrap().to_string_lossy(),
-			)
-		});
+		sort_files(&mut dir_files, self.sort_order, self.random_seed);
 
 		// Set the current file index to the first image
 		for (i, item) in dir_files.iter().enumerate() {
@@ -293,9 +469,32 @@ impl Directory {
 		self.img_i_to_file_i.clear();
 		self.file_i_to_img_i.clear();
 		self.files = dir_files;
+		self.generation += 1;
 		Ok(())
 	}
 
+	/// Watches the current folder for changes instead of the previous one
+	fn watch_directory(&mut self) {
+		if self.watcher.is_none() {
+			let proxy = self.proxy.clone();
+			self.watcher = create_watcher(self.changed.clone(), self.renamed.clone(), proxy);
+		}
+		let watcher = match &mut self.watcher {
+			Some(watcher) => watcher,
+			None => return,
+		};
+		if let Some(watched_path) = self.watched_path.take() {
+			let _ = watcher.unwatch(&watched_path);
+		}
+		match watcher.watch(&self.path, RecursiveMode::NonRecursive) {
+			Ok(()) => self.watched_path = Some(self.path.clone()),
+			Err(e) => debug!("Could not watch the folder {:?} for changes: {}", self.path, e),
+		}
+		// The new folder is collected anyways
+		self.changed.store(false, Ordering::SeqCst);
+		self.renamed.lock().unwrap().clear();
+	}
+
 	fn finished_filtering(&mut self) {
 		self.file_i_to_img_i.clear();
 		self.file_i_to_img_i.reserve(self.files.len());
@@ -311,8 +510,10 @@ impl Directory {
 	}
 
 	fn set_image_index_from_file_index(&mut self) {
-		if let Some(img_idx) = self.file_i_to_img_i.get(self.curr_file_idx) {
-			self.curr_image_idx = img_idx.unwrap() as usize;
+		// The current file is not an image if it was opened explicitly even though its format
+		// isn't recognized. It's still attempted to be loaded based on its content.
+		if let Some(Some(img_idx)) = self.file_i_to_img_i.get(self.curr_file_idx) {
+			self.curr_image_idx = *img_idx as usize;
 		}
 	}
 
@@ -325,3 +526,77 @@ impl Directory {
 		self.filter_action.is_ready()
 	}
 }
+
+fn create_watcher(
+	changed: Arc<AtomicBool>,
+	renamed: Arc<Mutex<Vec<(PathBuf, PathBuf)>>>,
+	proxy: EventLoopProxy<()>,
+) -> Option<RecommendedWatcher> {
+	// Some platforms report the old and the new name of a renamed file in separate events
+	let mut rename_from = None;
+	// Changing several files causes many events, one wake-up is enough until they are handled
+	let set_changed = move || {
+		if !changed.swap(true, Ordering::SeqCst) {
+			let _ = proxy.send_event(());
+		}
+	};
+	let result = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+		let event = match event {
+			Ok(event) if event.kind.is_access() => return,
+			Ok(event) => event,
+			// An error may mean that some changes were missed, so the folder is collected again
+			Err(_) => {
+				set_changed();
+				return;
+			}
+		};
+		if let EventKind::Modify(ModifyKind::Name(mode)) = event.kind {
+			let mut paths = event.paths.into_iter();
+			let rename = match mode {
+				RenameMode::Both => paths.next().zip(paths.next()),
+				RenameMode::From => {
+					rename_from = paths.next();
+					None
+				}
+				RenameMode::To => rename_from.take().zip(paths.next()),
+				_ => None,
+			};
+			if let Some(rename) = rename {
+				renamed.lock().unwrap().push(rename);
+			}
+		}
+		set_changed();
+	});
+	match result {
+		Ok(watcher) => Some(watcher),
+		Err(e) => {
+			debug!("Could not create the folder watcher: {}", e);
+			None
+		}
+	}
+}
+
+fn sort_files(files: &mut [DirItem], sort_order: SortOrder, random_seed: u64) {
+	// The other orders are stable, so the files that compare equal are ordered by name
+	files.sort_unstable_by(|a, b| {
+		lexical_sort::natural_lexical_cmp(
+			&a.path.file_name().unwrap().to_string_lossy(),
+			&b.path.file_name().unwrap().to_string_lossy(),
+		)
+	});
+	match sort_order {
+		SortOrder::Name => (),
+		SortOrder::ModifiedTime => files.sort_by_cached_key(|item| {
+			fs::metadata(&item.path).and_then(|metadata| metadata.modified()).ok()
+		}),
+		SortOrder::FileSize => {
+			files.sort_by_cached_key(|item| fs::metadata(&item.path).map(|m| m.len()).ok())
+		}
+		SortOrder::Random => files.sort_by_cached_key(|item| {
+			let mut hasher = DefaultHasher::new();
+			random_seed.hash(&mut hasher);
+			item.path.file_name().hash(&mut hasher);
+			hasher.finish()
+		}),
+	}
+}
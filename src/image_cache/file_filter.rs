@@ -0,0 +1,113 @@
+use std::ffi::OsStr;
+
+/// Decides which files of a folder are shown, by matching their names against wildcard patterns.
+/// `*` matches any number of characters and `?` matches a single character. Letter case is
+/// ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileFilter {
+	/// Lowercase. Every file is shown if this is empty.
+	patterns: Vec<Vec<char>>,
+}
+
+impl FileFilter {
+	/// Parses patterns separated by semicolons, like `*.png;render_*.jpg`
+	pub fn parse(patterns: &str) -> FileFilter {
+		let patterns = patterns
+			.split(';')
+			.map(|pattern| pattern.trim())
+			.filter(|pattern| !pattern.is_empty())
+			.map(|pattern| pattern.to_lowercase().chars().collect())
+			.collect();
+		FileFilter { patterns }
+	}
+
+	pub fn matches(&self, file_name: &OsStr) -> bool {
+		if self.patterns.is_empty() {
+			return true;
+		}
+		let name: Vec<char> = file_name.to_string_lossy().to_lowercase().chars().collect();
+		self.patterns.iter().any(|pattern| matches_pattern(pattern, &name))
+	}
+}
+
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+	let mut p = 0;
+	let mut n = 0;
+	// The position of the last star in the pattern, and the position in the name that it was
+	// tried to match until
+	let mut star: Option<(usize, usize)> = None;
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+			p += 1;
+			n += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			star = Some((p, n));
+			p += 1;
+		} else if let Some((star_p, star_n)) = star {
+			// Let the star match one more character
+			p = star_p + 1;
+			n = star_n + 1;
+			star = Some((star_p, n));
+		} else {
+			return false;
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn matches(patterns: &str, file_name: &str) -> bool {
+		FileFilter::parse(patterns).matches(OsStr::new(file_name))
+	}
+
+	#[test]
+	fn star_matches_any_number_of_characters() {
+		assert!(matches("*.png", "image.png"));
+		assert!(matches("*.png", ".png"));
+		assert!(matches("render_*.jpg", "render_01.jpg"));
+		assert!(matches("render_*.jpg", "render_.jpg"));
+		assert!(matches("a*b*c", "aXbYbZc"));
+		assert!(!matches("*.png", "image.jpg"));
+		assert!(!matches("*.png", "image.png.jpg"));
+	}
+
+	#[test]
+	fn question_mark_matches_one_character() {
+		assert!(matches("img?.png", "img1.png"));
+		assert!(!matches("img?.png", "img.png"));
+		assert!(!matches("img?.png", "img12.png"));
+	}
+
+	#[test]
+	fn trailing_star() {
+		assert!(matches("render_*", "render_"));
+		assert!(matches("render_*", "render_01.jpg"));
+		assert!(matches("render_**", "render_01.jpg"));
+		assert!(!matches("render_*", "render"));
+	}
+
+	#[test]
+	fn empty_patterns_match_everything() {
+		assert!(matches("", "image.png"));
+		assert!(matches(" ; ;", "image.png"));
+		assert!(!matches_pattern(&[], &['a']));
+		assert!(matches_pattern(&[], &[]));
+	}
+
+	#[test]
+	fn letter_case_is_ignored() {
+		assert!(matches("*.PNG", "image.png"));
+		assert!(matches("*.png", "IMAGE.PNG"));
+		assert!(matches("Ä*", "äpfel.jpg"));
+	}
+
+	#[test]
+	fn any_of_the_patterns_may_match() {
+		assert!(matches("*.png;render_*.jpg", "render_01.jpg"));
+		assert!(matches("*.png; render_*.jpg", "image.png"));
+		assert!(!matches("*.png;render_*.jpg", "image.jpg"));
+	}
+}
@@ -0,0 +1,133 @@
+//! OpenEXR and Radiance HDR images store colors beyond the range of the display. They are
+//! uploaded into floating point textures without clamping, and tone mapped in the fragment
+//! shader with an exposure that the user can adjust.
+//!
+//! The colors are linear, so they aren't converted to the color profile of the display.
+//!
+//! When the display has at least 10 bits per channel, 16-bit PNG and TIFF images take the same
+//! path without tone mapping, so that their subtle gradients don't band.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gelatin::{
+	glium::texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat},
+	image::{self, io::Limits, DynamicImage, ImageDecoder, ImageFormat, Rgba32FImage},
+};
+
+use super::decode_limit::{self, DecodeDecision};
+use super::image_loader::{downscaled_size, ImageLoaderError, Result};
+use super::TextureResult;
+
+static DEEP_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// A floating point texture of a high dynamic range or a deep color image
+pub struct FloatTexture {
+	pub tex: Texture2d,
+	/// Deep color images are shown without tone mapping
+	pub tone_map: bool,
+}
+
+pub fn is_hdr_format(format: ImageFormat) -> bool {
+	matches!(format, ImageFormat::OpenExr | ImageFormat::Hdr)
+}
+
+/// Set if the window got a surface with at least 10 bits per channel
+pub fn set_deep_color(deep_color: bool) {
+	DEEP_COLOR.store(deep_color, Ordering::Relaxed);
+}
+
+/// Decodes the image into linear RGBA floats. The image is downscaled if it's larger than the
+/// decode limit or than `max_texture_size`, because it's uploaded into a single texture.
+pub fn load(path: &Path, format: ImageFormat, max_texture_size: u32) -> Result<Rgba32FImage> {
+	let reader = || -> Result<_> {
+		let mut reader = image::io::Reader::new(BufReader::new(fs::File::open(path)?));
+		reader.set_format(format);
+		Ok(reader)
+	};
+	let (w, h) = reader()?.into_dimensions()?;
+	let mut reader = reader()?;
+	let (mut new_w, mut new_h) = match decode_limit::decide(path, w, h) {
+		DecodeDecision::Full => (w, h),
+		DecodeDecision::Refuse => {
+			return Err(ImageLoaderError::TooLarge { width: w, height: h });
+		}
+		DecodeDecision::Downscale { max_pixels } => {
+			// The user agreed to open this image
+			reader.limits(Limits::no_limits());
+			downscaled_size(w, h, max_pixels)
+		}
+	};
+	if new_w.max(new_h) > max_texture_size {
+		let scale = max_texture_size as f64 / new_w.max(new_h) as f64;
+		new_w = ((new_w as f64 * scale) as u32).clamp(1, max_texture_size);
+		new_h = ((new_h as f64 * scale) as u32).clamp(1, max_texture_size);
+	}
+	let image = reader.decode()?;
+	let image = if (new_w, new_h) == (w, h) { image } else { image.thumbnail(new_w, new_h) };
+	Ok(image.into_rgba32f())
+}
+
+/// Decodes an image with 16 bits per channel into linear RGBA floats if the display has deep
+/// color. Returns `None` if the image should be loaded with 8 bits per channel instead, which is
+/// also the case for images that have a color profile or are too large for a single texture.
+pub fn load_deep(
+	path: &Path,
+	format: ImageFormat,
+	max_texture_size: Option<u32>,
+) -> Result<Option<Rgba32FImage>> {
+	let max_texture_size = match max_texture_size {
+		Some(size) if DEEP_COLOR.load(Ordering::Relaxed) => size,
+		_ => return Ok(None),
+	};
+	let mut reader = image::io::Reader::new(BufReader::new(fs::File::open(path)?));
+	reader.set_format(format);
+	let mut decoder = reader.into_decoder()?;
+	let color_type = decoder.color_type();
+	let bytes_per_channel = color_type.bytes_per_pixel() / color_type.channel_count();
+	let (w, h) = decoder.dimensions();
+	if bytes_per_channel < 2 || w.max(h) > max_texture_size {
+		return Ok(None);
+	}
+	if decoder.icc_profile().unwrap_or(None).is_some() {
+		return Ok(None);
+	}
+	if !matches!(decode_limit::decide(path, w, h), DecodeDecision::Full) {
+		return Ok(None);
+	}
+	let mut image = DynamicImage::from_decoder(decoder)?.into_rgba32f();
+	for pixel in image.pixels_mut() {
+		for value in pixel.0[..3].iter_mut() {
+			*value = srgb_to_linear(*value);
+		}
+	}
+	Ok(Some(image))
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Half floats keep enough precision for display while taking half the memory of full floats
+pub fn texture_from_image(
+	display: &gelatin::Display,
+	image: &Rgba32FImage,
+) -> TextureResult<Texture2d> {
+	let (width, height) = image.dimensions();
+	let raw_image = RawImage2d {
+		data: Cow::Borrowed(image.as_raw().as_slice()),
+		format: ClientFormat::F32F32F32F32,
+		width,
+		height,
+	};
+	let format = UncompressedFloatFormat::F16F16F16F16;
+	let mipmaps = MipmapsOption::AutoGeneratedMipmaps;
+	Ok(Texture2d::with_format(display, raw_image, format, mipmaps)?)
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The Exif fields shown in the info panel, along with the label to display them with.
+const EXIF_FIELDS: &[(exif::Tag, &str)] = &[
+	(exif::Tag::Make, "Camera make"),
+	(exif::Tag::Model, "Camera model"),
+	(exif::Tag::LensModel, "Lens"),
+	(exif::Tag::DateTimeOriginal, "Taken"),
+	(exif::Tag::ExposureTime, "Exposure"),
+	(exif::Tag::FNumber, "Aperture"),
+	(exif::Tag::PhotographicSensitivity, "ISO"),
+	(exif::Tag::FocalLength, "Focal length"),
+	(exif::Tag::Flash, "Flash"),
+];
+
+const GPS_FIELDS: &[(exif::Tag, &str)] = &[
+	(exif::Tag::GPSLatitude, "Latitude"),
+	(exif::Tag::GPSLongitude, "Longitude"),
+	(exif::Tag::GPSAltitude, "Altitude"),
+];
+
+/// Information about an image file that is not needed for displaying it but which may be
+/// interesting for the user.
+#[derive(Debug, Clone, Default)]
+pub struct ImageInfo {
+	pub file_size: u64,
+	pub modified: Option<SystemTime>,
+	/// Human readable (label, value) pairs of the camera related Exif fields
+	pub exif_fields: Vec<(&'static str, String)>,
+	/// Human readable (label, value) pairs of the GPS location
+	pub gps_fields: Vec<(&'static str, String)>,
+	/// Set for texture files
+	#[cfg(feature = "textures")]
+	pub texture: Option<super::texture_file::TextureInfo>,
+}
+
+impl ImageInfo {
+	pub fn new(path: &Path, metadata: &fs::Metadata) -> ImageInfo {
+		let mut info = ImageInfo {
+			file_size: metadata.len(),
+			modified: metadata.modified().ok(),
+			..Default::default()
+		};
+		if let Ok(exif) = read_exif(path) {
+			info.exif_fields = collect_fields(&exif, EXIF_FIELDS);
+			info.gps_fields = collect_fields(&exif, GPS_FIELDS);
+		}
+		#[cfg(feature = "textures")]
+		if super::image_loader::file_kind(path) == Some(super::image_loader::FileKind::Texture) {
+			info.texture = super::texture_file::read_info(path);
+		}
+		info
+	}
+}
+
+pub fn read_exif(path: &Path) -> Result<exif::Exif, exif::Error> {
+	let file = fs::File::open(path)?;
+	let mut bufreader = std::io::BufReader::new(&file);
+	exif::Reader::new().read_from_container(&mut bufreader)
+}
+
+fn collect_fields(
+	exif: &exif::Exif,
+	fields: &[(exif::Tag, &'static str)],
+) -> Vec<(&'static str, String)> {
+	fields
+		.iter()
+		.filter_map(|&(tag, label)| {
+			let field = exif.get_field(tag, exif::In::PRIMARY)?;
+			let value = field.display_value().with_unit(exif).to_string();
+			let value = value.trim_matches('"').trim().to_owned();
+			if value.is_empty() {
+				None
+			} else {
+				Some((label, value))
+			}
+		})
+		.collect()
+}
@@ -1,52 +1,110 @@
 use std::borrow::Cow;
 use std::fs;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Cursor, Read};
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
 
+use gelatin::glium::CapabilitiesSource;
 use gelatin::image::{
 	self,
 	codecs::{gif::GifDecoder, png::PngDecoder},
-	AnimationDecoder, ImageFormat,
+	io::Limits,
+	AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat,
 };
+use lazy_static::lazy_static;
 use usvg::fontdb;
 
+use super::color_management;
+use super::decode_limit::{self, DecodeDecision, MAX_DOWNSCALE_DECODE_FACTOR};
+use super::hdr_image;
+use super::image_info::{read_exif, ImageInfo};
+#[cfg(feature = "layered")]
+use super::layered_file;
+use super::parallel_decode;
+use super::placeholder::{self, Placeholder};
+#[cfg(feature = "raw")]
+use super::raw_preview;
+#[cfg(feature = "textures")]
+use super::texture_file;
+use super::thumbnail_cache;
+use super::tiled_image;
+#[cfg(feature = "video")]
+use super::video_file;
+
 #[derive(Debug, thiserror::Error)]
-#[error("error happened in the image_loader: {description}")]
-pub struct ImageLoaderError {
-	pub description: Cow<'static, str>,
-}
-impl From<std::io::Error> for ImageLoaderError {
-	fn from(value: std::io::Error) -> Self {
-		ImageLoaderError { description: format!("IO error: {value}").into() }
-	}
+pub enum ImageLoaderError {
+	#[error("IO error: {0}")]
+	Io(#[from] std::io::Error),
+
+	#[error("image-crate error: {0}")]
+	Image(#[from] image::ImageError),
+
+	#[error("usvg error: {0}")]
+	Svg(#[from] usvg::Error),
+
+	/// An error of a decoder whose error type isn't kept, like the boxed errors of jxl-oxide
+	#[error("{decoder} error: {message}")]
+	Decoder { decoder: &'static str, message: String },
+
+	/// The user didn't allow decoding an image of this size, see `decode_limit::decide`
+	#[error("The image is too large to be decoded ({width}x{height})")]
+	TooLarge { width: u32, height: u32 },
+
+	/// Even the downscaled decoding of the image would need too much memory
+	#[error("The image is too large to be decoded even downscaled ({width}x{height})")]
+	TooLargeToDownscale { width: u32, height: u32 },
+
+	/// The file is damaged or uses a feature that isn't supported
+	#[error("{0}")]
+	Unsupported(Cow<'static, str>),
+
+	/// The request was cancelled, or the image cache doesn't take results anymore
+	#[error("The request was cancelled")]
+	Cancelled,
 }
-impl From<image::ImageError> for ImageLoaderError {
-	fn from(value: image::ImageError) -> Self {
-		ImageLoaderError { description: format!("image-crate error: {value}").into() }
+impl ImageLoaderError {
+	pub fn unsupported(description: impl Into<Cow<'static, str>>) -> Self {
+		ImageLoaderError::Unsupported(description.into())
 	}
-}
-impl From<usvg::Error> for ImageLoaderError {
-	fn from(value: usvg::Error) -> Self {
-		ImageLoaderError { description: format!("usvg error: {value}").into() }
+
+	pub fn decoder(decoder: &'static str, error: impl std::fmt::Display) -> Self {
+		ImageLoaderError::Decoder { decoder, message: error.to_string() }
 	}
 }
 
 pub type Result<T> = std::result::Result<T, ImageLoaderError>;
 
-/// We want to prevent prefetch operations taking place when the target image is not yet loaded.
-/// To implement this we define a variable that is read by the loader threads and
-/// which will only carry out the request if the focused request id matches their request or
-/// if the focused is set to `NON_EXISTENT_REQUEST_ID`
-pub static PRIORITY_REQUEST_ID: AtomicU32 = AtomicU32::new(0); // The first request usually
-pub const NON_EXISTENT_REQUEST_ID: u32 = std::u32::MAX;
+pub const NON_EXISTENT_REQUEST_ID: u32 = u32::MAX;
+
+/// The number of load results that may wait in each result queue. When a queue is full, the
+/// loader threads wait until the image cache takes a result, so that the frames of a large
+/// animation don't pile up in memory when they are decoded faster than they are uploaded.
+const RESULT_QUEUE_CAPACITY: usize = 4;
+
+/// Enough for 32-bit float RGBA, the largest pixels that the image crate decodes
+const MAX_BYTES_PER_PIXEL: u64 = 16;
 
 pub enum ImgFormat {
 	Image(ImageFormat),
 	Svg,
+	#[cfg(feature = "jxl")]
+	Jxl,
+	/// A camera RAW file, of which only the embedded preview is displayed
+	#[cfg(feature = "raw")]
+	Raw,
+	/// A DDS, KTX, or KTX2 texture, of which the selected mip level and layer is displayed
+	#[cfg(feature = "textures")]
+	Texture,
+	/// A PSD or XCF file, of which the flattened layers are displayed
+	#[cfg(feature = "layered")]
+	Layered,
+	/// A video clip, which is played like an animation if it's short
+	#[cfg(feature = "video")]
+	Video,
 }
 
 /// These values define the transformation for a pixel array which is to be displayed.
@@ -55,7 +113,7 @@ pub enum ImgFormat {
 /// image. This is represented by the value `Deg0`. All other cases must be interpreted as relative
 /// to this. The rotation part is counter-clockwise. When there's a flip it's always interpreted as
 /// if it happened after the rotation.
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Orientation {
 	/// Exif 1
 	#[default]
@@ -83,9 +141,121 @@ pub enum Orientation {
 	Deg90,
 }
 
+impl Orientation {
+	/// Maps an Exif orientation value to an `Orientation`.
+	///
+	/// According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
+	pub fn from_exif(value: u16) -> Option<Orientation> {
+		match value {
+			1 => Some(Orientation::Deg0),
+			2 => Some(Orientation::Deg0HorFlip),
+			3 => Some(Orientation::Deg180),
+			4 => Some(Orientation::Deg180HorFlip),
+			5 => Some(Orientation::Deg90VerFlip),
+			6 => Some(Orientation::Deg270),
+			7 => Some(Orientation::Deg270VerFlip),
+			8 => Some(Orientation::Deg90),
+			_ => None,
+		}
+	}
+
+	pub fn to_exif(self) -> u16 {
+		match self {
+			Orientation::Deg0 => 1,
+			Orientation::Deg0HorFlip => 2,
+			Orientation::Deg180 => 3,
+			Orientation::Deg180HorFlip => 4,
+			Orientation::Deg90VerFlip => 5,
+			Orientation::Deg270 => 6,
+			Orientation::Deg270VerFlip => 7,
+			Orientation::Deg90 => 8,
+		}
+	}
+
+	/// Returns true if the width and the height of the image are swapped when displayed
+	/// with this orientation.
+	pub fn swaps_dimensions(self) -> bool {
+		use Orientation::*;
+		matches!(self, Deg90 | Deg90VerFlip | Deg270 | Deg270VerFlip)
+	}
+
+	/// Every orientation can be described as a number of counter-clockwise quarter turns
+	/// followed by an optional horizontal flip. This returns that pair.
+	fn to_turns_and_flip(self) -> (u8, bool) {
+		use Orientation::*;
+		match self {
+			Deg0 => (0, false),
+			Deg90 => (1, false),
+			Deg180 => (2, false),
+			Deg270 => (3, false),
+			Deg0HorFlip => (0, true),
+			Deg90VerFlip => (1, true),
+			Deg180HorFlip => (2, true),
+			Deg270VerFlip => (3, true),
+		}
+	}
+
+	fn from_turns_and_flip(turns: u8, flip: bool) -> Orientation {
+		use Orientation::*;
+		match (turns % 4, flip) {
+			(0, false) => Deg0,
+			(1, false) => Deg90,
+			(2, false) => Deg180,
+			(3, false) => Deg270,
+			(0, true) => Deg0HorFlip,
+			(1, true) => Deg90VerFlip,
+			(2, true) => Deg180HorFlip,
+			(3, true) => Deg270VerFlip,
+			_ => unreachable!(),
+		}
+	}
+
+	/// Returns the orientation that results from first applying `self` and then `other`.
+	pub fn then(self, other: Orientation) -> Orientation {
+		let (turns, flip) = self.to_turns_and_flip();
+		let (other_turns, other_flip) = other.to_turns_and_flip();
+		// Rotating a flipped image counter-clockwise is the same as flipping an image
+		// that was rotated clockwise. So if `self` has a flip, the rotation of `other`
+		// has to be reversed to move it in front of that flip.
+		let other_turns = if flip { 4 - other_turns } else { other_turns };
+		Orientation::from_turns_and_flip(turns + other_turns, flip != other_flip)
+	}
+
+	/// Rotates the image by 90 degrees counter-clockwise
+	pub fn rotated_left(self) -> Orientation {
+		self.then(Orientation::Deg90)
+	}
+
+	/// Rotates the image by 90 degrees clockwise
+	pub fn rotated_right(self) -> Orientation {
+		self.then(Orientation::Deg270)
+	}
+
+	pub fn flipped_horizontally(self) -> Orientation {
+		self.then(Orientation::Deg0HorFlip)
+	}
+
+	pub fn flipped_vertically(self) -> Orientation {
+		self.then(Orientation::Deg180HorFlip)
+	}
+}
+
 /// Detects the format of an image file. It looks at the first 512 bytes;
 /// if that fails, it uses the file ending.
 pub fn detect_format(path: &Path) -> Result<ImgFormat> {
+	// Small textures are shorter than what's read below, and most RAW files look like TIFF
+	// files, so these are told apart by their extension first
+	match file_kind(path) {
+		#[cfg(feature = "raw")]
+		Some(FileKind::Raw) => return Ok(ImgFormat::Raw),
+		#[cfg(feature = "textures")]
+		Some(FileKind::Texture) => return Ok(ImgFormat::Texture),
+		#[cfg(feature = "layered")]
+		Some(FileKind::Layered) => return Ok(ImgFormat::Layered),
+		#[cfg(feature = "video")]
+		Some(FileKind::Video) => return Ok(ImgFormat::Video),
+		_ => (),
+	}
 	let mut file = fs::File::open(path)?;
 	let mut file_start_bytes = [0; 512];
 
@@ -94,6 +264,10 @@ pub fn detect_format(path: &Path) -> Result<ImgFormat> {
 		if path.extension() == Some(std::ffi::OsStr::new("svg")) {
 			return Ok(ImgFormat::Svg);
 		}
+		#[cfg(feature = "jxl")]
+		if is_jxl(&file_start_bytes) {
+			return Ok(ImgFormat::Jxl);
+		}
 		if let Ok(format) = image::guess_format(&file_start_bytes) {
 			return Ok(ImgFormat::Image(format));
 		}
@@ -103,25 +277,27 @@ pub fn detect_format(path: &Path) -> Result<ImgFormat> {
 	Ok(ImgFormat::Image(ImageFormat::from_path(path)?))
 }
 
+/// Checks the signature of a bare JPEG XL codestream and of the ISOBMFF based JPEG XL container
+#[cfg(feature = "jxl")]
+fn is_jxl(file_start_bytes: &[u8]) -> bool {
+	const CODESTREAM_SIGNATURE: &[u8] = &[0xFF, 0x0A];
+	const CONTAINER_SIGNATURE: &[u8] =
+		&[0x00, 0x00, 0x00, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+	file_start_bytes.starts_with(CODESTREAM_SIGNATURE)
+		|| file_start_bytes.starts_with(CONTAINER_SIGNATURE)
+}
+
 pub fn detect_orientation(path: &Path) -> std::result::Result<Orientation, exif::Error> {
-	let file = std::fs::File::open(path)?;
-	let mut bufreader = std::io::BufReader::new(&file);
-	let exifreader = exif::Reader::new();
-	let exif = exifreader.read_from_container(&mut bufreader)?;
+	orientation_from_exif(&read_exif(path)?)
+}
+
+pub fn orientation_from_exif(exif: &exif::Exif) -> std::result::Result<Orientation, exif::Error> {
 	if let Some(orientation) = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY) {
 		if let exif::Value::Short(ref shorts) = orientation.value {
 			if let Some(&exif_orientation) = shorts.first() {
-				// According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
-				match exif_orientation {
-					1 => Ok(Orientation::Deg0),
-					2 => Ok(Orientation::Deg0HorFlip),
-					3 => Ok(Orientation::Deg180),
-					4 => Ok(Orientation::Deg180HorFlip),
-					5 => Ok(Orientation::Deg90VerFlip),
-					6 => Ok(Orientation::Deg270),
-					7 => Ok(Orientation::Deg270VerFlip),
-					8 => Ok(Orientation::Deg90),
-					_ => {
+				match Orientation::from_exif(exif_orientation) {
+					Some(orientation) => Ok(orientation),
+					None => {
 						eprintln!("Invalid Exif orientation. Using default orientation.");
 						Ok(Orientation::Deg0)
 					}
@@ -138,8 +314,162 @@ pub fn detect_orientation(path: &Path) -> std::result::Result<Orientation, exif:
 }
 
 pub fn simple_load_image(path: &Path, image_format: ImageFormat) -> Result<image::RgbaImage> {
-	let reader = BufReader::new(fs::File::open(path)?);
-	Ok(image::load(reader, image_format)?.into_rgba8())
+	let (image, icc_profile) = decode_with_icc_profile(path, image_format, Limits::default())?;
+	Ok(to_srgb_rgba(image, icc_profile))
+}
+
+/// Decodes the image and also returns the ICC profile embedded in the file, if there's one.
+fn decode_with_icc_profile(
+	path: &Path,
+	image_format: ImageFormat,
+	limits: Limits,
+) -> Result<(DynamicImage, Option<Vec<u8>>)> {
+	let mut reader = image::io::Reader::new(BufReader::new(fs::File::open(path)?));
+	reader.set_format(image_format);
+	reader.limits(limits.clone());
+	let mut decoder = reader.into_decoder()?;
+	// This is the same allocation check that `image::io::Reader::decode` does
+	let mut limits = limits;
+	limits.reserve(decoder.total_bytes())?;
+	let icc_profile = decoder.icc_profile().unwrap_or_else(|e| {
+		log::warn!("Failed to read the ICC profile of {:?}: {}", path, e);
+		None
+	});
+	Ok((DynamicImage::from_decoder(decoder)?, icc_profile))
+}
+
+/// Decodes an image that isn't backed by a file, for example one that was read from the standard
+/// input. Only the first frame of animations is decoded.
+///
+/// The image can't be opened again after asking the user whether to downscale it, so images
+/// above the decode limit are only downscaled if the user chose to downscale every image.
+pub fn load_from_memory(bytes: &[u8]) -> Result<(image::RgbaImage, Orientation)> {
+	let image_format = image::guess_format(bytes)?;
+	let mut decoder =
+		image::io::Reader::with_format(Cursor::new(bytes), image_format).into_decoder()?;
+	let (w, h) = decoder.dimensions();
+	let new_size = match decode_limit::decide(Path::new("-"), w, h) {
+		DecodeDecision::Full => None,
+		DecodeDecision::Downscale { max_pixels }
+			if w as u64 * h as u64 <= max_pixels * MAX_DOWNSCALE_DECODE_FACTOR =>
+		{
+			let mut limits = Limits::no_limits();
+			limits.max_alloc = Some(max_pixels * MAX_DOWNSCALE_DECODE_FACTOR * MAX_BYTES_PER_PIXEL);
+			decoder.set_limits(limits)?;
+			Some(downscaled_size(w, h, max_pixels))
+		}
+		DecodeDecision::Downscale { .. } => {
+			return Err(ImageLoaderError::TooLargeToDownscale { width: w, height: h });
+		}
+		DecodeDecision::Refuse => return Err(ImageLoaderError::TooLarge { width: w, height: h }),
+	};
+	let icc_profile = decoder.icc_profile().unwrap_or_else(|e| {
+		log::warn!("Failed to read the ICC profile of the image: {}", e);
+		None
+	});
+	let mut image = DynamicImage::from_decoder(decoder)?;
+	if let Some((new_w, new_h)) = new_size {
+		image = image.thumbnail(new_w, new_h);
+	}
+	let mut image = to_srgb_rgba(image, icc_profile);
+	color_management::convert_to_display(&mut image);
+	let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes));
+	let orientation =
+		exif.and_then(|exif| orientation_from_exif(&exif)).unwrap_or(Orientation::Deg0);
+	Ok((image, orientation))
+}
+
+pub fn to_srgb_rgba(image: DynamicImage, icc_profile: Option<Vec<u8>>) -> image::RgbaImage {
+	let mut image = parallel_decode::into_rgba8(image);
+	if let Some(icc_profile) = icc_profile {
+		color_management::convert_to_srgb(&icc_profile, &mut image);
+	}
+	image
+}
+
+/// Like `simple_load_image` but first checks the dimensions of the image against the decode
+/// limit, so that extremely large images are never fully decoded without the user's consent.
+pub fn limited_load_image(path: &Path, image_format: ImageFormat) -> Result<image::RgbaImage> {
+	let mut reader = image::io::Reader::new(BufReader::new(fs::File::open(path)?));
+	reader.set_format(image_format);
+	let (w, h) = reader.into_dimensions()?;
+	match decode_limit::decide(path, w, h) {
+		DecodeDecision::Full => {
+			if image_format == ImageFormat::Jpeg && parallel_decode::is_large(w, h) {
+				if let Some(image) = parallel_decode::load_jpeg(path)? {
+					return Ok(image);
+				}
+			}
+			simple_load_image(path, image_format)
+		}
+		DecodeDecision::Refuse => Err(ImageLoaderError::TooLarge { width: w, height: h }),
+		DecodeDecision::Downscale { max_pixels } => {
+			let (new_w, new_h) = downscaled_size(w, h, max_pixels);
+			let max_decoded_pixels = max_pixels * MAX_DOWNSCALE_DECODE_FACTOR;
+			let scaled = match image_format {
+				ImageFormat::Jpeg => decode_scaled_jpeg(path, new_w, new_h, max_decoded_pixels)?,
+				_ => None,
+			};
+			let (image, icc_profile) = match scaled {
+				Some(scaled) => scaled,
+				None if w as u64 * h as u64 <= max_decoded_pixels => {
+					// Other decoders can't skip pixels, so the whole image is decoded first. The
+					// user agreed to open this image, so the default allocation limit of the
+					// image crate is raised to the size of the image.
+					let mut limits = Limits::no_limits();
+					limits.max_alloc = Some(max_decoded_pixels * MAX_BYTES_PER_PIXEL);
+					decode_with_icc_profile(path, image_format, limits)?
+				}
+				None => return Err(ImageLoaderError::TooLargeToDownscale { width: w, height: h }),
+			};
+			Ok(to_srgb_rgba(image.thumbnail(new_w, new_h), icc_profile))
+		}
+	}
+}
+
+/// Decodes a JPEG image at 1/2, 1/4 or 1/8 of its size, whichever is the smallest that is still
+/// at least as large as the requested size. This way only a fraction of the memory of the full
+/// image is needed. Returns `None` if the image can't be decoded like this.
+fn decode_scaled_jpeg(
+	path: &Path,
+	width: u32,
+	height: u32,
+	max_pixels: u64,
+) -> Result<Option<(DynamicImage, Option<Vec<u8>>)>> {
+	let file = BufReader::new(fs::File::open(path)?);
+	let mut decoder = jpeg_decoder::Decoder::new(file);
+	let requested = (width.min(u16::MAX as u32) as u16, height.min(u16::MAX as u32) as u16);
+	let (scaled_w, scaled_h) = match decoder.scale(requested.0, requested.1) {
+		Ok(size) => (size.0 as u32, size.1 as u32),
+		Err(e) => {
+			log::warn!("Could not decode {:?} at a smaller scale: {}", path, e);
+			return Ok(None);
+		}
+	};
+	if scaled_w as u64 * scaled_h as u64 > max_pixels {
+		return Ok(None);
+	}
+	let pixel_format = decoder.info().map(|info| info.pixel_format);
+	let pixels = decoder.decode().map_err(|e| ImageLoaderError::decoder("jpeg-decoder", e))?;
+	let image = match pixel_format {
+		Some(jpeg_decoder::PixelFormat::L8) => {
+			image::GrayImage::from_raw(scaled_w, scaled_h, pixels).map(DynamicImage::ImageLuma8)
+		}
+		Some(jpeg_decoder::PixelFormat::RGB24) => {
+			image::RgbImage::from_raw(scaled_w, scaled_h, pixels).map(DynamicImage::ImageRgb8)
+		}
+		// CMYK and 16-bit images go through the image crate
+		_ => None,
+	};
+	Ok(image.map(|image| (image, decoder.icc_profile())))
+}
+
+/// Returns the largest size with the same aspect ratio that has at most `max_pixels` pixels
+pub fn downscaled_size(w: u32, h: u32, max_pixels: u64) -> (u32, u32) {
+	let scale = (max_pixels as f64 / (w as f64 * h as f64)).sqrt();
+	let new_w = ((w as f64 * scale) as u32).max(1);
+	let new_h = ((h as f64 * scale) as u32).max(1);
+	(new_w, new_h)
 }
 
 /// Returns an iterator over the animation frames of a GIF file
@@ -178,15 +508,180 @@ pub fn load_svg(path: &std::path::Path) -> Result<image::RgbaImage> {
 	Ok(image::RgbaImage::from_raw(width, height, pixmap.take()).unwrap())
 }
 
+/// Decodes the largest JPEG preview embedded in a camera RAW file
+#[cfg(feature = "raw")]
+fn load_raw_preview(path: &Path) -> Result<image::RgbaImage> {
+	let bytes = fs::read(path)?;
+	for range in raw_preview::preview_ranges(&bytes) {
+		match image::load_from_memory_with_format(&bytes[range], ImageFormat::Jpeg) {
+			Ok(image) => return Ok(image.into_rgba8()),
+			Err(e) => log::debug!("Could not decode a preview in {:?}: {}", path, e),
+		}
+	}
+	Err(ImageLoaderError::unsupported("The RAW file doesn't have a JPEG preview"))
+}
+
+/// The number of pixels in the strips that downscaled JPEG XL images are rendered in. Only one
+/// strip is in memory at full resolution at a time.
+#[cfg(feature = "jxl")]
+const JXL_STRIP_PIXELS: u64 = 1 << 22;
+
+/// Decodes a JPEG XL file and passes its frames to `process_image`. Only the first frame is
+/// decoded if `allow_animation` is false.
+#[cfg(feature = "jxl")]
+fn load_jxl<F>(path: &Path, allow_animation: bool, req_id: u32, mut process_image: F) -> Result<()>
+where
+	F: FnMut(LoadResult) -> Result<()>,
+{
+	let mut image = jxl_oxide::JxlImage::builder()
+		.open(path)
+		.map_err(|e| ImageLoaderError::decoder("jxl-oxide", e))?;
+	let (w, h) = (image.width(), image.height());
+	let size = match decode_limit::decide(path, w, h) {
+		DecodeDecision::Full => None,
+		DecodeDecision::Downscale { max_pixels } => Some(downscaled_size(w, h, max_pixels)),
+		DecodeDecision::Refuse => {
+			return Err(ImageLoaderError::TooLarge { width: w, height: h });
+		}
+	};
+	// The frames are rendered in the color space of the image, which this profile describes
+	let icc_profile = image.rendered_icc();
+	let tick_nano = match &image.image_header().metadata.animation {
+		Some(animation) => {
+			animation.tps_denominator as u64 * 1_000_000_000 / animation.tps_numerator.max(1) as u64
+		}
+		None => 0,
+	};
+	let frame_count = image.num_loaded_keyframes();
+	let frame_count = if allow_animation { frame_count } else { frame_count.min(1) };
+	for index in 0..frame_count {
+		let (mut frame, duration) = match size {
+			Some(size) => render_jxl_downscaled(&mut image, index, size)?,
+			None => render_jxl_rows(&mut image, index, 0, h)?,
+		};
+		if !icc_profile.is_empty() {
+			color_management::convert_to_srgb(&icc_profile, &mut frame);
+		}
+		let delay_nano = duration as u64 * tick_nano;
+		// The orientation stored in the JPEG XL header is applied by the renderer
+		let orientation = Orientation::Deg0;
+		process_image(LoadResult::Frame { req_id, image: frame, delay_nano, orientation })?;
+	}
+	Ok(())
+}
+
+/// Renders a frame in strips and downscales each strip into its rows of the result, so that the
+/// frame is never in memory at full resolution. Also returns the duration of the frame in ticks.
+#[cfg(feature = "jxl")]
+fn render_jxl_downscaled(
+	image: &mut jxl_oxide::JxlImage,
+	index: usize,
+	(new_w, new_h): (u32, u32),
+) -> Result<(image::RgbaImage, u32)> {
+	let (w, h) = (image.width() as u64, image.height() as u64);
+	let strip_rows = (JXL_STRIP_PIXELS / w * new_h as u64 / h).max(1) as u32;
+	let mut frame = image::RgbaImage::new(new_w, new_h);
+	let mut duration = 0;
+	for top in (0..new_h).step_by(strip_rows as usize) {
+		let bottom = (top + strip_rows).min(new_h);
+		// The rows of the image that are downscaled into the rows `top..bottom` of the frame
+		let image_top = (top as u64 * h / new_h as u64) as u32;
+		let image_bottom = (bottom as u64 * h / new_h as u64) as u32;
+		let (strip, strip_duration) =
+			render_jxl_rows(image, index, image_top, image_bottom - image_top)?;
+		let strip = image::imageops::thumbnail(&strip, new_w, bottom - top);
+		image::imageops::replace(&mut frame, &strip, 0, top as i64);
+		duration = strip_duration;
+	}
+	Ok((frame, duration))
+}
+
+/// Renders `height` rows of a frame, starting at `top`, as 8-bit RGBA. Also returns the duration
+/// of the frame in ticks.
+#[cfg(feature = "jxl")]
+fn render_jxl_rows(
+	image: &mut jxl_oxide::JxlImage,
+	index: usize,
+	top: u32,
+	height: u32,
+) -> Result<(image::RgbaImage, u32)> {
+	use jxl_oxide::{CropInfo, PixelFormat};
+
+	let (color_channels, has_alpha) = match image.pixel_format() {
+		PixelFormat::Gray => (1, false),
+		PixelFormat::Graya => (1, true),
+		PixelFormat::Rgb => (3, false),
+		PixelFormat::Rgba => (3, true),
+		_ => return Err(ImageLoaderError::unsupported("CMYK JPEG XL images are not supported")),
+	};
+	let width = image.width();
+	image.set_image_region(CropInfo { width, height, left: 0, top });
+	let render =
+		image.render_frame(index).map_err(|e| ImageLoaderError::decoder("jxl-oxide", e))?;
+	let buffer = render.image_all_channels();
+	let mut pixels = Vec::with_capacity(buffer.width() * buffer.height() * 4);
+	let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+	for pixel in buffer.buf().chunks_exact(buffer.channels()) {
+		let alpha = if has_alpha { to_u8(pixel[color_channels]) } else { 255 };
+		let (r, g, b) = if color_channels == 1 {
+			(pixel[0], pixel[0], pixel[0])
+		} else {
+			(pixel[0], pixel[1], pixel[2])
+		};
+		pixels.extend_from_slice(&[to_u8(r), to_u8(g), to_u8(b), alpha]);
+	}
+	let frame = image::RgbaImage::from_raw(buffer.width() as u32, buffer.height() as u32, pixels)
+		.ok_or_else(|| {
+		ImageLoaderError::unsupported("The JPEG XL frame has an invalid size")
+	})?;
+	Ok((frame, render.duration()))
+}
+
+/// Loads a still PNG or TIFF image. Images with 16 bits per channel are loaded as a
+/// `LoadResult::FloatFrame` if the display has deep color, see `hdr_image::load_deep`.
+fn load_still(
+	path: &Path,
+	image_format: ImageFormat,
+	req_id: u32,
+	orientation: Orientation,
+	max_texture_size: Option<u32>,
+) -> Result<LoadResult> {
+	match hdr_image::load_deep(path, image_format, max_texture_size)? {
+		Some(image) => Ok(LoadResult::FloatFrame { req_id, image, orientation, tone_map: false }),
+		None => {
+			let image = limited_load_image(path, image_format)?;
+			Ok(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })
+		}
+	}
+}
+
+/// When `max_texture_size` is given, the frames that are too large to be uploaded at once are
+/// passed to `process_image` as `LoadResult::TiledFrame` with a downscaled preview.
 pub fn complex_load_image<F>(
 	path: &Path,
 	allow_animation: bool,
 	req_id: u32,
+	max_texture_size: Option<u32>,
 	mut process_image: F,
 ) -> Result<()>
 where
 	F: FnMut(LoadResult) -> Result<()>,
 {
+	let mut process_image = |frame: LoadResult| match (frame, max_texture_size) {
+		(LoadResult::Frame { req_id, image, delay_nano, orientation }, Some(max_size)) => {
+			match tiled_image::make_preview(&image, max_size) {
+				Some(preview) => process_image(LoadResult::TiledFrame {
+					req_id,
+					image,
+					preview,
+					delay_nano,
+					orientation,
+				}),
+				None => process_image(LoadResult::Frame { req_id, image, delay_nano, orientation }),
+			}
+		}
+		(frame, _) => process_image(frame),
+	};
 	let image_format = detect_format(path)?;
 	let orientation = detect_orientation(path).unwrap_or(Orientation::Deg0);
 
@@ -215,18 +710,61 @@ where
 					process_image(frame?)?;
 				}
 			} else {
-				let image = simple_load_image(path, ImageFormat::Png)?;
-				process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
+				let frame =
+					load_still(path, ImageFormat::Png, req_id, orientation, max_texture_size)?;
+				process_image(frame)?;
+			}
+		}
+		ImgFormat::Image(ImageFormat::Tiff) => {
+			let frame = load_still(path, ImageFormat::Tiff, req_id, orientation, max_texture_size)?;
+			process_image(frame)?;
+		}
+		// Without a texture size the image isn't displayed, so it's converted to 8 bits instead
+		ImgFormat::Image(image_format) if hdr_image::is_hdr_format(image_format) => {
+			match max_texture_size {
+				Some(max_size) => {
+					let image = hdr_image::load(path, image_format, max_size)?;
+					let tone_map = true;
+					process_image(LoadResult::FloatFrame { req_id, image, orientation, tone_map })?;
+				}
+				None => {
+					let image = limited_load_image(path, image_format)?;
+					process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
+				}
 			}
 		}
 		ImgFormat::Image(image_format) => {
-			let image = simple_load_image(path, image_format)?;
+			let image = limited_load_image(path, image_format)?;
 			process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
 		}
 		ImgFormat::Svg => {
 			let image = load_svg(path)?;
 			process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
 		}
+		#[cfg(feature = "jxl")]
+		ImgFormat::Jxl => load_jxl(path, allow_animation, req_id, process_image)?,
+		#[cfg(feature = "raw")]
+		ImgFormat::Raw => {
+			let image = load_raw_preview(path)?;
+			process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
+		}
+		#[cfg(feature = "textures")]
+		ImgFormat::Texture => {
+			let image = texture_file::load(path)?;
+			process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
+		}
+		#[cfg(feature = "layered")]
+		ImgFormat::Layered => {
+			let image = layered_file::load(path)?;
+			process_image(LoadResult::Frame { req_id, image, delay_nano: 0, orientation })?;
+		}
+		#[cfg(feature = "video")]
+		ImgFormat::Video => {
+			video_file::load(path, allow_animation, |image, delay_nano| {
+				let orientation = Orientation::Deg0;
+				process_image(LoadResult::Frame { req_id, image, delay_nano, orientation })
+			})?;
+		}
 	}
 
 	Ok(())
@@ -255,20 +793,85 @@ fn load_animation(
 	})
 }
 
+/// The extensions of the formats that the image crate and resvg decode
+const IMAGE_EXTENSIONS: &[&str] = &[
+	"jpg", "jpeg", "png", "apng", "gif", "webp", "tif", "tiff", "tga", "bmp", "ico", "hdr", "exr",
+	"pbm", "pam", "ppm", "pgm", "svg",
+];
+
+/// The loader that the files of an extension are passed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+	Image,
+	#[cfg(feature = "raw")]
+	Raw,
+	#[cfg(feature = "textures")]
+	Texture,
+	#[cfg(feature = "layered")]
+	Layered,
+	#[cfg(feature = "video")]
+	Video,
+}
+
+lazy_static! {
+	/// Every supported extension with its loader. `supported_extensions`, `file_kind` and
+	/// `is_file_supported` are all answered from this list.
+	static ref EXTENSIONS: Vec<(&'static str, FileKind)> = {
+		// The optional formats add more
+		#[allow(unused_mut)]
+		let mut extensions: Vec<_> =
+			IMAGE_EXTENSIONS.iter().map(|&ext| (ext, FileKind::Image)).collect();
+		#[cfg(feature = "avif")]
+		extensions.push(("avif", FileKind::Image));
+		#[cfg(feature = "jxl")]
+		extensions.push(("jxl", FileKind::Image));
+		#[cfg(feature = "raw")]
+		extensions.extend(raw_preview::RAW_EXTENSIONS.iter().map(|&ext| (ext, FileKind::Raw)));
+		#[cfg(feature = "textures")]
+		extensions
+			.extend(texture_file::TEXTURE_EXTENSIONS.iter().map(|&ext| (ext, FileKind::Texture)));
+		#[cfg(feature = "layered")]
+		extensions
+			.extend(layered_file::LAYERED_EXTENSIONS.iter().map(|&ext| (ext, FileKind::Layered)));
+		#[cfg(feature = "video")]
+		extensions.extend(video_file::VIDEO_EXTENSIONS.iter().map(|&ext| (ext, FileKind::Video)));
+		extensions
+	};
+
+	/// Lowercase, without the leading dot
+	static ref EXTRA_EXTENSIONS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+/// The files with these extensions are treated as supported, even if their format isn't
+/// recognized from their content
+pub fn set_extra_extensions(extensions: &[String]) {
+	let extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_lowercase());
+	*EXTRA_EXTENSIONS.write().unwrap() = extensions.collect();
+}
+
+/// The extensions of the supported files. Some files are also supported with other extensions,
+/// based on their content.
+#[cfg(feature = "dialogs")]
+pub fn supported_extensions() -> Vec<String> {
+	let mut extensions: Vec<String> = EXTENSIONS.iter().map(|&(ext, _)| ext.to_owned()).collect();
+	extensions.extend(EXTRA_EXTENSIONS.read().unwrap().iter().cloned());
+	extensions
+}
+
+/// Returns the loader of the file based on its extension, or `None` if the extension isn't
+/// one of the supported ones
+pub fn file_kind(path: &Path) -> Option<FileKind> {
+	let ext = path.extension()?.to_str()?.to_lowercase();
+	EXTENSIONS.iter().find(|&&(known, _)| known == ext).map(|&(_, kind)| kind)
+}
+
 pub fn is_file_supported(filename: &Path) -> bool {
-	if let Some(ext) = filename.extension() {
-		if let Some(ext) = ext.to_str() {
-			let ext = ext.to_lowercase();
-			match ext.as_str() {
-				"jpg" | "jpeg" | "png" | "apng" | "gif" | "webp" | "tif" | "tiff" | "tga"
-				| "bmp" | "ico" | "hdr" | "pbm" | "pam" | "ppm" | "pgm" => {
-					return true;
-				}
-				#[cfg(feature = "avif")]
-				"avif" => return true,
-				_ => (),
-			}
-		}
+	if file_kind(filename).is_some() {
+		return true;
+	}
+	let ext = filename.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+	if matches!(ext, Some(ext) if EXTRA_EXTENSIONS.read().unwrap().contains(&ext)) {
+		return true;
 	}
 	detect_format(filename).is_ok()
 }
@@ -277,12 +880,30 @@ pub fn is_file_supported(filename: &Path) -> bool {
 pub struct LoadRequest {
 	pub req_id: u32,
 	pub path: PathBuf,
+	/// Shared with the pending request, so that the loader threads can stop loading the image
+	/// when it's not needed anymore
+	cancelled: Arc<AtomicBool>,
+}
+
+impl LoadRequest {
+	pub fn new(req_id: u32, path: PathBuf) -> Self {
+		LoadRequest { req_id, path, cancelled: Arc::new(AtomicBool::new(false)) }
+	}
+
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Release);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Acquire)
+	}
 }
 
 pub enum LoadResult {
 	Start {
 		req_id: u32,
 		metadata: fs::Metadata,
+		info: Arc<ImageInfo>,
 	},
 	Frame {
 		req_id: u32,
@@ -292,11 +913,36 @@ pub enum LoadResult {
 		/// How much does the image need to be rotated counter-clockwise to be shown correctly
 		orientation: Orientation,
 	},
+	/// A frame that is too large to be uploaded at once. Only the preview is uploaded at first,
+	/// see `tiled_image`.
+	TiledFrame {
+		req_id: u32,
+		image: image::RgbaImage,
+		preview: image::RgbaImage,
+		delay_nano: u64,
+		orientation: Orientation,
+	},
+	/// An image in linear colors that is shown through a floating point texture, see
+	/// `hdr_image`
+	FloatFrame {
+		req_id: u32,
+		image: image::Rgba32FImage,
+		orientation: Orientation,
+		/// False for deep color images, which are in the range of the display already
+		tone_map: bool,
+	},
+	/// Shown until the first frame is decoded, see `placeholder`
+	Placeholder {
+		req_id: u32,
+		placeholder: Placeholder,
+	},
 	Done {
 		req_id: u32,
 	},
 	Failed {
 		req_id: u32,
+		/// Shown to the user
+		reason: String,
 	},
 }
 
@@ -305,66 +951,170 @@ impl LoadResult {
 		match self {
 			LoadResult::Start { req_id, .. } => *req_id,
 			LoadResult::Frame { req_id, .. } => *req_id,
+			LoadResult::TiledFrame { req_id, .. } => *req_id,
+			LoadResult::FloatFrame { req_id, .. } => *req_id,
+			LoadResult::Placeholder { req_id, .. } => *req_id,
 			LoadResult::Done { req_id, .. } => *req_id,
 			LoadResult::Failed { req_id, .. } => *req_id,
 		}
 	}
 }
 
+/// The results of the priority request are sent through a separate queue, so that they don't
+/// have to wait behind the results of the prefetched images.
+#[derive(Clone)]
+struct ResultSenders {
+	prefetched: ResultSender,
+	priority: ResultSender,
+}
+
+#[derive(Clone)]
+struct ResultSender {
+	sender: SyncSender<LoadResult>,
+	/// The number of results that were not received yet, including the ones that wait for
+	/// room in the queue
+	queued: Arc<AtomicUsize>,
+}
+
+impl ResultSender {
+	/// Blocks while the queue is full
+	fn send(&self, result: LoadResult) -> Result<()> {
+		self.queued.fetch_add(1, Ordering::Relaxed);
+		self.sender.send(result).map_err(|_| {
+			self.queued.fetch_sub(1, Ordering::Relaxed);
+			ImageLoaderError::Cancelled
+		})
+	}
+}
+
+/// The number of load results waiting in each queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuedResults {
+	pub prefetched: usize,
+	pub priority: usize,
+}
+
+/// Only the threads with a smaller index than `count` will take new requests. The others wait
+/// for `changed` without using the CPU.
+struct ActiveThreads {
+	count: Mutex<u32>,
+	changed: Condvar,
+}
+
 pub struct ImageLoader {
 	running: Arc<AtomicBool>,
+	active_threads: Arc<ActiveThreads>,
+	/// We want to prevent prefetch operations taking place when the target image is not yet
+	/// loaded. To implement this we define a variable that is read by the loader threads and
+	/// which will only carry out the request if the focused request id matches their request or
+	/// if the focused is set to `NON_EXISTENT_REQUEST_ID`.
+	///
+	/// Every window has its own image cache and loader, and the request ids are only unique
+	/// within a loader, so this must not be shared between loaders.
+	priority_request_id: Arc<AtomicU32>,
 	join_handles: Option<Vec<thread::JoinHandle<()>>>,
 	image_rx: Receiver<LoadResult>,
+	priority_image_rx: Receiver<LoadResult>,
+	queued_prefetched: Arc<AtomicUsize>,
+	queued_priority: Arc<AtomicUsize>,
 	path_tx: Sender<LoadRequest>,
 }
 
 impl ImageLoader {
 	/// # Arguemnts
 	/// * `capacity` - Number of bytes. The last image loaded will be the one at which the allocated memory reaches or exceeds capacity
-	pub fn new(threads: u32) -> ImageLoader {
+	/// * `display` - Frames larger than the maximal texture size of this are downscaled for
+	///   showing them, see `complex_load_image`
+	pub fn new(threads: u32, display: &gelatin::Display) -> ImageLoader {
+		let max_texture_size = display.get_capabilities().max_texture_size as u32;
 		let running = Arc::new(AtomicBool::from(true));
+		let active_threads =
+			Arc::new(ActiveThreads { count: Mutex::new(threads), changed: Condvar::new() });
+		parallel_decode::set_thread_count(threads);
+		// The first request usually
+		let priority_request_id = Arc::new(AtomicU32::new(0));
 		let (load_request_tx, load_request_rx) = channel();
 		let load_request_rx = Arc::new(Mutex::new(load_request_rx));
 
-		let (loaded_img_tx, loaded_img_rx) = channel();
+		let (loaded_img_tx, loaded_img_rx) = sync_channel(RESULT_QUEUE_CAPACITY);
+		let (priority_img_tx, priority_img_rx) = sync_channel(RESULT_QUEUE_CAPACITY);
+		let queued_prefetched = Arc::new(AtomicUsize::new(0));
+		let queued_priority = Arc::new(AtomicUsize::new(0));
+		let result_senders = ResultSenders {
+			prefetched: ResultSender { sender: loaded_img_tx, queued: queued_prefetched.clone() },
+			priority: ResultSender { sender: priority_img_tx, queued: queued_priority.clone() },
+		};
 
 		let mut join_handles = Vec::new();
-		for _ in 0..threads {
+		for thread_index in 0..threads {
 			let running = running.clone();
+			let active_threads = active_threads.clone();
+			let priority_request_id = priority_request_id.clone();
 			let request_recv = load_request_rx.clone();
 			let request_send = load_request_tx.clone();
-			let img_sender = loaded_img_tx.clone();
+			let result_senders = result_senders.clone();
 			join_handles.push(thread::spawn(move || {
-				Self::thread_loop(running, request_recv, request_send, img_sender);
+				Self::thread_loop(
+					running,
+					thread_index,
+					active_threads,
+					priority_request_id,
+					request_recv,
+					request_send,
+					result_senders,
+					max_texture_size,
+				);
 			}));
 		}
 
 		ImageLoader {
 			running,
+			active_threads,
+			priority_request_id,
 			join_handles: Some(join_handles),
 
 			image_rx: loaded_img_rx,
+			priority_image_rx: priority_img_rx,
+			queued_prefetched,
+			queued_priority,
 			path_tx: load_request_tx,
 		}
 	}
 
+	#[allow(clippy::too_many_arguments)]
 	fn thread_loop(
 		running: Arc<AtomicBool>,
+		thread_index: u32,
+		active_threads: Arc<ActiveThreads>,
+		priority_request_id: Arc<AtomicU32>,
 		request_recv: Arc<Mutex<Receiver<LoadRequest>>>,
 		request_send: Sender<LoadRequest>,
-		img_sender: Sender<LoadResult>,
+		result_senders: ResultSenders,
+		max_texture_size: u32,
 	) {
 		// The size was an arbitrary choice made with the argument that this should be
 		// enough to fit enough image file info to determine the format.
 
 		//let mut DEBUG_FAIL_COUNT = 0;
 		while running.load(Ordering::Acquire) {
+			{
+				// This thread may be paused to save resources
+				let mut count = active_threads.count.lock().unwrap();
+				while thread_index >= *count && running.load(Ordering::Acquire) {
+					count = active_threads.changed.wait(count).unwrap();
+				}
+			}
+			if !running.load(Ordering::Acquire) {
+				break;
+			}
 			let request;
+			let is_priority;
 			{
 				// It is very important that we release the mutex before starting to load the image
 				let load_request = request_recv.lock().unwrap();
-				let priority = PRIORITY_REQUEST_ID.load(Ordering::SeqCst);
+				let priority = priority_request_id.load(Ordering::SeqCst);
 				request = load_request.recv().unwrap();
+				is_priority = priority == request.req_id;
 				let focus_test_passed =
 					priority == request.req_id || priority == NON_EXISTENT_REQUEST_ID;
 				if !focus_test_passed {
@@ -376,50 +1126,164 @@ impl ImageLoader {
 					continue;
 				}
 			};
-			Self::load_and_send(&img_sender, request);
+			let img_sender =
+				if is_priority { &result_senders.priority } else { &result_senders.prefetched };
+			Self::load_and_send(img_sender, request, is_priority, max_texture_size);
 		}
 	}
 
+	/// Limits the number of threads that decode images. The rest of the threads stay idle.
+	/// At least one thread is always kept active.
+	pub fn set_active_threads(&self, count: u32) {
+		*self.active_threads.count.lock().unwrap() = count.max(1);
+		self.active_threads.changed.notify_all();
+		parallel_decode::set_thread_count(count.max(1));
+	}
+
+	pub fn active_threads(&self) -> u32 {
+		*self.active_threads.count.lock().unwrap()
+	}
+
+	/// Makes the loader threads only take the request with this id until it's finished
+	pub fn set_priority_request(&self, req_id: u32) {
+		self.priority_request_id.store(req_id, Ordering::SeqCst);
+	}
+
+	/// Lets the loader threads take any request if `req_id` was the priority request
+	pub fn finish_priority_request(&self, req_id: u32) {
+		let _ = self.priority_request_id.compare_exchange(
+			req_id,
+			NON_EXISTENT_REQUEST_ID,
+			Ordering::SeqCst,
+			Ordering::SeqCst,
+		);
+	}
+
 	pub fn try_recv_prefetched(&mut self) -> std::result::Result<LoadResult, TryRecvError> {
-		self.image_rx.try_recv()
+		let result = self.image_rx.try_recv()?;
+		self.queued_prefetched.fetch_sub(1, Ordering::Relaxed);
+		Ok(result)
+	}
+
+	/// Receives a result of a request that was the priority request when it was started
+	pub fn try_recv_priority(&mut self) -> std::result::Result<LoadResult, TryRecvError> {
+		let result = self.priority_image_rx.try_recv()?;
+		self.queued_priority.fetch_sub(1, Ordering::Relaxed);
+		Ok(result)
+	}
+
+	pub fn queued_results(&self) -> QueuedResults {
+		QueuedResults {
+			prefetched: self.queued_prefetched.load(Ordering::Relaxed),
+			priority: self.queued_priority.load(Ordering::Relaxed),
+		}
 	}
 
 	pub fn send_load_request(&mut self, request: LoadRequest) {
 		self.path_tx.send(request).unwrap();
 	}
 
-	fn load_and_send(img_sender: &Sender<LoadResult>, request: LoadRequest) {
-		fn try_load_and_send(img_sender: &Sender<LoadResult>, request: &LoadRequest) -> Result<()> {
+	/// Sending blocks while the result queue is full. The results are dropped if the loader
+	/// was dropped.
+	fn load_and_send(
+		img_sender: &ResultSender,
+		request: LoadRequest,
+		is_priority: bool,
+		max_texture_size: u32,
+	) {
+		fn try_load_and_send(
+			img_sender: &ResultSender,
+			request: &LoadRequest,
+			is_priority: bool,
+			max_texture_size: u32,
+		) -> Result<()> {
+			// The request may have waited in the queue while the user skipped the image
+			check_cancelled(request)?;
 			let metadata = fs::metadata(&request.path)?;
-			img_sender.send(LoadResult::Start { req_id: request.req_id, metadata }).unwrap();
-			complex_load_image(&request.path, true, request.req_id, |frame| {
-				img_sender.send(frame).unwrap();
-				Ok(())
+			let info = Arc::new(ImageInfo::new(&request.path, &metadata));
+			img_sender.send(LoadResult::Start { req_id: request.req_id, metadata, info })?;
+			// The prefetched images are usually decoded before they are shown
+			if is_priority {
+				if let Some(mut placeholder) = placeholder::load(&request.path) {
+					color_management::convert_to_display(&mut placeholder.image);
+					let req_id = request.req_id;
+					img_sender.send(LoadResult::Placeholder { req_id, placeholder })?;
+				}
+			}
+			let max_size = Some(max_texture_size);
+			let mut first_frame = true;
+			complex_load_image(&request.path, true, request.req_id, max_size, |mut frame| {
+				// Returning an error stops decoding the rest of the animation frames
+				check_cancelled(request)?;
+				if mem::take(&mut first_frame) && thumbnail_cache::is_enabled() {
+					save_thumbnails(&request.path, &frame);
+				}
+				match &mut frame {
+					LoadResult::Frame { image, .. } => color_management::convert_to_display(image),
+					LoadResult::TiledFrame { image, preview, .. } => {
+						color_management::convert_to_display(image);
+						color_management::convert_to_display(preview);
+					}
+					_ => (),
+				}
+				img_sender.send(frame)
 			})?;
 			Ok(())
 		}
 
-		img_sender
-			.send(match try_load_and_send(img_sender, &request) {
-				Ok(()) => LoadResult::Done { req_id: request.req_id },
-				Err(error) => {
-					eprintln!(
-						"Request #{}: Error occurred while loading file {:?}\n    {}",
-						request.req_id, request.path, error,
-					);
-					LoadResult::Failed { req_id: request.req_id }
-				}
-			})
-			.unwrap();
+		fn check_cancelled(request: &LoadRequest) -> Result<()> {
+			if request.is_cancelled() {
+				return Err(ImageLoaderError::Cancelled);
+			}
+			Ok(())
+		}
+
+		/// Made before converting the frame to the display profile, so that the thumbnails
+		/// look right everywhere
+		fn save_thumbnails(path: &Path, frame: &LoadResult) {
+			let (image, orientation) = match frame {
+				LoadResult::Frame { image, orientation, .. } => (image, *orientation),
+				// The preview is faster to downscale
+				LoadResult::TiledFrame { preview, orientation, .. } => (preview, *orientation),
+				_ => return,
+			};
+			if let Err(e) = thumbnail_cache::save_thumbnails(path, image, orientation) {
+				eprintln!("Could not save the thumbnails of {:?}: {}", path, e);
+			}
+		}
+
+		let result = match try_load_and_send(img_sender, &request, is_priority, max_texture_size) {
+			Ok(()) => LoadResult::Done { req_id: request.req_id },
+			// The frames that were sent are dropped by the cache
+			Err(_) if request.is_cancelled() => LoadResult::Done { req_id: request.req_id },
+			Err(error) => {
+				eprintln!(
+					"Request #{}: Error occurred while loading file {:?}\n    {}",
+					request.req_id, request.path, error,
+				);
+				LoadResult::Failed { req_id: request.req_id, reason: error.to_string() }
+			}
+		};
+		let _ = img_sender.send(result);
 	}
 }
 
 impl Drop for ImageLoader {
 	fn drop(&mut self) {
-		self.running.store(false, Ordering::Release);
+		{
+			// Holding the lock makes sure that the paused threads either see that the loader
+			// stopped or are already waiting for the notification
+			let _count = self.active_threads.count.lock().unwrap();
+			self.running.store(false, Ordering::Release);
+			self.active_threads.changed.notify_all();
+		}
+		// Dropping the receivers wakes up the threads that are waiting for space in the full
+		// result queues
+		self.image_rx = sync_channel(0).1;
+		self.priority_image_rx = sync_channel(0).1;
 		if let Some(join_handles) = self.join_handles.take() {
 			for _ in join_handles.iter() {
-				self.path_tx.send(LoadRequest { req_id: 0, path: PathBuf::from("") }).unwrap();
+				self.path_tx.send(LoadRequest::new(0, PathBuf::from(""))).unwrap();
 			}
 
 			for handle in join_handles.into_iter() {
@@ -0,0 +1,500 @@
+//! Flattens the working files of image editors, PSD and XCF, so that they can be previewed
+//! without opening the editor. Only the composited result is shown; the layers can't be
+//! inspected separately.
+//!
+//! Photoshop stores the composited image in PSD files, which the `psd` crate reads. GIMP doesn't,
+//! so the layers of XCF files are composited here. Every layer is drawn with the normal mode,
+//! which is close enough for a preview.
+
+use std::fs;
+use std::path::Path;
+
+use gelatin::image::{imageops, RgbaImage};
+
+use super::decode_limit::{self, DecodeDecision, MAX_DOWNSCALE_DECODE_FACTOR};
+use super::image_loader::{downscaled_size, ImageLoaderError, Result};
+
+pub const LAYERED_EXTENSIONS: &[&str] = &["psd", "xcf"];
+
+const XCF_MAGIC: &[u8] = b"gimp xcf ";
+const XCF_TILE_SIZE: u32 = 64;
+
+const PROP_END: u32 = 0;
+const PROP_COLORMAP: u32 = 1;
+const PROP_OPACITY: u32 = 6;
+const PROP_VISIBLE: u32 = 8;
+const PROP_APPLY_MASK: u32 = 11;
+const PROP_OFFSETS: u32 = 15;
+const PROP_COMPRESSION: u32 = 17;
+const PROP_GROUP_ITEM: u32 = 29;
+const PROP_ITEM_PATH: u32 = 30;
+const PROP_FLOAT_OPACITY: u32 = 33;
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_RLE: u8 = 1;
+
+/// Returns the composited image of the file
+pub fn load(path: &Path) -> Result<RgbaImage> {
+	let bytes = fs::read(path)?;
+	if bytes.starts_with(XCF_MAGIC) {
+		flatten_xcf(path, &bytes)
+	} else {
+		load_psd(path, &bytes)
+	}
+}
+
+/// The layers are composited at full size, so the size of the image is checked against the
+/// decode limit before anything is allocated for it
+struct SizeLimit {
+	/// The canvas and each layer may have at most this many pixels
+	max_pixels: u64,
+	/// The size that the composited image has to be scaled down to
+	downscaled_size: Option<(u32, u32)>,
+}
+
+impl SizeLimit {
+	fn new(path: &Path, width: u32, height: u32) -> Result<SizeLimit> {
+		let pixels = width as u64 * height as u64;
+		match decode_limit::decide(path, width, height) {
+			DecodeDecision::Full => {
+				let max_pixels = decode_limit::max_megapixels()
+					.map_or(u64::MAX, |megapixels| megapixels as u64 * 1_000_000);
+				Ok(SizeLimit { max_pixels: max_pixels.max(pixels), downscaled_size: None })
+			}
+			DecodeDecision::Downscale { max_pixels }
+				if pixels <= max_pixels * MAX_DOWNSCALE_DECODE_FACTOR =>
+			{
+				Ok(SizeLimit {
+					max_pixels: max_pixels * MAX_DOWNSCALE_DECODE_FACTOR,
+					downscaled_size: Some(downscaled_size(width, height, max_pixels)),
+				})
+			}
+			DecodeDecision::Downscale { .. } => {
+				Err(ImageLoaderError::TooLargeToDownscale { width, height })
+			}
+			DecodeDecision::Refuse => Err(ImageLoaderError::TooLarge { width, height }),
+		}
+	}
+
+	fn check_layer(&self, layer: &XcfLayer) -> Result<()> {
+		if layer.width as u64 * layer.height as u64 > self.max_pixels {
+			return Err(ImageLoaderError::unsupported(
+				"A layer of the XCF file is too large to be decoded",
+			));
+		}
+		Ok(())
+	}
+
+	fn apply(&self, image: RgbaImage) -> RgbaImage {
+		match self.downscaled_size {
+			Some((width, height)) => imageops::thumbnail(&image, width, height),
+			None => image,
+		}
+	}
+}
+
+fn load_psd(path: &Path, bytes: &[u8]) -> Result<RgbaImage> {
+	// The header is the signature, the version, 6 reserved bytes, the number of channels, the
+	// height and the width
+	let header = bytes.get(..22).filter(|header| header.starts_with(b"8BPS"));
+	let header = header
+		.ok_or_else(|| ImageLoaderError::unsupported("The PSD file has an invalid header"))?;
+	let height = u32::from_be_bytes([header[14], header[15], header[16], header[17]]);
+	let width = u32::from_be_bytes([header[18], header[19], header[20], header[21]]);
+	let limit = SizeLimit::new(path, width, height)?;
+	let psd = psd::Psd::from_bytes(bytes).map_err(|e| ImageLoaderError::decoder("PSD", e))?;
+	let image = RgbaImage::from_raw(psd.width(), psd.height(), psd.rgba()).ok_or_else(|| {
+		ImageLoaderError::unsupported("The composited image of the PSD file has the wrong size")
+	})?;
+	Ok(limit.apply(image))
+}
+
+/// Reads the big-endian values of an XCF file
+struct XcfReader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+	/// Files of version 11 and later use 64-bit pointers
+	wide_pointers: bool,
+}
+
+impl<'a> XcfReader<'a> {
+	fn at(&self, pos: usize) -> XcfReader<'a> {
+		XcfReader { bytes: self.bytes, pos, wide_pointers: self.wide_pointers }
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+		let end = self
+			.pos
+			.checked_add(len)
+			.ok_or_else(|| ImageLoaderError::unsupported("The XCF file is truncated"))?;
+		let bytes = self.bytes.get(self.pos..end);
+		let bytes =
+			bytes.ok_or_else(|| ImageLoaderError::unsupported("The XCF file is truncated"))?;
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u32(&mut self) -> Result<u32> {
+		let b = self.bytes(4)?;
+		Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	fn pointer(&mut self) -> Result<usize> {
+		if self.wide_pointers {
+			let high = self.u32()? as u64;
+			let low = self.u32()? as u64;
+			Ok((high << 32 | low) as usize)
+		} else {
+			Ok(self.u32()? as usize)
+		}
+	}
+
+	/// Reads pointers until the terminating zero
+	fn pointers(&mut self) -> Result<Vec<usize>> {
+		let mut pointers = Vec::new();
+		loop {
+			match self.pointer()? {
+				0 => return Ok(pointers),
+				pointer => pointers.push(pointer),
+			}
+		}
+	}
+
+	fn skip_string(&mut self) -> Result<()> {
+		let len = self.u32()? as usize;
+		self.bytes(len)?;
+		Ok(())
+	}
+
+	/// Calls `handle_property` with the type and the payload of each property
+	fn properties<F>(&mut self, mut handle_property: F) -> Result<()>
+	where
+		F: FnMut(u32, XcfReader<'a>) -> Result<()>,
+	{
+		loop {
+			let property_type = self.u32()?;
+			let len = self.u32()? as usize;
+			if property_type == PROP_END {
+				return Ok(());
+			}
+			let payload = self.bytes(len)?;
+			let payload = XcfReader { bytes: payload, pos: 0, wide_pointers: self.wide_pointers };
+			handle_property(property_type, payload)?;
+		}
+	}
+}
+
+/// The properties of the whole XCF image that are needed to decode the layers
+struct XcfImage {
+	compression: u8,
+	colormap: Vec<u8>,
+}
+
+struct XcfLayer {
+	width: u32,
+	height: u32,
+	kind: u32,
+	visible: bool,
+	opacity: f32,
+	x: i32,
+	y: i32,
+	is_group: bool,
+	/// The number of groups that the layer is in
+	depth: usize,
+	apply_mask: bool,
+	hierarchy: usize,
+	mask: usize,
+}
+
+fn flatten_xcf(path: &Path, bytes: &[u8]) -> Result<RgbaImage> {
+	let mut reader = XcfReader { bytes, pos: XCF_MAGIC.len(), wide_pointers: false };
+	let version = match reader.bytes(5)? {
+		b"file\0" => 0,
+		[b'v', digits @ .., 0] => std::str::from_utf8(digits)
+			.ok()
+			.and_then(|digits| digits.parse::<u32>().ok())
+			.ok_or_else(|| {
+				ImageLoaderError::unsupported("The version of the XCF file is invalid")
+			})?,
+		_ => return Err(ImageLoaderError::unsupported("The version of the XCF file is invalid")),
+	};
+	reader.wide_pointers = version >= 11;
+	let width = reader.u32()?;
+	let height = reader.u32()?;
+	let limit = SizeLimit::new(path, width, height)?;
+	let _base_type = reader.u32()?;
+	if version >= 4 {
+		// 8-bit gamma for version 4, 8-bit linear and 8-bit gamma for the later versions
+		let precision = reader.u32()?;
+		let supported = if version == 4 { precision == 0 } else { matches!(precision, 100 | 150) };
+		if !supported {
+			return Err(ImageLoaderError::unsupported("Only 8-bit XCF files are supported"));
+		}
+	}
+	let mut image = XcfImage { compression: COMPRESSION_NONE, colormap: Vec::new() };
+	reader.properties(|property_type, mut payload| {
+		match property_type {
+			PROP_COMPRESSION => image.compression = payload.u8()?,
+			PROP_COLORMAP => {
+				let color_count = payload.u32()? as usize;
+				image.colormap = payload.bytes(color_count * 3)?.to_vec();
+			}
+			_ => (),
+		}
+		Ok(())
+	})?;
+	if !matches!(image.compression, COMPRESSION_NONE | COMPRESSION_RLE) {
+		return Err(ImageLoaderError::unsupported(
+			"The compression of the XCF file is not supported",
+		));
+	}
+
+	let mut layers = Vec::new();
+	for pointer in reader.pointers()? {
+		let layer = read_layer(&mut reader.at(pointer))?;
+		limit.check_layer(&layer)?;
+		layers.push(layer);
+	}
+
+	let mut canvas = RgbaImage::new(width, height);
+	// The layers are listed from top to bottom, with each group followed by its contents. The
+	// visibility and the opacity of each group applies to its contents.
+	let mut group_opacities: Vec<Option<f32>> = Vec::new();
+	let mut drawn_layers = Vec::new();
+	for layer in layers.iter() {
+		group_opacities.truncate(layer.depth);
+		let parent_opacity = match group_opacities.last() {
+			Some(&opacity) => opacity,
+			None => Some(1.0),
+		};
+		let opacity = match parent_opacity {
+			Some(parent_opacity) if layer.visible => Some(parent_opacity * layer.opacity),
+			_ => None,
+		};
+		if layer.is_group {
+			group_opacities.push(opacity);
+		} else if let Some(opacity) = opacity {
+			drawn_layers.push((layer, opacity));
+		}
+	}
+	for (layer, opacity) in drawn_layers.into_iter().rev() {
+		draw_layer(&mut canvas, &reader, &image, layer, opacity)?;
+	}
+	Ok(limit.apply(canvas))
+}
+
+fn read_layer(reader: &mut XcfReader) -> Result<XcfLayer> {
+	let mut layer = XcfLayer {
+		width: reader.u32()?,
+		height: reader.u32()?,
+		kind: reader.u32()?,
+		visible: true,
+		opacity: 1.0,
+		x: 0,
+		y: 0,
+		is_group: false,
+		depth: 0,
+		apply_mask: false,
+		hierarchy: 0,
+		mask: 0,
+	};
+	reader.skip_string()?;
+	reader.properties(|property_type, mut payload| {
+		match property_type {
+			PROP_OPACITY => layer.opacity = payload.u32()? as f32 / 255.0,
+			PROP_FLOAT_OPACITY => layer.opacity = f32::from_bits(payload.u32()?),
+			PROP_VISIBLE => layer.visible = payload.u32()? != 0,
+			PROP_APPLY_MASK => layer.apply_mask = payload.u32()? != 0,
+			PROP_OFFSETS => {
+				layer.x = payload.u32()? as i32;
+				layer.y = payload.u32()? as i32;
+			}
+			PROP_GROUP_ITEM => layer.is_group = true,
+			// The index of the layer within each of its groups, and within its own list
+			PROP_ITEM_PATH => layer.depth = (payload.bytes.len() / 4).saturating_sub(1),
+			_ => (),
+		}
+		Ok(())
+	})?;
+	layer.hierarchy = reader.pointer()?;
+	layer.mask = reader.pointer()?;
+	Ok(layer)
+}
+
+/// Returns the pixels of a hierarchy, with `channels` bytes per pixel
+fn read_hierarchy(
+	reader: &XcfReader,
+	image: &XcfImage,
+	pointer: usize,
+	width: u32,
+	height: u32,
+) -> Result<(Vec<u8>, usize)> {
+	let mut hierarchy = reader.at(pointer);
+	hierarchy.u32()?;
+	hierarchy.u32()?;
+	let channels = hierarchy.u32()? as usize;
+	if !(1..=4).contains(&channels) {
+		return Err(ImageLoaderError::unsupported(
+			"The XCF file has an unsupported number of channels",
+		));
+	}
+	// Only the first level has the full resolution
+	let mut level = reader.at(hierarchy.pointer()?);
+	level.u32()?;
+	level.u32()?;
+	let tiles = level.pointers()?;
+	let columns = width.div_ceil(XCF_TILE_SIZE);
+	let rows = height.div_ceil(XCF_TILE_SIZE);
+	if tiles.len() < (columns * rows) as usize {
+		return Err(ImageLoaderError::unsupported("The XCF file is missing tiles"));
+	}
+	let mut pixels = vec![0; width as usize * height as usize * channels];
+	for (index, &tile) in tiles.iter().take((columns * rows) as usize).enumerate() {
+		let tile_x = index as u32 % columns * XCF_TILE_SIZE;
+		let tile_y = index as u32 / columns * XCF_TILE_SIZE;
+		let tile_width = XCF_TILE_SIZE.min(width - tile_x) as usize;
+		let tile_height = XCF_TILE_SIZE.min(height - tile_y) as usize;
+		let data = read_tile(&mut reader.at(tile), image, tile_width * tile_height, channels)?;
+		for row in 0..tile_height {
+			let start = ((tile_y as usize + row) * width as usize + tile_x as usize) * channels;
+			let src = row * tile_width * channels;
+			pixels[start..start + tile_width * channels]
+				.copy_from_slice(&data[src..src + tile_width * channels]);
+		}
+	}
+	Ok((pixels, channels))
+}
+
+/// Returns the interleaved pixels of a tile
+fn read_tile(
+	reader: &mut XcfReader,
+	image: &XcfImage,
+	pixel_count: usize,
+	channels: usize,
+) -> Result<Vec<u8>> {
+	if image.compression == COMPRESSION_NONE {
+		return Ok(reader.bytes(pixel_count * channels)?.to_vec());
+	}
+	// With RLE each channel is compressed separately
+	let mut data = vec![0; pixel_count * channels];
+	for channel in 0..channels {
+		let mut index = 0;
+		while index < pixel_count {
+			let opcode = reader.u8()?;
+			let (count, repeated) = match opcode {
+				0..=126 => (opcode as usize + 1, true),
+				127 => (reader.u8()? as usize * 256 + reader.u8()? as usize, true),
+				128 => (reader.u8()? as usize * 256 + reader.u8()? as usize, false),
+				_ => (256 - opcode as usize, false),
+			};
+			if index + count > pixel_count {
+				return Err(ImageLoaderError::unsupported("The XCF file has a corrupt tile"));
+			}
+			if repeated {
+				let value = reader.u8()?;
+				for i in index..index + count {
+					data[i * channels + channel] = value;
+				}
+			} else {
+				for (i, &value) in (index..index + count).zip(reader.bytes(count)?) {
+					data[i * channels + channel] = value;
+				}
+			}
+			index += count;
+		}
+	}
+	Ok(data)
+}
+
+fn draw_layer(
+	canvas: &mut RgbaImage,
+	reader: &XcfReader,
+	image: &XcfImage,
+	layer: &XcfLayer,
+	opacity: f32,
+) -> Result<()> {
+	let (pixels, channels) =
+		read_hierarchy(reader, image, layer.hierarchy, layer.width, layer.height)?;
+	let expected_channels = match layer.kind {
+		0 => 3,
+		1 => 4,
+		2 | 4 => 1,
+		3 | 5 => 2,
+		_ => {
+			return Err(ImageLoaderError::unsupported(
+				"The XCF file has a layer of an unknown type",
+			))
+		}
+	};
+	if channels != expected_channels {
+		return Err(ImageLoaderError::unsupported(
+			"The XCF file has a layer with the wrong number of channels",
+		));
+	}
+	let mask = if layer.apply_mask && layer.mask != 0 {
+		let mut channel = reader.at(layer.mask);
+		channel.u32()?;
+		channel.u32()?;
+		channel.skip_string()?;
+		channel.properties(|_, _| Ok(()))?;
+		let pointer = channel.pointer()?;
+		Some(read_hierarchy(reader, image, pointer, layer.width, layer.height)?.0)
+	} else {
+		None
+	};
+	for y in 0..layer.height {
+		let canvas_y = layer.y as i64 + y as i64;
+		if canvas_y < 0 || canvas_y >= canvas.height() as i64 {
+			continue;
+		}
+		for x in 0..layer.width {
+			let canvas_x = layer.x as i64 + x as i64;
+			if canvas_x < 0 || canvas_x >= canvas.width() as i64 {
+				continue;
+			}
+			let index = (y * layer.width + x) as usize;
+			let pixel = &pixels[index * channels..(index + 1) * channels];
+			let [r, g, b, a] = layer_color(image, layer.kind, pixel);
+			let mask_value = mask.as_ref().map_or(1.0, |mask| mask[index] as f32 / 255.0);
+			let alpha = a as f32 / 255.0 * opacity * mask_value;
+			let target = canvas.get_pixel_mut(canvas_x as u32, canvas_y as u32);
+			let target_alpha = target[3] as f32 / 255.0;
+			let out_alpha = alpha + target_alpha * (1.0 - alpha);
+			if out_alpha <= 0.0 {
+				continue;
+			}
+			for (channel, value) in [r, g, b].iter().enumerate() {
+				let blended = (*value as f32 * alpha
+					+ target[channel] as f32 * target_alpha * (1.0 - alpha))
+					/ out_alpha;
+				target[channel] = blended.round() as u8;
+			}
+			target[3] = (out_alpha * 255.0).round() as u8;
+		}
+	}
+	Ok(())
+}
+
+/// Converts a pixel of a layer to RGBA, based on the type of the layer
+fn layer_color(image: &XcfImage, kind: u32, pixel: &[u8]) -> [u8; 4] {
+	let indexed = |index: u8| {
+		let i = index as usize * 3;
+		match image.colormap.get(i..i + 3) {
+			Some(color) => [color[0], color[1], color[2]],
+			None => [0, 0, 0],
+		}
+	};
+	let alpha = |channel: usize| pixel.get(channel).copied().unwrap_or(255);
+	match kind {
+		0 | 1 => [pixel[0], pixel[1], pixel[2], alpha(3)],
+		2 | 3 => [pixel[0], pixel[0], pixel[0], alpha(1)],
+		_ => {
+			let [r, g, b] = indexed(pixel[0]);
+			[r, g, b, alpha(1)]
+		}
+	}
+}
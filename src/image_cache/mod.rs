@@ -5,11 +5,13 @@ use std::{
 	fs, mem,
 	path::{Path, PathBuf},
 	rc::Rc,
-	sync::atomic::Ordering,
+	sync::Arc,
 	time::SystemTime,
 };
 
-use log::trace;
+use log::{debug, trace, warn};
+
+use gelatin::winit::event_loop::EventLoopProxy;
 
 use gelatin::{
 	glium::{
@@ -20,9 +22,34 @@ use gelatin::{
 	image,
 };
 
+pub mod color_management;
+pub mod decode_limit;
+pub mod file_filter;
+pub mod hdr_image;
+pub mod image_info;
 pub mod image_loader;
-
-use self::{directory::DirItem, image_loader::*};
+#[cfg(feature = "layered")]
+pub mod layered_file;
+pub mod orientation_writer;
+mod parallel_decode;
+pub mod pixel_reader;
+mod placeholder;
+#[cfg(feature = "raw")]
+pub mod raw_preview;
+pub mod sidecar;
+#[cfg(feature = "textures")]
+pub mod texture_file;
+mod texture_upload;
+pub mod thumbnail_cache;
+pub mod tiled_image;
+#[cfg(feature = "video")]
+pub mod video_file;
+
+use self::{
+	directory::DirItem, file_filter::FileFilter, hdr_image::FloatTexture, image_info::ImageInfo,
+	image_loader::*, placeholder::Placeholder, sidecar::MetadataFilter,
+	texture_upload::TextureUpload, tiled_image::TiledImage,
+};
 
 mod pending_requests;
 use pending_requests::PendingRequests;
@@ -30,39 +57,35 @@ use pending_requests::PendingRequests;
 pub mod directory;
 use directory::Directory;
 
+use crate::configuration::{FolderEnd, SortOrder};
+
 #[derive(Debug, thiserror::Error)]
 pub enum TextureError {
 	#[error("ImageCache is waiting for the image loader to send result")]
 	WaitingOnLoader,
 
-	#[error("Other texture error: {0}")]
-	Other(Cow<'static, str>),
-}
-impl From<directory::Error> for TextureError {
-	fn from(value: directory::Error) -> Self {
-		Self::Other(format!("Directory error: {}", value).into())
-	}
-}
-impl From<glium::texture::TextureCreationError> for TextureError {
-	fn from(value: glium::texture::TextureCreationError) -> Self {
-		Self::Other(format!("glium failed to create the texture: {value}").into())
-	}
-}
-impl From<std::io::Error> for TextureError {
-	fn from(value: std::io::Error) -> Self {
-		Self::Other(format!("IO error occured during texture creation: {value}").into())
-	}
-}
-impl TextureError {
-	pub fn from_failed_request(req_id: u32) -> Self {
-		Self::Other(format!("Failed to load #{req_id}").into())
-	}
+	#[error("There isn't enough video memory to upload the image")]
+	OutOfMemory,
+
+	/// The image loader could not decode the image of the request
+	#[error("{reason}")]
+	Failed { req_id: u32, reason: String },
+
+	#[error("There's no current image in the folder")]
+	NoCurrentImage,
+
+	#[error("Directory error: {0}")]
+	Directory(#[from] directory::Error),
+
+	#[error("glium failed to create the texture: {0}")]
+	TextureCreation(#[from] glium::texture::TextureCreationError),
+
+	#[error("IO error occured during texture creation: {0}")]
+	Io(#[from] std::io::Error),
 }
 
 pub type TextureResult<T> = std::result::Result<T, TextureError>;
 
-// use self::texture_load_errors::*;
-
 #[derive(Debug, thiserror::Error)]
 pub enum PathResolutionError {
 	#[error("No image path has been specified. (Eg emulsion was started without it being requested to open an image or folder)")]
@@ -78,16 +101,29 @@ pub enum PathResolutionError {
 pub type PathedTextureResult =
 	std::result::Result<(PathBuf, TextureResult<AnimationFrameTexture>), PathResolutionError>;
 
+/// Images are not downscaled below this size when retrying an upload that ran out of
+/// video memory.
+const MIN_OUT_OF_MEMORY_DOWNSCALE_SIZE: u32 = 512;
+
 pub fn get_image_size_estimate(width: u32, height: u32) -> isize {
 	// In an RGBA image, each pixel is 4 bytes.
 	// counting all the mipmaps would add an additionnal multiplier of around ~1.6
 	// but only the gpu textures have mip maps so just multiply by 1.5
 	// 4 x 1.5 gives the factor 6.
-	(width * height * 6) as isize
+	width as isize * height as isize * 6
 }
 
 pub fn get_anim_size_estimate(frames: &[AnimationFrameTexture]) -> isize {
-	frames.iter().map(|frame| get_image_size_estimate(frame.w, frame.h)).sum()
+	let frame_size = |frame: &AnimationFrameTexture| {
+		let size = get_image_size_estimate(frame.w, frame.h);
+		// Half float pixels take twice as much memory
+		if frame.float.is_some() {
+			size * 2
+		} else {
+			size
+		}
+	};
+	frames.iter().map(frame_size).sum()
 }
 
 /// The request sender function must process all prefetched requests to avoid
@@ -126,11 +162,20 @@ pub struct AnimationFrameTexture {
 	/// of the cell at the 3rd column and 2nd row is
 	/// (3*cell_step_size, 2*cell_step_size)
 	pub cell_step_size: u32,
-	pub grid_rows: u32,
-	pub grid_cols: u32,
+	/// Very large images are shown progressively instead of through the grid, which is empty
+	/// then
+	pub tiled: Option<Rc<TiledImage>>,
+	/// High dynamic range and deep color images are shown through this floating point texture
+	/// instead of the grid, which is empty then
+	pub float: Option<Rc<FloatTexture>>,
+	/// A tiny blurred version of the image that is stretched over it while the image is
+	/// loading, see `placeholder`. The grid is empty then.
+	pub placeholder: Option<Rc<SrgbTexture2d>>,
 
 	pub delay_nano: u64,
 	pub orientation: Orientation,
+	/// Information about the file that this frame was loaded from
+	pub info: Option<Arc<ImageInfo>>,
 
 	/// The total width of the image. This equals to the sum of the widths of the
 	/// textures from a single row of the grid
@@ -145,20 +190,27 @@ impl AnimationFrameTexture {
 		image: image::RgbaImage,
 		delay_nano: u64,
 		orientation: Orientation,
+	) -> TextureResult<Self> {
+		Self::upload(display, &image, delay_nano, orientation, true)
+	}
+
+	/// Returns `TextureError::OutOfMemory` if the driver reported running out of
+	/// video memory during the upload.
+	fn upload(
+		display: &gelatin::Display,
+		image: &image::RgbaImage,
+		delay_nano: u64,
+		orientation: Orientation,
+		mipmaps: bool,
 	) -> TextureResult<Self> {
 		let (w, h) = image.dimensions();
-		let img_bytes = image.into_raw();
+		let img_bytes = image.as_raw();
 		let mut tex_grid = Vec::new();
 
-		// The reasoning behind dividing by 2 and taking the min with 4*1024, is
-		// that if the textures are going to be swaped out from GPU memory it
-		// might be easier to shuffle smaller chunks of memory around. (Because
-		// I believe that if the memory is fragmented, it is easier to find
-		// space for a smaller texture)
-		let max_size = (display.get_capabilities().max_texture_size as u32 / 2).min(4 * 1024);
+		// Forget about any earlier error so that only this upload is checked
+		gelatin::window::take_gpu_out_of_memory(display);
 
-		// DEBUG
-		// let max_size = 1024;
+		let max_size = grid_cell_size(display);
 
 		let grid_cols = ((w - 1) / max_size) + 1;
 		let grid_rows = ((h - 1) / max_size) + 1;
@@ -170,34 +222,134 @@ impl AnimationFrameTexture {
 				let cell_w = (w - offset_x).min(max_size);
 				let cell_h = (h - offset_y).min(max_size);
 				let tex = texture_from_img_rect(
-					display, w, h, &img_bytes, offset_x, offset_y, cell_w, cell_h,
+					display, w, h, img_bytes, offset_x, offset_y, cell_w, cell_h, mipmaps,
 				)?;
 				let item = TextureGridItem { tex, col, row };
 				tex_grid.push(item);
 			}
 		}
+		if gelatin::window::take_gpu_out_of_memory(display) {
+			return Err(TextureError::OutOfMemory);
+		}
 
 		Ok(AnimationFrameTexture {
 			tex_grid: Rc::new(tex_grid),
 			delay_nano,
 			orientation,
+			info: None,
 			w,
 			h,
 			cell_step_size: max_size,
-			grid_rows,
-			grid_cols,
+			tiled: None,
+			float: None,
+			placeholder: None,
+		})
+	}
+
+	/// Only uploads the preview, the tiles are uploaded later while the image is shown
+	fn tiled(
+		display: &gelatin::Display,
+		image: image::RgbaImage,
+		preview: &image::RgbaImage,
+		delay_nano: u64,
+		orientation: Orientation,
+	) -> TextureResult<Self> {
+		let (w, h) = image.dimensions();
+		let tiled = TiledImage::new(display, image, preview)?;
+		Ok(AnimationFrameTexture {
+			tex_grid: Rc::new(Vec::new()),
+			delay_nano,
+			orientation,
+			info: None,
+			w,
+			h,
+			cell_step_size: w.max(h),
+			tiled: Some(Rc::new(tiled)),
+			float: None,
+			placeholder: None,
+		})
+	}
+
+	/// The image has to fit into a single texture
+	fn float(
+		display: &gelatin::Display,
+		image: &image::Rgba32FImage,
+		orientation: Orientation,
+		tone_map: bool,
+	) -> TextureResult<Self> {
+		let (w, h) = image.dimensions();
+		gelatin::window::take_gpu_out_of_memory(display);
+		let tex = hdr_image::texture_from_image(display, image)?;
+		if gelatin::window::take_gpu_out_of_memory(display) {
+			return Err(TextureError::OutOfMemory);
+		}
+		Ok(AnimationFrameTexture {
+			tex_grid: Rc::new(Vec::new()),
+			delay_nano: 0,
+			orientation,
+			info: None,
+			w,
+			h,
+			cell_step_size: w.max(h),
+			tiled: None,
+			float: Some(Rc::new(FloatTexture { tex, tone_map })),
+			placeholder: None,
+		})
+	}
+
+	fn placeholder(display: &gelatin::Display, placeholder: &Placeholder) -> TextureResult<Self> {
+		let image = &placeholder.image;
+		let (tex_w, tex_h) = image.dimensions();
+		let bytes = image.as_raw();
+		let tex = texture_from_img_rect(display, tex_w, tex_h, bytes, 0, 0, tex_w, tex_h, true)?;
+		let (w, h) = (placeholder.width, placeholder.height);
+		Ok(AnimationFrameTexture {
+			tex_grid: Rc::new(Vec::new()),
+			delay_nano: 0,
+			orientation: placeholder.orientation,
+			info: None,
+			w,
+			h,
+			cell_step_size: w.max(h),
+			tiled: None,
+			float: None,
+			placeholder: Some(Rc::new(tex)),
 		})
 	}
 
 	pub fn oriented_dimensions(&self) -> (u32, u32) {
-		use Orientation::*;
-		match self.orientation {
-			Deg0 | Deg0HorFlip | Deg180 | Deg180HorFlip => (self.w, self.h),
-			Deg90 | Deg90VerFlip | Deg270 | Deg270VerFlip => (self.h, self.w),
+		if self.orientation.swaps_dimensions() {
+			(self.h, self.w)
+		} else {
+			(self.w, self.h)
 		}
 	}
 }
 
+/// The maximal width and height of the cells of the texture grid
+fn grid_cell_size(display: &gelatin::Display) -> u32 {
+	// The reasoning behind dividing by 2 and taking the min with 4*1024, is
+	// that if the textures are going to be swaped out from GPU memory it
+	// might be easier to shuffle smaller chunks of memory around. (Because
+	// I believe that if the memory is fragmented, it is easier to find
+	// space for a smaller texture)
+	(display.get_capabilities().max_texture_size as u32 / 2).min(4 * 1024)
+}
+
+fn mipmaps_option(img_w: u32, img_h: u32, mipmaps: bool) -> MipmapsOption {
+	let x_pow = 31 - img_w.leading_zeros();
+	let y_pow = 31 - img_h.leading_zeros();
+
+	let max_mipmap_levels = x_pow.min(y_pow).min(4);
+
+	if !mipmaps || max_mipmap_levels == 1 {
+		MipmapsOption::NoMipmap
+	} else {
+		MipmapsOption::AutoGeneratedMipmapsMax(max_mipmap_levels)
+		//MipmapsOption::AutoGeneratedMipmaps
+	}
+}
+
 /// img_bytes has to be an rgba8 buffer.
 #[allow(clippy::too_many_arguments)]
 fn texture_from_img_rect(
@@ -209,18 +361,18 @@ fn texture_from_img_rect(
 	offset_y: u32,
 	cell_w: u32,
 	cell_h: u32,
+	mipmaps: bool,
 ) -> TextureResult<SrgbTexture2d> {
-	let raw_image;
-	if img_w == cell_w {
+	let raw_image = if img_w == cell_w {
 		assert!(offset_x == 0);
 		let start = (offset_y as usize * img_w as usize) * 4;
 		let end = start + (cell_h as usize * cell_w as usize * 4);
-		raw_image = RawImage2d {
+		RawImage2d {
 			data: Cow::Borrowed(&img_bytes[start..end]),
 			format: glium::texture::ClientFormat::U8U8U8U8,
 			width: cell_w,
 			height: cell_h,
-		};
+		}
 	} else {
 		let cell_size = cell_w as usize * cell_h as usize * 4;
 		let mut cell_pixels = Vec::with_capacity(cell_size);
@@ -231,20 +383,10 @@ fn texture_from_img_rect(
 			let end = start + (cell_w as usize * 4);
 			cell_pixels.extend_from_slice(&img_bytes[start..end]);
 		}
-		raw_image = RawImage2d::from_raw_rgba(cell_pixels, (cell_w, cell_h));
-	}
-
-	let x_pow = 31 - img_w.leading_zeros();
-	let y_pow = 31 - img_h.leading_zeros();
-
-	let max_mipmap_levels = x_pow.min(y_pow).min(4);
-
-	let mipmaps = if max_mipmap_levels == 1 {
-		MipmapsOption::NoMipmap
-	} else {
-		MipmapsOption::AutoGeneratedMipmapsMax(max_mipmap_levels)
-		//MipmapsOption::AutoGeneratedMipmaps
+		RawImage2d::from_raw_rgba(cell_pixels, (cell_w, cell_h))
 	};
+
+	let mipmaps = mipmaps_option(img_w, img_h, mipmaps);
 	Ok(SrgbTexture2d::with_mipmaps(display, raw_image, mipmaps)?)
 }
 
@@ -261,14 +403,39 @@ struct CachedTexture {
 	/// If it's not fully loaded yet a `WaitingOnLoader` error is returned.
 	fully_loaded: bool,
 
-	/// - `false` if loading is still in progress or if succeeded.
-	/// - `true` if this failed to load,
-	failed: bool,
+	/// - `None` if loading is still in progress or if succeeded.
+	/// - The reason if this failed to load.
+	failure: Option<String>,
 
 	/// If the target file is an image this vector will have a single texture once the
 	/// image uploaded to the GPU. If the target file is an animated image like a gif,
 	/// these the frames
 	frames: Vec<AnimationFrameTexture>,
+	/// Shown while there are no frames yet
+	placeholder: Option<AnimationFrameTexture>,
+
+	info: Arc<ImageInfo>,
+}
+
+/// How much of the cache capacity the loaded images take up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+	/// The estimated number of bytes used by the loaded images
+	pub used: isize,
+	pub capacity: isize,
+	pub cached_images: usize,
+	pub pending_requests: usize,
+	pub queued_results: QueuedResults,
+	pub active_threads: u32,
+}
+
+/// The position of the shown frame in an animation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePosition {
+	pub index: usize,
+	/// The number of frames loaded so far
+	pub count: usize,
+	pub fully_loaded: bool,
 }
 
 /// The process of loading an image (or animation frame) consists of the following steps.
@@ -294,10 +461,33 @@ pub struct ImageCache {
 	remaining_capacity: isize,
 	total_capacity: isize,
 	curr_est_size: isize,
+	/// The maximum number of images that `prefetch_neighbors` may request. `None` means that
+	/// only the capacity of the cache limits prefetching.
+	max_prefetch_count: Option<usize>,
 
 	pending_requests: PendingRequests,
 	texture_cache: BTreeMap<u32, CachedTexture>,
 	loader: ImageLoader,
+
+	/// True if an upload ran out of video memory since the last call to
+	/// `take_video_memory_low`
+	video_memory_low: bool,
+
+	/// A large frame of a prefetched image that is uploaded over several calls of
+	/// `process_prefetched`
+	upload: Option<TextureUpload>,
+	/// The results of the request that came after the frame of `upload`. These are uploaded
+	/// once that's done.
+	upload_rest: Vec<LoadResult>,
+
+	/// The image that the difference mode compares the shown image with. It's kept while
+	/// other images are shown, and isn't counted against the capacity.
+	pinned: Option<AnimationFrameTexture>,
+
+	folder_end: FolderEnd,
+	/// Set after turning around at the last image with `FolderEnd::PingPong`, until turning
+	/// around at the first one
+	backwards: bool,
 }
 
 /// This is a store for the supported images loaded from a folder
@@ -305,25 +495,85 @@ pub struct ImageCache {
 /// The basic idea is to have a few images already in the memory while an image is shown on the screen
 impl ImageCache {
 	const MAX_PENDING_REQUESTS: usize = 5;
+	/// The number of prefetched load results that are received before uploading them
+	const MAX_BUFFERED_RESULTS: usize = 8;
 
 	/// # Arguments
 	/// * `capacity` - Number of bytes. The last image loaded will be the one at which the allocated memory reaches or exceeds capacity
-	pub fn new(capacity: isize, threads: u32) -> ImageCache {
+	/// * `proxy` - Wakes up the event loop when the opened folder changes
+	pub fn new(
+		capacity: isize,
+		threads: u32,
+		display: &gelatin::Display,
+		proxy: EventLoopProxy<()>,
+	) -> ImageCache {
 		ImageCache {
-			dir: Directory::new(),
+			dir: Directory::new(proxy),
 			//current_file_idx: 0,
 			current_frame_idx: 0,
 
 			remaining_capacity: capacity,
 			total_capacity: capacity,
 			curr_est_size: 1000, // 1 kb, an optimistic estimate for the image size before anything is loaded
+			max_prefetch_count: None,
 
 			pending_requests: PendingRequests::new(),
 			texture_cache: BTreeMap::new(),
-			loader: ImageLoader::new(threads),
+			loader: ImageLoader::new(threads, display),
+			video_memory_low: false,
+			upload: None,
+			upload_rest: Vec::new(),
+			pinned: None,
+			folder_end: FolderEnd::Wrap,
+			backwards: false,
 		}
 	}
 
+	pub fn set_max_prefetch_count(&mut self, count: Option<usize>) {
+		self.max_prefetch_count = count;
+	}
+
+	pub fn set_loader_threads(&mut self, count: u32) {
+		self.loader.set_active_threads(count);
+	}
+
+	/// Changes the number of bytes that the cache may use. If the cached images don't fit
+	/// anymore, the ones farthest from the current image are evicted on the next load.
+	pub fn set_capacity(&mut self, capacity: isize) {
+		self.remaining_capacity += capacity - self.total_capacity;
+		self.total_capacity = capacity;
+	}
+
+	/// Returns true if an image upload ran out of video memory since the last call.
+	/// Such an image is shown without mipmaps or downscaled, or not at all.
+	pub fn take_video_memory_low(&mut self) -> bool {
+		mem::take(&mut self.video_memory_low)
+	}
+
+	/// The number of images that have a cache entry, including the ones still being loaded
+	pub fn cached_image_count(&self) -> usize {
+		self.texture_cache.len()
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			used: self.total_capacity - self.remaining_capacity,
+			capacity: self.total_capacity,
+			cached_images: self.texture_cache.len(),
+			pending_requests: self.pending_requests.len(),
+			queued_results: self.loader.queued_results(),
+			active_threads: self.loader.active_threads(),
+		}
+	}
+
+	pub fn pin(&mut self, texture: Option<AnimationFrameTexture>) {
+		self.pinned = texture;
+	}
+
+	pub fn pinned(&self) -> Option<&AnimationFrameTexture> {
+		self.pinned.as_ref()
+	}
+
 	pub fn current_filename(&self) -> Option<OsString> {
 		self.dir.curr_filename()
 	}
@@ -358,6 +608,31 @@ impl ImageCache {
 		false
 	}
 
+	/// The placeholder of the current image while none of its frames are loaded yet
+	pub fn placeholder(&self) -> Option<AnimationFrameTexture> {
+		let desc = self.dir.curr_descriptor()?;
+		let tex = self.texture_cache.get(&desc.request_id)?;
+		if tex.failure.is_some() || !tex.frames.is_empty() {
+			return None;
+		}
+		tex.placeholder.clone()
+	}
+
+	/// Returns `None` if the current image is not an animation, or only its first frame is
+	/// loaded yet
+	pub fn current_frame(&self) -> Option<FramePosition> {
+		let desc = self.dir.curr_descriptor()?;
+		let img = self.texture_cache.get(&desc.request_id)?;
+		if img.frames.len() <= 1 {
+			return None;
+		}
+		Some(FramePosition {
+			index: self.current_frame_idx,
+			count: img.frames.len(),
+			fully_loaded: img.fully_loaded,
+		})
+	}
+
 	/// Fetches the contents of the folder and stores the list of image filenames to know which
 	/// files will be the next and previous.
 	///
@@ -381,6 +656,58 @@ impl ImageCache {
 		Ok(())
 	}
 
+	pub fn set_sort_order(&mut self, sort_order: SortOrder) -> directory::Result<()> {
+		self.dir.set_sort_order(sort_order)
+	}
+
+	pub fn set_folder_end(&mut self, folder_end: FolderEnd) {
+		self.folder_end = folder_end;
+		self.backwards = false;
+	}
+
+	pub fn folder_end(&self) -> FolderEnd {
+		self.folder_end
+	}
+
+	/// Returns true if the next image would be on the other end of the folder. That's the
+	/// first image while the folder is played backwards.
+	pub fn at_folder_end(&self) -> bool {
+		if self.backwards {
+			self.dir.is_at_first_image()
+		} else {
+			self.dir.is_at_last_image()
+		}
+	}
+
+	pub fn set_file_filter(&mut self, file_filter: FileFilter) -> directory::Result<()> {
+		self.dir.set_file_filter(file_filter)
+	}
+
+	pub fn set_metadata_filter(&mut self, filter: MetadataFilter) -> directory::Result<()> {
+		self.dir.set_metadata_filter(filter)
+	}
+
+	pub fn rename_file(&mut self, path: &Path, new_name: &OsStr) -> directory::Result<PathBuf> {
+		self.dir.rename_file(path, new_name)
+	}
+
+	/// Returns true if the files of the folder changed since the last call. Only the changes
+	/// made while the folder is watched are reported.
+	pub fn take_directory_changed(&self) -> bool {
+		self.dir.take_changed()
+	}
+
+	/// See `Directory::generation`
+	pub fn directory_generation(&self) -> u64 {
+		self.dir.generation()
+	}
+
+	/// Forgets all loaded images, so that they are decoded again when they are requested next.
+	pub fn clear_textures(&mut self) {
+		self.texture_cache.clear();
+		self.remaining_capacity = self.total_capacity;
+	}
+
 	pub fn load_at_index(
 		&mut self,
 		display: &gelatin::Display,
@@ -398,8 +725,7 @@ impl ImageCache {
 		Ok((path, result))
 	}
 
-	/// Returns `Err(errors::Error::from_kind(errors::ErrorKind::WaitingOnLoader))`
-	/// when the image
+	/// Returns `Err(TextureError::WaitingOnLoader)` when the image hasn't been loaded yet
 	pub fn load_specific(
 		&mut self,
 		display: &gelatin::Display,
@@ -428,9 +754,8 @@ impl ImageCache {
 			self.current_frame_idx = 0;
 		}
 		if self.dir.path() != parent {
-			let DirItem { path, request_id } = self.curr_dir_item().ok_or_else(|| {
-				TextureError::Other("Could not get path for current image".into())
-			})?;
+			let DirItem { path, request_id } =
+				self.curr_dir_item().ok_or(TextureError::NoCurrentImage)?;
 			self.send_request_for_file(path, request_id, RequestKind::Priority { display });
 			return Err(TextureError::WaitingOnLoader);
 		}
@@ -458,6 +783,7 @@ impl ImageCache {
 	fn refresh_cache(&mut self) {
 		trace!("Begin `refresh_cache`");
 		if let Some(curr_index) = self.dir.curr_img_index() {
+			self.cancel_stale_requests(curr_index);
 			let cache = mem::take(&mut self.texture_cache);
 
 			// Delete all entries that are outside the range of files around the current file
@@ -469,11 +795,16 @@ impl ImageCache {
 			sorted_files
 				.sort_unstable_by_key(|&(index, _)| (index as isize - curr_index as isize).abs());
 			self.remaining_capacity = self.total_capacity;
+			// The closest image is always kept, otherwise the current image would be loaded
+			// again and again when it doesn't fit into the capacity
+			let mut closest = true;
 			sorted_files.retain(|(_, (_, texture))| {
 				// TODO consider retaining individual frames.
 				let all_frames_size = get_anim_size_estimate(&texture.frames);
 
-				if self.remaining_capacity > (all_frames_size + self.curr_est_size) {
+				if mem::take(&mut closest)
+					|| self.remaining_capacity > (all_frames_size + self.curr_est_size)
+				{
 					self.remaining_capacity -= all_frames_size;
 					true
 				} else {
@@ -485,6 +816,26 @@ impl ImageCache {
 		}
 	}
 
+	/// Cancels the requests of the images that were skipped, so that the loader threads stop
+	/// decoding them. The current image and the ones after it that may be prefetched are kept.
+	fn cancel_stale_requests(&mut self, curr_index: usize) {
+		let needed: Vec<u32> = (curr_index..=curr_index + Self::MAX_PENDING_REQUESTS)
+			.filter_map(|index| self.dir.image_by_index(index).map(|item| item.request_id))
+			.collect();
+		let mut cancelled = Vec::new();
+		for (req_id, request) in self.pending_requests.iter_mut() {
+			if !needed.contains(req_id) && !request.is_cancelled() {
+				request.cancel();
+				cancelled.push(*req_id);
+			}
+		}
+		for req_id in cancelled {
+			// The frames that were already received would be taken for the whole image
+			self.texture_cache.remove(&req_id);
+			self.loader.finish_priority_request(req_id);
+		}
+	}
+
 	pub fn load_next(&mut self, display: &gelatin::Display) -> PathedTextureResult {
 		self.load_jump(display, 1, 0)
 	}
@@ -498,6 +849,7 @@ impl ImageCache {
 		file_jump_count: i32,
 		frame_jump_count: isize,
 	) -> PathedTextureResult {
+		let file_jump_count = self.apply_folder_end(file_jump_count);
 		if file_jump_count == 0 {
 			// Here, it is possible that the current image was already
 			// requested but not yet loaded.
@@ -536,10 +888,56 @@ impl ImageCache {
 		Ok((target_path, result))
 	}
 
+	/// Changes the direction or the length of the jump, so that it doesn't continue on the other
+	/// end of the folder unless `folder_end` allows it
+	fn apply_folder_end(&mut self, file_jump_count: i32) -> i32 {
+		if self.folder_end == FolderEnd::Wrap || file_jump_count == 0 {
+			return file_jump_count;
+		}
+		let count = if self.backwards { -file_jump_count } else { file_jump_count };
+		if count.abs() == 1 {
+			let (at_end, at_other_end) = if count > 0 {
+				(self.dir.is_at_last_image(), self.dir.is_at_first_image())
+			} else {
+				(self.dir.is_at_first_image(), self.dir.is_at_last_image())
+			};
+			if !at_end {
+				count
+			} else if self.folder_end == FolderEnd::PingPong && !at_other_end {
+				self.backwards = !self.backwards;
+				-count
+			} else {
+				0
+			}
+		} else if let (Some(curr_index), Some(img_count)) =
+			(self.dir.curr_img_index(), self.dir.image_count())
+		{
+			// Jumps over several images stop at the ends
+			let target_index =
+				(curr_index as isize + count as isize).clamp(0, img_count as isize - 1);
+			(target_index - curr_index as isize) as i32
+		} else {
+			count
+		}
+	}
+
 	fn receive_prefetched(&mut self) {
 		use std::sync::mpsc::TryRecvError;
 		loop {
-			match self.loader.try_recv_prefetched() {
+			let received = match self.loader.try_recv_priority() {
+				Err(TryRecvError::Empty) => {
+					// Leaving the rest in the queue makes the loader threads wait until
+					// these are uploaded
+					let buffered = self.pending_requests.buffered_result_count();
+					if buffered >= Self::MAX_BUFFERED_RESULTS {
+						debug!("Not receiving prefetched images, {} are waiting", buffered);
+						break;
+					}
+					self.loader.try_recv_prefetched()
+				}
+				received => received,
+			};
+			match received {
 				Ok(load_result) => {
 					self.pending_requests.add_load_result(load_result);
 				}
@@ -551,21 +949,22 @@ impl ImageCache {
 
 	pub fn process_prefetched(&mut self, display: &gelatin::Display) -> TextureResult<()> {
 		self.receive_prefetched();
+		if let Some(upload) = &mut self.upload {
+			if upload.step(display) {
+				return self.finish_upload(display);
+			}
+			return Ok(());
+		}
 		let mut uploaded_one = false;
 		let req_ids = self.pending_requests.get_all_ids();
 		let mut retval = Ok(());
 		for id in req_ids {
-			if let Some(results) = self.pending_requests.take_results(id) {
-				for result in results {
-					match self.upload_to_texture(display, result) {
-						Ok(_) => uploaded_one = true,
-						// it's okay to ignore if the image falied to load here, this is just pre-fetch.
-						// so we don't break the loop
-						Err(e) => {
-							retval = Err(e);
-						}
-					}
-				}
+			let results = self.pending_requests.take_results(id);
+			if let Some(results) = results.filter(|results| !results.is_empty()) {
+				// it's okay to ignore if the image falied to load here, this is just pre-fetch.
+				// so we don't break the loop
+				retval = self.upload_results(display, results);
+				uploaded_one = true;
 			}
 
 			// Clippy suggests an alternative that does not compile so we allow this case
@@ -580,6 +979,61 @@ impl ImageCache {
 		Ok(())
 	}
 
+	/// Uploads the results in order, and returns the last error. A large frame is uploaded over
+	/// several calls of `process_prefetched`, and the results after it wait until that's done.
+	fn upload_results(
+		&mut self,
+		display: &gelatin::Display,
+		results: Vec<LoadResult>,
+	) -> TextureResult<()> {
+		let mut retval = Ok(());
+		let mut results = results.into_iter();
+		while let Some(result) = results.next() {
+			let result = match result {
+				LoadResult::Frame { req_id, image, delay_nano, orientation }
+					if texture_upload::is_large(&image) && self.expects_frame(req_id) =>
+				{
+					match TextureUpload::new(display, req_id, image, delay_nano, orientation) {
+						Ok(upload) => {
+							self.upload = Some(upload);
+							self.upload_rest = results.collect();
+							return retval;
+						}
+						Err(err) => self.add_frame(req_id, Err(err)),
+					}
+				}
+				result => self.upload_to_texture(display, result),
+			};
+			if let Err(err) = result {
+				retval = Err(err);
+			}
+		}
+		retval
+	}
+
+	fn finish_upload(&mut self, display: &gelatin::Display) -> TextureResult<()> {
+		let mut upload = match self.upload.take() {
+			Some(upload) => upload,
+			None => return Ok(()),
+		};
+		let req_id = upload.req_id;
+		let rest = mem::take(&mut self.upload_rest);
+		let mut result = Ok(None);
+		if self.expects_frame(req_id) {
+			let upload_result = match upload.finish(display) {
+				Err(TextureError::OutOfMemory) => {
+					let TextureUpload { image, delay_nano, orientation, .. } = upload;
+					self.upload_frame(display, image, delay_nano, orientation)
+				}
+				upload_result => upload_result,
+			};
+			result = self.add_frame(req_id, upload_result);
+		}
+		let rest_result = self.upload_results(display, rest);
+		result?;
+		rest_result
+	}
+
 	/// This funciton will check if the image which we are at, is already avaialbe.
 	///
 	///
@@ -595,9 +1049,14 @@ impl ImageCache {
 		frame_id: isize,
 	) -> TextureResult<AnimationFrameTexture> {
 		trace!("Begin `try_getting_requested_image` in `image_cache`");
-		let DirItem { path, request_id: req_id } = self
-			.curr_dir_item()
-			.ok_or_else(|| TextureError::Other("Could not get path for current image".into()))?;
+		let DirItem { path, request_id: req_id } =
+			self.curr_dir_item().ok_or(TextureError::NoCurrentImage)?;
+
+		// The shown image can't wait for the upload to finish over several frames
+		if let Some(upload) = self.upload.as_mut().filter(|upload| upload.req_id == req_id) {
+			upload.upload_all(display);
+			self.finish_upload(display)?;
+		}
 
 		// Check if it's among the prefetched, and upload it, if it is
 		if let Some(results) = self.pending_requests.take_results(req_id) {
@@ -609,8 +1068,8 @@ impl ImageCache {
 
 		// Check if it is inside the texture cache first
 		if let Some(tex) = self.texture_cache.get(&req_id) {
-			if tex.failed {
-				return Err(TextureError::from_failed_request(req_id));
+			if let Some(reason) = &tex.failure {
+				return Err(TextureError::Failed { req_id, reason: reason.clone() });
 			}
 			let modified = fs::metadata(&path).ok().and_then(|m| m.modified().ok());
 			let mut get_from_cache = false;
@@ -626,12 +1085,8 @@ impl ImageCache {
 			if get_from_cache {
 				let count = tex.frames.len() as isize;
 				if tex.fully_loaded || (frame_id >= 0 && frame_id < count) {
-					let wrapped_id;
-					if frame_id < 0 {
-						wrapped_id = count + (frame_id % count);
-					} else {
-						wrapped_id = frame_id % count;
-					}
+					let wrapped_id =
+						if frame_id < 0 { count + (frame_id % count) } else { frame_id % count };
 					if let Some(frame) = tex.frames.get(wrapped_id as usize) {
 						self.current_frame_idx = wrapped_id as usize;
 						return Ok(frame.clone());
@@ -641,7 +1096,7 @@ impl ImageCache {
 			return Err(TextureError::WaitingOnLoader);
 		}
 		if self.pending_requests.contains(&req_id) {
-			PRIORITY_REQUEST_ID.store(req_id, Ordering::SeqCst);
+			self.loader.set_priority_request(req_id);
 			return Err(TextureError::WaitingOnLoader);
 		}
 		self.send_request_for_file(path, req_id, RequestKind::Priority { display });
@@ -650,6 +1105,43 @@ impl ImageCache {
 		Err(TextureError::WaitingOnLoader)
 	}
 
+	/// Uploads the frame to the GPU. When there isn't enough video memory, the cache
+	/// capacity is halved to evict the images farthest from the current one, then the
+	/// upload is retried first without mipmaps and then with the image downscaled.
+	fn upload_frame(
+		&mut self,
+		display: &gelatin::Display,
+		mut image: image::RgbaImage,
+		delay_nano: u64,
+		orientation: Orientation,
+	) -> TextureResult<AnimationFrameTexture> {
+		let mut mipmaps = true;
+		loop {
+			match AnimationFrameTexture::upload(display, &image, delay_nano, orientation, mipmaps) {
+				Err(TextureError::OutOfMemory) => (),
+				result => return result,
+			}
+			self.video_memory_low = true;
+			self.set_capacity(self.total_capacity / 2);
+			self.refresh_cache();
+			let (w, h) = image.dimensions();
+			if mipmaps {
+				warn!("Ran out of video memory, retrying the upload without mipmaps");
+				mipmaps = false;
+			} else if w.min(h) / 2 >= MIN_OUT_OF_MEMORY_DOWNSCALE_SIZE {
+				warn!("Ran out of video memory, retrying the upload at {}x{}", w / 2, h / 2);
+				image = image::imageops::resize(
+					&image,
+					w / 2,
+					h / 2,
+					image::imageops::FilterType::Triangle,
+				);
+			} else {
+				return Err(TextureError::OutOfMemory);
+			}
+		}
+	}
+
 	fn upload_to_texture(
 		&mut self,
 		display: &gelatin::Display,
@@ -657,7 +1149,7 @@ impl ImageCache {
 	) -> TextureResult<Option<AnimationFrameTexture>> {
 		use std::collections::btree_map::Entry;
 		match load_result {
-			LoadResult::Start { req_id, metadata } => {
+			LoadResult::Start { req_id, metadata, info } => {
 				let curr_mod_time = metadata.modified().ok();
 				if let Some(cancelled) = self.pending_requests.cancelled(&req_id) {
 					if cancelled {
@@ -673,8 +1165,10 @@ impl ImageCache {
 							needs_update: false,
 							fully_loaded: false,
 							mod_time: curr_mod_time,
-							failed: false,
+							failure: None,
 							frames: Vec::new(),
+							placeholder: None,
+							info,
 						});
 					}
 					Entry::Occupied(mut entry) => {
@@ -692,27 +1186,52 @@ impl ImageCache {
 							self.remaining_capacity += old_size_estimate;
 							let mut_entry = entry.get_mut();
 							mut_entry.frames.clear();
+							mut_entry.placeholder = None;
 							mut_entry.mod_time = curr_mod_time;
+							mut_entry.info = info;
 						}
 					}
 				}
 				Ok(None)
 			}
 			LoadResult::Frame { req_id, image, delay_nano, orientation } => {
-				if let Some(cancelled) = self.pending_requests.cancelled(&req_id) {
-					if cancelled {
-						return Ok(None);
-					}
-				} else {
+				if !self.expects_frame(req_id) {
+					return Ok(None);
+				}
+				let upload_result = self.upload_frame(display, image, delay_nano, orientation);
+				self.add_frame(req_id, upload_result)
+			}
+			LoadResult::TiledFrame { req_id, image, preview, delay_nano, orientation } => {
+				if !self.expects_frame(req_id) {
 					return Ok(None);
 				}
-				let size_estimate = get_image_size_estimate(image.width(), image.height());
+				let upload_result =
+					AnimationFrameTexture::tiled(display, image, &preview, delay_nano, orientation);
+				self.add_frame(req_id, upload_result)
+			}
+			LoadResult::FloatFrame { req_id, image, orientation, tone_map } => {
+				if !self.expects_frame(req_id) {
+					return Ok(None);
+				}
+				let upload_result =
+					AnimationFrameTexture::float(display, &image, orientation, tone_map);
+				self.add_frame(req_id, upload_result)
+			}
+			LoadResult::Placeholder { req_id, placeholder } => {
+				if !self.expects_frame(req_id) {
+					return Ok(None);
+				}
+				// The image is loaded anyway, so this isn't an error of the request
+				let mut texture = match AnimationFrameTexture::placeholder(display, &placeholder) {
+					Ok(texture) => texture,
+					Err(e) => {
+						warn!("Could not upload the placeholder of the image: {}", e);
+						return Ok(None);
+					}
+				};
 				if let Some(entry) = self.texture_cache.get_mut(&req_id) {
-					let anim_frame =
-						AnimationFrameTexture::from_image(display, image, delay_nano, orientation)?;
-					entry.frames.push(anim_frame.clone());
-					self.remaining_capacity -= size_estimate;
-					return Ok(Some(anim_frame));
+					texture.info = Some(entry.info.clone());
+					entry.placeholder = Some(texture);
 				}
 				Ok(None)
 			}
@@ -720,40 +1239,67 @@ impl ImageCache {
 				if let Some(tex) = self.texture_cache.get_mut(&req_id) {
 					tex.fully_loaded = true;
 				}
-				let _ = PRIORITY_REQUEST_ID.compare_exchange(
-					req_id,
-					NON_EXISTENT_REQUEST_ID,
-					Ordering::SeqCst,
-					Ordering::SeqCst,
-				);
+				self.loader.finish_priority_request(req_id);
 				self.pending_requests.set_finished(&req_id);
 				Ok(None)
 			}
-			LoadResult::Failed { req_id } => {
+			LoadResult::Failed { req_id, reason } => {
 				if let Some(tex) = self.texture_cache.get_mut(&req_id) {
 					tex.fully_loaded = true;
-					tex.failed = true;
+					tex.failure = Some(reason.clone());
 				}
-				let _ = PRIORITY_REQUEST_ID.compare_exchange(
-					req_id,
-					NON_EXISTENT_REQUEST_ID,
-					Ordering::SeqCst,
-					Ordering::SeqCst,
-				);
+				self.loader.finish_priority_request(req_id);
 				self.pending_requests.set_finished(&req_id);
-				Err(TextureError::from_failed_request(req_id))
+				Err(TextureError::Failed { req_id, reason })
 			}
 		}
 	}
 
+	/// Returns false if the frames of the request are not needed anymore
+	fn expects_frame(&self, req_id: u32) -> bool {
+		match self.pending_requests.cancelled(&req_id) {
+			Some(cancelled) => !cancelled && self.texture_cache.contains_key(&req_id),
+			None => false,
+		}
+	}
+
+	fn add_frame(
+		&mut self,
+		req_id: u32,
+		upload_result: TextureResult<AnimationFrameTexture>,
+	) -> TextureResult<Option<AnimationFrameTexture>> {
+		// Evicting entries to make room for the upload may have removed this one too
+		let entry = match self.texture_cache.get_mut(&req_id) {
+			Some(entry) => entry,
+			None => return Ok(None),
+		};
+		let mut anim_frame = match upload_result {
+			Ok(anim_frame) => anim_frame,
+			Err(err) => {
+				entry.fully_loaded = true;
+				entry.failure = Some(err.to_string());
+				return Err(err);
+			}
+		};
+		anim_frame.info = Some(entry.info.clone());
+		entry.frames.push(anim_frame.clone());
+		entry.placeholder = None;
+		self.remaining_capacity -= get_image_size_estimate(anim_frame.w, anim_frame.h);
+		Ok(Some(anim_frame))
+	}
+
 	pub fn prefetch_neighbors(&mut self) {
 		if let Some(mut index) = self.dir.curr_img_index() {
 			// Send enough load requests so that the estimated total will just fill the cache
 			let mut estimated_remaining_cap = self.remaining_capacity;
+			let max_index = self.max_prefetch_count.map(|count| index + count);
 
 			while estimated_remaining_cap > self.curr_est_size {
 				// Send a load request for the closest file not in the cache or outdated
 				index += 1;
+				if max_index.is_some_and(|max_index| index > max_index) {
+					break;
+				}
 				if self.prefetch_at_index(index) {
 					estimated_remaining_cap -= self.curr_est_size;
 				} else {
@@ -817,12 +1363,12 @@ impl ImageCache {
 			self.texture_cache.remove(&req_id);
 		}
 		if kind.priority() {
-			PRIORITY_REQUEST_ID.store(req_id, Ordering::SeqCst);
+			self.loader.set_priority_request(req_id);
 		}
 		if self.pending_requests.contains(&req_id) {
 			return false;
 		}
-		let request = LoadRequest { req_id, path: file_path };
+		let request = LoadRequest::new(req_id, file_path);
 		self.pending_requests.add_request(request.clone());
 		self.loader.send_load_request(request);
 		true
@@ -834,6 +1380,7 @@ impl ImageCache {
 		}
 		self.texture_cache.clear();
 		self.remaining_capacity = self.total_capacity;
+		self.backwards = false;
 
 		// Cancel all pending load requests
 		for (_, request) in self.pending_requests.iter_mut() {
@@ -894,10 +1441,7 @@ fn get_file_name_and_parent(path: &Path) -> std::io::Result<(OsString, PathBuf)>
 	let file_name = match path.file_name() {
 		Some(f) => f.to_owned(),
 		None => {
-			return Err(io::Error::new(
-				io::ErrorKind::Other,
-				format!("Could not get file name from path {:?}", path),
-			))
+			return Err(io::Error::other(format!("Could not get file name from path {:?}", path)))
 		}
 	};
 	let parent = match path.parent() {
@@ -911,10 +1455,10 @@ fn get_file_name_and_parent(path: &Path) -> std::io::Result<(OsString, PathBuf)>
 		None => {
 			let mut path = path.canonicalize()?;
 			if !path.pop() {
-				return Err(io::Error::new(
-					io::ErrorKind::Other,
-					format!("Could not get parent directory of {:?}", path),
-				));
+				return Err(io::Error::other(format!(
+					"Could not get parent directory of {:?}",
+					path
+				)));
 			}
 			path
 		}
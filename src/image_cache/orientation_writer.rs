@@ -0,0 +1,121 @@
+//! Writes the orientation of an image back into the file, without re-encoding the pixels.
+//!
+//! This only updates the value of an existing Exif orientation tag. Files that don't have
+//! such a tag are left untouched and an error is returned.
+
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::image_loader::Orientation;
+
+const ORIENTATION_TAG: u16 = 0x0112;
+const SHORT_TYPE: u16 = 3;
+
+fn other_err<S: Into<String>>(msg: S) -> io::Error {
+	io::Error::other(msg.into())
+}
+
+#[derive(Copy, Clone)]
+pub(super) enum ByteOrder {
+	Little,
+	Big,
+}
+
+impl ByteOrder {
+	pub(super) fn read_u16(self, bytes: &[u8], offset: usize) -> io::Result<u16> {
+		let b = bytes
+			.get(offset..offset + 2)
+			.ok_or_else(|| other_err("Unexpected end of Exif data"))?;
+		Ok(match self {
+			ByteOrder::Little => u16::from_le_bytes([b[0], b[1]]),
+			ByteOrder::Big => u16::from_be_bytes([b[0], b[1]]),
+		})
+	}
+
+	pub(super) fn read_u32(self, bytes: &[u8], offset: usize) -> io::Result<u32> {
+		let b = bytes
+			.get(offset..offset + 4)
+			.ok_or_else(|| other_err("Unexpected end of Exif data"))?;
+		Ok(match self {
+			ByteOrder::Little => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+			ByteOrder::Big => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+		})
+	}
+
+	fn u16_bytes(self, value: u16) -> [u8; 2] {
+		match self {
+			ByteOrder::Little => value.to_le_bytes(),
+			ByteOrder::Big => value.to_be_bytes(),
+		}
+	}
+}
+
+/// Returns the offset of the TIFF header within the file. This is where the Exif data starts.
+fn find_tiff_header(bytes: &[u8]) -> io::Result<usize> {
+	if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+		return Ok(0);
+	}
+	if !bytes.starts_with(&[0xFF, 0xD8]) {
+		return Err(other_err("Saving the orientation is only supported for JPEG and TIFF files"));
+	}
+	let mut pos = 2;
+	while pos + 4 <= bytes.len() {
+		if bytes[pos] != 0xFF {
+			break;
+		}
+		let marker = bytes[pos + 1];
+		// Start of scan or end of image. The metadata segments are all before these.
+		if marker == 0xDA || marker == 0xD9 {
+			break;
+		}
+		let seg_len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+		let seg_start = pos + 4;
+		if marker == 0xE1 && bytes[seg_start..].starts_with(b"Exif\0\0") {
+			return Ok(seg_start + 6);
+		}
+		pos += 2 + seg_len;
+	}
+	Err(other_err("The file doesn't contain Exif data"))
+}
+
+/// Returns the file offset of the value of the orientation entry in the first IFD.
+fn find_orientation_value(bytes: &[u8]) -> io::Result<(usize, ByteOrder)> {
+	let tiff_start = find_tiff_header(bytes)?;
+	let tiff = &bytes[tiff_start..];
+	let order = if tiff.starts_with(b"II") {
+		ByteOrder::Little
+	} else if tiff.starts_with(b"MM") {
+		ByteOrder::Big
+	} else {
+		return Err(other_err("Invalid TIFF header in the Exif data"));
+	};
+	let ifd_offset = order.read_u32(tiff, 4)? as usize;
+	let entry_count = order.read_u16(tiff, ifd_offset)? as usize;
+	for i in 0..entry_count {
+		let entry = ifd_offset + 2 + i * 12;
+		if order.read_u16(tiff, entry)? != ORIENTATION_TAG {
+			continue;
+		}
+		let value_type = order.read_u16(tiff, entry + 2)?;
+		let count = order.read_u32(tiff, entry + 4)?;
+		if value_type != SHORT_TYPE || count != 1 {
+			return Err(other_err("The Exif orientation tag has an unexpected format"));
+		}
+		// A single short fits into the 4 byte value field so it's stored inline.
+		return Ok((tiff_start + entry + 8, order));
+	}
+	Err(other_err(
+		"The file has no Exif orientation tag. Adding one or rotating the pixels is not supported",
+	))
+}
+
+/// Overwrites the Exif orientation tag of the file at `path` with `orientation`.
+pub fn save_orientation(path: &Path, orientation: Orientation) -> io::Result<()> {
+	let bytes = fs::read(path)?;
+	let (offset, order) = find_orientation_value(&bytes)?;
+	let mut file = fs::OpenOptions::new().write(true).open(path)?;
+	file.seek(SeekFrom::Start(offset as u64))?;
+	file.write_all(&order.u16_bytes(orientation.to_exif()))?;
+	file.flush()
+}
@@ -0,0 +1,345 @@
+//! Decodes huge images on several threads, so that they are shown sooner.
+//!
+//! JPEG files that have restart markers and full resolution chroma are split into strips of MCU
+//! rows at the markers. Each strip is turned into a small JPEG file of its own with the headers
+//! of the original file, and the strips are decoded at the same time. The compressed data of PNG
+//! files can only be inflated in order, so for those only the conversion of the decoded pixels
+//! is done in parallel.
+//!
+//! The strips are processed on a single pool of threads that all loader threads share, so
+//! decoding several large images at once doesn't start more threads than there are cores.
+
+use std::fs;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+use gelatin::image::{codecs::jpeg::JpegDecoder, DynamicImage, ImageDecoder, RgbaImage};
+use lazy_static::lazy_static;
+use log::warn;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use super::image_loader::{to_srgb_rgba, ImageLoaderError, Result};
+
+/// Images with fewer pixels than this are decoded on a single thread
+const MIN_PIXELS: u64 = 16 * 1_000_000;
+
+/// Zero means that the number of threads is the number of CPU cores
+static THREADS: AtomicU32 = AtomicU32::new(0);
+
+lazy_static! {
+	/// Has a thread for every CPU core. `THREADS` only limits how many strips an image is split
+	/// into.
+	static ref POOL: Option<ThreadPool> = ThreadPoolBuilder::new()
+		.thread_name(|index| format!("strip decoder {index}"))
+		.build()
+		.map_err(|e| warn!("Could not start the threads for decoding in parallel: {}", e))
+		.ok();
+}
+
+/// Uses as many threads as the image loader does
+pub fn set_thread_count(count: u32) {
+	THREADS.store(count, Ordering::Relaxed);
+}
+
+fn thread_count() -> usize {
+	match THREADS.load(Ordering::Relaxed) {
+		0 => thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+		count => count as usize,
+	}
+}
+
+pub fn is_large(width: u32, height: u32) -> bool {
+	width as u64 * height as u64 >= MIN_PIXELS
+}
+
+/// Splits `pixels` into strips of whole rows and calls `process` with each strip and the index
+/// of its first row. The strips are processed at the same time if the image is large.
+pub fn for_each_strip<F>(pixels: &mut [u8], row_len: usize, process: F)
+where
+	F: Fn(usize, &mut [u8]) + Sync,
+{
+	let rows = pixels.len() / row_len.max(1);
+	let threads = thread_count();
+	let pool = match POOL.as_ref() {
+		Some(pool) if threads > 1 && rows > 1 && (pixels.len() as u64 / 4) >= MIN_PIXELS => pool,
+		_ => {
+			process(0, pixels);
+			return;
+		}
+	};
+	let strip_rows = rows.div_ceil(threads);
+	let process = &process;
+	pool.scope(|scope| {
+		for (index, strip) in pixels.chunks_mut(strip_rows * row_len).enumerate() {
+			scope.spawn(move |_| process(index * strip_rows, strip));
+		}
+	});
+}
+
+/// Like `DynamicImage::into_rgba8`, but large RGB images are converted on several threads
+pub fn into_rgba8(image: DynamicImage) -> RgbaImage {
+	let rgb = match image {
+		DynamicImage::ImageRgb8(rgb) if is_large(rgb.width(), rgb.height()) => rgb,
+		image => return image.into_rgba8(),
+	};
+	let (w, h) = rgb.dimensions();
+	let mut pixels = vec![0; w as usize * h as usize * 4];
+	let src_row_len = w as usize * 3;
+	for_each_strip(&mut pixels, w as usize * 4, |first_row, strip| {
+		let src = &rgb.as_raw()[first_row * src_row_len..];
+		for (dst, src) in strip.chunks_exact_mut(4).zip(src.chunks_exact(3)) {
+			dst[..3].copy_from_slice(src);
+			dst[3] = 255;
+		}
+	});
+	RgbaImage::from_raw(w, h, pixels).unwrap()
+}
+
+/// Returns `None` if the file has no restart markers that the image could be split at. The
+/// image has to be decoded on a single thread then.
+pub fn load_jpeg(path: &Path) -> Result<Option<RgbaImage>> {
+	let pool = match POOL.as_ref() {
+		Some(pool) => pool,
+		None => return Ok(None),
+	};
+	let bytes = fs::read(path)?;
+	let layout = match JpegLayout::parse(&bytes) {
+		Some(layout) => layout,
+		None => return Ok(None),
+	};
+	let strips = match layout.strips(thread_count()) {
+		Some(strips) => strips,
+		None => return Ok(None),
+	};
+	let (w, h) = (layout.width, layout.height);
+	let row_len = w as usize * 4;
+	let mut pixels = vec![0; row_len * h as usize];
+	let mut results: Vec<Result<()>> = strips.iter().map(|_| Ok(())).collect();
+	pool.scope(|scope| {
+		let mut rest = pixels.as_mut_slice();
+		for (strip, result) in strips.iter().zip(results.iter_mut()) {
+			let (dst, next) = rest.split_at_mut(strip.height as usize * row_len);
+			rest = next;
+			let layout = &layout;
+			scope.spawn(move |_| {
+				let decode = AssertUnwindSafe(|| layout.decode_strip(strip, dst));
+				*result = panic::catch_unwind(decode).unwrap_or_else(|_| {
+					Err(ImageLoaderError::unsupported("A thread panicked while decoding a strip"))
+				});
+			});
+		}
+	});
+	for result in results {
+		result?;
+	}
+	Ok(RgbaImage::from_raw(w, h, pixels))
+}
+
+/// A range of MCU rows that is decoded on its own
+struct Strip {
+	/// The range of restart intervals that the strip consists of
+	first_interval: usize,
+	end_interval: usize,
+	/// In pixels
+	height: u32,
+}
+
+/// The parts of a baseline JPEG file that are needed to split it into strips
+struct JpegLayout<'a> {
+	/// Everything from the start of the file until the compressed data of the scan
+	header: &'a [u8],
+	/// The offset of the image height in `header`
+	height_pos: usize,
+	width: u32,
+	height: u32,
+	mcu_width: u32,
+	mcu_height: u32,
+	/// The number of MCUs between two restart markers
+	restart_interval: u32,
+	/// The compressed data between the restart markers
+	intervals: Vec<&'a [u8]>,
+}
+
+impl<'a> JpegLayout<'a> {
+	fn parse(bytes: &'a [u8]) -> Option<Self> {
+		if !bytes.starts_with(&[0xFF, 0xD8]) {
+			return None;
+		}
+		let mut pos = 2;
+		let mut sof = None;
+		let mut restart_interval = 0;
+		let (header_end, scan_components) = loop {
+			if *bytes.get(pos)? != 0xFF {
+				return None;
+			}
+			// Markers may be preceded by any number of fill bytes
+			while *bytes.get(pos + 1)? == 0xFF {
+				pos += 1;
+			}
+			let marker = bytes[pos + 1];
+			let len = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+			let segment = bytes.get(pos + 4..pos + 2 + len)?;
+			match marker {
+				// Baseline and extended sequential Huffman coding
+				0xC0 | 0xC1 => sof = Some((pos + 4, segment)),
+				// Progressive, lossless and arithmetic coding can't be split at restart markers.
+				// C4, C8 and CC are other kinds of segments.
+				0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+				0xDD => {
+					restart_interval = u16::from_be_bytes([*segment.first()?, *segment.get(1)?]);
+				}
+				0xDA => break (pos + 2 + len, *segment.first()?),
+				0xD9 => return None,
+				_ => (),
+			}
+			pos += 2 + len;
+		};
+		let (sof_pos, sof) = sof?;
+		let precision = *sof.first()?;
+		let height = u16::from_be_bytes([*sof.get(1)?, *sof.get(2)?]) as u32;
+		let width = u16::from_be_bytes([*sof.get(3)?, *sof.get(4)?]) as u32;
+		let components = *sof.get(5)?;
+		// A zero height means that the height is only given after the scan
+		if precision != 8 || width == 0 || height == 0 || restart_interval == 0 {
+			return None;
+		}
+		// The components have to be in a single interleaved scan
+		if scan_components != components {
+			return None;
+		}
+		// Subsampled chroma is interpolated from the chroma of the neighbouring rows, which a strip
+		// decoded on its own doesn't have, so the edges of the strips would show. A single
+		// component is coded in 8x8 blocks regardless of its sampling factors.
+		if components > 1 {
+			for component in 0..components as usize {
+				if *sof.get(6 + component * 3 + 1)? != 0x11 {
+					return None;
+				}
+			}
+		}
+		let (mcu_width, mcu_height) = (8, 8);
+		let intervals = split_at_restart_markers(bytes.get(header_end..)?)?;
+		let layout = JpegLayout {
+			header: &bytes[..header_end],
+			height_pos: sof_pos + 1,
+			width,
+			height,
+			mcu_width,
+			mcu_height,
+			restart_interval: restart_interval as u32,
+			intervals,
+		};
+		let total_mcus = layout.mcus_per_row() as usize * layout.mcu_rows() as usize;
+		let interval_count = (total_mcus - 1) / restart_interval as usize + 1;
+		if layout.intervals.len() != interval_count {
+			return None;
+		}
+		Some(layout)
+	}
+
+	fn mcus_per_row(&self) -> u32 {
+		(self.width - 1) / self.mcu_width + 1
+	}
+
+	fn mcu_rows(&self) -> u32 {
+		(self.height - 1) / self.mcu_height + 1
+	}
+
+	/// Splits the image into at most `count` strips. A strip has to start at a restart marker,
+	/// which is also the start of an MCU row.
+	fn strips(&self, count: usize) -> Option<Vec<Strip>> {
+		let mcus_per_row = self.mcus_per_row() as usize;
+		let mcu_rows = self.mcu_rows() as usize;
+		let interval = self.restart_interval as usize;
+		// The number of MCU rows between the restart markers that start an MCU row
+		let row_step = interval / gcd(interval, mcus_per_row);
+		let strip_rows = (mcu_rows + count - 1) / count.max(1);
+		let strip_rows = strip_rows.div_ceil(row_step) * row_step;
+		if count <= 1 || strip_rows >= mcu_rows {
+			return None;
+		}
+		let mut strips = Vec::new();
+		let mut first_row = 0;
+		while first_row < mcu_rows {
+			let end_row = (first_row + strip_rows).min(mcu_rows);
+			let end_interval = if end_row == mcu_rows {
+				self.intervals.len()
+			} else {
+				end_row * mcus_per_row / interval
+			};
+			let y = first_row as u32 * self.mcu_height;
+			let height = (end_row as u32 * self.mcu_height).min(self.height) - y;
+			let first_interval = first_row * mcus_per_row / interval;
+			strips.push(Strip { first_interval, end_interval, height });
+			first_row = end_row;
+		}
+		Some(strips)
+	}
+
+	/// Decodes the strip into `dst`, which is the part of the RGBA pixels of the whole image
+	/// that the strip covers
+	fn decode_strip(&self, strip: &Strip, dst: &mut [u8]) -> Result<()> {
+		let intervals = &self.intervals[strip.first_interval..strip.end_interval];
+		let data_len: usize = intervals.iter().map(|interval| interval.len() + 2).sum();
+		let mut bytes = Vec::with_capacity(self.header.len() + data_len + 2);
+		bytes.extend_from_slice(self.header);
+		let height = (strip.height as u16).to_be_bytes();
+		bytes[self.height_pos..self.height_pos + 2].copy_from_slice(&height);
+		for (index, interval) in intervals.iter().enumerate() {
+			// The restart markers are numbered again, because decoders may check their order
+			if index > 0 {
+				bytes.extend_from_slice(&[0xFF, 0xD0 + ((index - 1) % 8) as u8]);
+			}
+			bytes.extend_from_slice(interval);
+		}
+		bytes.extend_from_slice(&[0xFF, 0xD9]);
+
+		let mut decoder = JpegDecoder::new(Cursor::new(bytes))?;
+		let icc_profile = decoder.icc_profile().unwrap_or(None);
+		let image = to_srgb_rgba(DynamicImage::from_decoder(decoder)?, icc_profile);
+		if image.dimensions() != (self.width, strip.height) {
+			return Err(ImageLoaderError::unsupported("A strip was decoded with the wrong size"));
+		}
+		dst.copy_from_slice(image.as_raw());
+		Ok(())
+	}
+}
+
+/// Returns the compressed data between the restart markers, or `None` if the scan isn't
+/// followed by the end of the image
+fn split_at_restart_markers(data: &[u8]) -> Option<Vec<&[u8]>> {
+	let mut intervals = Vec::new();
+	let mut start = 0;
+	let mut pos = 0;
+	loop {
+		pos += data.get(pos..)?.iter().position(|&b| b == 0xFF)?;
+		match *data.get(pos + 1)? {
+			// A zero byte after 0xFF means that 0xFF is part of the data
+			0x00 => pos += 2,
+			// Fill byte
+			0xFF => pos += 1,
+			0xD0..=0xD7 => {
+				intervals.push(&data[start..pos]);
+				pos += 2;
+				start = pos;
+			}
+			0xD9 => {
+				intervals.push(&data[start..pos]);
+				return Some(intervals);
+			}
+			// Another scan or a marker that would be lost by splitting the image
+			_ => return None,
+		}
+	}
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+	if b == 0 {
+		a
+	} else {
+		gcd(b, a % b)
+	}
+}
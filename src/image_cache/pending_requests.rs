@@ -9,7 +9,7 @@ use super::image_loader::*;
 /// See: `ImageCache::ongoing_requests`
 pub struct PendingRequestInfo {
 	//path: PathBuf,
-	cancelled: bool,
+	request: LoadRequest,
 	finished: bool,
 	// mod_time: Option<SystemTime>,
 
@@ -21,8 +21,13 @@ pub struct PendingRequestInfo {
 }
 
 impl PendingRequestInfo {
+	/// Also tells the loader threads to stop loading the image
 	pub fn cancel(&mut self) {
-		self.cancelled = true;
+		self.request.cancel();
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.request.is_cancelled()
 	}
 }
 
@@ -53,13 +58,18 @@ impl PendingRequests {
 		}
 	}
 
+	/// The number of load results that were received but not taken yet
+	pub fn buffered_result_count(&self) -> usize {
+		self.by_id.values().map(|info| info.results.len()).sum()
+	}
+
 	/// This returns all the ids including the finished item's
 	pub fn get_all_ids(&self) -> Vec<u32> {
 		self.by_id.keys().copied().collect()
 	}
 
 	pub fn cancelled(&self, id: &u32) -> Option<bool> {
-		self.get(id).map(|i| i.cancelled)
+		self.get(id).map(|i| i.is_cancelled())
 	}
 
 	pub fn contains(&self, id: &u32) -> bool {
@@ -99,7 +109,7 @@ impl PendingRequests {
 		self.by_id.insert(
 			request.req_id,
 			PendingRequestInfo {
-				cancelled: false,
+				request,
 				//path: request.path,
 				finished: false,
 				results: Vec::with_capacity(3),
@@ -0,0 +1,105 @@
+//! Reads back the pixels of the image textures from the GPU, so that the colors of the
+//! displayed image can be inspected.
+
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	rc::Rc,
+};
+
+use gelatin::{
+	glium::texture::RawImage2d,
+	image::{Rgba, RgbaImage},
+};
+
+use super::{color_management, AnimationFrameTexture, TextureGridItem};
+
+/// Keeps a CPU-side copy of the texture cells that were already read, because reading a
+/// texture back from the GPU is slow.
+#[derive(Default)]
+pub struct PixelReader {
+	/// The texture grid that the cells in `cells` were read from
+	tex_grid: Option<Rc<Vec<TextureGridItem>>>,
+	cells: HashMap<(u32, u32), RgbaImage>,
+}
+
+impl PixelReader {
+	pub fn new() -> PixelReader {
+		Default::default()
+	}
+
+	/// Returns the average color of the `size` x `size` texels centered on the texel at `x`,
+	/// `y`. The color components are linear and are between 0 and 1. These are the colors of the
+	/// image, not the ones that the textures were converted to for the display.
+	pub fn average_linear(
+		&mut self,
+		texture: &AnimationFrameTexture,
+		x: u32,
+		y: u32,
+		size: u32,
+	) -> Option<[f32; 4]> {
+		let same_grid = matches!(&self.tex_grid, Some(grid) if Rc::ptr_eq(grid, &texture.tex_grid));
+		if !same_grid {
+			self.cells.clear();
+			self.tex_grid = Some(texture.tex_grid.clone());
+		}
+		if x >= texture.w || y >= texture.h {
+			return None;
+		}
+		let radius = size / 2;
+		let mut samples = Vec::new();
+		for sample_y in y.saturating_sub(radius)..=(y + radius).min(texture.h - 1) {
+			for sample_x in x.saturating_sub(radius)..=(x + radius).min(texture.w - 1) {
+				if let Some(pixel) = self.pixel(texture, sample_x, sample_y) {
+					samples.extend_from_slice(&pixel.0);
+				}
+			}
+		}
+		let count = samples.len() / 4;
+		if count == 0 {
+			return None;
+		}
+		let mut samples = RgbaImage::from_raw(count as u32, 1, samples)?;
+		color_management::convert_from_display(&mut samples);
+		let mut sum = [0.0f32; 4];
+		for pixel in samples.pixels() {
+			sum[0] += srgb_to_linear(pixel[0]);
+			sum[1] += srgb_to_linear(pixel[1]);
+			sum[2] += srgb_to_linear(pixel[2]);
+			sum[3] += pixel[3] as f32 / 255.0;
+		}
+		Some(sum.map(|component| component / count as f32))
+	}
+
+	fn pixel(&mut self, texture: &AnimationFrameTexture, x: u32, y: u32) -> Option<Rgba<u8>> {
+		if let Some(tiled) = &texture.tiled {
+			return tiled.pixel(x, y);
+		}
+		let step = texture.cell_step_size;
+		let key = (x / step, y / step);
+		let cell = match self.cells.entry(key) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => {
+				let cell = texture.tex_grid.iter().find(|cell| (cell.col, cell.row) == key)?;
+				// The texture stores the sRGB encoded values, which is what we get back here
+				let raw: RawImage2d<u8> = cell.tex.read();
+				entry.insert(RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())?)
+			}
+		};
+		cell.get_pixel_checked(x - key.0 * step, y - key.1 * step).copied()
+	}
+}
+
+pub fn srgb_to_linear(value: u8) -> f32 {
+	let value = value as f32 / 255.0;
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+pub fn linear_to_srgb(value: f32) -> u8 {
+	let value =
+		if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+	(value * 255.0).round().clamp(0.0, 255.0) as u8
+}
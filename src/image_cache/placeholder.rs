@@ -0,0 +1,67 @@
+//! A blurry placeholder is shown in place of the image while it's decoded, so that jumping to an
+//! image that isn't loaded yet shows something right away. The placeholder is made from a
+//! thumbnail that's cheap to get: an up-to-date one from the thumbnail cache, or the one that
+//! cameras embed into the Exif data of JPEG files.
+
+use std::mem;
+use std::path::Path;
+
+use gelatin::image::{self, imageops, ImageFormat, RgbaImage};
+
+use super::image_info::read_exif;
+use super::image_loader::{detect_orientation, orientation_from_exif, Orientation};
+use super::thumbnail_cache;
+
+/// The longer side of the placeholder is at most this many pixels
+const PLACEHOLDER_SIZE: u32 = 32;
+
+/// In pixels of the placeholder
+const BLUR_SIGMA: f32 = 1.0;
+
+pub struct Placeholder {
+	pub image: RgbaImage,
+	/// The size of the full image, the placeholder is stretched over it
+	pub width: u32,
+	pub height: u32,
+	pub orientation: Orientation,
+}
+
+/// Returns `None` if there's no thumbnail of the image
+pub fn load(path: &Path) -> Option<Placeholder> {
+	let (mut width, mut height) = image::image_dimensions(path).ok()?;
+	let (thumbnail, orientation) = match thumbnail_cache::load_thumbnail(path) {
+		Some(thumbnail) => {
+			// The orientation of the image is applied to the thumbnail already
+			let orientation = detect_orientation(path).unwrap_or(Orientation::Deg0);
+			if orientation.swaps_dimensions() {
+				mem::swap(&mut width, &mut height);
+			}
+			(thumbnail, Orientation::Deg0)
+		}
+		None => exif_thumbnail(path)?,
+	};
+	let (thumb_w, thumb_h) = thumbnail.dimensions();
+	// Some cameras pad the Exif thumbnail to a different aspect ratio, which would look stretched
+	let ratio = (thumb_w as f64 * height as f64) / (thumb_h as f64 * width as f64);
+	if !(0.95..=1.05).contains(&ratio) {
+		return None;
+	}
+	let scale = (PLACEHOLDER_SIZE as f64 / thumb_w.max(thumb_h) as f64).min(1.0);
+	let small_w = ((thumb_w as f64 * scale) as u32).max(1);
+	let small_h = ((thumb_h as f64 * scale) as u32).max(1);
+	let small = imageops::thumbnail(&thumbnail, small_w, small_h);
+	let image = imageops::blur(&small, BLUR_SIGMA);
+	Some(Placeholder { image, width, height, orientation })
+}
+
+/// The thumbnail is stored with the same orientation as the image
+fn exif_thumbnail(path: &Path) -> Option<(RgbaImage, Orientation)> {
+	let exif = read_exif(path).ok()?;
+	let field = |tag: exif::Tag| exif.get_field(tag, exif::In::THUMBNAIL)?.value.get_uint(0);
+	let offset = field(exif::Tag::JPEGInterchangeFormat)? as usize;
+	let len = field(exif::Tag::JPEGInterchangeFormatLength)? as usize;
+	let bytes = exif.buf().get(offset..offset.checked_add(len)?)?;
+	let thumbnail = image::load_from_memory_with_format(bytes, ImageFormat::Jpeg).ok()?;
+	let orientation = orientation_from_exif(&exif).unwrap_or(Orientation::Deg0);
+	Some((thumbnail.into_rgba8(), orientation))
+}
@@ -0,0 +1,144 @@
+//! Finds the JPEG previews that cameras embed into their RAW files. Decoding the actual sensor
+//! data would be a lot slower, and the preview is good enough for flipping through a shoot.
+
+use std::io;
+use std::ops::Range;
+
+use super::orientation_writer::ByteOrder;
+
+pub const RAW_EXTENSIONS: &[&str] =
+	&["arw", "cr2", "dng", "nef", "nrw", "orf", "pef", "raf", "rw2", "sr2", "srf", "srw"];
+
+const NEW_SUBFILE_TYPE_TAG: u16 = 0x00FE;
+const COMPRESSION_TAG: u16 = 0x0103;
+const STRIP_OFFSETS_TAG: u16 = 0x0111;
+const STRIP_BYTE_COUNTS_TAG: u16 = 0x0117;
+const SUB_IFDS_TAG: u16 = 0x014A;
+const JPEG_OFFSET_TAG: u16 = 0x0201;
+const JPEG_LENGTH_TAG: u16 = 0x0202;
+/// Panasonic stores the preview in this tag of the first IFD
+const PANASONIC_JPEG_TAG: u16 = 0x002E;
+
+/// Compression values of baseline and "new style" JPEG
+const JPEG_COMPRESSIONS: &[u32] = &[6, 7];
+
+/// Protects against files where the IFDs refer to each other in a loop
+const MAX_IFD_COUNT: usize = 32;
+
+const FUJIFILM_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+
+/// Returns the byte ranges of the JPEG images embedded in the RAW file, largest first.
+pub fn preview_ranges(bytes: &[u8]) -> Vec<Range<usize>> {
+	let mut ranges = Vec::new();
+	if bytes.starts_with(FUJIFILM_MAGIC) {
+		let _ = fujifilm_jpeg_ranges(bytes, &mut ranges);
+	} else {
+		// The TIFF parser stops at the first invalid entry, but it still returns the previews
+		// that it found until that point
+		let _ = tiff_jpeg_ranges(bytes, &mut ranges);
+	}
+	ranges.retain(|range| {
+		let jpeg = bytes.get(range.clone());
+		range.start < range.end && jpeg.is_some_and(|jpeg| jpeg.starts_with(&[0xFF, 0xD8]))
+	});
+	ranges.sort_by_key(|range| std::cmp::Reverse(range.len()));
+	ranges.dedup();
+	ranges
+}
+
+/// Fujifilm files start with their own header, which gives where the JPEG preview is
+fn fujifilm_jpeg_ranges(bytes: &[u8], ranges: &mut Vec<Range<usize>>) -> io::Result<()> {
+	let order = ByteOrder::Big;
+	let offset = order.read_u32(bytes, 84)? as usize;
+	let length = order.read_u32(bytes, 88)? as usize;
+	ranges.push(offset..offset + length);
+	Ok(())
+}
+
+/// Walks the IFDs of a TIFF based RAW file (this includes most RAW formats) and collects the
+/// JPEG images that it finds.
+fn tiff_jpeg_ranges(bytes: &[u8], ranges: &mut Vec<Range<usize>>) -> io::Result<()> {
+	let order = if bytes.starts_with(b"II") {
+		ByteOrder::Little
+	} else if bytes.starts_with(b"MM") {
+		ByteOrder::Big
+	} else {
+		return Ok(());
+	};
+	let mut pending_ifds = vec![order.read_u32(bytes, 4)? as usize];
+	let mut visited_count = 0;
+	while let Some(ifd) = pending_ifds.pop() {
+		if ifd == 0 || visited_count >= MAX_IFD_COUNT {
+			continue;
+		}
+		visited_count += 1;
+		let mut jpeg_offset = None;
+		let mut jpeg_length = None;
+		let mut strip_offset = None;
+		let mut strip_length = None;
+		let mut compression = None;
+		let mut is_preview = true;
+		let entry_count = order.read_u16(bytes, ifd)? as usize;
+		for i in 0..entry_count {
+			let entry = ifd + 2 + i * 12;
+			let first_value = || entry_values(bytes, order, entry).map(|v| v.first().copied());
+			match order.read_u16(bytes, entry)? {
+				SUB_IFDS_TAG => {
+					let sub_ifds = entry_values(bytes, order, entry)?;
+					pending_ifds.extend(sub_ifds.into_iter().map(|ifd| ifd as usize));
+				}
+				JPEG_OFFSET_TAG => jpeg_offset = first_value()?,
+				JPEG_LENGTH_TAG => jpeg_length = first_value()?,
+				STRIP_OFFSETS_TAG => strip_offset = first_value()?,
+				STRIP_BYTE_COUNTS_TAG => strip_length = first_value()?,
+				COMPRESSION_TAG => compression = first_value()?,
+				// DNG files store the sensor data as lossless JPEG, which is marked as not
+				// being a reduced resolution image
+				NEW_SUBFILE_TYPE_TAG => is_preview = first_value()? != Some(0),
+				PANASONIC_JPEG_TAG => {
+					let offset = order.read_u32(bytes, entry + 8)? as usize;
+					let length = order.read_u32(bytes, entry + 4)? as usize;
+					ranges.push(offset..offset + length);
+				}
+				_ => (),
+			}
+		}
+		if let (Some(offset), Some(length)) = (jpeg_offset, jpeg_length) {
+			ranges.push(offset as usize..offset as usize + length as usize);
+		}
+		let is_jpeg = matches!(compression, Some(c) if JPEG_COMPRESSIONS.contains(&c));
+		if let (true, true, Some(offset), Some(length)) =
+			(is_jpeg, is_preview, strip_offset, strip_length)
+		{
+			ranges.push(offset as usize..offset as usize + length as usize);
+		}
+		pending_ifds.push(order.read_u32(bytes, ifd + 2 + entry_count * 12)? as usize);
+	}
+	Ok(())
+}
+
+/// Returns the values of an IFD entry that has SHORT, LONG or IFD values. Returns an empty
+/// vector for other types.
+fn entry_values(bytes: &[u8], order: ByteOrder, entry: usize) -> io::Result<Vec<u32>> {
+	const SHORT: u16 = 3;
+	const LONG: u16 = 4;
+	const IFD: u16 = 13;
+	let value_size = match order.read_u16(bytes, entry + 2)? {
+		SHORT => 2,
+		LONG | IFD => 4,
+		_ => return Ok(Vec::new()),
+	};
+	let count = order.read_u32(bytes, entry + 4)? as usize;
+	// The values are stored inline if they fit into the 4 byte value field
+	let start = if count.saturating_mul(value_size) <= 4 {
+		entry + 8
+	} else {
+		order.read_u32(bytes, entry + 8)? as usize
+	};
+	(0..count)
+		.map(|i| match value_size {
+			2 => order.read_u16(bytes, start + i * 2).map(u32::from),
+			_ => order.read_u32(bytes, start + i * 4),
+		})
+		.collect()
+}
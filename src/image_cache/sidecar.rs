@@ -0,0 +1,626 @@
+//! Reads and writes the rating and the tags of images in XMP sidecar files. These are the
+//! `xmp:Rating` and `dc:subject` properties, which most photo managers understand.
+//!
+//! An existing sidecar is updated in place, keeping everything else in it. Only the properties
+//! that were changed are rewritten, so values that Emulsion doesn't understand, like the
+//! rejected rating of -1, are kept. A new sidecar is named after the whole file name of the
+//! image, like `photo.jpg.xmp`, but sidecars named after the name without the extension, like
+//! `photo.xmp`, are also read and updated.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use quick_xml::{
+	escape::{escape, unescape},
+	events::{BytesEnd, BytesStart, Event},
+	name::{LocalName, Namespace, NamespaceResolver, QName, ResolveResult},
+	NsReader, Writer,
+};
+
+pub const MAX_RATING: u8 = 5;
+
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// The empty packet that new sidecars are created from
+const EMPTY_PACKET: &str = "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\"/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>
+";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImageMetadata {
+	/// From 1 to 5 stars, or 0 if the image is not rated
+	pub rating: u8,
+	pub tags: Vec<String>,
+}
+
+impl ImageMetadata {
+	/// Formats the rating as stars followed by the tags, like "★★★☆☆ beach, family"
+	pub fn describe(&self) -> String {
+		let mut text = String::new();
+		if self.rating > 0 {
+			let rating = self.rating.min(MAX_RATING) as usize;
+			text.push_str(&"★".repeat(rating));
+			text.push_str(&"☆".repeat(MAX_RATING as usize - rating));
+		}
+		if !self.tags.is_empty() {
+			if !text.is_empty() {
+				text.push(' ');
+			}
+			text.push_str(&self.tags.join(", "));
+		}
+		text
+	}
+
+	/// Adds the tag if the image doesn't have it, and removes it otherwise
+	pub fn toggle_tag(&mut self, tag: &str) {
+		match self.tags.iter().position(|t| t == tag) {
+			Some(index) => {
+				self.tags.remove(index);
+			}
+			None => self.tags.push(tag.to_owned()),
+		}
+	}
+}
+
+/// Decides which images are shown based on their rating and tags
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MetadataFilter {
+	pub min_rating: u8,
+	/// The images have to have all of these
+	pub tags: Vec<String>,
+}
+
+impl MetadataFilter {
+	pub fn is_empty(&self) -> bool {
+		self.min_rating == 0 && self.tags.is_empty()
+	}
+
+	/// Reads the sidecar of the image unless the filter is empty
+	pub fn matches(&self, image: &Path) -> bool {
+		if self.is_empty() {
+			return true;
+		}
+		let metadata = read(image);
+		metadata.rating >= self.min_rating
+			&& self.tags.iter().all(|tag| metadata.tags.contains(tag))
+	}
+}
+
+/// Returns the default metadata if the image has no sidecar or it can't be read
+pub fn read(image: &Path) -> ImageMetadata {
+	existing_sidecar(image)
+		.and_then(|path| fs::read_to_string(path).ok())
+		.and_then(|xmp| parse(&xmp).ok())
+		.unwrap_or_default()
+}
+
+/// Stores the metadata in the sidecar of the image, creating it if needed
+pub fn write(image: &Path, metadata: &ImageMetadata) -> io::Result<()> {
+	let (path, xmp) = match existing_sidecar(image) {
+		Some(path) => {
+			let xmp = fs::read_to_string(&path)?;
+			(path, xmp)
+		}
+		None => (new_sidecar_path(image), EMPTY_PACKET.to_owned()),
+	};
+	match update_packet(&xmp, metadata) {
+		Ok(updated) if updated == xmp => Ok(()),
+		Ok(updated) => fs::write(path, updated),
+		Err(e) => {
+			let message = format!("{:?}: {}", path, e);
+			Err(io::Error::new(io::ErrorKind::InvalidData, message))
+		}
+	}
+}
+
+fn new_sidecar_path(image: &Path) -> PathBuf {
+	let mut name = image.file_name().unwrap_or_default().to_owned();
+	name.push(".xmp");
+	image.with_file_name(name)
+}
+
+fn existing_sidecar(image: &Path) -> Option<PathBuf> {
+	let full_name = new_sidecar_path(image);
+	if full_name.is_file() {
+		return Some(full_name);
+	}
+	let stem_name = image.with_extension("xmp");
+	if stem_name.is_file() && stem_name != image {
+		return Some(stem_name);
+	}
+	None
+}
+
+#[derive(Debug, thiserror::Error)]
+enum PacketError {
+	#[error("{0}")]
+	Xml(#[from] quick_xml::Error),
+	#[error("{0}")]
+	Io(#[from] io::Error),
+	#[error("There's no rdf:Description")]
+	NoDescription,
+	#[error("The {0} prefix is used for another namespace")]
+	PrefixInUse(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Property {
+	Rating,
+	Subject,
+}
+
+fn is_named(name: &(ResolveResult, LocalName), namespace: &str, local_name: &str) -> bool {
+	let in_namespace = match &name.0 {
+		ResolveResult::Bound(Namespace(bound)) => *bound == namespace.as_bytes(),
+		_ => false,
+	};
+	in_namespace && name.1.as_ref() == local_name.as_bytes()
+}
+
+fn element_property(resolver: &NamespaceResolver, name: QName) -> Option<Property> {
+	let name = resolver.resolve_element(name);
+	if is_named(&name, XMP_NAMESPACE, "Rating") {
+		Some(Property::Rating)
+	} else if is_named(&name, DC_NAMESPACE, "subject") {
+		Some(Property::Subject)
+	} else {
+		None
+	}
+}
+
+fn is_description(resolver: &NamespaceResolver, name: QName) -> bool {
+	is_named(&resolver.resolve_element(name), RDF_NAMESPACE, "Description")
+}
+
+fn is_rating_attribute(resolver: &NamespaceResolver, name: QName) -> bool {
+	is_named(&resolver.resolve_attribute(name), XMP_NAMESPACE, "Rating")
+}
+
+/// The rating is a real number in XMP. Anything outside of 1 to 5, like -1 for rejected
+/// images, counts as not rated.
+fn parse_rating(rating: &str) -> u8 {
+	match rating.trim().parse::<f64>() {
+		Ok(rating) if rating >= 1.0 && rating <= MAX_RATING as f64 => rating.round() as u8,
+		_ => 0,
+	}
+}
+
+/// Reads the rating and the tags from any `rdf:Description` of the packet, whatever prefixes
+/// their namespaces are bound to
+fn parse(xmp: &str) -> quick_xml::Result<ImageMetadata> {
+	let mut reader = NsReader::from_str(xmp);
+	let mut metadata = ImageMetadata::default();
+	let mut depth = 0;
+	// The property element being read, the depth it's at, and the text read so far
+	let mut property: Option<(Property, usize, String)> = None;
+	loop {
+		let event = reader.read_event()?;
+		match &event {
+			Event::Start(start) | Event::Empty(start) => {
+				let resolver = reader.resolver();
+				if is_description(resolver, start.name()) {
+					for attribute in start.attributes() {
+						let attribute = attribute?;
+						if is_rating_attribute(resolver, attribute.key) {
+							metadata.rating = parse_rating(&unescape(&String::from_utf8_lossy(
+								&attribute.value,
+							))?);
+						}
+					}
+				}
+				if let Event::Start(_) = event {
+					if property.is_none() {
+						if let Some(found) = element_property(resolver, start.name()) {
+							property = Some((found, depth, String::new()));
+						}
+					}
+					depth += 1;
+				}
+			}
+			Event::End(end) => {
+				depth -= 1;
+				match property.take() {
+					Some((Property::Rating, start_depth, text)) if start_depth == depth => {
+						metadata.rating = parse_rating(&text);
+					}
+					Some((Property::Subject, start_depth, text)) => {
+						let name = reader.resolver().resolve_element(end.name());
+						let tag = text.trim();
+						if is_named(&name, RDF_NAMESPACE, "li") && !tag.is_empty() {
+							metadata.tags.push(tag.to_owned());
+						}
+						if start_depth != depth {
+							property = Some((Property::Subject, start_depth, String::new()));
+						}
+					}
+					other => property = other,
+				}
+			}
+			Event::Text(text) => {
+				if let Some((_, _, read)) = &mut property {
+					read.push_str(&text.decode()?);
+				}
+			}
+			Event::CData(text) => {
+				if let Some((_, _, read)) = &mut property {
+					read.push_str(&text.decode()?);
+				}
+			}
+			Event::GeneralRef(reference) => {
+				if let Some((_, _, read)) = &mut property {
+					read.push_str(&unescape(&format!("&{};", reference.decode()?))?);
+				}
+			}
+			Event::Eof => break,
+			_ => (),
+		}
+	}
+	Ok(metadata)
+}
+
+/// Replaces the rating and the tags in the packet if they changed, wherever they are. New
+/// values are put in the first `rdf:Description`.
+fn update_packet(xmp: &str, metadata: &ImageMetadata) -> Result<String, PacketError> {
+	let current = parse(xmp)?;
+	let update_rating = current.rating != metadata.rating;
+	let update_tags = current.tags != metadata.tags;
+	if !update_rating && !update_tags {
+		return Ok(xmp.to_owned());
+	}
+
+	let mut reader = NsReader::from_str(xmp);
+	let mut writer = Writer::new(Vec::with_capacity(xmp.len()));
+	// The reader skips the byte order mark
+	if xmp.starts_with('\u{FEFF}') {
+		writer.get_mut().extend_from_slice("\u{FEFF}".as_bytes());
+	}
+	let mut depth = 0;
+	// The depth of the property element that is being removed
+	let mut removing: Option<usize> = None;
+	// Whitespace is held back until the next event, because it's removed along with the
+	// property that follows it
+	let mut whitespace = None;
+	let mut added = false;
+	loop {
+		let event = reader.read_event()?;
+		if let Some(removed_depth) = removing {
+			match event {
+				Event::Start(_) => depth += 1,
+				Event::End(_) => {
+					depth -= 1;
+					if depth == removed_depth {
+						removing = None;
+					}
+				}
+				Event::Eof => break,
+				_ => (),
+			}
+			continue;
+		}
+		match event {
+			Event::Text(text) if text.iter().all(u8::is_ascii_whitespace) => {
+				if let Some(previous) = whitespace.replace(Event::Text(text)) {
+					writer.write_event(previous)?;
+				}
+				continue;
+			}
+			Event::Start(ref start) | Event::Empty(ref start) => {
+				let is_empty = matches!(event, Event::Empty(_));
+				let resolver = reader.resolver();
+				let remove = match element_property(resolver, start.name()) {
+					Some(Property::Rating) => update_rating,
+					Some(Property::Subject) => update_tags,
+					None => false,
+				};
+				if remove {
+					whitespace = None;
+					if !is_empty {
+						removing = Some(depth);
+						depth += 1;
+					}
+					continue;
+				}
+				if !is_empty {
+					depth += 1;
+				}
+				if is_description(resolver, start.name()) && (!added || update_rating) {
+					let add = !added;
+					added = true;
+					let (start, contents) = update_description(
+						resolver,
+						start,
+						metadata,
+						update_rating,
+						add,
+						update_tags,
+					)?;
+					if let Some(whitespace) = whitespace.take() {
+						writer.write_event(whitespace)?;
+					}
+					if is_empty && !contents.is_empty() {
+						let end =
+							BytesEnd::new(String::from_utf8_lossy(start.name().0).into_owned());
+						writer.write_event(Event::Start(start))?;
+						writer.get_mut().extend_from_slice(contents.as_bytes());
+						// Indented like the packets that Emulsion creates
+						writer.get_mut().extend_from_slice(b"\n  ");
+						writer.write_event(Event::End(end))?;
+					} else if is_empty {
+						writer.write_event(Event::Empty(start))?;
+					} else {
+						writer.write_event(Event::Start(start))?;
+						writer.get_mut().extend_from_slice(contents.as_bytes());
+					}
+					continue;
+				}
+			}
+			Event::End(_) => depth -= 1,
+			Event::Eof => break,
+			_ => (),
+		}
+		if let Some(whitespace) = whitespace.take() {
+			writer.write_event(whitespace)?;
+		}
+		writer.write_event(event)?;
+	}
+	if let Some(whitespace) = whitespace {
+		writer.write_event(whitespace)?;
+	}
+	if !added {
+		return Err(PacketError::NoDescription);
+	}
+	Ok(String::from_utf8(writer.into_inner()).expect("The packet is UTF-8"))
+}
+
+/// Returns the start tag of the `rdf:Description` without the rating if it's updated. If
+/// `add` is set, the new rating is added to it along with the namespaces that are needed,
+/// and the elements of the new tags are returned to be put in it.
+fn update_description(
+	resolver: &NamespaceResolver,
+	start: &BytesStart,
+	metadata: &ImageMetadata,
+	update_rating: bool,
+	add: bool,
+	update_tags: bool,
+) -> Result<(BytesStart<'static>, String), PacketError> {
+	let name = String::from_utf8_lossy(start.name().0).into_owned();
+	let raw_attributes = String::from_utf8_lossy(start.attributes_raw());
+	let mut tag = name.clone();
+	// New attributes are separated like the last one, which keeps them on separate lines if
+	// the others are
+	let mut separator = " ";
+	let mut rest = raw_attributes.as_ref();
+	for attribute in start.attributes() {
+		let attribute = attribute.map_err(quick_xml::Error::from)?;
+		// The attributes are well-formed, so this finds the end of the quoted value
+		let key_start = rest.len() - rest.trim_start().len();
+		let after_key = rest[key_start + attribute.key.0.len()..].trim_start();
+		let value = after_key[1..].trim_start();
+		let quote = &value[..1];
+		let end = rest.len() - value.len() + 1 + value[1..].find(quote).unwrap_or(0) + 1;
+		let (raw_attribute, remaining) = rest.split_at(end);
+		rest = remaining;
+		if update_rating && is_rating_attribute(resolver, attribute.key) {
+			continue;
+		}
+		tag.push_str(raw_attribute);
+		if key_start > 0 {
+			separator = &raw_attribute[..key_start];
+		}
+	}
+	let mut push_attribute = |key: &str, value: &str| {
+		tag.push_str(separator);
+		tag.push_str(&format!("{}=\"{}\"", key, value));
+	};
+
+	let mut contents = String::new();
+	if add && update_rating && metadata.rating > 0 {
+		if needs_declaration(resolver, "xmp", XMP_NAMESPACE)? {
+			push_attribute("xmlns:xmp", XMP_NAMESPACE);
+		}
+		push_attribute("xmp:Rating", &metadata.rating.to_string());
+	}
+	if add && update_tags && !metadata.tags.is_empty() {
+		if needs_declaration(resolver, "dc", DC_NAMESPACE)? {
+			push_attribute("xmlns:dc", DC_NAMESPACE);
+		}
+		// The prefix of the description is bound to the RDF namespace
+		let rdf = match name.find(':') {
+			Some(colon) => &name[..=colon],
+			None => "",
+		};
+		contents.push_str(&format!("\n   <dc:subject>\n    <{}Bag>\n", rdf));
+		for tag in metadata.tags.iter() {
+			contents.push_str(&format!("     <{0}li>{1}</{0}li>\n", rdf, escape(tag.as_str())));
+		}
+		contents.push_str(&format!("    </{}Bag>\n   </dc:subject>", rdf));
+	}
+	// The whitespace before the end of the tag
+	tag.push_str(rest);
+	let name_len = name.len();
+	Ok((BytesStart::from_content(tag, name_len), contents))
+}
+
+/// Returns true if the prefix has to be declared before it's used for the namespace
+fn needs_declaration(
+	resolver: &NamespaceResolver,
+	prefix: &'static str,
+	namespace: &str,
+) -> Result<bool, PacketError> {
+	let name = format!("{}:name", prefix);
+	match resolver.resolve_attribute(QName(name.as_bytes())).0 {
+		ResolveResult::Bound(Namespace(bound)) if bound == namespace.as_bytes() => Ok(false),
+		ResolveResult::Bound(_) => Err(PacketError::PrefixInUse(prefix)),
+		_ => Ok(true),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Written by Lightroom Classic for a rejected raw file
+	const LIGHTROOM: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="Adobe XMP Core 7.0-c000 1.000000, 0000/00/00-00:00:00        ">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:crs="http://ns.adobe.com/camera-raw-settings/1.0/"
+   xmp:ModifyDate="2023-06-11T14:02:31+02:00"
+   xmp:Rating="-1"
+   xmp:Label="Red"
+   tiff:Make="FUJIFILM"
+   tiff:Model="X-T3"
+   crs:Version="15.3"
+   crs:WhiteBalance="As Shot"
+   crs:Exposure2012="+0.35">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>beach</rdf:li>
+     <rdf:li>Tom &amp; Jerry</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <crs:ToneCurvePV2012>
+    <rdf:Seq>
+     <rdf:li>0, 0</rdf:li>
+     <rdf:li>255, 255</rdf:li>
+    </rdf:Seq>
+   </crs:ToneCurvePV2012>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+	/// Written by darktable, which uses several descriptions and the `xap` prefix
+	const DARKTABLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    xmlns:darktable="http://darktable.sf.net/"
+   exif:DateTimeOriginal="2022:08:20 17:45:12"
+   darktable:import_timestamp="63797568000000000"
+   darktable:xmp_version="5"
+   darktable:history_end="1">
+   <darktable:history>
+    <rdf:Seq>
+     <rdf:li
+      darktable:num="0"
+      darktable:operation="exposure"
+      darktable:enabled="1"
+      darktable:params="0000000000000000000080bf"/>
+    </rdf:Seq>
+   </darktable:history>
+  </rdf:Description>
+  <rdf:Description rdf:about=""
+    xmlns:xap="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/">
+   <xap:Rating>3</xap:Rating>
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>darktable|format|nef</rdf:li>
+     <rdf:li>family</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+	fn metadata(rating: u8, tags: &[&str]) -> ImageMetadata {
+		ImageMetadata { rating, tags: tags.iter().map(|&tag| tag.to_owned()).collect() }
+	}
+
+	#[test]
+	fn reads_lightroom_sidecar() {
+		// Rejected images count as not rated
+		assert_eq!(parse(LIGHTROOM).unwrap(), metadata(0, &["beach", "Tom & Jerry"]));
+	}
+
+	#[test]
+	fn reads_darktable_sidecar() {
+		assert_eq!(parse(DARKTABLE).unwrap(), metadata(3, &["darktable|format|nef", "family"]));
+	}
+
+	#[test]
+	fn unchanged_metadata_leaves_packet_unchanged() {
+		for xmp in [LIGHTROOM, DARKTABLE, EMPTY_PACKET].iter() {
+			let current = parse(xmp).unwrap();
+			assert_eq!(update_packet(xmp, &current).unwrap(), *xmp);
+		}
+	}
+
+	#[test]
+	fn changing_tags_keeps_rejected_rating() {
+		let changed = metadata(0, &["beach", "Tom & Jerry", "sunset"]);
+		let updated = update_packet(LIGHTROOM, &changed).unwrap();
+		assert_eq!(parse(&updated).unwrap(), changed);
+		assert!(updated.contains(r#"xmp:Rating="-1""#));
+		assert!(updated.contains("<rdf:li>Tom &amp; Jerry</rdf:li>"));
+		// Everything else is kept as it was
+		let unchanged_start = LIGHTROOM.find("   <crs:ToneCurvePV2012>").unwrap();
+		assert!(updated.contains(&LIGHTROOM[unchanged_start..]));
+		assert!(
+			updated.contains("    xmlns:crs=\"http://ns.adobe.com/camera-raw-settings/1.0/\"\n")
+		);
+		assert!(updated.contains(r#"crs:Exposure2012="+0.35""#));
+	}
+
+	#[test]
+	fn rating_replaces_rejected_rating() {
+		let changed = metadata(5, &["beach", "Tom & Jerry"]);
+		let updated = update_packet(LIGHTROOM, &changed).unwrap();
+		assert_eq!(parse(&updated).unwrap(), changed);
+		assert!(!updated.contains(r#"xmp:Rating="-1""#));
+		assert!(updated.contains("\n   xmp:Rating=\"5\""));
+		assert!(updated.contains(r#"xmp:Label="Red""#));
+	}
+
+	#[test]
+	fn updates_darktable_sidecar() {
+		let changed = metadata(4, &["family"]);
+		let updated = update_packet(DARKTABLE, &changed).unwrap();
+		assert_eq!(parse(&updated).unwrap(), changed);
+		assert!(!updated.contains("<xap:Rating>"));
+		// The rating element is replaced by an attribute of the first description
+		assert!(updated.contains("xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\""));
+		let history_start = DARKTABLE.find("   <darktable:history>").unwrap();
+		let history_end = DARKTABLE.find("</darktable:history>").unwrap();
+		assert!(updated.contains(&DARKTABLE[history_start..history_end]));
+	}
+
+	#[test]
+	fn removing_everything_removes_properties() {
+		let updated = update_packet(DARKTABLE, &ImageMetadata::default()).unwrap();
+		assert_eq!(parse(&updated).unwrap(), ImageMetadata::default());
+		assert!(!updated.contains("Rating"));
+		assert!(!updated.contains("dc:subject"));
+		assert!(updated.contains("darktable:history_end=\"1\""));
+	}
+
+	#[test]
+	fn fills_empty_packet() {
+		let changed = metadata(2, &["<tag>"]);
+		let updated = update_packet(EMPTY_PACKET, &changed).unwrap();
+		assert_eq!(parse(&updated).unwrap(), changed);
+		assert!(updated.starts_with("<?xpacket begin=\"\u{FEFF}\""));
+		assert!(updated.contains("<rdf:li>&lt;tag&gt;</rdf:li>"));
+		let cleared = update_packet(&updated, &ImageMetadata::default()).unwrap();
+		assert_eq!(parse(&cleared).unwrap(), ImageMetadata::default());
+	}
+
+	#[test]
+	fn packet_without_description_is_rejected() {
+		let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"/>"#;
+		assert!(update_packet(xmp, &metadata(1, &[])).is_err());
+	}
+}
@@ -0,0 +1,439 @@
+//! Decodes the texture files of games and 3D applications: DDS, KTX and KTX2. These can hold
+//! several mip levels, array layers, and cubemap faces, of which one is displayed at a time.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use gelatin::image::{imageops, RgbaImage};
+use lazy_static::lazy_static;
+
+use super::decode_limit::{self, DecodeDecision, MAX_DOWNSCALE_DECODE_FACTOR};
+use super::image_loader::{downscaled_size, ImageLoaderError, Result};
+
+pub const TEXTURE_EXTENSIONS: &[&str] = &["dds", "ktx", "ktx2"];
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX_MAGIC: &[u8] = &[0xAB, b'K', b'T', b'X', b' ', b'1', b'1', 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_MAGIC: &[u8] = &[0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// The most array layers that graphics APIs allow, times the cubemap faces. The header of a
+/// broken file may claim many more, and an offset is stored for each.
+const MAX_LAYER_COUNT: u32 = 2048 * 6;
+
+/// The mip level and the layer that are displayed. This applies to every texture file, and is
+/// clamped to the levels and layers that the file has.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+	pub level: u32,
+	/// The index of the array layer times the face count, plus the index of the cubemap face
+	pub layer: u32,
+}
+
+lazy_static! {
+	static ref SELECTION: Mutex<Selection> = Mutex::new(Selection::default());
+}
+
+pub fn selection() -> Selection {
+	*SELECTION.lock().unwrap()
+}
+
+/// The images have to be loaded again for this to take effect
+pub fn set_selection(selection: Selection) {
+	*SELECTION.lock().unwrap() = selection;
+}
+
+/// The levels and layers of a texture file, and which of them is displayed
+#[derive(Debug, Clone, Copy)]
+pub struct TextureInfo {
+	pub level: u32,
+	pub level_count: u32,
+	pub layer: u32,
+	pub layer_count: u32,
+}
+
+/// Returns `None` if the file is not a texture file that can be displayed
+pub fn read_info(path: &Path) -> Option<TextureInfo> {
+	let bytes = fs::read(path).ok()?;
+	let layout = parse_layout(&bytes).ok()?;
+	let selection = layout.clamp(selection());
+	Some(TextureInfo {
+		level: selection.level,
+		level_count: layout.level_count,
+		layer: selection.layer,
+		layer_count: layout.layer_count,
+	})
+}
+
+/// Decodes the selected level and layer of the texture
+pub fn load(path: &Path) -> Result<RgbaImage> {
+	let bytes = fs::read(path)?;
+	let layout = parse_layout(&bytes)?;
+	let selection = layout.clamp(selection());
+	let width = (layout.width >> selection.level).max(1);
+	let height = (layout.height >> selection.level).max(1);
+	// The compressed formats can only be decoded at full size
+	let pixels = width as u64 * height as u64;
+	let new_size = match decode_limit::decide(path, width, height) {
+		DecodeDecision::Full => None,
+		DecodeDecision::Downscale { max_pixels }
+			if pixels <= max_pixels * MAX_DOWNSCALE_DECODE_FACTOR =>
+		{
+			Some(downscaled_size(width, height, max_pixels))
+		}
+		DecodeDecision::Downscale { .. } => {
+			return Err(ImageLoaderError::TooLargeToDownscale { width, height })
+		}
+		DecodeDecision::Refuse => return Err(ImageLoaderError::TooLarge { width, height }),
+	};
+	let start = layout.offsets[(selection.level * layout.layer_count + selection.layer) as usize];
+	let data = layout
+		.level_size(selection.level)
+		.ok()
+		.and_then(|size| bytes.get(start..start.checked_add(size)?))
+		.ok_or_else(|| ImageLoaderError::unsupported("The texture file is truncated"))?;
+	let image = decode(layout.format, data, width, height)?;
+	Ok(match new_size {
+		Some((new_w, new_h)) => imageops::thumbnail(&image, new_w, new_h),
+		None => image,
+	})
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PixelFormat {
+	Rgba8,
+	Bgra8,
+	/// 32 bits per pixel, with the bits of the red, green, blue, and alpha channels given by
+	/// masks. A zero alpha mask means that the texture is opaque.
+	Masked32([u32; 4]),
+	Bc1,
+	Bc3,
+	Bc4,
+	Bc5,
+	Bc6h,
+	Bc7,
+}
+
+impl PixelFormat {
+	/// The number of bytes that an image of this size takes. `None` if that doesn't fit in
+	/// `usize`.
+	fn level_size(self, width: u32, height: u32) -> Option<usize> {
+		let block_count = (width.div_ceil(4) as usize).checked_mul(height.div_ceil(4) as usize)?;
+		match self {
+			PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Masked32(_) => {
+				(width as usize).checked_mul(height as usize)?.checked_mul(4)
+			}
+			PixelFormat::Bc1 | PixelFormat::Bc4 => block_count.checked_mul(8),
+			PixelFormat::Bc3 | PixelFormat::Bc5 | PixelFormat::Bc6h | PixelFormat::Bc7 => {
+				block_count.checked_mul(16)
+			}
+		}
+	}
+}
+
+struct Layout {
+	format: PixelFormat,
+	width: u32,
+	height: u32,
+	level_count: u32,
+	/// The array layers times the cubemap faces
+	layer_count: u32,
+	/// Where the data of each image starts, indexed by `level * layer_count + layer`
+	offsets: Vec<usize>,
+}
+
+impl Layout {
+	/// The level count is limited to the levels that the size allows, because each level
+	/// halves the size until it reaches 1 by 1
+	fn new(
+		format: PixelFormat,
+		width: u32,
+		height: u32,
+		level_count: u32,
+		layer_count: Option<u32>,
+	) -> Result<Self> {
+		if width == 0 || height == 0 {
+			return Err(ImageLoaderError::unsupported("The texture has no pixels"));
+		}
+		let layer_count = layer_count
+			.filter(|&count| count <= MAX_LAYER_COUNT)
+			.ok_or_else(|| ImageLoaderError::unsupported("The texture has too many layers"))?;
+		let level_count = level_count.min(32 - width.max(height).leading_zeros());
+		let offsets = vec![0; (level_count * layer_count) as usize];
+		Ok(Layout { format, width, height, level_count, layer_count, offsets })
+	}
+
+	fn level_size(&self, level: u32) -> Result<usize> {
+		let width = (self.width >> level).max(1);
+		let height = (self.height >> level).max(1);
+		self.format.level_size(width, height).ok_or_else(too_large)
+	}
+
+	fn clamp(&self, selection: Selection) -> Selection {
+		Selection {
+			level: selection.level.min(self.level_count - 1),
+			layer: selection.layer.min(self.layer_count - 1),
+		}
+	}
+}
+
+fn too_large() -> ImageLoaderError {
+	ImageLoaderError::unsupported("The size of the texture is invalid")
+}
+
+/// Checked, because the offsets are read from the file
+fn add_offset(offset: usize, size: usize) -> Result<usize> {
+	offset.checked_add(size).ok_or_else(too_large)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+	match bytes.get(offset..offset + 4) {
+		Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+		None => Err(ImageLoaderError::unsupported("The texture file is truncated")),
+	}
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+	Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+fn parse_layout(bytes: &[u8]) -> Result<Layout> {
+	if bytes.starts_with(DDS_MAGIC) {
+		parse_dds(bytes)
+	} else if bytes.starts_with(KTX_MAGIC) {
+		parse_ktx(bytes)
+	} else if bytes.starts_with(KTX2_MAGIC) {
+		parse_ktx2(bytes)
+	} else {
+		Err(ImageLoaderError::unsupported("Not a DDS, KTX, or KTX2 file"))
+	}
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<Layout> {
+	const FOURCC_FLAG: u32 = 0x4;
+	const RGB_FLAG: u32 = 0x40;
+	const CUBEMAP_CAPS: u32 = 0x200;
+	const CUBEMAP_MISC_FLAG: u32 = 0x4;
+
+	let height = read_u32(bytes, 12)?;
+	let width = read_u32(bytes, 16)?;
+	let depth = read_u32(bytes, 24)?;
+	let level_count = read_u32(bytes, 28)?.max(1);
+	let pixel_flags = read_u32(bytes, 80)?;
+	let four_cc = bytes
+		.get(84..88)
+		.ok_or_else(|| ImageLoaderError::unsupported("The texture file is truncated"))?;
+	let caps2 = read_u32(bytes, 112)?;
+	if depth > 1 {
+		return Err(ImageLoaderError::unsupported("Volume textures are not supported"));
+	}
+	let mut data_start = 128;
+	let mut layer_count = Some(if caps2 & CUBEMAP_CAPS != 0 { 6 } else { 1 });
+	let format = if pixel_flags & FOURCC_FLAG != 0 {
+		match four_cc {
+			b"DXT1" => PixelFormat::Bc1,
+			b"DXT4" | b"DXT5" => PixelFormat::Bc3,
+			b"ATI1" | b"BC4U" => PixelFormat::Bc4,
+			b"ATI2" | b"BC5U" => PixelFormat::Bc5,
+			b"DX10" => {
+				let dxgi_format = read_u32(bytes, 128)?;
+				let misc_flag = read_u32(bytes, 136)?;
+				let array_size = read_u32(bytes, 140)?.max(1);
+				let face_count = if misc_flag & CUBEMAP_MISC_FLAG != 0 { 6 } else { 1 };
+				layer_count = array_size.checked_mul(face_count);
+				data_start = 148;
+				match dxgi_format {
+					28 | 29 => PixelFormat::Rgba8,
+					87 | 91 => PixelFormat::Bgra8,
+					71 | 72 => PixelFormat::Bc1,
+					77 | 78 => PixelFormat::Bc3,
+					80 => PixelFormat::Bc4,
+					83 => PixelFormat::Bc5,
+					95 => PixelFormat::Bc6h,
+					98 | 99 => PixelFormat::Bc7,
+					_ => {
+						return Err(ImageLoaderError::unsupported(
+							"The DXGI format of the texture is not supported",
+						))
+					}
+				}
+			}
+			_ => {
+				return Err(ImageLoaderError::unsupported(
+					"The compression of the texture is not supported",
+				))
+			}
+		}
+	} else if pixel_flags & RGB_FLAG != 0 && read_u32(bytes, 88)? == 32 {
+		let masks = [
+			read_u32(bytes, 92)?,
+			read_u32(bytes, 96)?,
+			read_u32(bytes, 100)?,
+			read_u32(bytes, 104)?,
+		];
+		PixelFormat::Masked32(masks)
+	} else {
+		return Err(ImageLoaderError::unsupported(
+			"The pixel format of the texture is not supported",
+		));
+	};
+	// Each layer has all of its levels before the next layer
+	let mut layout = Layout::new(format, width, height, level_count, layer_count)?;
+	let mut offset = data_start;
+	for layer in 0..layout.layer_count {
+		for level in 0..layout.level_count {
+			layout.offsets[(level * layout.layer_count + layer) as usize] = offset;
+			offset = add_offset(offset, layout.level_size(level)?)?;
+		}
+	}
+	Ok(layout)
+}
+
+fn parse_ktx(bytes: &[u8]) -> Result<Layout> {
+	const LITTLE_ENDIAN: u32 = 0x04030201;
+	const GL_UNSIGNED_BYTE: u32 = 0x1401;
+	const GL_RGBA: u32 = 0x1908;
+	const GL_BGRA: u32 = 0x80E1;
+
+	if read_u32(bytes, 12)? != LITTLE_ENDIAN {
+		return Err(ImageLoaderError::unsupported("Big endian KTX files are not supported"));
+	}
+	let gl_type = read_u32(bytes, 16)?;
+	let gl_format = read_u32(bytes, 24)?;
+	let gl_internal_format = read_u32(bytes, 28)?;
+	let width = read_u32(bytes, 36)?;
+	let height = read_u32(bytes, 40)?.max(1);
+	let depth = read_u32(bytes, 44)?;
+	let array_size = read_u32(bytes, 48)?.max(1);
+	let face_count = read_u32(bytes, 52)?.max(1);
+	let level_count = read_u32(bytes, 56)?.max(1);
+	let key_value_size = read_u32(bytes, 60)? as usize;
+	if depth > 1 {
+		return Err(ImageLoaderError::unsupported("Volume textures are not supported"));
+	}
+	let format = match (gl_type, gl_format, gl_internal_format) {
+		(GL_UNSIGNED_BYTE, GL_RGBA, _) => PixelFormat::Rgba8,
+		(GL_UNSIGNED_BYTE, GL_BGRA, _) => PixelFormat::Bgra8,
+		(0, _, 0x83F0 | 0x83F1 | 0x8C4C | 0x8C4D) => PixelFormat::Bc1,
+		(0, _, 0x83F3 | 0x8C4F) => PixelFormat::Bc3,
+		(0, _, 0x8DBB) => PixelFormat::Bc4,
+		(0, _, 0x8DBD) => PixelFormat::Bc5,
+		(0, _, 0x8E8F) => PixelFormat::Bc6h,
+		(0, _, 0x8E8C | 0x8E8D) => PixelFormat::Bc7,
+		_ => {
+			return Err(ImageLoaderError::unsupported(
+				"The format of the KTX texture is not supported",
+			))
+		}
+	};
+	// Each level starts with its size, followed by the faces of each array layer. The sizes of
+	// the supported formats are multiples of four, so there's no padding.
+	let layer_count = array_size.checked_mul(face_count);
+	let mut layout = Layout::new(format, width, height, level_count, layer_count)?;
+	let mut offset = add_offset(64, key_value_size)?;
+	for level in 0..layout.level_count {
+		offset = add_offset(offset, 4)?;
+		for layer in 0..layout.layer_count {
+			layout.offsets[(level * layout.layer_count + layer) as usize] = offset;
+			offset = add_offset(offset, layout.level_size(level)?)?;
+		}
+	}
+	Ok(layout)
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<Layout> {
+	const LEVEL_INDEX_START: usize = 80;
+
+	let vk_format = read_u32(bytes, 12)?;
+	let width = read_u32(bytes, 20)?;
+	let height = read_u32(bytes, 24)?.max(1);
+	let depth = read_u32(bytes, 28)?;
+	let array_size = read_u32(bytes, 32)?.max(1);
+	let face_count = read_u32(bytes, 36)?.max(1);
+	let level_count = read_u32(bytes, 40)?.max(1);
+	let supercompression = read_u32(bytes, 44)?;
+	if depth > 1 {
+		return Err(ImageLoaderError::unsupported("Volume textures are not supported"));
+	}
+	if supercompression != 0 {
+		return Err(ImageLoaderError::unsupported(
+			"Supercompressed KTX2 textures are not supported",
+		));
+	}
+	let format = match vk_format {
+		37 | 43 => PixelFormat::Rgba8,
+		44 | 50 => PixelFormat::Bgra8,
+		131..=134 => PixelFormat::Bc1,
+		137 | 138 => PixelFormat::Bc3,
+		139 => PixelFormat::Bc4,
+		141 => PixelFormat::Bc5,
+		143 => PixelFormat::Bc6h,
+		145 | 146 => PixelFormat::Bc7,
+		_ => {
+			return Err(ImageLoaderError::unsupported(
+				"The format of the KTX2 texture is not supported",
+			))
+		}
+	};
+	// The level index gives where each level starts. Within a level, the faces of each array
+	// layer follow each other.
+	let layer_count = array_size.checked_mul(face_count);
+	let mut layout = Layout::new(format, width, height, level_count, layer_count)?;
+	for level in 0..layout.level_count {
+		let level_start = read_u64(bytes, LEVEL_INDEX_START + level as usize * 24)?;
+		let level_start = usize::try_from(level_start).map_err(|_| too_large())?;
+		for layer in 0..layout.layer_count {
+			let layer_start = (layer as usize).checked_mul(layout.level_size(level)?);
+			let offset = add_offset(level_start, layer_start.ok_or_else(too_large)?)?;
+			layout.offsets[(level * layout.layer_count + layer) as usize] = offset;
+		}
+	}
+	Ok(layout)
+}
+
+fn decode(format: PixelFormat, data: &[u8], width: u32, height: u32) -> Result<RgbaImage> {
+	let (w, h) = (width as usize, height as usize);
+	let rgba = match format {
+		PixelFormat::Rgba8 => data.to_vec(),
+		PixelFormat::Bgra8 => data.chunks_exact(4).flat_map(|p| [p[2], p[1], p[0], p[3]]).collect(),
+		PixelFormat::Masked32(masks) => data
+			.chunks_exact(4)
+			.flat_map(|p| {
+				let pixel = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+				let alpha = if masks[3] == 0 { 255 } else { masked_channel(pixel, masks[3]) };
+				let [r, g, b] = [0, 1, 2].map(|i| masked_channel(pixel, masks[i]));
+				[r, g, b, alpha]
+			})
+			.collect(),
+		_ => {
+			let mut pixels = vec![0u32; w * h];
+			let result = match format {
+				PixelFormat::Bc1 => texture2ddecoder::decode_bc1(data, w, h, &mut pixels),
+				PixelFormat::Bc3 => texture2ddecoder::decode_bc3(data, w, h, &mut pixels),
+				PixelFormat::Bc4 => texture2ddecoder::decode_bc4(data, w, h, &mut pixels),
+				PixelFormat::Bc5 => texture2ddecoder::decode_bc5(data, w, h, &mut pixels),
+				PixelFormat::Bc6h => texture2ddecoder::decode_bc6_unsigned(data, w, h, &mut pixels),
+				_ => texture2ddecoder::decode_bc7(data, w, h, &mut pixels),
+			};
+			result.map_err(ImageLoaderError::unsupported)?;
+			// The decoded pixels are packed as BGRA
+			pixels
+				.into_iter()
+				.flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, p as u8, (p >> 24) as u8])
+				.collect()
+		}
+	};
+	RgbaImage::from_raw(width, height, rgba)
+		.ok_or_else(|| ImageLoaderError::unsupported("The texture file is truncated"))
+}
+
+/// Scales the bits of the channel to the 0..=255 range
+fn masked_channel(pixel: u32, mask: u32) -> u8 {
+	if mask == 0 {
+		return 0;
+	}
+	let value = (pixel & mask) >> mask.trailing_zeros();
+	let max = mask >> mask.trailing_zeros();
+	(value as u64 * 255 / max as u64) as u8
+}
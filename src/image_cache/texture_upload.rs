@@ -0,0 +1,143 @@
+//! Uploads the large frames of the prefetched images over several frames through pixel buffers,
+//! so that the shown image doesn't stutter while the neighbouring images are prefetched. The
+//! driver copies from a pixel buffer to the texture in the background, so only copying the
+//! pixels into the buffer is done on the main thread.
+
+use std::{mem, rc::Rc};
+
+use gelatin::{
+	glium::texture::{pixel_buffer::PixelBuffer, SrgbTexture2d},
+	image::RgbaImage,
+};
+
+use super::{
+	grid_cell_size, image_loader::Orientation, mipmaps_option, AnimationFrameTexture, TextureError,
+	TextureGridItem, TextureResult,
+};
+
+/// Frames with fewer pixels than this are uploaded at once
+const MIN_PIXELS: u64 = 8 * 1_000_000;
+
+/// The number of bytes that are copied into a pixel buffer in a step
+const BYTES_PER_STEP: usize = 16 * 1024 * 1024;
+
+pub fn is_large(image: &RgbaImage) -> bool {
+	image.width() as u64 * image.height() as u64 >= MIN_PIXELS
+}
+
+pub struct TextureUpload {
+	pub req_id: u32,
+	pub image: RgbaImage,
+	pub delay_nano: u64,
+	pub orientation: Orientation,
+	cells: Vec<TextureGridItem>,
+	cell_step_size: u32,
+	/// The index of the cell that is being uploaded, and the next row of it
+	next_cell: usize,
+	next_row: u32,
+}
+
+impl TextureUpload {
+	/// Creates the textures of the grid without uploading anything into them yet
+	pub fn new(
+		display: &gelatin::Display,
+		req_id: u32,
+		image: RgbaImage,
+		delay_nano: u64,
+		orientation: Orientation,
+	) -> TextureResult<Self> {
+		let (w, h) = image.dimensions();
+		// Forget about any earlier error so that only this upload is checked
+		gelatin::window::take_gpu_out_of_memory(display);
+		let cell_step_size = grid_cell_size(display);
+		let grid_cols = ((w - 1) / cell_step_size) + 1;
+		let grid_rows = ((h - 1) / cell_step_size) + 1;
+		let mut cells = Vec::new();
+		for row in 0..grid_rows {
+			for col in 0..grid_cols {
+				let cell_w = (w - col * cell_step_size).min(cell_step_size);
+				let cell_h = (h - row * cell_step_size).min(cell_step_size);
+				let mipmaps = mipmaps_option(w, h, true);
+				let tex = SrgbTexture2d::empty_with_mipmaps(display, mipmaps, cell_w, cell_h)?;
+				cells.push(TextureGridItem { tex, col, row });
+			}
+		}
+		Ok(TextureUpload {
+			req_id,
+			image,
+			delay_nano,
+			orientation,
+			cells,
+			cell_step_size,
+			next_cell: 0,
+			next_row: 0,
+		})
+	}
+
+	/// Uploads the next rows of the image. Returns true when the whole image is uploaded.
+	pub fn step(&mut self, display: &gelatin::Display) -> bool {
+		let cell = match self.cells.get(self.next_cell) {
+			Some(cell) => cell,
+			None => return true,
+		};
+		let (cell_w, cell_h) = cell.tex.dimensions();
+		let rows = (BYTES_PER_STEP / (cell_w as usize * 4)) as u32;
+		let rows = rows.clamp(1, cell_h - self.next_row);
+		let x = cell.col * self.cell_step_size;
+		let y = cell.row * self.cell_step_size + self.next_row;
+		let img_w = self.image.width() as usize;
+		let img_bytes = self.image.as_raw();
+		let mut pixels = Vec::with_capacity(cell_w as usize * rows as usize);
+		for y in y..(y + rows) {
+			let start = (y as usize * img_w + x as usize) * 4;
+			let end = start + cell_w as usize * 4;
+			let row = img_bytes[start..end].chunks_exact(4);
+			pixels.extend(row.map(|pixel| (pixel[0], pixel[1], pixel[2], pixel[3])));
+		}
+		let buffer = PixelBuffer::new_empty(display, pixels.len());
+		buffer.write(&pixels);
+		let row_range = self.next_row..(self.next_row + rows);
+		cell.tex.main_level().raw_upload_from_pixel_buffer(
+			buffer.as_slice(),
+			0..cell_w,
+			row_range,
+			0..1,
+		);
+		self.next_row += rows;
+		if self.next_row == cell_h {
+			self.next_cell += 1;
+			self.next_row = 0;
+		}
+		self.next_cell == self.cells.len()
+	}
+
+	/// Uploads the rest of the image at once
+	pub fn upload_all(&mut self, display: &gelatin::Display) {
+		while !self.step(display) {}
+	}
+
+	/// Returns `TextureError::OutOfMemory` if the driver reported running out of video memory
+	/// during the upload. The image is kept, so that the upload can be retried.
+	pub fn finish(&mut self, display: &gelatin::Display) -> TextureResult<AnimationFrameTexture> {
+		for cell in self.cells.iter() {
+			// Safe, because every level 0 texel of the cell was written by `step`
+			unsafe { cell.tex.generate_mipmaps() };
+		}
+		if gelatin::window::take_gpu_out_of_memory(display) {
+			return Err(TextureError::OutOfMemory);
+		}
+		let (w, h) = self.image.dimensions();
+		Ok(AnimationFrameTexture {
+			tex_grid: Rc::new(mem::take(&mut self.cells)),
+			cell_step_size: self.cell_step_size,
+			tiled: None,
+			float: None,
+			placeholder: None,
+			delay_nano: self.delay_nano,
+			orientation: self.orientation,
+			info: None,
+			w,
+			h,
+		})
+	}
+}
@@ -0,0 +1,223 @@
+//! Stores the thumbnails of the viewed images on the disk as described by the freedesktop
+//! thumbnail specification, so that they are shared with file managers and other viewers.
+//!
+//! A thumbnail is a PNG file in the folder of its size class, named by the MD5 hash of the URI
+//! of the image. The URI and the modification time of the image are stored in the PNG, so that
+//! outdated thumbnails are recognized and made again.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use gelatin::image::{self, imageops, RgbaImage};
+use md5::{Digest, Md5};
+
+use super::image_loader::Orientation;
+use crate::clipboard_handler::{apply_orientation, file_uri};
+
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+const SIZE_KEY: &str = "Thumb::Size";
+const WIDTH_KEY: &str = "Thumb::Image::Width";
+const HEIGHT_KEY: &str = "Thumb::Image::Height";
+const SOFTWARE_KEY: &str = "Software";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeClass {
+	Normal,
+	Large,
+}
+
+impl SizeClass {
+	/// The longer side of the thumbnails is at most this many pixels
+	pub fn max_size(self) -> u32 {
+		match self {
+			SizeClass::Normal => 128,
+			SizeClass::Large => 256,
+		}
+	}
+
+	fn folder_name(self) -> &'static str {
+		match self {
+			SizeClass::Normal => "normal",
+			SizeClass::Large => "large",
+		}
+	}
+}
+
+/// The size classes that the loaded images get thumbnails in. These are the ones that file
+/// managers use the most.
+const SAVED_SIZE_CLASSES: &[SizeClass] = &[SizeClass::Large, SizeClass::Normal];
+
+/// Thumbnails are only saved if this is enabled
+pub fn set_enabled(enabled: bool) {
+	ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+	ENABLED.load(Ordering::Relaxed)
+}
+
+/// `$XDG_CACHE_HOME/thumbnails`, which is `~/.cache/thumbnails` by default
+fn thumbnails_folder() -> Option<PathBuf> {
+	directories_next::BaseDirs::new().map(|dirs| dirs.cache_dir().join("thumbnails"))
+}
+
+/// The file that a thumbnail was made from
+struct Source {
+	uri: String,
+	/// The modification time in seconds since the Unix epoch
+	mtime: u64,
+	file_size: u64,
+}
+
+impl Source {
+	/// `path` has to be absolute
+	fn new(path: &Path) -> io::Result<Source> {
+		let metadata = fs::metadata(path)?;
+		let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		Ok(Source { uri: file_uri(path), mtime, file_size: metadata.len() })
+	}
+
+	fn thumbnail_name(&self) -> String {
+		format!("{:x}.png", Md5::digest(self.uri.as_bytes()))
+	}
+}
+
+/// Saves the thumbnails of the image, unless up-to-date ones already exist. The thumbnails are
+/// made from `image` with the orientation applied.
+pub fn save_thumbnails(path: &Path, image: &RgbaImage, orientation: Orientation) -> io::Result<()> {
+	let folder = match thumbnails_folder() {
+		Some(folder) => folder,
+		None => return Err(io::Error::new(io::ErrorKind::NotFound, "No cache folder")),
+	};
+	let path = path.canonicalize()?;
+	// The specification forbids making thumbnails of thumbnails
+	if path.starts_with(&folder) {
+		return Ok(());
+	}
+	let source = Source::new(&path)?;
+	for &size_class in SAVED_SIZE_CLASSES.iter() {
+		let target = folder.join(size_class.folder_name()).join(source.thumbnail_name());
+		if is_up_to_date(&target, &source) {
+			continue;
+		}
+		let max_size = size_class.max_size();
+		let (w, h) = image.dimensions();
+		let thumbnail = if w <= max_size && h <= max_size {
+			image.clone()
+		} else {
+			let scale = max_size as f64 / w.max(h) as f64;
+			let thumb_w = ((w as f64 * scale) as u32).max(1);
+			let thumb_h = ((h as f64 * scale) as u32).max(1);
+			imageops::thumbnail(image, thumb_w, thumb_h)
+		};
+		let thumbnail = apply_orientation(thumbnail, orientation);
+		write_thumbnail(&target, &thumbnail, &source, image.dimensions())?;
+	}
+	Ok(())
+}
+
+/// Returns an up-to-date thumbnail of the image if there's one, made by any program. The
+/// orientation of the image is applied to the thumbnail.
+pub fn load_thumbnail(path: &Path) -> Option<RgbaImage> {
+	let folder = thumbnails_folder()?;
+	let source = Source::new(&path.canonicalize().ok()?).ok()?;
+	// The smaller thumbnail is faster to decode
+	[SizeClass::Normal, SizeClass::Large].iter().find_map(|size_class| {
+		let thumbnail = folder.join(size_class.folder_name()).join(source.thumbnail_name());
+		if !is_up_to_date(&thumbnail, &source) {
+			return None;
+		}
+		image::open(&thumbnail).ok().map(|image| image.into_rgba8())
+	})
+}
+
+fn is_up_to_date(thumbnail: &Path, source: &Source) -> bool {
+	let file = match File::open(thumbnail) {
+		Ok(file) => file,
+		Err(_) => return false,
+	};
+	let reader = match png::Decoder::new(BufReader::new(file)).read_info() {
+		Ok(reader) => reader,
+		Err(_) => return false,
+	};
+	let text = &reader.info().uncompressed_latin1_text;
+	let value = |key: &str| text.iter().find(|chunk| chunk.keyword == key).map(|c| &c.text);
+	// Some programs write the modification time with a fraction
+	let mtime = value(MTIME_KEY).and_then(|mtime| mtime.parse::<f64>().ok());
+	value(URI_KEY) == Some(&source.uri) && mtime.map(|mtime| mtime as u64) == Some(source.mtime)
+}
+
+fn write_thumbnail(
+	target: &Path,
+	thumbnail: &RgbaImage,
+	source: &Source,
+	(image_w, image_h): (u32, u32),
+) -> io::Result<()> {
+	let folder = match target.parent() {
+		Some(folder) => folder,
+		None => return Err(io::Error::new(io::ErrorKind::NotFound, "No thumbnail folder")),
+	};
+	create_private_folder(folder)?;
+	// Other programs may read the thumbnail at any time, so it's only moved in place once it's
+	// complete
+	let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+	let temp_path = folder.join(format!(".{}.emulsion-{}", file_name, std::process::id()));
+	let chunks = [
+		(URI_KEY, source.uri.clone()),
+		(MTIME_KEY, source.mtime.to_string()),
+		(SIZE_KEY, source.file_size.to_string()),
+		(WIDTH_KEY, image_w.to_string()),
+		(HEIGHT_KEY, image_h.to_string()),
+		(SOFTWARE_KEY, format!("Emulsion {}", env!("CARGO_PKG_VERSION"))),
+	];
+	let result =
+		encode_png(&temp_path, thumbnail, &chunks).and_then(|()| fs::rename(&temp_path, target));
+	if result.is_err() {
+		let _ = fs::remove_file(&temp_path);
+	}
+	result
+}
+
+fn encode_png(path: &Path, image: &RgbaImage, text_chunks: &[(&str, String)]) -> io::Result<()> {
+	let file = create_private_file(path)?;
+	let (w, h) = image.dimensions();
+	let mut encoder = png::Encoder::new(BufWriter::new(file), w, h);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	for (keyword, text) in text_chunks.iter() {
+		encoder.add_text_chunk(keyword.to_string(), text.clone())?;
+	}
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(image.as_raw())?;
+	writer.finish()?;
+	Ok(())
+}
+
+/// The thumbnails may reveal which images the user viewed, so only the user can access them
+#[cfg(unix)]
+fn create_private_folder(folder: &Path) -> io::Result<()> {
+	use std::os::unix::fs::DirBuilderExt;
+	fs::DirBuilder::new().recursive(true).mode(0o700).create(folder)
+}
+
+#[cfg(not(unix))]
+fn create_private_folder(folder: &Path) -> io::Result<()> {
+	fs::create_dir_all(folder)
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> io::Result<File> {
+	use std::os::unix::fs::OpenOptionsExt;
+	fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> io::Result<File> {
+	File::create(path)
+}
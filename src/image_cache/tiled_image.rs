@@ -0,0 +1,165 @@
+//! Very large images are shown progressively. Only a downscaled preview is uploaded when the
+//! image is loaded, and the tiles of the full resolution image are uploaded over several frames
+//! once the view is zoomed in far enough for the preview to look blurry. Only the tiles that
+//! intersect the view are kept in video memory, the full image stays on the CPU side.
+//!
+//! Images that are larger than the maximal texture size of the GPU are shown this way too.
+
+use std::cell::RefCell;
+
+use gelatin::{
+	glium::texture::SrgbTexture2d,
+	image::{imageops, Rgba, RgbaImage},
+};
+use log::warn;
+
+use super::{texture_from_img_rect, TextureResult};
+
+/// Images with more pixels than this are shown progressively
+pub const TILED_MIN_PIXELS: u64 = 64 * 1_000_000;
+
+/// The width and height of the preview is at most this many pixels
+const PREVIEW_MAX_SIZE: u32 = 2048;
+
+/// Smaller tiles than the cells of a texture grid, so that uploading one doesn't take long
+const TILE_SIZE: u32 = 1024;
+
+/// The number of tiles that are uploaded in a frame. The rest are uploaded in the next frames
+/// so that the view stays responsive.
+const MAX_TILE_UPLOADS_PER_FRAME: usize = 2;
+
+/// Returns the downscaled preview of the image if it's large enough to be shown progressively
+pub fn make_preview(image: &RgbaImage, max_texture_size: u32) -> Option<RgbaImage> {
+	let (w, h) = image.dimensions();
+	let fits_texture = w <= max_texture_size && h <= max_texture_size;
+	if fits_texture && (w as u64 * h as u64) < TILED_MIN_PIXELS {
+		return None;
+	}
+	let max_size = PREVIEW_MAX_SIZE.min(max_texture_size);
+	let scale = max_size as f64 / w.max(h) as f64;
+	let preview_w = ((w as f64 * scale) as u32).max(1);
+	let preview_h = ((h as f64 * scale) as u32).max(1);
+	Some(imageops::thumbnail(image, preview_w, preview_h))
+}
+
+/// A rectangle of the image in the 0 to 1 range of the image width and height
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRect {
+	pub min: [f32; 2],
+	pub max: [f32; 2],
+}
+
+pub struct TiledImage {
+	/// The full image, the tiles are uploaded from this
+	pub source: RgbaImage,
+	pub preview: SrgbTexture2d,
+	cols: u32,
+	rows: u32,
+	/// The uploaded tiles in row-major order
+	tiles: RefCell<Vec<Option<SrgbTexture2d>>>,
+}
+
+impl TiledImage {
+	pub fn new(
+		display: &gelatin::Display,
+		source: RgbaImage,
+		preview: &RgbaImage,
+	) -> TextureResult<Self> {
+		let (preview_w, preview_h) = preview.dimensions();
+		let preview = texture_from_img_rect(
+			display,
+			preview_w,
+			preview_h,
+			preview.as_raw(),
+			0,
+			0,
+			preview_w,
+			preview_h,
+			true,
+		)?;
+		let (w, h) = source.dimensions();
+		let cols = (w - 1) / TILE_SIZE + 1;
+		let rows = (h - 1) / TILE_SIZE + 1;
+		let tiles = RefCell::new((0..cols * rows).map(|_| None).collect());
+		Ok(TiledImage { source, preview, cols, rows, tiles })
+	}
+
+	/// The number of image pixels that a texel of the preview covers in one dimension
+	pub fn preview_scale(&self) -> f32 {
+		self.source.width() as f32 / self.preview.dimensions().0 as f32
+	}
+
+	/// The preview is enough while its texels aren't magnified on the screen. `texel_size` is
+	/// the size of an image pixel in physical pixels.
+	pub fn needs_tiles(&self, texel_size: f32) -> bool {
+		texel_size * self.preview_scale() > 1.0
+	}
+
+	/// Frees the tiles outside of `visible`, and uploads a few of the missing tiles inside it,
+	/// starting from the center. All tiles are freed if `visible` is `None`.
+	///
+	/// Returns true if there are visible tiles left to upload.
+	pub fn update(&self, display: &gelatin::Display, visible: Option<ImageRect>) -> bool {
+		let mut tiles = self.tiles.borrow_mut();
+		let visible = match visible {
+			Some(visible) => visible,
+			None => {
+				tiles.iter_mut().for_each(|tile| *tile = None);
+				return false;
+			}
+		};
+		let (w, h) = self.source.dimensions();
+		let tile_range = |min: f32, max: f32, size: u32, count: u32| {
+			let first = (min.max(0.0) * size as f32) as u32 / TILE_SIZE;
+			let last = (max.min(1.0) * size as f32) as u32 / TILE_SIZE;
+			first.min(count - 1)..=last.min(count - 1)
+		};
+		let col_range = tile_range(visible.min[0], visible.max[0], w, self.cols);
+		let row_range = tile_range(visible.min[1], visible.max[1], h, self.rows);
+		let center_col = (col_range.start() + col_range.end()) as f32 / 2.0;
+		let center_row = (row_range.start() + row_range.end()) as f32 / 2.0;
+		let mut missing = Vec::new();
+		for row in 0..self.rows {
+			for col in 0..self.cols {
+				let index = (row * self.cols + col) as usize;
+				if !col_range.contains(&col) || !row_range.contains(&row) {
+					tiles[index] = None;
+				} else if tiles[index].is_none() {
+					let dist = (col as f32 - center_col).abs() + (row as f32 - center_row).abs();
+					missing.push((dist, col, row));
+				}
+			}
+		}
+		missing.sort_by(|a, b| a.0.total_cmp(&b.0));
+		for &(_, col, row) in missing.iter().take(MAX_TILE_UPLOADS_PER_FRAME) {
+			let x = col * TILE_SIZE;
+			let y = row * TILE_SIZE;
+			let tile_w = (w - x).min(TILE_SIZE);
+			let tile_h = (h - y).min(TILE_SIZE);
+			let bytes = self.source.as_raw();
+			match texture_from_img_rect(display, w, h, bytes, x, y, tile_w, tile_h, true) {
+				Ok(tex) => tiles[(row * self.cols + col) as usize] = Some(tex),
+				Err(e) => {
+					warn!("Could not upload a tile of the image: {}", e);
+					return false;
+				}
+			}
+		}
+		missing.len() > MAX_TILE_UPLOADS_PER_FRAME
+	}
+
+	/// Calls `draw_tile` with each uploaded tile and the position of its corner in image pixels
+	pub fn for_each_tile(&self, mut draw_tile: impl FnMut(&SrgbTexture2d, u32, u32)) {
+		let tiles = self.tiles.borrow();
+		for (index, tile) in tiles.iter().enumerate() {
+			if let Some(tex) = tile {
+				let index = index as u32;
+				draw_tile(tex, (index % self.cols) * TILE_SIZE, (index / self.cols) * TILE_SIZE);
+			}
+		}
+	}
+
+	pub fn pixel(&self, x: u32, y: u32) -> Option<Rgba<u8>> {
+		self.source.get_pixel_checked(x, y).copied()
+	}
+}
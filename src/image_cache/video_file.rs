@@ -0,0 +1,238 @@
+//! Shows short video clips among the images of the folder. Clips are played in a loop like
+//! animated images, and only the first frame is shown of longer videos.
+//!
+//! The frames are decoded by a `VideoDecoder`. FFmpeg is run for this by default, so that no
+//! video library has to be built into Emulsion, but a decoder that uses a library can be
+//! plugged in instead.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::RwLock;
+
+use gelatin::image::RgbaImage;
+use lazy_static::lazy_static;
+
+use super::decode_limit::{self, DecodeDecision};
+use super::image_loader::{downscaled_size, ImageLoaderError, Result};
+
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "webm", "mkv", "avi"];
+
+pub const DEFAULT_MAX_LOOP_SECONDS: u32 = 10;
+
+/// The frames of a looped clip are kept in memory, so the clip is downscaled until all of them
+/// fit into this part of the image cache
+const LOOP_CACHE_SHARE: u64 = 8;
+
+/// Used until `configure` is called, 64 MB of RGBA frames
+const DEFAULT_MAX_LOOP_PIXELS: u64 = 16 * 1_000_000;
+
+/// Used when the decoder can't tell the frame rate
+const DEFAULT_FRAME_RATE: f64 = 25.0;
+
+/// The properties of the video stream that decoding it needs
+pub struct VideoInfo {
+	pub width: u32,
+	pub height: u32,
+	/// Zero if it's not known
+	pub frame_rate: f64,
+	/// Zero if it's not known
+	pub duration_secs: f64,
+}
+
+/// Decodes the frames of video files
+pub trait VideoDecoder: Send + Sync {
+	fn probe(&self, path: &Path) -> Result<VideoInfo>;
+
+	/// Calls `process_frame` with at most `max_frames` frames from the start of the video,
+	/// scaled to `width` and `height`. Decoding stops when `process_frame` returns an error.
+	fn decode(
+		&self,
+		path: &Path,
+		width: u32,
+		height: u32,
+		max_frames: u64,
+		process_frame: &mut dyn FnMut(RgbaImage) -> Result<()>,
+	) -> Result<()>;
+}
+
+/// Runs the `ffprobe` and `ffmpeg` programs
+pub struct Ffmpeg {
+	pub ffmpeg: PathBuf,
+	pub ffprobe: PathBuf,
+}
+
+impl Default for Ffmpeg {
+	fn default() -> Self {
+		Ffmpeg { ffmpeg: "ffmpeg".into(), ffprobe: "ffprobe".into() }
+	}
+}
+
+impl VideoDecoder for Ffmpeg {
+	fn probe(&self, path: &Path) -> Result<VideoInfo> {
+		let output = Command::new(&self.ffprobe)
+			.args(["-v", "error", "-select_streams", "v:0"])
+			.args(["-show_entries", "stream=width,height,avg_frame_rate:format=duration"])
+			.args(["-of", "default=noprint_wrappers=1"])
+			.arg(path)
+			.stdin(Stdio::null())
+			.output()
+			.map_err(|e| {
+				ImageLoaderError::unsupported(format!("Could not run {:?}: {}", self.ffprobe, e))
+			})?;
+		if !output.status.success() {
+			let stderr = String::from_utf8_lossy(&output.stderr);
+			return Err(ImageLoaderError::unsupported(format!(
+				"ffprobe failed: {}",
+				stderr.trim()
+			)));
+		}
+		let mut info = VideoInfo { width: 0, height: 0, frame_rate: 0.0, duration_secs: 0.0 };
+		for line in String::from_utf8_lossy(&output.stdout).lines() {
+			let (key, value) = match line.split_once('=') {
+				Some(entry) => entry,
+				None => continue,
+			};
+			match key {
+				"width" => info.width = value.parse().unwrap_or(0),
+				"height" => info.height = value.parse().unwrap_or(0),
+				"avg_frame_rate" => info.frame_rate = parse_rate(value),
+				"duration" => info.duration_secs = value.parse().unwrap_or(0.0),
+				_ => (),
+			}
+		}
+		if info.width == 0 || info.height == 0 {
+			return Err(ImageLoaderError::unsupported(format!("{:?} has no video stream", path)));
+		}
+		Ok(info)
+	}
+
+	fn decode(
+		&self,
+		path: &Path,
+		width: u32,
+		height: u32,
+		max_frames: u64,
+		process_frame: &mut dyn FnMut(RgbaImage) -> Result<()>,
+	) -> Result<()> {
+		// The rotation of phone videos is not applied, because the probed size is the size
+		// before the rotation
+		let mut child = Command::new(&self.ffmpeg)
+			.args(["-v", "error", "-noautorotate", "-i"])
+			.arg(path)
+			.arg("-frames:v")
+			.arg(max_frames.to_string())
+			.arg("-vf")
+			.arg(format!("scale={}:{}", width, height))
+			.args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::null())
+			.spawn()
+			.map_err(|e| {
+				ImageLoaderError::unsupported(format!("Could not run {:?}: {}", self.ffmpeg, e))
+			})?;
+		let mut stdout = match child.stdout.take() {
+			Some(stdout) => stdout,
+			None => {
+				return Err(ImageLoaderError::unsupported("The output of FFmpeg is not available"))
+			}
+		};
+		let frame_len = width as usize * height as usize * 4;
+		let mut frame_count = 0;
+		let result = loop {
+			let mut pixels = vec![0; frame_len];
+			match stdout.read_exact(&mut pixels) {
+				Ok(()) => (),
+				Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+				Err(e) => break Err(e.into()),
+			}
+			let frame = match RgbaImage::from_raw(width, height, pixels) {
+				Some(frame) => frame,
+				None => {
+					break Err(ImageLoaderError::unsupported(
+						"FFmpeg returned a frame with an invalid size",
+					))
+				}
+			};
+			frame_count += 1;
+			if let Err(e) = process_frame(frame) {
+				break Err(e);
+			}
+		};
+		// FFmpeg is still running if the decoding was stopped early
+		let _ = child.kill();
+		let _ = child.wait();
+		result?;
+		if frame_count == 0 {
+			return Err(ImageLoaderError::unsupported(format!(
+				"FFmpeg could not decode {:?}",
+				path
+			)));
+		}
+		Ok(())
+	}
+}
+
+struct Settings {
+	decoder: Box<dyn VideoDecoder>,
+	max_loop_secs: u32,
+	max_loop_pixels: u64,
+}
+
+lazy_static! {
+	static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings {
+		decoder: Box::new(Ffmpeg::default()),
+		max_loop_secs: DEFAULT_MAX_LOOP_SECONDS,
+		max_loop_pixels: DEFAULT_MAX_LOOP_PIXELS,
+	});
+}
+
+/// Clips up to `max_loop_secs` long are played in a loop. Their frames take up at most an
+/// eighth of `cache_capacity`, which is in bytes.
+pub fn configure(decoder: Box<dyn VideoDecoder>, max_loop_secs: u32, cache_capacity: u64) {
+	let max_loop_pixels = cache_capacity / LOOP_CACHE_SHARE / 4;
+	*SETTINGS.write().unwrap() = Settings { decoder, max_loop_secs, max_loop_pixels };
+}
+
+/// Calls `process_frame` with each frame and the time that it's shown for in nanoseconds
+pub fn load<F>(path: &Path, allow_animation: bool, mut process_frame: F) -> Result<()>
+where
+	F: FnMut(RgbaImage, u64) -> Result<()>,
+{
+	let settings = SETTINGS.read().unwrap();
+	let info = settings.decoder.probe(path)?;
+	let (w, h) = (info.width, info.height);
+	let max_pixels = match decode_limit::decide(path, w, h) {
+		DecodeDecision::Full => w as u64 * h as u64,
+		DecodeDecision::Downscale { max_pixels } => max_pixels,
+		DecodeDecision::Refuse => {
+			return Err(ImageLoaderError::TooLarge { width: w, height: h });
+		}
+	};
+	let frame_rate = if info.frame_rate > 0.0 { info.frame_rate } else { DEFAULT_FRAME_RATE };
+	let looped = allow_animation
+		&& info.duration_secs > 0.0
+		&& info.duration_secs <= settings.max_loop_secs as f64;
+	let (max_frames, max_pixels) = if looped {
+		let frame_count = (info.duration_secs * frame_rate).ceil().max(1.0) as u64;
+		(frame_count, max_pixels.min(settings.max_loop_pixels / frame_count))
+	} else {
+		(1, max_pixels)
+	};
+	let (width, height) =
+		if max_pixels < w as u64 * h as u64 { downscaled_size(w, h, max_pixels) } else { (w, h) };
+	let delay_nano = if looped { (1_000_000_000.0 / frame_rate) as u64 } else { 0 };
+	settings
+		.decoder
+		.decode(path, width, height, max_frames, &mut |frame| process_frame(frame, delay_nano))
+}
+
+/// Parses a frame rate like "30000/1001"
+fn parse_rate(rate: &str) -> f64 {
+	let (numerator, denominator) = rate.split_once('/').unwrap_or((rate, "1"));
+	match (numerator.parse::<f64>(), denominator.parse::<f64>()) {
+		(Ok(numerator), Ok(denominator)) if denominator > 0.0 => numerator / denominator,
+		_ => 0.0,
+	}
+}
@@ -1,10 +1,11 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Command;
 use std::rc::Rc;
 
-use crate::configuration::Configuration;
-use gelatin::winit::keyboard::ModifiersState;
+use crate::configuration::{self, Configuration, FolderConfiguration, OpenWithProgram};
+use gelatin::winit::{event::MouseButton, keyboard::ModifiersState};
 use lazy_static::lazy_static;
 
 pub static TOGGLE_FULLSCREEN_NAME: &str = "toggle_fullscreen";
@@ -15,12 +16,20 @@ pub static IMG_ORIG_NAME: &str = "img_orig";
 pub static IMG_FIT_NAME: &str = "img_fit";
 pub static IMG_FIT_BEST_NAME: &str = "img_fit_best";
 pub static IMG_DEL_NAME: &str = "img_del";
+pub static UNDO_DELETE_NAME: &str = "undo_delete";
+pub static RENAME_NAME: &str = "rename";
 pub static IMG_COPY_NAME: &str = "img_copy";
 pub static PAN_NAME: &str = "pan";
 pub static PAN_VERT_NAME: &str = "pan_vert"; // Vertical panning
 pub static PAN_HOR_NAME: &str = "pan_hor"; // Horizontal panning
 pub static PLAY_ANIM_NAME: &str = "play_anim";
 pub static PLAY_PRESENT_NAME: &str = "play_present";
+pub static TOGGLE_ANIMATION_NAME: &str = "toggle_animation";
+pub static STEP_FRAME_FORWARD_NAME: &str = "step_frame_forward";
+pub static STEP_FRAME_BACKWARD_NAME: &str = "step_frame_backward";
+pub static SET_LOOP_START_NAME: &str = "set_loop_start";
+pub static SET_LOOP_END_NAME: &str = "set_loop_end";
+pub static CLEAR_LOOP_NAME: &str = "clear_loop";
 pub static PLAY_PRESENT_RND_NAME: &str = "play_present_rnd";
 pub static TOGGLE_ANTIALIAS_NAME: &str = "toggle_antialias";
 pub static SET_AUTOMATIC_ANTIALIAS_NAME: &str = "automatic_antialias";
@@ -30,29 +39,260 @@ pub static PAN_LEFT_NAME: &str = "pan_left";
 pub static PAN_RIGHT_NAME: &str = "pan_right";
 pub static PAN_UP_NAME: &str = "pan_up";
 pub static PAN_DOWN_NAME: &str = "pan_down";
+pub static PAN_PAGE_UP_NAME: &str = "pan_page_up";
+pub static PAN_PAGE_DOWN_NAME: &str = "pan_page_down";
+pub static PAN_PAGE_LEFT_NAME: &str = "pan_page_left";
+pub static PAN_PAGE_RIGHT_NAME: &str = "pan_page_right";
+pub static PAN_HOME_NAME: &str = "pan_home";
+pub static PAN_END_NAME: &str = "pan_end";
+pub static TOGGLE_COMPARE_NAME: &str = "toggle_compare";
+pub static TOGGLE_COMPARE_SYNC_NAME: &str = "toggle_compare_sync";
+pub static TOGGLE_PANORAMA_TOUR_NAME: &str = "toggle_panorama_tour";
+pub static CYCLE_SORT_ORDER_NAME: &str = "cycle_sort_order";
+pub static RECENT_FOLDERS_NAME: &str = "recent_folders";
+pub static OPEN_WITH_NAME: &str = "open_with";
+pub static OPEN_CONTAINING_FOLDER_NAME: &str = "open_containing_folder";
+pub static RATE_0_NAME: &str = "rate_0";
+pub static RATE_1_NAME: &str = "rate_1";
+pub static RATE_2_NAME: &str = "rate_2";
+pub static RATE_3_NAME: &str = "rate_3";
+pub static RATE_4_NAME: &str = "rate_4";
+pub static RATE_5_NAME: &str = "rate_5";
+pub static CYCLE_RATING_FILTER_NAME: &str = "cycle_rating_filter";
+#[cfg(feature = "textures")]
+pub static NEXT_MIP_LEVEL_NAME: &str = "next_mip_level";
+#[cfg(feature = "textures")]
+pub static PREV_MIP_LEVEL_NAME: &str = "prev_mip_level";
+#[cfg(feature = "textures")]
+pub static NEXT_TEXTURE_LAYER_NAME: &str = "next_texture_layer";
+#[cfg(feature = "textures")]
+pub static PREV_TEXTURE_LAYER_NAME: &str = "prev_texture_layer";
+pub static ROTATE_LEFT_NAME: &str = "rotate_left";
+pub static ROTATE_RIGHT_NAME: &str = "rotate_right";
+pub static FLIP_HOR_NAME: &str = "flip_hor";
+pub static FLIP_VERT_NAME: &str = "flip_vert";
+pub static SAVE_ROTATION_NAME: &str = "save_rotation";
+pub static SET_WALLPAPER_NAME: &str = "set_wallpaper";
+pub static OPEN_DOWNSCALED_NAME: &str = "open_downscaled";
+pub static ALWAYS_DOWNSCALE_NAME: &str = "always_downscale";
+pub static RETRY_LOAD_NAME: &str = "retry_load";
+pub static TOGGLE_INFO_NAME: &str = "toggle_info";
+pub static TOGGLE_CACHE_STATS_NAME: &str = "toggle_cache_stats";
+pub static TOGGLE_SELECTION_NAME: &str = "toggle_selection";
+pub static COPY_SELECTION_NAME: &str = "copy_selection";
+pub static COPY_VIEW_NAME: &str = "copy_view";
+pub static COPY_PATH_NAME: &str = "copy_path";
+pub static COPY_FILE_NAME: &str = "copy_file";
+pub static EXPORT_IMAGE_NAME: &str = "export_image";
+pub static EXPORT_VIEW_NAME: &str = "export_view";
+pub static TOGGLE_INSPECTOR_NAME: &str = "toggle_inspector";
+pub static INSPECTOR_SAMPLE_SIZE_NAME: &str = "inspector_sample_size";
+pub static INSPECTOR_LINEAR_NAME: &str = "inspector_linear";
+pub static CYCLE_BACKGROUND_NAME: &str = "cycle_background";
+pub static PRESENT_INTERVAL_INC_NAME: &str = "present_interval_inc";
+pub static PRESENT_INTERVAL_DEC_NAME: &str = "present_interval_dec";
+#[cfg(feature = "dialogs")]
+pub static OPEN_FILE_NAME: &str = "open_file";
+#[cfg(feature = "dialogs")]
+pub static OPEN_FOLDER_NAME: &str = "open_folder";
+pub static NEW_WINDOW_NAME: &str = "new_window";
+pub static TOGGLE_ALWAYS_ON_TOP_NAME: &str = "toggle_always_on_top";
+pub static TOGGLE_BORDERLESS_NAME: &str = "toggle_borderless";
+pub static TOGGLE_BOTTOM_BAR_AUTOHIDE_NAME: &str = "toggle_bottom_bar_autohide";
+pub static OPACITY_INC_NAME: &str = "opacity_inc";
+pub static OPACITY_DEC_NAME: &str = "opacity_dec";
+pub static TOGGLE_REFERENCE_MODE_NAME: &str = "toggle_reference_mode";
+pub static ZOOM_25_NAME: &str = "zoom_25";
+pub static ZOOM_50_NAME: &str = "zoom_50";
+pub static ZOOM_100_NAME: &str = "zoom_100";
+pub static ZOOM_200_NAME: &str = "zoom_200";
+pub static ZOOM_400_NAME: &str = "zoom_400";
+pub static TOGGLE_VIEW_LOCK_NAME: &str = "toggle_view_lock";
+pub static EXPOSURE_INC_NAME: &str = "exposure_inc";
+pub static EXPOSURE_DEC_NAME: &str = "exposure_dec";
+pub static EXPOSURE_RESET_NAME: &str = "exposure_reset";
+pub static SHOW_RED_CHANNEL_NAME: &str = "show_red_channel";
+pub static SHOW_GREEN_CHANNEL_NAME: &str = "show_green_channel";
+pub static SHOW_BLUE_CHANNEL_NAME: &str = "show_blue_channel";
+pub static SHOW_ALPHA_CHANNEL_NAME: &str = "show_alpha_channel";
+pub static SHOW_LUMINANCE_NAME: &str = "show_luminance";
+pub static TOGGLE_ADJUSTMENTS_NAME: &str = "toggle_adjustments";
+pub static RESET_ADJUSTMENTS_NAME: &str = "reset_adjustments";
+pub static PIN_DIFFERENCE_IMAGE_NAME: &str = "pin_difference_image";
+pub static TOGGLE_DIFFERENCE_NAME: &str = "toggle_difference";
+pub static CYCLE_DIFFERENCE_AMPLIFICATION_NAME: &str = "cycle_difference_amplification";
+pub static COMMAND_PALETTE_NAME: &str = "command_palette";
+pub static EDIT_BINDINGS_NAME: &str = "edit_bindings";
+
+/// An action that can be bound to keys
+#[derive(Debug, Copy, Clone)]
+pub struct Action {
+	pub name: &'static str,
+	/// Used if the config has no binding for the action
+	pub default_keys: &'static [&'static str],
+	/// Set if the action lasts while its key is held, like panning, instead of happening once
+	/// when the key is pressed. These can't be triggered without a key.
+	pub held: bool,
+}
+
+impl Action {
+	fn new(name: &'static str, default_keys: &'static [&'static str]) -> Self {
+		Action { name, default_keys, held: false }
+	}
+
+	fn held(name: &'static str, default_keys: &'static [&'static str]) -> Self {
+		Action { name, default_keys, held: true }
+	}
+}
 
 lazy_static! {
-	pub static ref DEFAULT_BINDINGS: HashMap<&'static str, Vec<&'static str>> = {
-		let mut m = HashMap::new();
-		m.insert(TOGGLE_FULLSCREEN_NAME, vec!["F11", "Return"]);
-		m.insert(ESCAPE_NAME, vec!["Escape"]);
-		m.insert(IMG_NEXT_NAME, vec!["D", "Right", "PageDown"]);
-		m.insert(IMG_PREV_NAME, vec!["A", "Left", "PageUp"]);
-		m.insert(IMG_ORIG_NAME, vec!["Q", "1"]);
-		m.insert(IMG_FIT_NAME, vec!["F"]);
-		m.insert(IMG_FIT_BEST_NAME, vec!["E"]);
-		m.insert(IMG_DEL_NAME, vec!["Delete"]);
-		m.insert(IMG_COPY_NAME, vec!["CmdCtrl+C"]);
-		m.insert(PAN_NAME, vec!["Space"]);
-		m.insert(PLAY_ANIM_NAME, vec!["Alt+A", "Alt+V"]);
-		m.insert(PLAY_PRESENT_NAME, vec!["P"]);
-		m.insert(PLAY_PRESENT_RND_NAME, vec!["Alt+P"]);
-		m.insert(TOGGLE_ANTIALIAS_NAME, vec!["S"]);
-		m.insert(SET_AUTOMATIC_ANTIALIAS_NAME, vec!["Alt+S"]);
-		m
+	/// Every action, for listing them in the command palette and the help
+	pub static ref ACTIONS: Vec<Action> = {
+		// The "dialogs" and "textures" features add more
+		#[allow(unused_mut)]
+		let mut actions = vec![
+			Action::new(TOGGLE_FULLSCREEN_NAME, &["F11", "Return"]),
+			Action::new(ESCAPE_NAME, &["Escape"]),
+			Action::new(IMG_NEXT_NAME, &["D", "Right", "PageDown"]),
+			Action::new(IMG_PREV_NAME, &["A", "Left", "PageUp"]),
+			Action::new(IMG_ORIG_NAME, &["Q", "1"]),
+			Action::new(IMG_FIT_NAME, &["F"]),
+			Action::new(IMG_FIT_BEST_NAME, &["E"]),
+			Action::new(IMG_DEL_NAME, &["Delete"]),
+			Action::new(UNDO_DELETE_NAME, &["CmdCtrl+Z"]),
+			Action::new(RENAME_NAME, &["F2"]),
+			Action::new(IMG_COPY_NAME, &["CmdCtrl+C"]),
+			Action::held(PAN_NAME, &["Space"]),
+			Action::held(PAN_VERT_NAME, &[]),
+			Action::held(PAN_HOR_NAME, &[]),
+			Action::held(ZOOM_IN_NAME, &[]),
+			Action::held(ZOOM_OUT_NAME, &[]),
+			Action::held(PAN_LEFT_NAME, &[]),
+			Action::held(PAN_RIGHT_NAME, &[]),
+			Action::held(PAN_UP_NAME, &[]),
+			Action::held(PAN_DOWN_NAME, &[]),
+			Action::new(PLAY_ANIM_NAME, &["Alt+A", "Alt+V"]),
+			Action::new(PLAY_PRESENT_NAME, &["P"]),
+			Action::new(TOGGLE_ANIMATION_NAME, &["G"]),
+			Action::new(STEP_FRAME_FORWARD_NAME, &["."]),
+			Action::new(STEP_FRAME_BACKWARD_NAME, &[","]),
+			Action::new(SET_LOOP_START_NAME, &["["]),
+			Action::new(SET_LOOP_END_NAME, &["]"]),
+			Action::new(CLEAR_LOOP_NAME, &["\\"]),
+			Action::new(PLAY_PRESENT_RND_NAME, &["Alt+P"]),
+			Action::new(TOGGLE_ANTIALIAS_NAME, &["S"]),
+			Action::new(SET_AUTOMATIC_ANTIALIAS_NAME, &["Alt+S"]),
+			Action::new(ROTATE_LEFT_NAME, &["Alt+Left"]),
+			Action::new(ROTATE_RIGHT_NAME, &["Alt+Right"]),
+			Action::new(FLIP_HOR_NAME, &["Alt+H"]),
+			Action::new(FLIP_VERT_NAME, &["Alt+J"]),
+			// Saving the rotation modifies the file so there's no default binding for it.
+			// Neither for setting the wallpaper, which changes the desktop.
+			Action::new(SAVE_ROTATION_NAME, &[]),
+			Action::new(SET_WALLPAPER_NAME, &[]),
+			Action::new(OPEN_DOWNSCALED_NAME, &["O"]),
+			Action::new(ALWAYS_DOWNSCALE_NAME, &["Alt+O"]),
+			Action::new(RETRY_LOAD_NAME, &["F5"]),
+			Action::new(TOGGLE_INFO_NAME, &["I"]),
+			Action::new(TOGGLE_CACHE_STATS_NAME, &["Alt+I"]),
+			Action::new(TOGGLE_SELECTION_NAME, &["C"]),
+			Action::new(COPY_SELECTION_NAME, &["Alt+C"]),
+			Action::new(COPY_VIEW_NAME, &["CmdCtrl+Alt+C"]),
+			Action::new(COPY_PATH_NAME, &["CmdCtrl+Alt+P"]),
+			Action::new(COPY_FILE_NAME, &["CmdCtrl+Alt+F"]),
+			Action::new(EXPORT_IMAGE_NAME, &["CmdCtrl+S"]),
+			Action::new(EXPORT_VIEW_NAME, &["CmdCtrl+Shift+S"]),
+			Action::new(TOGGLE_INSPECTOR_NAME, &["K"]),
+			Action::new(INSPECTOR_SAMPLE_SIZE_NAME, &["Alt+K"]),
+			Action::new(INSPECTOR_LINEAR_NAME, &["Alt+L"]),
+			Action::new(CYCLE_BACKGROUND_NAME, &["B"]),
+			Action::new(PRESENT_INTERVAL_INC_NAME, &["Add", "="]),
+			Action::new(PRESENT_INTERVAL_DEC_NAME, &["Subtract"]),
+			Action::new(NEW_WINDOW_NAME, &["CmdCtrl+N"]),
+			Action::new(TOGGLE_ALWAYS_ON_TOP_NAME, &["Alt+T"]),
+			Action::new(TOGGLE_BORDERLESS_NAME, &["Alt+B"]),
+			Action::new(TOGGLE_BOTTOM_BAR_AUTOHIDE_NAME, &["Alt+Shift+B"]),
+			Action::new(OPACITY_INC_NAME, &["Alt+Add"]),
+			Action::new(OPACITY_DEC_NAME, &["Alt+Subtract"]),
+			Action::new(TOGGLE_REFERENCE_MODE_NAME, &["Alt+F"]),
+			Action::new(ZOOM_25_NAME, &["Alt+1"]),
+			Action::new(ZOOM_50_NAME, &["Alt+2"]),
+			Action::new(ZOOM_100_NAME, &["Alt+3"]),
+			Action::new(ZOOM_200_NAME, &["Alt+4"]),
+			Action::new(ZOOM_400_NAME, &["Alt+5"]),
+			Action::new(TOGGLE_VIEW_LOCK_NAME, &["V"]),
+			Action::new(EXPOSURE_INC_NAME, &["Alt+Up"]),
+			Action::new(EXPOSURE_DEC_NAME, &["Alt+Down"]),
+			Action::new(EXPOSURE_RESET_NAME, &["Alt+E"]),
+			Action::new(SHOW_RED_CHANNEL_NAME, &["Shift+R"]),
+			Action::new(SHOW_GREEN_CHANNEL_NAME, &["Shift+G"]),
+			Action::new(SHOW_BLUE_CHANNEL_NAME, &["Shift+B"]),
+			Action::new(SHOW_ALPHA_CHANNEL_NAME, &["Shift+A"]),
+			Action::new(SHOW_LUMINANCE_NAME, &["Shift+L"]),
+			Action::new(TOGGLE_ADJUSTMENTS_NAME, &["Alt+Shift+A"]),
+			Action::new(RESET_ADJUSTMENTS_NAME, &["Alt+Shift+R"]),
+			Action::new(PIN_DIFFERENCE_IMAGE_NAME, &["CmdCtrl+P"]),
+			Action::new(TOGGLE_DIFFERENCE_NAME, &["Alt+D"]),
+			Action::new(CYCLE_DIFFERENCE_AMPLIFICATION_NAME, &["Alt+Shift+D"]),
+			Action::new(COMMAND_PALETTE_NAME, &["CmdCtrl+Shift+P"]),
+			Action::new(EDIT_BINDINGS_NAME, &["CmdCtrl+K"]),
+			Action::new(PAN_PAGE_UP_NAME, &["Up"]),
+			Action::new(PAN_PAGE_DOWN_NAME, &["Down"]),
+			Action::new(PAN_PAGE_LEFT_NAME, &["CmdCtrl+Left"]),
+			Action::new(PAN_PAGE_RIGHT_NAME, &["CmdCtrl+Right"]),
+			Action::new(PAN_HOME_NAME, &["Home"]),
+			Action::new(PAN_END_NAME, &["End"]),
+			Action::new(TOGGLE_COMPARE_NAME, &["X"]),
+			Action::new(TOGGLE_COMPARE_SYNC_NAME, &["Alt+X"]),
+			Action::new(TOGGLE_PANORAMA_TOUR_NAME, &["T"]),
+			Action::new(CYCLE_SORT_ORDER_NAME, &["Alt+N"]),
+			Action::new(RECENT_FOLDERS_NAME, &["R"]),
+			Action::new(OPEN_WITH_NAME, &["W"]),
+			Action::new(OPEN_CONTAINING_FOLDER_NAME, &["CmdCtrl+Shift+E"]),
+			Action::new(RATE_0_NAME, &["CmdCtrl+0"]),
+			Action::new(RATE_1_NAME, &["CmdCtrl+1"]),
+			Action::new(RATE_2_NAME, &["CmdCtrl+2"]),
+			Action::new(RATE_3_NAME, &["CmdCtrl+3"]),
+			Action::new(RATE_4_NAME, &["CmdCtrl+4"]),
+			Action::new(RATE_5_NAME, &["CmdCtrl+5"]),
+			Action::new(CYCLE_RATING_FILTER_NAME, &["Alt+R"]),
+		];
+		#[cfg(feature = "dialogs")]
+		actions.extend_from_slice(&[
+			Action::new(OPEN_FILE_NAME, &["CmdCtrl+O"]),
+			Action::new(OPEN_FOLDER_NAME, &["CmdCtrl+Shift+O"]),
+		]);
+		#[cfg(feature = "textures")]
+		actions.extend_from_slice(&[
+			Action::new(NEXT_MIP_LEVEL_NAME, &["M"]),
+			Action::new(PREV_MIP_LEVEL_NAME, &["Alt+M"]),
+			Action::new(NEXT_TEXTURE_LAYER_NAME, &["L"]),
+			Action::new(PREV_TEXTURE_LAYER_NAME, &["CmdCtrl+L"]),
+		]);
+		actions
 	};
 }
 
+pub fn find_action(action_name: &str) -> Option<&'static Action> {
+	ACTIONS.iter().find(|action| action.name == action_name)
+}
+
+/// The inputs of scrolling, which can be bound like keys
+pub static SCROLL_UP_INPUT: &str = "scrollup";
+pub static SCROLL_DOWN_INPUT: &str = "scrolldown";
+
+/// Returns the name that the mouse button is bound by, like the name of a key. The left and
+/// right buttons are used for panning, so they can't be bound.
+pub fn mouse_button_to_input_key(button: MouseButton, double_click: bool) -> Option<&'static str> {
+	match (button, double_click) {
+		(MouseButton::Middle, false) => Some("mousemiddle"),
+		(MouseButton::Middle, true) => Some("mousemiddledouble"),
+		(MouseButton::Back, _) => Some("mouseback"),
+		(MouseButton::Forward, _) => Some("mouseforward"),
+		_ => None,
+	}
+}
+
 pub fn char_to_input_key(ch: &str) -> String {
 	let mut input_key = String::with_capacity(8);
 	if ch == " " {
@@ -81,35 +321,78 @@ fn substitute_command_parameters(string: &str, var_map: &HashMap<&str, &str>) ->
 /// valid UTF-8.
 pub fn execute_triggered_commands(
 	config: Rc<RefCell<Configuration>>,
+	folder_config: &FolderConfiguration,
 	input_key: &str,
 	modifiers: ModifiersState,
 	img_path: &str,
 	folder_path: &str,
 ) {
 	let config = config.borrow();
-	if let Some(ref commands) = config.commands {
-		let mut var_map = HashMap::with_capacity(2);
-		var_map.insert("${img}", img_path);
-		var_map.insert("${folder}", folder_path);
-		for command in commands.iter() {
-			if keys_triggered(&command.input, input_key, modifiers) {
-				let mut cmd = Command::new(&command.program);
-				if let Some(ref args) = command.args {
-					cmd.args(args.iter().map(|arg| substitute_command_parameters(arg, &var_map)));
-				}
-				if let Some(ref envs) = command.envs {
-					cmd.envs(
-						envs.iter().map(|env_var| (env_var.name.as_str(), env_var.value.as_str())),
-					);
-				}
-				if let Err(e) = cmd.status() {
-					eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
-				}
-			}
+	for command in config.folder_commands(folder_config) {
+		if keys_triggered(&command.input, input_key, modifiers) {
+			execute_command(command, img_path, folder_path);
 		}
 	}
 }
 
+/// Runs the user command and waits for it to exit. The paths have to be str for the same reason
+/// as with `execute_triggered_commands`.
+pub fn execute_command(command: &configuration::Command, img_path: &str, folder_path: &str) {
+	let mut var_map = HashMap::with_capacity(2);
+	var_map.insert("${img}", img_path);
+	var_map.insert("${folder}", folder_path);
+	let mut cmd = Command::new(&command.program);
+	if let Some(ref args) = command.args {
+		cmd.args(args.iter().map(|arg| substitute_command_parameters(arg, &var_map)));
+	}
+	if let Some(ref envs) = command.envs {
+		cmd.envs(envs.iter().map(|env_var| (env_var.name.as_str(), env_var.value.as_str())));
+	}
+	if let Err(e) = cmd.status() {
+		eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
+	}
+}
+
+/// Starts the program with the image, without waiting for it to exit. The paths have to be str
+/// for the same reason as with `execute_triggered_commands`.
+pub fn open_with(
+	program: &OpenWithProgram,
+	img_path: &str,
+	folder_path: &str,
+) -> std::io::Result<()> {
+	let mut var_map = HashMap::with_capacity(2);
+	var_map.insert("${img}", img_path);
+	var_map.insert("${folder}", folder_path);
+	let mut cmd = Command::new(&program.program);
+	match program.args {
+		Some(ref args) => {
+			cmd.args(args.iter().map(|arg| substitute_command_parameters(arg, &var_map)));
+		}
+		None => {
+			cmd.arg(img_path);
+		}
+	}
+	if let Some(ref envs) = program.envs {
+		cmd.envs(envs.iter().map(|env_var| (env_var.name.as_str(), env_var.value.as_str())));
+	}
+	cmd.spawn().map(|_| ())
+}
+
+/// The modifiers that bindings can have, in lowercase
+const MODIFIER_NAMES: [&str; 5] = ["alt", "ctrl", "logo", "shift", "cmdctrl"];
+
+/// Returns why the binding can never be triggered, or `None` if it looks fine. The key itself
+/// isn't checked, because it can be any character that the keyboard layout types.
+pub fn binding_problem(binding: &str) -> Option<String> {
+	let parts = binding.split('+').map(|s| s.trim().to_lowercase()).collect::<Vec<_>>();
+	let (key, modifiers) = parts.split_last()?;
+	if key.is_empty() {
+		return Some(format!("{:?} has no key, the + key is called \"Add\"", binding));
+	}
+	let unknown = modifiers.iter().find(|modifier| !MODIFIER_NAMES.contains(&modifier.as_str()))?;
+	Some(format!("{:?} has an unknown modifier {:?}", binding, unknown))
+}
+
 pub fn keys_triggered<S: AsRef<str>>(
 	keys: &[S],
 	input_key: &str,
@@ -128,11 +411,13 @@ pub fn keys_triggered<S: AsRef<str>>(
 		let mut has_alt = false;
 		let mut has_ctrl = false;
 		let mut has_logo = false;
+		let mut has_shift = false;
 		for mod_str in parts.iter().take(parts.len() - 1) {
 			match mod_str.as_ref() {
 				"alt" => has_alt = true,
 				"ctrl" => has_ctrl = true,
 				"logo" => has_logo = true,
+				"shift" => has_shift = true,
 				"cmdctrl" => {
 					if cfg!(target_os = "macos") {
 						has_logo = true;
@@ -146,6 +431,7 @@ pub fn keys_triggered<S: AsRef<str>>(
 		if has_alt == modifiers.alt_key()
 			&& has_ctrl == modifiers.control_key()
 			&& has_logo == modifiers.super_key()
+			&& has_shift == modifiers.shift_key()
 		{
 			return true;
 		}
@@ -164,8 +450,78 @@ pub fn action_triggered(
 	if let Some(Some(keys)) = bindings.map(|b| b.get(action_name)) {
 		keys_triggered(keys.as_slice(), input_key, modifiers)
 	} else {
-		let empty = Vec::new();
-		let keys = DEFAULT_BINDINGS.get(action_name).unwrap_or(&empty);
-		keys_triggered(keys.as_slice(), input_key, modifiers)
+		let keys = find_action(action_name).map_or(&[][..], |action| action.default_keys);
+		keys_triggered(keys, input_key, modifiers)
+	}
+}
+
+/// Returns the folder that the input moves or copies the current image into, and true if it
+/// copies.
+pub fn quick_sort_target(
+	config: &Configuration,
+	input_key: &str,
+	modifiers: ModifiersState,
+) -> Option<(PathBuf, bool)> {
+	let section = config.quick_sort.as_ref()?;
+	let targets = [(&section.move_to, false), (&section.copy_to, true)];
+	targets.iter().find_map(|(folders, copy)| {
+		let mut folders = folders.iter().flatten();
+		let found = folders.find(|(key, _)| keys_triggered(&[key], input_key, modifiers));
+		found.map(|(_, folder)| (folder.clone(), *copy))
+	})
+}
+
+/// Returns the tag that the input adds to or removes from the current image
+pub fn tag_key_target(
+	config: &Configuration,
+	input_key: &str,
+	modifiers: ModifiersState,
+) -> Option<String> {
+	let tag_keys = config.metadata.as_ref()?.tag_keys.as_ref()?;
+	let mut tag_keys = tag_keys.iter();
+	let found = tag_keys.find(|(key, _)| keys_triggered(&[key], input_key, modifiers));
+	found.map(|(_, tag)| tag.clone())
+}
+
+/// Returns the modifiers that the bindings are matched against. They have to match the held
+/// modifiers exactly, but if nothing is bound to the input with Shift, Shift is ignored,
+/// because it's often needed for typing the key itself.
+pub fn binding_modifiers(
+	config: &Configuration,
+	folder_config: &FolderConfiguration,
+	input_key: &str,
+	modifiers: ModifiersState,
+) -> ModifiersState {
+	if !modifiers.shift_key() || input_bound(config, folder_config, input_key, modifiers) {
+		return modifiers;
+	}
+	let mut without_shift = modifiers;
+	without_shift.remove(ModifiersState::SHIFT);
+	without_shift
+}
+
+/// Returns true if the input triggers any action or user command.
+pub fn input_bound(
+	config: &Configuration,
+	folder_config: &FolderConfiguration,
+	input_key: &str,
+	modifiers: ModifiersState,
+) -> bool {
+	let configured_actions = config.bindings.iter().flatten().map(|(name, _)| name.as_str());
+	let mut actions = configured_actions.chain(ACTIONS.iter().map(|action| action.name));
+	let mut commands = config.folder_commands(folder_config).into_iter();
+	actions.any(|name| keys_triggered(&action_keys(config, name), input_key, modifiers))
+		|| commands.any(|command| keys_triggered(&command.input, input_key, modifiers))
+		|| quick_sort_target(config, input_key, modifiers).is_some()
+		|| tag_key_target(config, input_key, modifiers).is_some()
+}
+
+/// Returns the keys that trigger the action, taking the user's configuration into account.
+pub fn action_keys(config: &Configuration, action_name: &str) -> Vec<String> {
+	if let Some(Some(keys)) = config.bindings.as_ref().map(|b| b.get(action_name)) {
+		keys.clone()
+	} else {
+		let keys = find_action(action_name).map(|action| action.default_keys);
+		keys.map(|keys| keys.iter().map(|k| k.to_string()).collect()).unwrap_or_default()
 	}
 }
@@ -2,6 +2,7 @@
 
 use std::cell::{Cell, RefCell};
 use std::f32;
+use std::io::Read;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -14,8 +15,10 @@ use lazy_static::lazy_static;
 use log::{debug, trace};
 
 use gelatin::winit::{
+	self,
 	dpi::{PhysicalPosition, PhysicalSize},
-	event::WindowEvent,
+	event::{Event, WindowEvent},
+	event_loop::EventLoopProxy,
 	window::Icon,
 };
 use gelatin::{
@@ -30,24 +33,48 @@ use gelatin::{
 	NextUpdate, Widget,
 };
 
-use crate::configuration::Theme;
-use crate::configuration::{Cache, ConfigWindowSection, Configuration};
+use crate::config_watcher::{ConfigChange, ConfigWatcher};
+use crate::configuration::{
+	Cache, ConfigUpdateSection, ConfigWindowSection, Configuration, Theme, ThemeSetting, ViewZoom,
+};
+use crate::image_cache::image_loader::{self, ImageLoaderError};
+use crate::onboarding::OnboardingAnswers;
+use crate::self_test::SelfTest;
+use crate::single_instance::InstanceListener;
 use crate::version::Version;
 use crate::widgets::{
-	bottom_bar::BottomBar, copy_notification::CopyNotifications, help_screen::*, picture_widget::*,
+	adjustments_panel::AdjustmentsPanel, bottom_bar::BottomBar, help_screen::*, picture_widget::*,
+	text_overlay::PictureOverlays, toast::Toasts,
 };
 
 mod clipboard_handler;
 mod cmd_line;
+mod config_validation;
+mod config_watcher;
 mod configuration;
+mod contact_sheet;
+mod deletion;
+#[cfg(feature = "dialogs")]
+mod dialogs;
+mod display_profile;
+mod display_transform;
+mod export;
+mod file_info;
+mod file_operations;
 mod handle_panic;
 mod image_cache;
 mod input_handling;
+mod onboarding;
 mod parallel_action;
 mod playback_manager;
+mod power;
+mod self_test;
 mod shaders;
+mod single_instance;
+mod text_rendering;
 mod utils;
 mod version;
+mod wallpaper;
 mod widgets;
 
 lazy_static! {
@@ -56,6 +83,15 @@ lazy_static! {
 	pub static ref PROJECT_DIRS: Option<ProjectDirs> = ProjectDirs::from("", "", "Emulsion");
 }
 
+/// Starting with `--software-render` is suggested after this many failed starts in a row
+const FAILED_STARTS_BEFORE_SUGGESTION: u32 = 2;
+
+/// The limits of `ui_scale` in the `[window]` section of the config
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 4.0;
+
+const WEBSITE_URL: &str = "https://arturkovacs.github.io/emulsion-website/";
+
 static NEW_VERSION: &[u8] = include_bytes!("../resource/new-version-available.png");
 static NEW_VERSION_LIGHT: &[u8] = include_bytes!("../resource/new-version-available-light.png");
 static VISIT_SITE: &[u8] = include_bytes!("../resource/visit-site.png");
@@ -81,20 +117,71 @@ fn main() {
 	let (config_path, cache_path) = get_config_and_cache_paths();
 
 	let args = cmd_line::parse_args(&config_path, &cache_path);
+	if args.software_render {
+		request_software_rendering();
+	}
+
+	if let Some(path) = &args.info {
+		std::process::exit(file_info::run(path, args.json));
+	}
+
+	// This blocks until the other end of the pipe is closed, so it's done before the window
+	// is created
+	let stdin_bytes = match args.file_path.as_deref() {
+		Some("-") => Some(read_stdin()),
+		_ => None,
+	};
 
 	let cache = Cache::load(&cache_path);
 	let config = Configuration::load(&config_path);
 
 	debug!("Read cache: {cache:#?}");
 	debug!("Read config: {config:#?}");
+	let config_warnings = config_validation::validate_file(&config_path);
+	config_validation::print_warnings(&config_path, &config_warnings);
 
 	let first_launch = cache.is_err();
 	let cache = Arc::new(Mutex::new(cache.unwrap_or_default()));
 	let config = Rc::new(RefCell::new(config.unwrap_or_default()));
 
-	if args.displayed_folders.is_some() {
-		config.borrow_mut().title.get_or_insert_with(Default::default).displayed_folders =
-			args.displayed_folders;
+	config.borrow_mut().overrides.displayed_folders = args.displayed_folders;
+	config.borrow_mut().overrides.filter = args.filter;
+	config.borrow_mut().overrides.software_render = args.software_render;
+	config.borrow_mut().overrides.exit_after_last = args.exit_after_last;
+	// The config file may not exist yet
+	config.borrow_mut().file_path = config_path.clone();
+
+	if let Some(contact_sheet_args) = &args.contact_sheet {
+		std::process::exit(contact_sheet::run(contact_sheet_args, &config.borrow()));
+	}
+
+	let single_instance = !args.self_test
+		&& config.borrow().window.as_ref().and_then(|w| w.single_instance).unwrap_or(false);
+	let cache_folder = cache_path.parent().map(|p| p.to_owned()).unwrap_or_default();
+	if single_instance {
+		if let Some(file_path) = args.file_path.as_deref().filter(|&p| p != "-") {
+			if single_instance::forward_to_running_instance(&cache_folder, file_path.as_ref()) {
+				return;
+			}
+		}
+	}
+
+	// Counted as a failure until the window is open, because a broken graphics driver may take
+	// the whole process down
+	{
+		let mut cache = cache.lock().unwrap();
+		let failed_starts = cache.graphics.failed_starts;
+		if failed_starts >= FAILED_STARTS_BEFORE_SUGGESTION && !args.software_render {
+			eprintln!(
+				"The window couldn't be opened the last {} times. If the graphics driver is \
+				broken, try starting Emulsion with --software-render",
+				failed_starts
+			);
+		}
+		cache.graphics.failed_starts += 1;
+		if let Err(e) = cache.save(&cache_path) {
+			eprintln!("{}", e);
+		}
 	}
 
 	let mut application = Application::<()>::new();
@@ -132,6 +219,9 @@ fn main() {
 			.size(size)
 			.position(Some(pos))
 			.app_id(Some("Emulsion".into()))
+			.software_rendering(args.software_render)
+			.transparent(window_cfg.as_ref().is_some_and(|cfg| cfg.opacity.is_some()))
+			.deep_color(window_cfg.as_ref().and_then(|cfg| cfg.deep_color).unwrap_or(false))
 			.build()
 			.unwrap();
 		let window = Window::new(&mut application, window_desc);
@@ -139,43 +229,77 @@ fn main() {
 		if let Some(ConfigWindowSection { start_fullscreen: Some(true), .. }) = window_cfg {
 			window.set_fullscreen(true);
 		}
+		if let Some(window_cfg) = window_cfg {
+			apply_window_config(&window, window_cfg);
+		}
 		window
 	};
+	{
+		let mut cache = cache.lock().unwrap();
+		cache.graphics.failed_starts = 0;
+		if let Err(e) = cache.save(&cache_path) {
+			eprintln!("{}", e);
+		}
+	}
 	add_window_movement_listener(&window, cache.clone());
 
+	let config_watcher = Rc::new(RefCell::new(ConfigWatcher::new(
+		config.clone(),
+		config_path.clone(),
+		application.create_loop_proxy(),
+	)));
+	{
+		let config_watcher = config_watcher.clone();
+		application.add_global_event_handler(move |event| {
+			if let Event::UserEvent(()) = event {
+				config_watcher.borrow_mut().update();
+			}
+			NextUpdate::Latest
+		});
+	}
+
 	let update_label_image = Rc::new(Picture::from_encoded_bytes(NEW_VERSION));
 	let update_label_image_light = Rc::new(Picture::from_encoded_bytes(NEW_VERSION_LIGHT));
 	let update_label = make_update_label();
 
 	let update_notification = make_update_notification(update_label.clone());
 
-	let usage_img = Picture::from_encoded_bytes(USAGE);
-	let help_screen = Rc::new(HelpScreen::new(usage_img));
-	let left_to_pan_img = Picture::from_encoded_bytes(LEFT_TO_PAN);
-	let left_to_pan_hint = Rc::new(HelpScreen::new(left_to_pan_img));
-
-	let copy_notifications_widget = Rc::new(Label::new());
-	let copy_notifications = CopyNotifications::new(&copy_notifications_widget);
-
-	let bottom_bar = Rc::new(BottomBar::new(&config.borrow()));
-	let picture_widget = make_picture_widget(
-		&window,
-		bottom_bar.clone(),
-		left_to_pan_hint.clone(),
-		copy_notifications,
-		config.clone(),
-		cache.clone(),
-	);
+	let proxy = application.create_loop_proxy();
+	let ViewerWidgets { picture_widget, bottom_bar, help_screen, picture_area_container } =
+		make_viewer_widgets(&window, config.clone(), cache.clone(), &config_watcher, &proxy);
 
-	if let Some(file_path) = args.file_path {
+	let session_to_restore = last_session(&config.borrow(), &cache.lock().unwrap());
+	let self_test_exit_code = Rc::new(Cell::new(None));
+	if args.self_test {
+		match SelfTest::new(picture_widget.clone(), self_test_exit_code.clone()) {
+			Ok(mut self_test) => application.add_global_event_handler(move |_| self_test.update()),
+			Err(e) => {
+				eprintln!("Could not create the self-test images: {}", e);
+				std::process::exit(1);
+			}
+		}
+	} else if let Some(stdin_bytes) = stdin_bytes {
+		// Decoded only now, because the decode limit is set from the config by the picture widget
+		let stdin_image = stdin_bytes
+			.map_err(ImageLoaderError::Io)
+			.and_then(|bytes| image_loader::load_from_memory(&bytes));
+		match stdin_image {
+			Ok((image, orientation)) => picture_widget.show_memory_image(image, orientation),
+			Err(e) => eprintln!("Could not open the image from the standard input: {}", e),
+		}
+	} else if let Some(file_path) = args.file_path {
 		picture_widget.jump_to_path(file_path);
+		// Start loading the image before anything else, so that it's shown as soon as possible
+		picture_widget.dispatch_load_request();
+	} else if let Some((file_path, zoom)) = session_to_restore {
+		picture_widget.restore_session(file_path, zoom);
+		picture_widget.dispatch_load_request();
 	}
+	picture_widget.show_config_warnings(&config_warnings);
+	help_screen.decode_in_background();
+	update_label_image.decode_in_background();
+	update_label_image_light.decode_in_background();
 
-	let picture_area_container = make_picture_area_container();
-	picture_area_container.add_child(picture_widget.clone());
-	picture_area_container.add_child(copy_notifications_widget);
-	picture_area_container.add_child(left_to_pan_hint);
-	picture_area_container.add_child(help_screen.clone());
 	picture_area_container.add_child(update_notification.clone());
 
 	let root_container = make_root_container();
@@ -185,12 +309,7 @@ fn main() {
 	let update_available = Arc::new(AtomicBool::new(false));
 	let update_check_done = Arc::new(AtomicBool::new(false));
 
-	let theme = {
-		Rc::new(Cell::new(match &config.borrow().window {
-			Some(ConfigWindowSection { theme: Some(theme_cfg), .. }) => *theme_cfg,
-			_ => cache.lock().unwrap().theme(),
-		}))
-	};
+	let theme = Rc::new(Cell::new(initial_theme(&config.borrow(), &cache, &window)));
 
 	let set_theme = {
 		let update_label = update_label;
@@ -202,16 +321,13 @@ fn main() {
 		let bottom_bar = bottom_bar.clone();
 
 		Rc::new(move || {
+			apply_theme(theme.get(), &window, &picture_widget);
 			match theme.get() {
 				Theme::Light => {
-					picture_widget.set_bright_shade(0.96);
-					window.set_bg_color([0.85, 0.85, 0.85, 1.0]);
 					update_notification.set_bg_color([0.06, 0.06, 0.06, 1.0]);
 					update_label.set_icon(Some(update_label_image_light.clone()));
 				}
 				Theme::Dark => {
-					picture_widget.set_bright_shade(0.11);
-					window.set_bg_color([0.03, 0.03, 0.03, 1.0]);
 					update_notification.set_bg_color([0.85, 0.85, 0.85, 1.0]);
 					update_label.set_icon(Some(update_label_image.clone()));
 				}
@@ -220,8 +336,18 @@ fn main() {
 		})
 	};
 	set_theme();
+	{
+		let set_theme = set_theme.clone();
+		follow_system_theme(&window, config.clone(), theme.clone(), move || set_theme());
+	}
+	{
+		let set_theme = set_theme.clone();
+		let (cache, theme) = (cache.clone(), theme.clone());
+		follow_config_theme(&config_watcher, &window, cache, theme, move || set_theme());
+	}
 	{
 		let cache = cache.clone();
+		let theme = theme.clone();
 		let set_theme = set_theme.clone();
 		bottom_bar.theme_button.set_on_click(move || {
 			let new_theme = theme.get().switch_theme();
@@ -230,31 +356,43 @@ fn main() {
 			set_theme();
 		});
 	}
-	{
-		let slider = bottom_bar.slider.clone();
-		let picture_widget = picture_widget.clone();
-		bottom_bar.slider.set_on_value_change(move || {
-			picture_widget.jump_to_index(slider.value());
-		});
-	}
-	{
-		let picture_widget = picture_widget.clone();
-		bottom_bar.orig_scale_button.set_on_click(move || {
-			picture_widget.set_img_size_to_orig();
-		});
-	}
-	{
-		let picture_widget = picture_widget.clone();
-		bottom_bar.fit_best_button.set_on_click(move || {
-			picture_widget.set_img_size_to_fit(false);
-		});
-	}
-	{
-		bottom_bar.fit_stretch_button.set_on_click(move || {
-			picture_widget.set_img_size_to_fit(true);
+	// The questions of the first launch replace the help screen
+	if first_launch && !args.self_test {
+		let config = config.clone();
+		let cache = cache.clone();
+		let theme = theme.clone();
+		let set_theme = set_theme.clone();
+		let config_path = config_path.clone();
+		picture_widget.start_onboarding(move |answers: &OnboardingAnswers| {
+			theme.set(answers.theme);
+			cache.lock().unwrap().set_theme(answers.theme);
+			set_theme();
+			{
+				let mut config = config.borrow_mut();
+				let window_cfg = config.window.get_or_insert_with(Default::default);
+				window_cfg.theme = Some(answers.theme.into());
+				config.updates = Some(ConfigUpdateSection { check_updates: answers.check_updates });
+				let image = config.image.get_or_insert_with(Default::default);
+				image.fit_stretches = Some(answers.fit_stretches);
+			}
+			if let Err(e) = onboarding::save_answers(answers, &config_path) {
+				eprintln!("Could not save the answers: {}", e);
+			}
+			if answers.open_website {
+				if let Err(e) = open::that(WEBSITE_URL) {
+					eprintln!("Could not open the website: {}", e);
+				}
+			}
 		});
 	}
-	let help_visible = Cell::new(first_launch);
+	picture_widget.set_on_new_window(new_window_action(
+		config.clone(),
+		cache.clone(),
+		theme,
+		config_watcher,
+		proxy,
+	));
+	let help_visible = Cell::new(false);
 	help_screen.set_visible(help_visible.get());
 	update_notification.set_visible(help_visible.get() && update_available.load(Ordering::SeqCst));
 	{
@@ -274,8 +412,38 @@ fn main() {
 
 	window.set_root(root_container);
 
+	if single_instance {
+		match InstanceListener::start(&cache_folder, application.create_loop_proxy()) {
+			Ok(listener) => {
+				let picture_widget = picture_widget.clone();
+				let window = window.clone();
+				application.add_global_event_handler(move |event| {
+					if let Event::UserEvent(()) = event {
+						// The config and folder watchers wake up the event loop too
+						let received = listener.take_received();
+						if received.is_empty() {
+							return NextUpdate::Latest;
+						}
+						for path in received {
+							picture_widget.jump_to_path(path);
+						}
+						{
+							let winit_window = window.window_mut();
+							winit_window.set_minimized(false);
+							winit_window.focus_window();
+						}
+						window.request_redraw();
+					}
+					NextUpdate::Latest
+				});
+			}
+			Err(e) => eprintln!("Could not listen for the images opened by other instances: {}", e),
+		}
+	}
+
+	// On the first launch the user is asked first
 	let check_updates_enabled =
-		config.borrow().updates.as_ref().map(|u| u.check_updates).unwrap_or(true);
+		!first_launch && config.borrow().updates.as_ref().map(|u| u.check_updates).unwrap_or(true);
 
 	let update_checker_join_handle = {
 		let updates = &mut cache.lock().unwrap().updates;
@@ -314,15 +482,58 @@ fn main() {
 	});
 
 	application.set_at_exit(Some(move || {
+		picture_widget.remember_view();
+		picture_widget.remember_session();
 		cache.lock().unwrap().save(cache_path).unwrap();
 		if let Some(h) = update_checker_join_handle {
 			h.join().unwrap();
 		}
 	}));
 	application.start_event_loop();
+	if let Some(exit_code) = self_test_exit_code.get() {
+		std::process::exit(exit_code);
+	}
 }
 // ========================================================
 
+/// The image of the previous session and the zoom that it was shown with, if the config asks for
+/// restoring them
+fn last_session(config: &Configuration, cache: &Cache) -> Option<(PathBuf, Option<ViewZoom>)> {
+	let window_cfg = config.window.as_ref()?;
+	if window_cfg.restore_last_image != Some(true) {
+		return None;
+	}
+	let path = PathBuf::from(cache.session.last_file.as_ref()?);
+	if !path.is_file() {
+		return None;
+	}
+	let zoom = cache.session.zoom.filter(|_| window_cfg.restore_last_zoom == Some(true));
+	Some((path, zoom))
+}
+
+/// Mesa reads these variables when the OpenGL library is loaded. They're set before any other
+/// thread is started, because setting environment variables isn't thread safe.
+#[cfg(not(any(target_os = "macos", windows)))]
+fn request_software_rendering() {
+	std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+	std::env::set_var("GALLIUM_DRIVER", "llvmpipe");
+}
+
+/// The software renderer is chosen by the config of the OpenGL context
+#[cfg(target_os = "macos")]
+fn request_software_rendering() {}
+
+#[cfg(windows)]
+fn request_software_rendering() {
+	log::warn!("Software rendering is not available on Windows, the GPU is used instead");
+}
+
+fn read_stdin() -> std::io::Result<Vec<u8>> {
+	let mut bytes = Vec::new();
+	std::io::stdin().lock().read_to_end(&mut bytes)?;
+	Ok(bytes)
+}
+
 fn make_icon() -> Icon {
 	let img = image::load_from_memory(include_bytes!("../resource/emulsion48.png")).unwrap();
 	let rgba = img.into_rgba8();
@@ -348,6 +559,399 @@ fn add_window_movement_listener(window: &Window, cache: Arc<Mutex<Cache>>) {
 	});
 }
 
+/// The widgets that every window has. Each window has its own picture widget, and thus its own
+/// playback manager and image cache.
+struct ViewerWidgets {
+	picture_widget: Rc<PictureWidget>,
+	bottom_bar: Rc<BottomBar>,
+	help_screen: Rc<HelpScreen>,
+	/// Contains the picture widget and the widgets displayed over it
+	picture_area_container: Rc<VerticalLayoutContainer>,
+}
+
+fn make_viewer_widgets(
+	window: &Rc<Window>,
+	config: Rc<RefCell<Configuration>>,
+	cache: Arc<Mutex<Cache>>,
+	config_watcher: &RefCell<ConfigWatcher>,
+	proxy: &EventLoopProxy<()>,
+) -> ViewerWidgets {
+	image_cache::hdr_image::set_deep_color(window.color_bits() >= 10);
+	let usage_img = Picture::from_encoded_bytes(USAGE);
+	let help_screen = Rc::new(HelpScreen::new(usage_img));
+	let left_to_pan_img = Picture::from_encoded_bytes(LEFT_TO_PAN);
+	let left_to_pan_hint = Rc::new(HelpScreen::new(left_to_pan_img));
+
+	let toasts = Toasts::new();
+	let toasts_widget = toasts.widget.clone();
+
+	let overlays = PictureOverlays::new();
+	let overlay_widgets = overlays.widgets();
+	let rename_box = overlays.rename_box.clone();
+	let zoom_box = overlays.zoom_box.clone();
+	let command_palette = overlays.command_palette.text_box.clone();
+
+	let bottom_bar = Rc::new(BottomBar::new(&config.borrow()));
+	let picture_widget = make_picture_widget(
+		window,
+		bottom_bar.clone(),
+		left_to_pan_hint.clone(),
+		toasts,
+		overlays,
+		config.clone(),
+		cache.clone(),
+		proxy.clone(),
+	);
+
+	let adjustments_panel = Rc::new(AdjustmentsPanel::new());
+	picture_widget.set_adjustments_panel(adjustments_panel.clone());
+	let picture_row = make_picture_row();
+	picture_row.add_child(picture_widget.clone());
+	{
+		// Only weak references are held, because the picture widget holds on to this
+		let window = Rc::downgrade(window);
+		let picture_row = Rc::downgrade(&picture_row);
+		let picture_widget_weak = Rc::downgrade(&picture_widget);
+		let bottom_bar = bottom_bar.clone();
+		let left_to_pan_hint = left_to_pan_hint.clone();
+		let proxy = proxy.clone();
+		picture_widget.set_compare_widget_factory(move || {
+			let window = window.upgrade()?;
+			let picture_row = picture_row.upgrade()?;
+			let picture_widget = picture_widget_weak.upgrade()?;
+			// Shows the pinned image of the compare mode. Its notifications and overlays are not
+			// displayed, because the other picture widget has those.
+			let compare_widget = make_picture_widget(
+				&window,
+				bottom_bar.clone(),
+				left_to_pan_hint.clone(),
+				Toasts::new(),
+				PictureOverlays::new(),
+				config.clone(),
+				cache.clone(),
+				proxy.clone(),
+			);
+			// The pinned image is on the left
+			picture_row.remove_child(picture_widget.clone());
+			picture_row.add_child(compare_widget.clone());
+			picture_row.add_child(picture_widget);
+			Some(compare_widget)
+		});
+	}
+
+	let picture_area_container = make_picture_area_container();
+	picture_area_container.add_child(picture_row);
+	picture_area_container.add_child(toasts_widget);
+	for overlay_widget in overlay_widgets {
+		picture_area_container.add_child(overlay_widget);
+	}
+	picture_area_container.add_child(rename_box.clone());
+	picture_area_container.add_child(zoom_box.clone());
+	picture_area_container.add_child(command_palette.clone());
+	picture_area_container.add_child(adjustments_panel.widget.clone());
+	picture_area_container.add_child(left_to_pan_hint);
+	picture_area_container.add_child(help_screen.clone());
+
+	// The picture widget holds on to the bottom bar, so the callbacks only hold weak references
+	// to it. Otherwise the widgets of a closed window would never be freed.
+	{
+		let bottom_bar = Rc::downgrade(&bottom_bar);
+		picture_widget.subscribe_playback(move |event| {
+			if let Some(bottom_bar) = bottom_bar.upgrade() {
+				bottom_bar.handle_playback_event(event);
+			}
+		});
+	}
+	{
+		let bottom_bar = Rc::downgrade(&bottom_bar);
+		window.add_global_event_handler(move |window, event| {
+			if let Some(bottom_bar) = bottom_bar.upgrade() {
+				bottom_bar.handle_window_event(window, event);
+			}
+		});
+	}
+	{
+		let slider = Rc::downgrade(&bottom_bar.slider);
+		let picture_widget = Rc::downgrade(&picture_widget);
+		bottom_bar.slider.set_on_value_change(move || {
+			let widgets = (picture_widget.upgrade(), slider.upgrade());
+			if let (Some(picture_widget), Some(slider)) = widgets {
+				picture_widget.jump_to_index(slider.value());
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		config_watcher.borrow_mut().subscribe(move |change| {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.apply_config_change(change);
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		rename_box.set_on_submit(move |new_name| {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.rename_current_file(&new_name);
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		zoom_box.set_on_submit(move |text| {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.set_zoom_from_text(&text);
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		command_palette.set_on_submit(move |_| {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.run_palette_selection();
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		bottom_bar.zoom_button.set_on_click(move || {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.start_zoom_input();
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		bottom_bar.orig_scale_button.set_on_click(move || {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.set_img_size_to_orig();
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		bottom_bar.fit_best_button.set_on_click(move || {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.set_img_size_to_fit(false);
+			}
+		});
+	}
+	{
+		let picture_widget = Rc::downgrade(&picture_widget);
+		bottom_bar.fit_stretch_button.set_on_click(move || {
+			if let Some(picture_widget) = picture_widget.upgrade() {
+				picture_widget.set_img_size_to_fit(true);
+			}
+		});
+	}
+	ViewerWidgets { picture_widget, bottom_bar, help_screen, picture_area_container }
+}
+
+/// Applies the always on top, borderless and opacity options
+fn apply_window_config(window: &Window, window_cfg: &ConfigWindowSection) {
+	if let Some(always_on_top) = window_cfg.always_on_top {
+		window.set_always_on_top(always_on_top);
+	}
+	if let Some(borderless) = window_cfg.borderless {
+		window.set_borderless(borderless);
+	}
+	if let Some(opacity) = window_cfg.opacity {
+		window.set_opacity(opacity);
+	}
+	if let Some(ui_scale) = window_cfg.ui_scale {
+		window.set_ui_scale(ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE));
+	}
+}
+
+/// The theme from the config, or the one that was last chosen with the theme button
+fn initial_theme(config: &Configuration, cache: &Mutex<Cache>, window: &Window) -> Theme {
+	let setting = config.window.as_ref().and_then(|window_cfg| window_cfg.theme);
+	match setting {
+		Some(ThemeSetting::Light) => Theme::Light,
+		Some(ThemeSetting::Dark) => Theme::Dark,
+		Some(ThemeSetting::Auto) => match system_theme(window) {
+			Some(theme) => theme,
+			None => cache.lock().unwrap().theme(),
+		},
+		None => cache.lock().unwrap().theme(),
+	}
+}
+
+/// Returns `None` if the operating system doesn't tell whether it prefers a dark theme
+fn system_theme(window: &Window) -> Option<Theme> {
+	let theme = window.window_mut().theme()?;
+	Some(theme_from_winit(theme))
+}
+
+fn theme_from_winit(theme: winit::window::Theme) -> Theme {
+	match theme {
+		winit::window::Theme::Light => Theme::Light,
+		winit::window::Theme::Dark => Theme::Dark,
+	}
+}
+
+/// Switches the theme when the preference of the operating system changes, if the theme is set
+/// to "auto" in the config
+fn follow_system_theme<F: Fn() + 'static>(
+	window: &Window,
+	config: Rc<RefCell<Configuration>>,
+	theme: Rc<Cell<Theme>>,
+	set_theme: F,
+) {
+	window.add_global_event_handler(move |_, event| {
+		if let WindowEvent::ThemeChanged(system_theme) = event {
+			let config = config.borrow();
+			let setting = config.window.as_ref().and_then(|window_cfg| window_cfg.theme);
+			if setting == Some(ThemeSetting::Auto) {
+				theme.set(theme_from_winit(*system_theme));
+				set_theme();
+			}
+		}
+	});
+}
+
+/// Switches the theme when the `theme` option is changed in the config file
+fn follow_config_theme<F: Fn() + 'static>(
+	config_watcher: &RefCell<ConfigWatcher>,
+	window: &Rc<Window>,
+	cache: Arc<Mutex<Cache>>,
+	theme: Rc<Cell<Theme>>,
+	set_theme: F,
+) {
+	let window = Rc::downgrade(window);
+	config_watcher.borrow_mut().subscribe(move |change| {
+		let (previous, config) = match *change {
+			ConfigChange::Reloaded { previous, config, .. } => (previous, config),
+			ConfigChange::Failed(_) => return,
+		};
+		let setting = |config: &Configuration| config.window.as_ref().and_then(|w| w.theme);
+		if setting(config) == setting(previous) {
+			return;
+		}
+		if let Some(window) = window.upgrade() {
+			theme.set(initial_theme(config, &cache, &window));
+			set_theme();
+		}
+	});
+}
+
+fn apply_theme(theme: Theme, window: &Window, picture_widget: &PictureWidget) {
+	match theme {
+		Theme::Light => {
+			picture_widget.set_bright_shade(0.96);
+			window.set_bg_color([0.85, 0.85, 0.85, 1.0]);
+		}
+		Theme::Dark => {
+			picture_widget.set_bright_shade(0.11);
+			window.set_bg_color([0.03, 0.03, 0.03, 1.0]);
+		}
+	}
+	picture_widget.set_toast_theme(theme);
+}
+
+fn new_window_action(
+	config: Rc<RefCell<Configuration>>,
+	cache: Arc<Mutex<Cache>>,
+	theme: Rc<Cell<Theme>>,
+	config_watcher: Rc<RefCell<ConfigWatcher>>,
+	proxy: EventLoopProxy<()>,
+) -> impl Fn(Option<PathBuf>) {
+	move |path| {
+		let watcher = config_watcher.clone();
+		open_new_window(path, config.clone(), cache.clone(), theme.clone(), watcher, proxy.clone())
+	}
+}
+
+/// Opens an additional window with its own picture widget. The window is created by the event
+/// loop, so this can be called while the event loop is running.
+fn open_new_window(
+	path: Option<PathBuf>,
+	config: Rc<RefCell<Configuration>>,
+	cache: Arc<Mutex<Cache>>,
+	theme: Rc<Cell<Theme>>,
+	config_watcher: Rc<RefCell<ConfigWatcher>>,
+	proxy: EventLoopProxy<()>,
+) {
+	let size = {
+		let window_cache = &cache.lock().unwrap().window;
+		PhysicalSize::new(window_cache.win_w, window_cache.win_h)
+	};
+	let window_cfg = config.borrow().window.clone();
+	let window_desc = WindowDescriptorBuilder::default()
+		.icon(Some(make_icon()))
+		.size(size)
+		.app_id(Some("Emulsion".into()))
+		.software_rendering(config.borrow().overrides.software_render)
+		.transparent(window_cfg.as_ref().is_some_and(|cfg| cfg.opacity.is_some()))
+		.deep_color(window_cfg.as_ref().and_then(|cfg| cfg.deep_color).unwrap_or(false))
+		.build()
+		.unwrap();
+	create_window(window_desc, move |window| {
+		if let Some(window_cfg) = &window_cfg {
+			apply_window_config(&window, window_cfg);
+		}
+		let ViewerWidgets { picture_widget, bottom_bar, help_screen, picture_area_container } =
+			make_viewer_widgets(&window, config.clone(), cache.clone(), &config_watcher, &proxy);
+		picture_widget.set_on_new_window(new_window_action(
+			config.clone(),
+			cache.clone(),
+			theme.clone(),
+			config_watcher.clone(),
+			proxy.clone(),
+		));
+		if let Some(path) = path {
+			picture_widget.jump_to_path(path);
+		}
+
+		let set_theme = {
+			let window = Rc::downgrade(&window);
+			let picture_widget = Rc::downgrade(&picture_widget);
+			let bottom_bar = Rc::downgrade(&bottom_bar);
+			let theme = theme.clone();
+			Rc::new(move || {
+				let widgets = (window.upgrade(), picture_widget.upgrade(), bottom_bar.upgrade());
+				if let (Some(window), Some(picture_widget), Some(bottom_bar)) = widgets {
+					apply_theme(theme.get(), &window, &picture_widget);
+					bottom_bar.set_theme(theme.get(), false);
+				}
+			})
+		};
+		set_theme();
+		{
+			let set_theme = set_theme.clone();
+			follow_system_theme(&window, config.clone(), theme.clone(), move || set_theme());
+		}
+		{
+			let set_theme = set_theme.clone();
+			let (cache, theme) = (cache.clone(), theme.clone());
+			follow_config_theme(&config_watcher, &window, cache, theme, move || set_theme());
+		}
+		bottom_bar.theme_button.set_on_click(move || {
+			let new_theme = theme.get().switch_theme();
+			theme.set(new_theme);
+			cache.lock().unwrap().set_theme(new_theme);
+			set_theme();
+		});
+		{
+			let help_visible = Cell::new(false);
+			let bottom_bar_weak = Rc::downgrade(&bottom_bar);
+			help_screen.set_visible(false);
+			bottom_bar.help_button.set_on_click(move || {
+				help_visible.set(!help_visible.get());
+				help_screen.set_visible(help_visible.get());
+				if let Some(bottom_bar) = bottom_bar_weak.upgrade() {
+					bottom_bar.set_help_visible(help_visible.get());
+				}
+			});
+		}
+
+		let root_container = make_root_container();
+		root_container.add_child(picture_area_container);
+		root_container.add_child(bottom_bar.widget.clone());
+		window.set_root(root_container);
+	});
+}
+
 fn make_root_container() -> Rc<VerticalLayoutContainer> {
 	let container = Rc::new(VerticalLayoutContainer::new());
 	container.set_margin_all(0.0);
@@ -364,6 +968,15 @@ fn make_picture_area_container() -> Rc<VerticalLayoutContainer> {
 	picture_area_container
 }
 
+/// Holds the picture widget and the widget of the compare mode next to each other
+fn make_picture_row() -> Rc<HorizontalLayoutContainer> {
+	let picture_row = Rc::new(HorizontalLayoutContainer::new());
+	picture_row.set_margin_all(0.0);
+	picture_row.set_height(Length::Stretch { min: 0.0, max: f32::INFINITY });
+	picture_row.set_width(Length::Stretch { min: 0.0, max: f32::INFINITY });
+	picture_row
+}
+
 fn make_update_label() -> Rc<Label> {
 	let update_label = Rc::new(Label::new());
 	update_label.set_margin_top(4.0);
@@ -388,7 +1001,7 @@ fn make_update_notification(update_label: Rc<Label>) -> Rc<HorizontalLayoutConta
 	update_button.set_fixed_size(LogicalVector::new(100.0, 24.0));
 	update_button.set_horizontal_align(Alignment::Center);
 	update_button.set_on_click(|| {
-		open::that("https://arturkovacs.github.io/emulsion-website/").unwrap();
+		open::that(WEBSITE_URL).unwrap();
 	});
 
 	container.add_child(update_label);
@@ -396,22 +1009,27 @@ fn make_update_notification(update_label: Rc<Label>) -> Rc<HorizontalLayoutConta
 	container
 }
 
+#[allow(clippy::too_many_arguments)]
 fn make_picture_widget(
 	window: &Rc<Window>,
 	bottom_bar: Rc<BottomBar>,
 	left_to_pan_hint: Rc<HelpScreen>,
-	copy_notifications: CopyNotifications,
+	toasts: Toasts,
+	overlays: PictureOverlays,
 	config: Rc<RefCell<Configuration>>,
 	cache: Arc<Mutex<Cache>>,
+	proxy: EventLoopProxy<()>,
 ) -> Rc<PictureWidget> {
 	let picture_widget = Rc::new(PictureWidget::new(
 		&window.display_mut(),
 		window,
 		bottom_bar,
 		left_to_pan_hint,
-		copy_notifications,
+		toasts,
+		overlays,
 		config,
 		cache,
+		proxy,
 	));
 	picture_widget.set_height(Length::Stretch { min: 0.0, max: f32::INFINITY });
 	picture_widget.set_width(Length::Stretch { min: 0.0, max: f32::INFINITY });
@@ -0,0 +1,126 @@
+//! The questions that are asked on the first launch. They set up the options that new users ask
+//! about the most, and the answers are written into the config file.
+
+use std::path::Path;
+
+use crate::configuration::{self, Theme};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnboardingAnswers {
+	pub theme: Theme,
+	pub check_updates: bool,
+	/// Small images are stretched to fit the window too
+	pub fit_stretches: bool,
+	/// Open the website, which lists the key bindings
+	pub open_website: bool,
+}
+
+impl Default for OnboardingAnswers {
+	fn default() -> Self {
+		OnboardingAnswers {
+			theme: Theme::Light,
+			check_updates: true,
+			fit_stretches: false,
+			open_website: false,
+		}
+	}
+}
+
+struct Question {
+	text: &'static str,
+	choices: &'static [&'static str],
+}
+
+const QUESTIONS: &[Question] = &[
+	Question { text: "Which theme do you prefer?", choices: &["Light", "Dark"] },
+	Question {
+		text: "Should Emulsion check for new versions once a day?",
+		choices: &["Yes", "No"],
+	},
+	Question {
+		text: "How should the images fit into the window?",
+		choices: &["Only shrink the large images", "Stretch the small images too"],
+	},
+	Question {
+		text: "The key bindings are listed on the website. They can be changed in the\n\
+			[bindings] section of the config file.",
+		choices: &["Finish", "Finish and open the website"],
+	},
+];
+
+pub struct Onboarding {
+	/// The index of the current question
+	step: usize,
+	answers: OnboardingAnswers,
+}
+
+impl Onboarding {
+	pub fn new() -> Self {
+		Onboarding { step: 0, answers: OnboardingAnswers::default() }
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.step >= QUESTIONS.len()
+	}
+
+	pub fn answers(&self) -> OnboardingAnswers {
+		self.answers
+	}
+
+	/// Answers the current question with the choice at `index`. Choices that the question
+	/// doesn't have are ignored.
+	pub fn answer(&mut self, index: usize) {
+		let question = match QUESTIONS.get(self.step) {
+			Some(question) => question,
+			None => return,
+		};
+		if index >= question.choices.len() {
+			return;
+		}
+		let first = index == 0;
+		match self.step {
+			0 => self.answers.theme = if first { Theme::Light } else { Theme::Dark },
+			1 => self.answers.check_updates = first,
+			2 => self.answers.fit_stretches = !first,
+			_ => self.answers.open_website = !first,
+		}
+		self.step += 1;
+	}
+
+	/// The current question with its choices
+	pub fn text(&self) -> String {
+		let question = match QUESTIONS.get(self.step) {
+			Some(question) => question,
+			None => return String::new(),
+		};
+		let mut text = format!(
+			"Welcome to Emulsion ({} of {})\n\n{}\n",
+			self.step + 1,
+			QUESTIONS.len(),
+			question.text
+		);
+		for (i, choice) in question.choices.iter().enumerate() {
+			text.push_str(&format!("\n{}   {}", i + 1, choice));
+		}
+		text.push_str("\n\nPress the number of an answer, or Escape to skip the questions");
+		text
+	}
+}
+
+impl Default for Onboarding {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Writes the answers into the config file, keeping everything else in it
+pub fn save_answers(answers: &OnboardingAnswers, config_path: &Path) -> Result<(), String> {
+	let theme = toml::Value::try_from(answers.theme).map_err(|e| e.to_string())?;
+	let theme = theme.to_string().parse::<toml_edit::Value>().map_err(|e| e.to_string())?;
+	let options = vec![
+		("window", "theme", Some(theme)),
+		("updates", "check_updates", Some(answers.check_updates.into())),
+		("image", "fit_stretches", Some(answers.fit_stretches.into())),
+	];
+	configuration::save_options(config_path, options)
+}
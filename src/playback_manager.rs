@@ -1,8 +1,11 @@
+use std::ffi::OsStr;
+use std::fs;
 use std::io::Write;
 use std::marker::PhantomData;
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 use rand::seq::SliceRandom;
@@ -10,18 +13,36 @@ use rand::thread_rng;
 
 use log::{debug, trace};
 
+use gelatin::image::RgbaImage;
 use gelatin::window::Window;
+use gelatin::winit::event_loop::EventLoopProxy;
 use gelatin::Display;
 
+use crate::configuration::{ConfigCacheSection, FolderEnd, SortOrder};
 use crate::image_cache::{
-	self, AnimationFrameTexture, ImageCache, PathResolutionError, PathedTextureResult,
-	TextureResult,
+	self, file_filter::FileFilter, image_loader::Orientation, sidecar::MetadataFilter,
+	AnimationFrameTexture, CacheStats, FramePosition, ImageCache, PathResolutionError,
+	PathedTextureResult, TextureResult,
 };
 
 use image_cache::directory;
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 
+/// While saving power animations are played at most at this framerate
+const POWER_SAVING_MAX_FRAMERATE: u64 = 15;
+const POWER_SAVING_PREFETCH_COUNT: usize = 1;
+
+const MAX_LOADER_THREADS: u32 = 32;
+
+pub const DEFAULT_PRESENT_INTERVAL_SECS: f32 = 6.0;
+
+const DEFAULT_SYNC_LOAD_MAX_KB: u64 = 1024;
+/// Large enough for the small files to be decoded, while a slow disk doesn't keep the window
+/// from showing up
+const SYNC_LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+const SYNC_LOAD_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum LoadRequest {
 	None,
@@ -32,7 +53,7 @@ pub enum LoadRequest {
 	Jump(i32),
 }
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum PlaybackState {
 	Paused,
 	Forward,
@@ -41,6 +62,29 @@ pub enum PlaybackState {
 	//Backward,
 }
 
+/// Something that happened to the shown image or to the playback. See
+/// `PlaybackManager::subscribe`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackEvent {
+	/// A different file is shown, or none. The frames of animations don't count.
+	ImageChanged(LoadedImgPath),
+	/// The file couldn't be loaded. Sent after the `ImageChanged` event of the file.
+	LoadFailed {
+		path: PathBuf,
+		error: String,
+	},
+	PlaybackStateChanged(PlaybackState),
+	/// The time that each image is shown for in presentation mode, in seconds
+	PresentIntervalChanged(f32),
+	/// The files of the folder were collected again, or another folder was opened
+	DirectoryChanged,
+	/// The animation was paused or resumed, or another frame is shown while it's paused.
+	/// `None` while the animation plays and for still images.
+	PausedFrameChanged(Option<FramePosition>),
+}
+
+type Subscriber = Box<dyn FnMut(&PlaybackEvent)>;
+
 trait Playback: Sized {
 	fn load_next(image_cache: &mut ImageCache, display: &Display) -> PathedTextureResult;
 
@@ -150,32 +194,140 @@ pub struct PlaybackManager {
 	// filename: Option<OsString>,
 	folder_player: ImgSequencePlayer<FolderPlayback>,
 	image_player: ImgSequencePlayer<AnimPlayback>,
+
+	/// The folder of the shown image if it was deleted or unmounted
+	unavailable_folder: Option<PathBuf>,
+
+	/// An image that isn't backed by a file and is waiting to be uploaded to the GPU
+	pending_memory_image: Option<(RgbaImage, Orientation)>,
+
+	thread_count: u32,
+	/// See `update_image_until_loaded`
+	sync_load_max_bytes: u64,
+
+	/// The reason why the shown file couldn't be loaded
+	load_error: Option<String>,
+
+	subscribers: Vec<Subscriber>,
+	/// What the subscribers were last told about
+	notified_path: LoadedImgPath,
+	notified_state: PlaybackState,
+	notified_directory: u64,
+	notified_frame: Option<FramePosition>,
 }
 
 impl PlaybackManager {
-	pub fn new() -> Self {
-		let cache_capaxity = match sys_info::mem_info() {
-			Ok(value) => {
+	pub fn new(
+		display: &Display,
+		cache_config: &ConfigCacheSection,
+		proxy: EventLoopProxy<()>,
+	) -> Self {
+		let cache_capaxity = match (cache_config.max_memory_mb, sys_info::mem_info()) {
+			(Some(max_memory_mb), _) => (max_memory_mb * 1024 * 1024) as isize,
+			(None, Ok(value)) => {
 				// value originally reported in KiB
 				((value.total / 8) * 1024) as isize
 			}
-			_ => {
+			(None, Err(_)) => {
 				eprintln!("Could not get system memory size, using default value");
 				// bytes
 				500_000_000
 			}
 		};
 
-		let thread_count = match sys_info::cpu_num() {
-			Ok(value) => value.clamp(2, 4),
-			_ => 4,
+		let thread_count = match (cache_config.threads, sys_info::cpu_num()) {
+			(Some(threads), _) => threads.clamp(1, MAX_LOADER_THREADS),
+			(None, Ok(value)) => value.clamp(2, 4),
+			(None, Err(_)) => 4,
 		};
+		let sync_load_max_kb = cache_config.sync_load_max_kb.unwrap_or(DEFAULT_SYNC_LOAD_MAX_KB);
 
 		PlaybackManager {
 			//playback_state: PlaybackState::Paused,
-			image_cache: ImageCache::new(cache_capaxity, thread_count),
+			image_cache: ImageCache::new(cache_capaxity, thread_count, display, proxy),
 			folder_player: ImgSequencePlayer::new(),
 			image_player: ImgSequencePlayer::new(),
+			unavailable_folder: None,
+			pending_memory_image: None,
+			thread_count,
+			sync_load_max_bytes: sync_load_max_kb * 1024,
+			load_error: None,
+			subscribers: Vec::new(),
+			notified_path: LoadedImgPath::NotYetLoaded,
+			notified_state: PlaybackState::Paused,
+			notified_directory: 0,
+			notified_frame: None,
+		}
+	}
+
+	/// Calls the callback with the events from now on, after calling it with the current
+	/// image, playback state and presentation interval. This way the UI doesn't have to poll
+	/// the playback manager for changes.
+	///
+	/// The events are sent while the playback manager is borrowed, so the callback must not
+	/// try to access it.
+	pub fn subscribe<F: FnMut(&PlaybackEvent) + 'static>(&mut self, mut callback: F) {
+		callback(&PlaybackEvent::ImageChanged(self.notified_path.clone()));
+		callback(&PlaybackEvent::PlaybackStateChanged(self.notified_state));
+		callback(&PlaybackEvent::PresentIntervalChanged(self.present_interval_secs()));
+		callback(&PlaybackEvent::PausedFrameChanged(self.notified_frame));
+		self.subscribers.push(Box::new(callback));
+	}
+
+	fn send_event(&mut self, event: PlaybackEvent) {
+		for subscriber in self.subscribers.iter_mut() {
+			subscriber(&event);
+		}
+	}
+
+	/// Sends the events about the changes since the last call
+	fn notify_subscribers(&mut self) {
+		if self.folder_player.file_path != self.notified_path {
+			self.notified_path = self.folder_player.file_path.clone();
+			self.load_error = None;
+			self.send_event(PlaybackEvent::ImageChanged(self.notified_path.clone()));
+		}
+		if let Some(error) = self.folder_player.load_error.take() {
+			if let LoadedImgPath::ErrLoading(path) = &self.folder_player.file_path {
+				let path = path.clone();
+				self.load_error = Some(error.clone());
+				self.send_event(PlaybackEvent::LoadFailed { path, error });
+			}
+		}
+		let state = self.playback_state();
+		if state != self.notified_state {
+			self.notified_state = state;
+			self.send_event(PlaybackEvent::PlaybackStateChanged(state));
+		}
+		let directory = self.image_cache.directory_generation();
+		if directory != self.notified_directory {
+			self.notified_directory = directory;
+			self.send_event(PlaybackEvent::DirectoryChanged);
+		}
+		let frame = match self.image_player.playback_state() {
+			PlaybackState::Paused => self.image_cache.current_frame(),
+			_ => None,
+		};
+		if frame != self.notified_frame {
+			self.notified_frame = frame;
+			self.send_event(PlaybackEvent::PausedFrameChanged(frame));
+		}
+	}
+
+	/// Reduces the resources used for loading and playback when `saving_power` is true,
+	/// otherwise restores the defaults.
+	pub fn set_power_saving(&mut self, saving_power: bool) {
+		if saving_power {
+			self.image_cache.set_max_prefetch_count(Some(POWER_SAVING_PREFETCH_COUNT));
+			self.image_cache.set_loader_threads(1);
+			let min_delay = NANOS_PER_SEC / POWER_SAVING_MAX_FRAMERATE;
+			self.folder_player.min_frame_delay_nanos = min_delay;
+			self.image_player.min_frame_delay_nanos = min_delay;
+		} else {
+			self.image_cache.set_max_prefetch_count(None);
+			self.image_cache.set_loader_threads(self.thread_count);
+			self.folder_player.min_frame_delay_nanos = 0;
+			self.image_player.min_frame_delay_nanos = 0;
 		}
 	}
 
@@ -183,8 +335,61 @@ impl PlaybackManager {
 		self.folder_player.playback_state()
 	}
 
+	pub fn present_interval_secs(&self) -> f32 {
+		self.folder_player.present_interval_secs
+	}
+
+	/// Sets the time that each image is shown for in presentation mode
+	pub fn set_present_interval_secs(&mut self, secs: f32) {
+		if self.folder_player.present_interval_secs != secs {
+			self.folder_player.present_interval_secs = secs;
+			self.send_event(PlaybackEvent::PresentIntervalChanged(secs));
+		}
+	}
+
+	/// When `false`, the presentation stops after the last image instead of continuing with
+	/// the first one
+	pub fn set_loop_presentation(&mut self, loop_presentation: bool) {
+		self.folder_player.loop_presentation = loop_presentation;
+	}
+
+	/// Returns true once after the presentation stopped at the last image
+	pub fn take_presentation_finished(&mut self) -> bool {
+		mem::take(&mut self.folder_player.presentation_finished)
+	}
+
+	/// See `ImgSequencePlayer::presentation_progress`
+	pub fn presentation_progress(&self) -> Option<f32> {
+		self.folder_player.presentation_progress()
+	}
+
+	/// The number of images shown and the time elapsed since the presentation was started
+	pub fn presentation_stats(&self) -> (usize, Duration) {
+		let player = &self.folder_player;
+		(player.presented_count, player.present_start_time.elapsed())
+	}
+
+	/// See `ImageCache::set_capacity`
+	pub fn set_cache_capacity(&mut self, capacity: isize) {
+		self.image_cache.set_capacity(capacity);
+	}
+
+	pub fn cached_image_count(&self) -> usize {
+		self.image_cache.cached_image_count()
+	}
+
+	pub fn cache_stats(&self) -> CacheStats {
+		self.image_cache.stats()
+	}
+
+	/// See `ImageCache::take_video_memory_low`
+	pub fn take_video_memory_low(&mut self) -> bool {
+		self.image_cache.take_video_memory_low()
+	}
+
 	pub fn start_playback_forward(&mut self) {
 		self.folder_player.start_playback_forward();
+		self.notify_subscribers();
 		// self.playback_start_time = Instant::now();
 		// self.frame_count_since_playback_start = 0;
 		// self.playback_state = PlaybackState::Forward;
@@ -192,11 +397,80 @@ impl PlaybackManager {
 
 	pub fn pause_playback(&mut self) {
 		self.folder_player.pause_playback();
+		self.notify_subscribers();
 		//self.playback_state = PlaybackState::Paused;
 	}
 
+	/// Pauses the animation of the shown image, or resumes it
+	pub fn toggle_animation(&mut self) {
+		match self.image_player.playback_state() {
+			PlaybackState::Paused => self.image_player.start_playback_forward(),
+			_ => self.image_player.pause_playback(),
+		}
+		self.notify_subscribers();
+	}
+
+	/// Pauses the animation and shows the frame `offset` frames away from the shown one,
+	/// wrapping around at the ends. Does nothing if the image is not an animation.
+	pub fn step_frame(&mut self, offset: i32) {
+		if self.image_cache.current_frame().is_some() {
+			self.image_player.pause_playback();
+			self.image_player.request_load(LoadRequest::Jump(offset));
+		}
+	}
+
+	/// See `ImageCache::current_frame`
+	pub fn current_frame(&self) -> Option<FramePosition> {
+		self.image_cache.current_frame()
+	}
+
+	/// Pauses the animation and shows the frame at `index`
+	pub fn jump_to_frame(&mut self, index: usize) {
+		if let Some(frame) = self.image_cache.current_frame() {
+			self.image_player.pause_playback();
+			let offset = index as i32 - frame.index as i32;
+			self.image_player.request_load(LoadRequest::Jump(offset));
+		}
+	}
+
+	/// The first and the last frame that the animation loops between, if a loop is set. The
+	/// last one is `usize::MAX` if the loop lasts until the end of the animation.
+	pub fn loop_range(&self) -> Option<(usize, usize)> {
+		self.image_player.loop_range
+	}
+
+	/// Makes the animation loop from the shown frame. Returns the index of the frame, or
+	/// `None` if the image is not an animation.
+	pub fn set_loop_start(&mut self) -> Option<usize> {
+		let index = self.image_cache.current_frame()?.index;
+		let end = match self.image_player.loop_range {
+			Some((_, end)) if end >= index => end,
+			_ => usize::MAX,
+		};
+		self.image_player.loop_range = Some((index, end));
+		Some(index)
+	}
+
+	/// Makes the animation loop until the shown frame. Returns the index of the frame, or
+	/// `None` if the image is not an animation.
+	pub fn set_loop_end(&mut self) -> Option<usize> {
+		let index = self.image_cache.current_frame()?.index;
+		let start = match self.image_player.loop_range {
+			Some((start, _)) if start <= index => start,
+			_ => 0,
+		};
+		self.image_player.loop_range = Some((start, index));
+		Some(index)
+	}
+
+	/// Plays the whole animation again
+	pub fn clear_loop_range(&mut self) {
+		self.image_player.loop_range = None;
+	}
+
 	pub fn start_random_presentation(&mut self) {
 		self.folder_player.start_random_presentation(&mut self.image_cache);
+		self.notify_subscribers();
 		//self.playback_start_time = Instant::now();
 		//self.frame_count_since_playback_start = 0;
 		//self.playback_state = PlaybackState::RandomPresent;
@@ -205,6 +479,7 @@ impl PlaybackManager {
 
 	pub fn start_presentation(&mut self) {
 		self.folder_player.start_presentation();
+		self.notify_subscribers();
 		// self.playback_start_time = Instant::now();
 		// self.frame_count_since_playback_start = 0;
 		// self.playback_state = PlaybackState::Present;
@@ -222,11 +497,25 @@ impl PlaybackManager {
 
 	pub fn update_directory(&mut self) -> directory::Result<()> {
 		debug!("In `update_directory`");
+		if let Some(folder) = self.unavailable_folder.clone() {
+			// Don't retry until the folder is available again
+			if folder.is_dir() {
+				self.unavailable_folder = None;
+				self.image_cache.update_directory()?;
+				self.request_load(LoadRequest::FilePath(folder));
+			}
+			return Ok(());
+		}
 		if let LoadRequest::None = self.folder_player.load_request {
 			let curr_path = self.image_cache.current_file_path();
 			debug!("In `update_directory`, current_file_path is: {:?}", curr_path);
 			if curr_path.is_some() {
-				self.image_cache.update_directory()?;
+				if let Err(e) = self.image_cache.update_directory() {
+					if let directory::Error::FolderUnavailable(folder) = &e {
+						self.set_folder_unavailable(folder.clone());
+					}
+					return Err(e);
+				}
 
 				// The there's no file to open, just request to open the empty path.
 				// This will hide the previously loaded image.
@@ -238,7 +527,96 @@ impl PlaybackManager {
 		Ok(())
 	}
 
+	/// Fetches the contents of the folder again if they changed on the disk. Returns true if
+	/// the folder was updated.
+	pub fn update_directory_if_changed(&mut self) -> directory::Result<bool> {
+		// The changes made while an image is being opened are picked up after it's opened
+		if self.folder_player.load_request != LoadRequest::None
+			|| !self.image_cache.take_directory_changed()
+		{
+			return Ok(false);
+		}
+		self.update_directory()?;
+		Ok(true)
+	}
+
+	/// The order of the images when moving to the next or previous image, and on the slider
+	pub fn set_sort_order(&mut self, sort_order: SortOrder) -> directory::Result<()> {
+		self.image_cache.set_sort_order(sort_order)
+	}
+
+	/// What happens when moving past the last or the first image of the folder
+	pub fn set_folder_end(&mut self, folder_end: FolderEnd) {
+		self.image_cache.set_folder_end(folder_end);
+	}
+
+	/// Only the files that match the filter are shown
+	pub fn set_file_filter(&mut self, file_filter: FileFilter) -> directory::Result<()> {
+		self.image_cache.set_file_filter(file_filter)
+	}
+
+	/// Only the images whose rating and tags match the filter are shown
+	pub fn set_metadata_filter(&mut self, filter: MetadataFilter) -> directory::Result<()> {
+		self.image_cache.set_metadata_filter(filter)
+	}
+
+	/// Renames a file of the current folder and shows it under the new name if it's the
+	/// current file
+	pub fn rename_file(&mut self, path: &Path, new_name: &OsStr) -> directory::Result<()> {
+		self.image_cache.rename_file(path, new_name)?;
+		self.update_directory()
+	}
+
+	/// Returns true if no image was opened, and none is being opened
+	pub fn is_empty(&self) -> bool {
+		self.folder_player.file_path == LoadedImgPath::NotYetLoaded
+			&& self.folder_player.load_request == LoadRequest::None
+	}
+
+	/// The folder of the shown image, if it was deleted or unmounted
+	pub fn unavailable_folder(&self) -> Option<&Path> {
+		self.unavailable_folder.as_deref()
+	}
+
+	/// Stops showing the current image and stops trying to load images from the folder
+	fn set_folder_unavailable(&mut self, folder: PathBuf) {
+		self.image_cache.clear_textures();
+		self.folder_player.clear();
+		self.image_player.clear();
+		self.unavailable_folder = Some(folder);
+	}
+
+	/// Shows an image that isn't backed by a file, for example one that was read from the
+	/// standard input. There's no folder to navigate in while this image is shown.
+	pub fn show_memory_image(&mut self, image: RgbaImage, orientation: Orientation) {
+		self.image_cache.clear_textures();
+		self.folder_player.clear();
+		self.image_player.clear();
+		self.unavailable_folder = None;
+		self.folder_player.file_path = LoadedImgPath::Memory;
+		self.pending_memory_image = Some((image, orientation));
+	}
+
+	/// Decodes all images again, for example because the color profile of the display changed
+	pub fn reload_images(&mut self) {
+		self.image_cache.clear_textures();
+		if let Some(path) = self.image_cache.current_file_path() {
+			self.request_load(LoadRequest::FilePath(path));
+		}
+	}
+
 	pub fn request_load(&mut self, request: LoadRequest) {
+		if let LoadRequest::FilePath(..) = request {
+			self.unavailable_folder = None;
+			if self.folder_player.file_path == LoadedImgPath::Memory {
+				self.pending_memory_image = None;
+				self.folder_player.clear();
+				self.image_player.clear();
+			}
+		} else if self.folder_player.file_path == LoadedImgPath::Memory {
+			// There are no other images to navigate to
+			return;
+		}
 		self.folder_player.request_load(request);
 		self.image_player.request_load(LoadRequest::Jump(0));
 	}
@@ -247,13 +625,87 @@ impl PlaybackManager {
 		self.image_player.image_texture()
 	}
 
+	/// Keeps the shown frame for the difference mode, see `ImageCache::pin`. Returns false if
+	/// no image is shown.
+	pub fn pin_shown_image(&mut self) -> bool {
+		let texture = self.image_texture().filter(|texture| texture.placeholder.is_none());
+		let pinned = texture.is_some();
+		self.image_cache.pin(texture);
+		pinned
+	}
+
+	pub fn pinned_image(&self) -> Option<AnimationFrameTexture> {
+		self.image_cache.pinned().cloned()
+	}
+
 	/// The path to the image file which is currently rendered onto the screen.
 	pub fn shown_file_path(&self) -> &LoadedImgPath {
 		&self.folder_player.file_path
 	}
 
+	/// The reason why the shown file couldn't be loaded, while it's shown
+	pub fn load_error(&self) -> Option<&str> {
+		self.load_error.as_deref()
+	}
+
+	/// Sends the requested file to the image loader right away, instead of waiting for the next
+	/// `update_image`. The request stays pending so that `update_image` shows the image once
+	/// it's loaded.
+	pub fn dispatch_load_request(&mut self, window: &Window) {
+		if let LoadRequest::FilePath(path) = &self.folder_player.load_request {
+			let display = window.display_mut();
+			// The result is picked up by `update_image`, this only starts the loading
+			let _ = self.image_cache.load_specific(&display, path, None);
+		}
+	}
+
+	/// True if the requested file is small enough to be waited for with
+	/// `update_image_until_loaded`
+	pub fn requested_file_is_small(&self) -> bool {
+		match &self.folder_player.load_request {
+			LoadRequest::FilePath(path) => {
+				fs::metadata(path).is_ok_and(|metadata| metadata.len() <= self.sync_load_max_bytes)
+			}
+			_ => false,
+		}
+	}
+
+	/// Like `update_image`, but blocks until the requested image is shown or couldn't be
+	/// loaded. The loader threads decode the image in the meantime, which takes about as long
+	/// as decoding it on this thread would. Gives up after `SYNC_LOAD_TIMEOUT`.
+	pub fn update_image_until_loaded(&mut self, window: &Window) -> gelatin::NextUpdate {
+		let deadline = Instant::now() + SYNC_LOAD_TIMEOUT;
+		loop {
+			let next_update = self.update_image(window);
+			let waiting = self.folder_player.load_request == LoadRequest::Jump(0);
+			if !waiting || Instant::now() >= deadline {
+				return next_update;
+			}
+			thread::sleep(SYNC_LOAD_POLL_INTERVAL);
+		}
+	}
+
+	/// Shows the next image or frame when it's time, and sends the events about the changes
 	pub fn update_image(&mut self, window: &Window) -> gelatin::NextUpdate {
+		let next_update = self.update_textures(window);
+		self.notify_subscribers();
+		next_update
+	}
+
+	fn update_textures(&mut self, window: &Window) -> gelatin::NextUpdate {
 		let display = window.display_mut();
+		if self.folder_player.file_path == LoadedImgPath::Memory {
+			if let Some((image, orientation)) = self.pending_memory_image.take() {
+				match AnimationFrameTexture::from_image(&display, image, 0, orientation) {
+					Ok(texture) => self.image_player.image_texture = Some(texture),
+					Err(err) => {
+						eprintln!("Error occurred while uploading the image: {}", err);
+						self.folder_player.file_path = LoadedImgPath::NotYetLoaded;
+					}
+				}
+			}
+			return gelatin::NextUpdate::Latest;
+		}
 		let prev_file = self.folder_player.image_texture();
 		let next_update = self.folder_player.update_image(&display, &mut self.image_cache);
 		trace!("Folder player next update: {:?}", next_update);
@@ -263,6 +715,7 @@ impl PlaybackManager {
 			file_changed = !Rc::ptr_eq(&prev.tex_grid, &new.tex_grid);
 		}
 		if file_changed {
+			self.image_player.loop_range = None;
 			self.image_player.start_playback_forward();
 			self.image_player.request_load(LoadRequest::Jump(0));
 		}
@@ -276,11 +729,13 @@ impl PlaybackManager {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoadedImgPath {
 	NotYetLoaded,
 	ErrLoading(PathBuf),
 	Loaded(PathBuf),
+	/// The image isn't backed by a file
+	Memory,
 }
 
 impl LoadedImgPath {
@@ -301,6 +756,24 @@ struct ImgSequencePlayer<P: Playback> {
 	image_texture: Option<AnimationFrameTexture>,
 	file_path: LoadedImgPath,
 
+	/// Frames are shown for at least this long, regardless of the delay of the frame
+	min_frame_delay_nanos: u64,
+
+	/// The time that each image is shown for in presentation mode
+	present_interval_secs: f32,
+	/// If false, the presentation stops at the last image
+	loop_presentation: bool,
+	/// Set when the presentation stopped at the last image
+	presentation_finished: bool,
+	/// Set when loading the file failed
+	load_error: Option<String>,
+	present_start_time: Instant,
+	/// The number of images shown since the presentation was started
+	presented_count: usize,
+	/// The first and the last frame that the animation loops between. The last one is
+	/// `usize::MAX` if the loop lasts until the end of the animation.
+	loop_range: Option<(usize, usize)>,
+
 	_playback: PhantomData<P>,
 }
 
@@ -315,6 +788,14 @@ impl<P: Playback> ImgSequencePlayer<P> {
 			load_request: LoadRequest::None,
 			image_texture: None,
 			file_path: LoadedImgPath::NotYetLoaded,
+			min_frame_delay_nanos: 0,
+			present_interval_secs: DEFAULT_PRESENT_INTERVAL_SECS,
+			loop_presentation: true,
+			presentation_finished: false,
+			load_error: None,
+			present_start_time: Instant::now(),
+			presented_count: 0,
+			loop_range: None,
 
 			_playback: PhantomData,
 		}
@@ -341,6 +822,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
 		self.frametime_drift_offset = 0;
 		//self.frame_count_since_playback_start = 0;
 		self.playback_state = PlaybackState::RandomPresent;
+		self.reset_presentation_stats();
 		self.fill_present_remainig_with_random(image_cache)
 	}
 
@@ -349,12 +831,45 @@ impl<P: Playback> ImgSequencePlayer<P> {
 		self.frametime_drift_offset = 0;
 		//self.frame_count_since_playback_start = 0;
 		self.playback_state = PlaybackState::Present;
+		self.reset_presentation_stats();
+	}
+
+	/// The part of the time that the current image is shown for that has passed, between 0 and
+	/// 1. Returns `None` if there's no presentation playing.
+	pub fn presentation_progress(&self) -> Option<f32> {
+		match self.playback_state {
+			PlaybackState::Present | PlaybackState::RandomPresent => (),
+			_ => return None,
+		}
+		let interval_nanos = (self.present_interval_secs as f64 * NANOS_PER_SEC as f64).max(1.0);
+		let elapsed_nanos = self.last_frame_change_time.elapsed().as_nanos() as f64
+			+ self.frametime_drift_offset as f64;
+		Some((elapsed_nanos / interval_nanos).clamp(0.0, 1.0) as f32)
+	}
+
+	fn reset_presentation_stats(&mut self) {
+		self.present_start_time = Instant::now();
+		// The image that was shown when the presentation started counts as well
+		self.presented_count = 1;
+	}
+
+	fn finish_presentation(&mut self) {
+		self.playback_state = PlaybackState::Paused;
+		self.presentation_finished = true;
 	}
 
 	pub fn request_load(&mut self, request: LoadRequest) {
 		self.load_request = request;
 	}
 
+	/// Forgets the shown image and stops the playback
+	pub fn clear(&mut self) {
+		self.playback_state = PlaybackState::Paused;
+		self.load_request = LoadRequest::None;
+		self.image_texture = None;
+		self.file_path = LoadedImgPath::NotYetLoaded;
+	}
+
 	pub fn image_texture(&self) -> Option<AnimationFrameTexture> {
 		self.image_texture.clone()
 	}
@@ -382,13 +897,18 @@ impl<P: Playback> ImgSequencePlayer<P> {
 		// function early. And at the same time I want to use it's value as it is at this line.
 		let mut load_request = LoadRequest::None;
 		mem::swap(&mut self.load_request, &mut load_request);
-		let frame_delta_time_nanos;
-		match self.playback_state {
+		let frame_delta_time_nanos = match self.playback_state {
 			PlaybackState::Present | PlaybackState::RandomPresent => {
-				frame_delta_time_nanos = (NANOS_PER_SEC * 6) as i64;
+				(self.present_interval_secs as f64 * NANOS_PER_SEC as f64) as i64
 			}
 			_ => {
-				frame_delta_time_nanos = P::delay_nanos(self) as i64;
+				let delay_nanos = P::delay_nanos(self);
+				// A delay of zero means that there's nothing to play
+				if delay_nanos > 0 {
+					delay_nanos.max(self.min_frame_delay_nanos) as i64
+				} else {
+					0
+				}
 			}
 		};
 		if self.playback_state == PlaybackState::Paused {
@@ -419,33 +939,59 @@ impl<P: Playback> ImgSequencePlayer<P> {
 			// This assumes that the following frames have the same delay but that's okay considering that
 			// if frame step is greater than 1 it almost certainly means that we couldn't load the
 			// next frame quiclky enough so there's not much else to do here.
-			let frame_step;
-			if frame_delta_time_nanos > 0 {
-				frame_step = elapsed_nanos / frame_delta_time_nanos;
-			} else {
-				frame_step = 0;
-			}
+			let frame_step =
+				if frame_delta_time_nanos > 0 { elapsed_nanos / frame_delta_time_nanos } else { 0 };
 			if frame_step > 0 {
 				load_request = match self.playback_state {
-					PlaybackState::Forward | PlaybackState::Present => {
-						// if we can't load the frames quickly enough,
-						// we won't jump over frames, but instead play the animation slower.
-						LoadRequest::Jump(frame_step.min(1) as i32)
+					PlaybackState::Forward => {
+						let frame = image_cache.current_frame().map(|frame| frame.index);
+						match (self.loop_range, frame) {
+							// The loop starts over after its last frame
+							(Some((start, end)), Some(i)) if i < start || i >= end => {
+								LoadRequest::Jump(start as i32 - i as i32)
+							}
+							// if we can't load the frames quickly enough,
+							// we won't jump over frames, but instead play the animation slower.
+							_ => LoadRequest::Jump(frame_step.min(1) as i32),
+						}
+					}
+					PlaybackState::Present => {
+						// A looping presentation turns around or wraps depending on the
+						// `folder_end`, but it can't loop if that stops at the end
+						let stops =
+							!self.loop_presentation || image_cache.folder_end() == FolderEnd::Stop;
+						if image_cache.at_folder_end() && stops {
+							self.finish_presentation();
+							LoadRequest::None
+						} else {
+							self.presented_count += 1;
+							LoadRequest::Jump(1)
+						}
 					}
 					PlaybackState::RandomPresent => {
 						let mut target = None;
 						for _ in 0..frame_step {
-							target = self.present_remaining.pop();
-							if target.is_none() {
-								// Restart
-								// WARNING we silently assume that the folder is fully
-								// filtered at this point.
-								self.fill_present_remainig_with_random(image_cache);
-								target = self.present_remaining.pop();
+							match self.present_remaining.pop() {
+								Some(index) => target = Some(index),
+								None if !self.loop_presentation => break,
+								None => {
+									// Restart
+									// WARNING we silently assume that the folder is fully
+									// filtered at this point.
+									self.fill_present_remainig_with_random(image_cache);
+									target = self.present_remaining.pop();
+								}
 							}
 						}
 						match target {
-							Some(index) => LoadRequest::LoadAtIndex(index),
+							Some(index) => {
+								self.presented_count += 1;
+								LoadRequest::LoadAtIndex(index)
+							}
+							None if !self.loop_presentation => {
+								self.finish_presentation();
+								LoadRequest::None
+							}
 							None => LoadRequest::None,
 						}
 					}
@@ -454,7 +1000,9 @@ impl<P: Playback> ImgSequencePlayer<P> {
 				self.last_frame_change_time = Instant::now();
 				self.frametime_drift_offset = -nanos_til_next;
 			} else {
-				image_cache.process_prefetched(display).unwrap();
+				if let Err(e) = image_cache.process_prefetched(display) {
+					eprintln!("Failed to process prefetched images with error '{:?}'", e);
+				}
 				const BUISY_WAIT_THRESHOLD: f32 = 0.8;
 				if elapsed_nanos > (frame_delta_time_nanos as f32 * BUISY_WAIT_THRESHOLD) as i64 {
 					// Just buisy wait if we are getting very close to the next frame swap
@@ -500,7 +1048,15 @@ impl<P: Playback> ImgSequencePlayer<P> {
 						self.image_texture = Some(frame);
 						self.file_path = LoadedImgPath::Loaded(path);
 					}
-					Err(image_cache::TextureError::WaitingOnLoader) => {
+					Err(image_cache::TextureError::WaitingOnLoader)
+					| Err(image_cache::TextureError::Directory(
+						directory::Error::WaitingOnFolderFilter,
+					)) => {
+						// The placeholder is replaced once the image is loaded
+						if let Some(placeholder) = image_cache.placeholder() {
+							self.image_texture = Some(placeholder);
+							self.file_path = LoadedImgPath::Loaded(path);
+						}
 						// Set the load request to jump in place so that
 						// next time we attempt to load this again.
 						self.load_request = LoadRequest::Jump(0);
@@ -509,6 +1065,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
 					Err(err) => {
 						self.image_texture = None;
 						self.file_path = LoadedImgPath::ErrLoading(path);
+						self.load_error = Some(err.to_string());
 						let stderr = &mut ::std::io::stderr();
 						let stderr_errmsg = "Error writing to stderr";
 						writeln!(stderr, "Error occurred while loading image: {}", err)
@@ -0,0 +1,151 @@
+//! Detects whether the computer is running on battery, so that Emulsion can use less
+//! resources in that case.
+
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::configuration::PowerSaving;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the result of the first check is looked for, so that power saving starts soon
+/// after Emulsion is started on battery
+const FIRST_RESULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns `None` if the power source could not be determined.
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> Option<bool> {
+	let entries = std::fs::read_dir("/sys/class/power_supply").ok()?;
+	let mut found_mains = false;
+	for entry in entries.flatten() {
+		let path = entry.path();
+		let kind = std::fs::read_to_string(path.join("type")).unwrap_or_default();
+		if kind.trim() == "Mains" {
+			found_mains = true;
+			let online = std::fs::read_to_string(path.join("online")).unwrap_or_default();
+			if online.trim() == "1" {
+				return Some(false);
+			}
+		}
+	}
+	// A desktop computer usually doesn't report any power supply at all
+	if found_mains {
+		Some(true)
+	} else {
+		None
+	}
+}
+
+/// Returns `None` if the power source could not be determined.
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> Option<bool> {
+	let output = std::process::Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+	let output = String::from_utf8_lossy(&output.stdout);
+	if output.contains("'Battery Power'") {
+		Some(true)
+	} else if output.contains("'AC Power'") {
+		Some(false)
+	} else {
+		None
+	}
+}
+
+/// Returns `None` if the power source could not be determined.
+#[cfg(windows)]
+pub fn on_battery() -> Option<bool> {
+	use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+	let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+	if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+		return None;
+	}
+	// `SystemStatusFlag` is 1 when the battery saver is turned on
+	if status.SystemStatusFlag == 1 {
+		return Some(true);
+	}
+	match status.ACLineStatus {
+		0 => Some(true),
+		1 => Some(false),
+		_ => None,
+	}
+}
+
+/// Returns `None` if the power source could not be determined.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn on_battery() -> Option<bool> {
+	None
+}
+
+/// Periodically checks the power source and decides whether Emulsion should save power.
+///
+/// Finding out the power source may start a program, so the checks are done on a thread of
+/// their own and `update` only picks up their results.
+pub struct PowerMonitor {
+	setting: PowerSaving,
+	/// The results of the checks. Only used with `PowerSaving::Auto`.
+	results: Option<Receiver<bool>>,
+	received_result: bool,
+	last_update: Option<Instant>,
+	saving_power: bool,
+}
+
+impl PowerMonitor {
+	pub fn new(setting: PowerSaving) -> Self {
+		let results = match setting {
+			PowerSaving::Auto => Some(spawn_checker()),
+			_ => None,
+		};
+		PowerMonitor {
+			setting,
+			results,
+			received_result: false,
+			last_update: None,
+			saving_power: false,
+		}
+	}
+
+	pub fn saving_power(&self) -> bool {
+		self.saving_power
+	}
+
+	/// Returns `true` if the power saving state changed since the last call.
+	pub fn update(&mut self) -> bool {
+		self.last_update = Some(Instant::now());
+		let saving_power = match self.setting {
+			PowerSaving::Always => true,
+			PowerSaving::Never => false,
+			PowerSaving::Auto => match self.results.as_ref().and_then(|r| r.try_iter().last()) {
+				Some(on_battery) => {
+					self.received_result = true;
+					on_battery
+				}
+				None => return false,
+			},
+		};
+		let changed = saving_power != self.saving_power;
+		self.saving_power = saving_power;
+		changed
+	}
+
+	pub fn next_check_time(&self) -> Option<Instant> {
+		match self.setting {
+			PowerSaving::Auto => {
+				let interval =
+					if self.received_result { CHECK_INTERVAL } else { FIRST_RESULT_POLL_INTERVAL };
+				self.last_update.map(|t| t + interval)
+			}
+			_ => None,
+		}
+	}
+}
+
+/// Checks the power source every `CHECK_INTERVAL` until the receiver is dropped
+fn spawn_checker() -> Receiver<bool> {
+	let (sender, receiver) = channel();
+	thread::spawn(move || {
+		while sender.send(on_battery().unwrap_or(false)).is_ok() {
+			thread::sleep(CHECK_INTERVAL);
+		}
+	});
+	receiver
+}
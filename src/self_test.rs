@@ -0,0 +1,275 @@
+//! The `--self-test` mode, which gives packagers a quick way to check that their build can
+//! actually display images.
+//!
+//! The test images are generated in memory and written to a temporary folder, because the image
+//! cache works with folders. The picture widget is then driven through navigation, zooming,
+//! animation playback and cache eviction, and the process exits with a non-zero status code if
+//! any of the steps failed.
+
+use std::{
+	cell::Cell,
+	fs, io,
+	path::{Path, PathBuf},
+	rc::Rc,
+	time::{Duration, Instant},
+};
+
+use gelatin::{
+	application::request_exit,
+	image::{
+		codecs::gif::{GifEncoder, Repeat},
+		Delay, Frame, ImageError, Rgba, RgbaImage,
+	},
+	NextUpdate,
+};
+
+use crate::{
+	image_cache::{get_image_size_estimate, TextureGridItem},
+	playback_manager::{LoadRequest, LoadedImgPath},
+	widgets::picture_widget::PictureWidget,
+};
+
+const IMAGE_SIZE: u32 = 512;
+const STILL_IMAGE_COUNT: usize = 6;
+const ANIMATION_FRAME_COUNT: u32 = 4;
+const ANIMATION_FRAME_DELAY_MS: u32 = 100;
+
+/// A step fails if it doesn't finish in this much time
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy)]
+enum Navigation {
+	Open,
+	Next,
+	Previous,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+	/// Navigates and waits until the image at the given index is shown
+	Navigate(Navigation, usize),
+	ZoomIn,
+	ZoomOut,
+	/// Waits until the animation shows a different frame
+	AnimationAdvances,
+	/// Checks that images far from the current one were evicted from the cache
+	CacheEvicted,
+}
+
+/// Writes the images used by the self-test into `dir`, and returns their paths in the order in
+/// which they are listed in the folder. The last one is animated.
+fn write_test_images(dir: &Path) -> Result<Vec<PathBuf>, ImageError> {
+	fs::create_dir_all(dir)?;
+	let mut paths = Vec::new();
+	for i in 0..STILL_IMAGE_COUNT {
+		let (kind, image) = if i % 2 == 0 {
+			("gradient", gradient(i as u32))
+		} else {
+			("checker", checkerboard(IMAGE_SIZE, 8 << (i % 3), i as u32))
+		};
+		let path = dir.join(format!("{:02}-{}.png", i, kind));
+		image.save(&path)?;
+		paths.push(path);
+	}
+	let path = dir.join(format!("{:02}-animation.gif", STILL_IMAGE_COUNT));
+	{
+		let mut encoder = GifEncoder::new(fs::File::create(&path)?);
+		encoder.set_repeat(Repeat::Infinite)?;
+		let delay = Delay::from_numer_denom_ms(ANIMATION_FRAME_DELAY_MS, 1);
+		let frames = (0..ANIMATION_FRAME_COUNT)
+			.map(|i| Frame::from_parts(checkerboard(IMAGE_SIZE / 4, 4 << i, i), 0, 0, delay));
+		encoder.encode_frames(frames)?;
+	}
+	paths.push(path);
+	Ok(paths)
+}
+
+fn gradient(seed: u32) -> RgbaImage {
+	RgbaImage::from_fn(IMAGE_SIZE, IMAGE_SIZE, |x, y| {
+		let r = x * 255 / IMAGE_SIZE;
+		let g = y * 255 / IMAGE_SIZE;
+		Rgba([r as u8, g as u8, (seed * 40 % 256) as u8, 255])
+	})
+}
+
+fn checkerboard(size: u32, cell_size: u32, seed: u32) -> RgbaImage {
+	let dark = (seed * 30 % 128) as u8;
+	RgbaImage::from_fn(size, size, |x, y| {
+		if (x / cell_size + y / cell_size).is_multiple_of(2) {
+			Rgba([255, 255, 255, 255])
+		} else {
+			Rgba([dark, dark, dark, 255])
+		}
+	})
+}
+
+/// The state of a step that has started
+struct RunningStep {
+	started: Instant,
+	texel_size: f32,
+	texture: Option<Rc<Vec<TextureGridItem>>>,
+}
+
+pub struct SelfTest {
+	picture_widget: Rc<PictureWidget>,
+	/// The process exits with this status code after the event loop finished
+	exit_code: Rc<Cell<Option<i32>>>,
+	dir: PathBuf,
+	images: Vec<PathBuf>,
+	steps: Vec<Step>,
+	current_step: usize,
+	running: Option<RunningStep>,
+}
+
+impl SelfTest {
+	pub fn new(
+		picture_widget: Rc<PictureWidget>,
+		exit_code: Rc<Cell<Option<i32>>>,
+	) -> Result<SelfTest, ImageError> {
+		let dir = std::env::temp_dir().join(format!("emulsion-self-test-{}", std::process::id()));
+		let images = write_test_images(&dir)?;
+		let last = images.len() - 1;
+		let mut steps = vec![Step::Navigate(Navigation::Open, 0)];
+		steps.extend((1..=last).map(|i| Step::Navigate(Navigation::Next, i)));
+		steps.extend([Step::AnimationAdvances, Step::CacheEvicted]);
+		steps.push(Step::Navigate(Navigation::Previous, last - 1));
+		steps.extend([Step::ZoomIn, Step::ZoomOut]);
+		steps.push(Step::Navigate(Navigation::Open, 0));
+
+		// Only about two images fit into the cache, so that navigating through the folder has to
+		// evict images
+		let image_size = get_image_size_estimate(IMAGE_SIZE, IMAGE_SIZE);
+		picture_widget.set_cache_capacity(image_size * 5 / 2);
+		Ok(SelfTest {
+			picture_widget,
+			exit_code,
+			dir,
+			images,
+			steps,
+			current_step: 0,
+			running: None,
+		})
+	}
+
+	/// Starts or checks the current step. This should be called from a global event handler.
+	pub fn update(&mut self) -> NextUpdate {
+		let step = match self.steps.get(self.current_step) {
+			Some(step) => *step,
+			None => return NextUpdate::Latest,
+		};
+		let running = match &self.running {
+			Some(running) => running,
+			None => {
+				self.start(step);
+				return NextUpdate::WaitUntil(Instant::now() + POLL_INTERVAL);
+			}
+		};
+		let started = running.started;
+		match self.check(step, running) {
+			Ok(true) => {
+				println!("self-test: {:?} ... ok", step);
+				self.current_step += 1;
+				self.running = None;
+				if self.current_step == self.steps.len() {
+					self.finish(0);
+				}
+			}
+			Ok(false) if started.elapsed() < STEP_TIMEOUT => (),
+			Ok(false) => {
+				println!("self-test: {:?} ... timed out", step);
+				self.finish(1);
+			}
+			Err(error) => {
+				println!("self-test: {:?} ... failed: {}", step, error);
+				self.finish(1);
+			}
+		}
+		NextUpdate::WaitUntil(Instant::now() + POLL_INTERVAL)
+	}
+
+	fn start(&mut self, step: Step) {
+		let widget = &self.picture_widget;
+		let running = RunningStep {
+			started: Instant::now(),
+			texel_size: widget.img_texel_size(),
+			texture: widget.shown_texture().map(|texture| texture.tex_grid),
+		};
+		match step {
+			Step::Navigate(Navigation::Open, index) => {
+				widget.jump_to_path(self.images[index].clone());
+			}
+			Step::Navigate(Navigation::Next, _) => widget.request_load(LoadRequest::LoadNext),
+			Step::Navigate(Navigation::Previous, _) => {
+				widget.request_load(LoadRequest::LoadPrevious)
+			}
+			Step::ZoomIn => widget.zoom_at_center(1.0),
+			Step::ZoomOut => widget.zoom_at_center(-1.0),
+			Step::AnimationAdvances | Step::CacheEvicted => (),
+		}
+		self.running = Some(running);
+	}
+
+	/// Returns `Ok(true)` if the step finished successfully and `Ok(false)` if it's still
+	/// in progress.
+	fn check(&self, step: Step, running: &RunningStep) -> Result<bool, String> {
+		let widget = &self.picture_widget;
+		match step {
+			Step::Navigate(_, index) => match widget.shown_file_path() {
+				LoadedImgPath::Loaded(path) => {
+					// The placeholder is shown until the image is loaded
+					let texture = widget.shown_texture();
+					let placeholder = texture.and_then(|texture| texture.placeholder);
+					Ok(path == self.images[index] && placeholder.is_none())
+				}
+				LoadedImgPath::ErrLoading(path) => Err(format!("could not load {:?}", path)),
+				LoadedImgPath::NotYetLoaded => Ok(false),
+				LoadedImgPath::Memory => Err("an in-memory image is shown".into()),
+			},
+			Step::ZoomIn | Step::ZoomOut => {
+				let texel_size = widget.img_texel_size();
+				let zoomed = match step {
+					Step::ZoomIn => texel_size > running.texel_size,
+					_ => texel_size < running.texel_size,
+				};
+				if zoomed {
+					Ok(true)
+				} else {
+					let (prev, curr) = (running.texel_size, texel_size);
+					Err(format!("the texel size changed from {} to {}", prev, curr))
+				}
+			}
+			Step::AnimationAdvances => {
+				let texture = widget.shown_texture().map(|texture| texture.tex_grid);
+				Ok(match (&running.texture, &texture) {
+					(Some(prev), Some(curr)) => !Rc::ptr_eq(prev, curr),
+					_ => false,
+				})
+			}
+			Step::CacheEvicted => {
+				let count = widget.cached_image_count();
+				if count < self.images.len() {
+					Ok(true)
+				} else {
+					Err(format!("all {} images are still in the cache", count))
+				}
+			}
+		}
+	}
+
+	fn finish(&mut self, exit_code: i32) {
+		self.current_step = self.steps.len();
+		if let Err(e) = remove_dir(&self.dir) {
+			eprintln!("Could not remove the self-test folder {:?}: {}", self.dir, e);
+		}
+		self.exit_code.set(Some(exit_code));
+		request_exit();
+	}
+}
+
+fn remove_dir(dir: &Path) -> io::Result<()> {
+	if dir.exists() {
+		fs::remove_dir_all(dir)?;
+	}
+	Ok(())
+}
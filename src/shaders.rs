@@ -1,2 +1,19 @@
 pub static VERTEX_140: &str = include_str!("shaders/vertex_140.glsl");
 pub static FRAGMENT_140: &str = include_str!("shaders/fragment_140.glsl");
+/// Draws floating point textures. High dynamic range images are tone mapped, deep color images
+/// are drawn as they are.
+pub static FRAGMENT_HDR_140: &str = include_str!("shaders/fragment_hdr_140.glsl");
+/// Shows the difference between an image and the pinned image of the difference mode
+pub static DIFFERENCE_140: &str = include_str!("shaders/difference_140.glsl");
+static DISPLAY_TRANSFORM_140: &str = include_str!("shaders/display_transform_140.glsl");
+static CHANNEL_140: &str = include_str!("shaders/channel_140.glsl");
+static ADJUSTMENTS_140: &str = include_str!("shaders/adjustments_140.glsl");
+
+/// Inserts the functions of the display transform, the channel isolation and the adjustments
+/// into a fragment shader that uses them
+pub fn with_common_functions(fragment_shader: &str) -> String {
+	// The version directive has to stay on the first line
+	let (version, body) = fragment_shader.split_once('\n').unwrap_or(("", fragment_shader));
+	let common = [DISPLAY_TRANSFORM_140, CHANNEL_140, ADJUSTMENTS_140].join("\n");
+	format!("{}\n{}\n{}", version, common, body)
+}
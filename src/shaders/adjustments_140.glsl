@@ -0,0 +1,11 @@
+uniform float adjust_exposure;
+uniform float adjust_gamma;
+uniform float adjust_saturation;
+
+// The adjustments of the adjustments panel. The exposure is a factor here. The color is linear.
+vec3 apply_adjustments(vec3 color) {
+    color = max(color * adjust_exposure, vec3(0.0));
+    color = pow(color, vec3(1.0 / adjust_gamma));
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    return mix(vec3(luminance), color, adjust_saturation);
+}
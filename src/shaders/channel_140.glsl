@@ -0,0 +1,22 @@
+uniform int channel;
+
+// Shows a single channel of the image as opaque gray. Zero shows the image as it is, 1 to 4 the
+// red, green, blue and alpha channels, and 5 the luminance.
+vec4 isolate_channel(vec4 color) {
+    float value;
+    if (channel == 1) {
+        value = color.r;
+    } else if (channel == 2) {
+        value = color.g;
+    } else if (channel == 3) {
+        value = color.b;
+    } else if (channel == 4) {
+        value = color.a;
+    } else if (channel == 5) {
+        // The colors are linear here, so these are the Rec.709 luminance coefficients
+        value = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    } else {
+        return color;
+    }
+    return vec4(vec3(value), 1.0);
+}
@@ -0,0 +1,15 @@
+#version 140
+uniform sampler2D tex;
+uniform sampler2D pinned_tex;
+uniform float lod_level;
+uniform float amplification;
+in vec2 v_tex_coords;
+out vec4 f_color;
+void main() {
+    vec4 color = textureLod(tex, v_tex_coords, lod_level);
+    vec4 pinned = textureLod(pinned_tex, v_tex_coords, lod_level);
+    vec4 difference = min(abs(color - pinned) * amplification, 1.0);
+    // The difference of the alpha channels is only shown if that channel is isolated
+    difference = isolate_channel(difference);
+    f_color = vec4(apply_display_transform(difference.rgb), 1.0);
+}
@@ -0,0 +1,31 @@
+uniform int display_transform;
+uniform sampler3D lut;
+uniform float lut_size;
+uniform vec3 lut_domain_min;
+uniform vec3 lut_domain_max;
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+}
+
+// The framebuffer encodes the output to sRGB, so the transforms return the linear color whose
+// sRGB encoding is the value that should be sent to the display.
+vec3 apply_display_transform(vec3 color) {
+    color = clamp(color, 0.0, 1.0);
+    if (display_transform == 1) {
+        // Rec.709 displays have a pure 2.4 gamma
+        return srgb_to_linear(pow(color, vec3(1.0 / 2.4)));
+    } else if (display_transform == 2) {
+        vec3 encoded = linear_to_srgb(color);
+        vec3 coords = (encoded - lut_domain_min) / (lut_domain_max - lut_domain_min);
+        coords = clamp(coords, 0.0, 1.0);
+        // The first and the last entries are at the centers of the edge texels
+        coords = coords * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+        return srgb_to_linear(clamp(texture(lut, coords).rgb, 0.0, 1.0));
+    }
+    return color;
+}
@@ -1,14 +1,21 @@
 #version 140
 uniform sampler2D tex;
 uniform float bright_shade;
+uniform bool checkerboard;
+uniform vec3 bg_color;
 uniform float lod_level;
 in vec2 v_tex_coords;
 out vec4 f_color;
 void main() {
     vec4 color = textureLod(tex, v_tex_coords, lod_level);
+    color.rgb = apply_adjustments(color.rgb);
+    color = isolate_channel(color);
+    color.rgb = apply_display_transform(color.rgb);
     const float grid_size = 12.0;
     vec4 grid_color;
-    if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
+    if (!checkerboard) {
+        grid_color = vec4(bg_color, 1.0);
+    } else if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
         ^^ (mod(gl_FragCoord.y, grid_size * 2.0) < grid_size)
     ) {
         grid_color = vec4(bright_shade);
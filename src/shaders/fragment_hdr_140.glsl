@@ -0,0 +1,45 @@
+#version 140
+uniform sampler2D tex;
+uniform float bright_shade;
+uniform bool checkerboard;
+uniform vec3 bg_color;
+uniform float lod_level;
+uniform float exposure;
+uniform bool tone_mapping;
+in vec2 v_tex_coords;
+out vec4 f_color;
+
+// The fit of the ACES filmic curve by Krzysztof Narkowicz. The input and the output are linear.
+vec3 tone_map(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = textureLod(tex, v_tex_coords, lod_level);
+    if (tone_mapping) {
+        color.rgb = tone_map(max(color.rgb, vec3(0.0)) * exposure);
+    } else {
+        color.rgb = clamp(color.rgb, 0.0, 1.0);
+    }
+    color.a = clamp(color.a, 0.0, 1.0);
+    color.rgb = apply_adjustments(color.rgb);
+    color = isolate_channel(color);
+    color.rgb = apply_display_transform(color.rgb);
+    const float grid_size = 12.0;
+    vec4 grid_color;
+    if (!checkerboard) {
+        grid_color = vec4(bg_color, 1.0);
+    } else if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
+        ^^ (mod(gl_FragCoord.y, grid_size * 2.0) < grid_size)
+    ) {
+        grid_color = vec4(bright_shade);
+    } else {
+        grid_color = vec4(bright_shade * 0.55);
+    }
+    f_color = mix(grid_color, color, color.a);
+}
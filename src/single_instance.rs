@@ -0,0 +1,297 @@
+//! Forwards the images opened while Emulsion is already running to the running instance, so
+//! that they are shown in its window instead of in a new one.
+//!
+//! The running instance listens on a Unix socket in the runtime folder (or the cache folder
+//! where there is no runtime folder), and on a named pipe on Windows. Only the same user can
+//! connect to either. The other instances send the length of the path followed by the path,
+//! and wait for a reply byte, which means that the path was received.
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use gelatin::winit::event_loop::EventLoopProxy;
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// Longer paths are rejected
+const MAX_PATH_LENGTH: u32 = 64 * 1024;
+const ACKNOWLEDGEMENT: u8 = 1;
+
+/// Sends the path to the running instance. Returns false if no instance is running or if it
+/// couldn't be reached, in which case this instance should show the image itself.
+pub fn forward_to_running_instance(cache_folder: &Path, path: &Path) -> bool {
+	// The running instance may have been started from a different folder
+	let path = match std::env::current_dir() {
+		Ok(current_dir) if path.is_relative() => current_dir.join(path),
+		_ => path.to_owned(),
+	};
+	let path = match path.to_str() {
+		Some(path) => path,
+		None => return false,
+	};
+	let mut stream = match platform::connect(cache_folder) {
+		Ok(stream) => stream,
+		// The instance that created the socket isn't running anymore
+		Err(_) => return false,
+	};
+	let mut message = (path.len() as u32).to_le_bytes().to_vec();
+	message.extend_from_slice(path.as_bytes());
+	let mut reply = [0];
+	stream.write_all(&message).is_ok()
+		&& stream.read_exact(&mut reply).is_ok()
+		&& reply[0] == ACKNOWLEDGEMENT
+}
+
+/// Receives the paths forwarded by other instances. The event loop is woken up with a user
+/// event whenever a path is received.
+pub struct InstanceListener {
+	received: Arc<Mutex<Vec<PathBuf>>>,
+	_listener: Arc<platform::Listener>,
+}
+
+impl InstanceListener {
+	pub fn start(cache_folder: &Path, proxy: EventLoopProxy<()>) -> io::Result<InstanceListener> {
+		let listener = Arc::new(platform::Listener::bind(cache_folder)?);
+		let received = Arc::new(Mutex::new(Vec::new()));
+		{
+			let listener = listener.clone();
+			let received = received.clone();
+			thread::spawn(move || loop {
+				let mut stream = match listener.accept() {
+					Ok(stream) => stream,
+					Err(_) => continue,
+				};
+				if let Some(path) = read_path(&mut stream) {
+					received.lock().unwrap().push(path);
+					// Only reply once the path is queued, so that the other instance can't
+					// exit before it's shown
+					let _ = stream.write_all(&[ACKNOWLEDGEMENT]);
+					let _ = stream.flush();
+					if proxy.send_event(()).is_err() {
+						// The event loop has exited
+						return;
+					}
+				}
+			});
+		}
+		Ok(InstanceListener { received, _listener: listener })
+	}
+
+	/// Returns the paths received since the last call
+	pub fn take_received(&self) -> Vec<PathBuf> {
+		std::mem::take(&mut *self.received.lock().unwrap())
+	}
+}
+
+fn read_path(stream: &mut impl Read) -> Option<PathBuf> {
+	let mut length = [0; 4];
+	stream.read_exact(&mut length).ok()?;
+	let length = u32::from_le_bytes(length);
+	if length > MAX_PATH_LENGTH {
+		return None;
+	}
+	let mut path = vec![0; length as usize];
+	stream.read_exact(&mut path).ok()?;
+	String::from_utf8(path).ok().map(PathBuf::from)
+}
+
+#[cfg(unix)]
+mod platform {
+	use std::fs;
+	use std::io;
+	use std::os::unix::fs::{MetadataExt, PermissionsExt};
+	use std::os::unix::net::{UnixListener, UnixStream};
+	use std::path::{Path, PathBuf};
+
+	use super::TIMEOUT;
+
+	const SOCKET_FILE_NAME: &str = "emulsion.sock";
+
+	/// The runtime folder is only accessible to its owner, the socket itself is made so too
+	fn socket_path(cache_folder: &Path) -> PathBuf {
+		let base_dirs = directories_next::BaseDirs::new();
+		match base_dirs.as_ref().and_then(|dirs| dirs.runtime_dir()) {
+			Some(runtime_dir) => runtime_dir.join(SOCKET_FILE_NAME),
+			None => cache_folder.join(SOCKET_FILE_NAME),
+		}
+	}
+
+	pub fn connect(cache_folder: &Path) -> io::Result<UnixStream> {
+		let stream = UnixStream::connect(socket_path(cache_folder))?;
+		stream.set_read_timeout(Some(TIMEOUT))?;
+		stream.set_write_timeout(Some(TIMEOUT))?;
+		Ok(stream)
+	}
+
+	pub struct Listener {
+		listener: UnixListener,
+		path: PathBuf,
+		/// Identifies the socket file, to tell if another instance has replaced it since
+		inode: (u64, u64),
+	}
+
+	impl Listener {
+		pub fn bind(cache_folder: &Path) -> io::Result<Listener> {
+			let path = socket_path(cache_folder);
+			// Left behind by an instance that didn't exit cleanly, because `connect` failed
+			// before this was called
+			if path.exists() {
+				fs::remove_file(&path)?;
+			}
+			let listener = UnixListener::bind(&path)?;
+			fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+			let metadata = fs::metadata(&path)?;
+			Ok(Listener { listener, path, inode: (metadata.dev(), metadata.ino()) })
+		}
+
+		pub fn accept(&self) -> io::Result<UnixStream> {
+			let (stream, _) = self.listener.accept()?;
+			// Keeps a client that doesn't send anything from blocking the others
+			stream.set_read_timeout(Some(TIMEOUT))?;
+			stream.set_write_timeout(Some(TIMEOUT))?;
+			Ok(stream)
+		}
+	}
+
+	impl Drop for Listener {
+		fn drop(&mut self) {
+			if let Ok(metadata) = fs::metadata(&self.path) {
+				if (metadata.dev(), metadata.ino()) == self.inode {
+					let _ = fs::remove_file(&self.path);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(windows)]
+mod platform {
+	use std::fs::{File, OpenOptions};
+	use std::io::{self, Read, Write};
+	use std::os::windows::io::{FromRawHandle, RawHandle};
+	use std::path::Path;
+	use std::sync::Mutex;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	use windows_sys::Win32::{
+		Foundation::{
+			CloseHandle, GetLastError, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE,
+			INVALID_HANDLE_VALUE,
+		},
+		Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+		System::Pipes::{
+			ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+			PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+		},
+	};
+
+	use super::TIMEOUT;
+
+	const BUFFER_SIZE: u32 = 4096;
+
+	/// Named pipes are shared by all sessions, so the name includes the user name. The default
+	/// security descriptor only lets the creator write to the pipe.
+	fn pipe_name() -> String {
+		let user = std::env::var("USERNAME").unwrap_or_default();
+		format!(r"\\.\pipe\emulsion-{}", user)
+	}
+
+	pub fn connect(_cache_folder: &Path) -> io::Result<File> {
+		let start = Instant::now();
+		loop {
+			match OpenOptions::new().read(true).write(true).open(pipe_name()) {
+				// All instances of the pipe are busy while the running instance handles
+				// another connection
+				Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+					if start.elapsed() > TIMEOUT {
+						return Err(e);
+					}
+					thread::sleep(Duration::from_millis(10));
+				}
+				result => return result,
+			}
+		}
+	}
+
+	/// The server end of a connection
+	pub struct Connection(File);
+
+	impl Read for Connection {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			self.0.read(buf)
+		}
+	}
+
+	impl Write for Connection {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.write(buf)
+		}
+
+		/// Waits until the client has read everything, because the data that is still in the
+		/// pipe may be lost when the handle is closed
+		fn flush(&mut self) -> io::Result<()> {
+			self.0.sync_all()
+		}
+	}
+
+	pub struct Listener {
+		/// The first instance of the pipe is created when binding, so that an error can be
+		/// reported if another process already owns the pipe name
+		first: Mutex<Option<HANDLE>>,
+	}
+
+	impl Listener {
+		pub fn bind(_cache_folder: &Path) -> io::Result<Listener> {
+			let first = create_pipe(true)?;
+			Ok(Listener { first: Mutex::new(Some(first)) })
+		}
+
+		/// Waits for a client to connect to a new instance of the pipe
+		pub fn accept(&self) -> io::Result<Connection> {
+			let pipe = match self.first.lock().unwrap().take() {
+				Some(first) => first,
+				None => create_pipe(false)?,
+			};
+			unsafe {
+				if ConnectNamedPipe(pipe, std::ptr::null_mut()) == 0
+					&& GetLastError() != ERROR_PIPE_CONNECTED
+				{
+					let error = io::Error::last_os_error();
+					CloseHandle(pipe);
+					return Err(error);
+				}
+				// Closing the file closes the handle
+				Ok(Connection(File::from_raw_handle(pipe as RawHandle)))
+			}
+		}
+	}
+
+	fn create_pipe(first: bool) -> io::Result<HANDLE> {
+		let name: Vec<u16> = pipe_name().encode_utf16().chain(Some(0)).collect();
+		let mut open_mode = PIPE_ACCESS_DUPLEX;
+		if first {
+			open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+		}
+		let pipe_mode =
+			PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS;
+		let pipe = unsafe {
+			CreateNamedPipeW(
+				name.as_ptr(),
+				open_mode,
+				pipe_mode,
+				PIPE_UNLIMITED_INSTANCES,
+				BUFFER_SIZE,
+				BUFFER_SIZE,
+				TIMEOUT.as_millis() as u32,
+				std::ptr::null(),
+			)
+		};
+		if pipe == INVALID_HANDLE_VALUE {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(pipe)
+	}
+}
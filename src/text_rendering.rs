@@ -0,0 +1,135 @@
+//! Gelatin can only draw pictures, so text is laid out and rasterized with usvg and resvg
+//! (the same libraries that are used for loading SVG images) and then shown as the icon of
+//! a `Label`.
+
+use gelatin::image::{Rgba, RgbaImage};
+use lazy_static::lazy_static;
+use usvg::fontdb;
+
+/// The text is rendered at a higher resolution than its logical size so that it stays sharp
+/// on high DPI displays.
+const RENDER_SCALE: f32 = 2.0;
+/// Space between the text and the edge of the background, in logical pixels.
+const PADDING: f32 = 8.0;
+/// The icons are drawn in a box of this size
+const ICON_VIEWBOX: f32 = 24.0;
+/// The height of the icons relative to the font size
+const ICON_SCALE: f32 = 2.5;
+
+lazy_static! {
+	static ref FONT_DB: fontdb::Database = {
+		let mut fontdb = fontdb::Database::new();
+		fontdb.load_system_fonts();
+		fontdb
+	};
+}
+
+pub struct TextStyle {
+	pub font_size: f32,
+	pub color: [u8; 3],
+	pub background: [u8; 4],
+}
+
+impl Default for TextStyle {
+	fn default() -> Self {
+		TextStyle { font_size: 14.0, color: [235, 235, 235], background: [20, 20, 20, 210] }
+	}
+}
+
+pub struct RenderedText {
+	pub image: RgbaImage,
+	/// The width of the image in logical pixels
+	pub width: f32,
+	/// The height of the image in logical pixels
+	pub height: f32,
+}
+
+fn escape_xml(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	for ch in text.chars() {
+		match ch {
+			'&' => result.push_str("&amp;"),
+			'<' => result.push_str("&lt;"),
+			'>' => result.push_str("&gt;"),
+			'"' => result.push_str("&quot;"),
+			_ => result.push(ch),
+		}
+	}
+	result
+}
+
+/// Renders each line of `text` below the previous one, on top of a rectangle filled with the
+/// background color. Returns `None` if the text is empty or could not be rendered.
+pub fn render_text(text: &str, style: &TextStyle) -> Option<RenderedText> {
+	render(None, text, style)
+}
+
+/// Like `render_text`, but draws `icon` above the text. The icon is SVG markup that's drawn
+/// in a 24 by 24 box, with the text color as the default fill.
+pub fn render_text_with_icon(icon: &str, text: &str, style: &TextStyle) -> Option<RenderedText> {
+	render(Some(icon), text, style)
+}
+
+fn render(icon: Option<&str>, text: &str, style: &TextStyle) -> Option<RenderedText> {
+	let font_size = style.font_size * RENDER_SCALE;
+	let line_height = font_size * 1.3;
+	let [r, g, b] = style.color;
+
+	let mut svg = String::from(r#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1">"#);
+	let icon_height = match icon {
+		Some(icon) => {
+			let icon_height = font_size * ICON_SCALE;
+			svg.push_str(&format!(
+				r#"<g transform="scale({})" fill="rgb({r},{g},{b})">{}</g>"#,
+				icon_height / ICON_VIEWBOX,
+				icon
+			));
+			icon_height
+		}
+		None => 0.0,
+	};
+	svg.push_str(&format!(
+		r#"<text font-family="sans-serif" font-size="{font_size}" fill="rgb({r},{g},{b})" xml:space="preserve">"#
+	));
+	for (i, line) in text.lines().enumerate() {
+		svg.push_str(&format!(
+			r#"<tspan x="0" y="{}">{}</tspan>"#,
+			icon_height + (i + 1) as f32 * line_height,
+			escape_xml(line)
+		));
+	}
+	svg.push_str("</text></svg>");
+
+	let tree = match usvg::Tree::from_str(&svg, &usvg::Options::default(), &FONT_DB) {
+		Ok(tree) => tree,
+		Err(e) => {
+			log::error!("Failed to lay out text: {}", e);
+			return None;
+		}
+	};
+	let bbox = tree.root().abs_bounding_box();
+	if bbox.width() <= 0.0 || bbox.height() <= 0.0 {
+		return None;
+	}
+
+	let padding = PADDING * RENDER_SCALE;
+	let width = (bbox.width() + 2.0 * padding).ceil() as u32;
+	let height = (bbox.height() + 2.0 * padding).ceil() as u32;
+	let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+	let [bg_r, bg_g, bg_b, bg_a] = style.background;
+	pixmap.fill(tiny_skia::Color::from_rgba8(bg_r, bg_g, bg_b, bg_a));
+	let transform = tiny_skia::Transform::from_translate(padding - bbox.x(), padding - bbox.y());
+	resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+	// The pixmap stores premultiplied colors but the textures are expected to be straight.
+	let mut image = RgbaImage::new(width, height);
+	for (dst, src) in image.pixels_mut().zip(pixmap.pixels()) {
+		let c = src.demultiply();
+		*dst = Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+	}
+	Some(RenderedText {
+		image,
+		width: width as f32 / RENDER_SCALE,
+		height: height as f32 / RENDER_SCALE,
+	})
+}
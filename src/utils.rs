@@ -1,3 +1,8 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use gelatin::winit::keyboard::{Key, NamedKey};
 use log::warn;
 
@@ -326,3 +331,99 @@ pub fn virtual_keycode_to_string(key: &Key) -> String {
 		},
 	}
 }
+
+/// Formats a number of bytes using the largest fitting binary unit, e.g. "2.4 MiB"
+pub fn format_file_size(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", size, UNITS[unit])
+	}
+}
+
+/// Formats the duration as "h:mm:ss", or as "m:ss" if it's shorter than an hour
+pub fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+	if hours > 0 {
+		format!("{}:{:02}:{:02}", hours, minutes, secs)
+	} else {
+		format!("{}:{:02}", minutes, secs)
+	}
+}
+
+/// Formats the time as "YYYY-MM-DD hh:mm UTC"
+pub fn format_system_time(time: SystemTime) -> String {
+	let secs = match time.duration_since(UNIX_EPOCH) {
+		Ok(duration) => duration.as_secs() as i64,
+		Err(e) => -(e.duration().as_secs() as i64),
+	};
+	let days = secs.div_euclid(86400);
+	let secs_of_day = secs.rem_euclid(86400);
+
+	// Converts the number of days since 1970-01-01 to a date in the proleptic Gregorian
+	// calendar. See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let day_of_era = z.rem_euclid(146097);
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!(
+		"{:04}-{:02}-{:02} {:02}:{:02} UTC",
+		year,
+		month,
+		day,
+		secs_of_day / 3600,
+		(secs_of_day % 3600) / 60
+	)
+}
+
+/// Shows the file in the file manager of the system. The file is selected on Windows and
+/// macOS, elsewhere its folder is opened.
+pub fn reveal_in_file_manager(path: &Path) -> io::Result<()> {
+	let path = path.canonicalize()?;
+	reveal_command(&path)?.spawn().map(|_| ())
+}
+
+#[cfg(windows)]
+fn reveal_command(path: &Path) -> io::Result<Command> {
+	use std::os::windows::process::CommandExt;
+
+	// `canonicalize` returns a verbatim path, which Explorer doesn't accept
+	let path = path.to_string_lossy();
+	let path = path.strip_prefix(r"\\?\").unwrap_or(&*path);
+	let mut command = Command::new("explorer");
+	// Explorer splits its arguments by itself, so the path is quoted after the comma
+	command.raw_arg(format!("/select,\"{}\"", path));
+	Ok(command)
+}
+
+#[cfg(target_os = "macos")]
+fn reveal_command(path: &Path) -> io::Result<Command> {
+	let mut command = Command::new("open");
+	command.arg("-R").arg(path);
+	Ok(command)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn reveal_command(path: &Path) -> io::Result<Command> {
+	let folder = path
+		.parent()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The file has no folder"))?;
+	let mut command = Command::new("xdg-open");
+	command.arg(folder);
+	Ok(command)
+}
@@ -0,0 +1,152 @@
+//! Sets an image file as the desktop wallpaper.
+//!
+//! Windows and macOS have a single way of doing this. On Linux and the BSDs it depends on the
+//! desktop environment, which is told by `XDG_CURRENT_DESKTOP`. GNOME and the desktops derived
+//! from it, KDE Plasma, MATE and Xfce are supported.
+
+use std::path::Path;
+
+/// Returns a message describing the error if the wallpaper couldn't be set
+pub fn set_wallpaper(path: &Path) -> Result<(), String> {
+	// The desktop may read the file long after this returns, so the path can't be relative
+	let path = path.canonicalize().map_err(|e| e.to_string())?;
+	platform::set_wallpaper(&path)
+}
+
+#[cfg(not(windows))]
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+	let output = std::process::Command::new(program)
+		.args(args)
+		.output()
+		.map_err(|e| format!("Could not run {}: {}", program, e))?;
+	if output.status.success() {
+		Ok(())
+	} else {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		Err(format!("{} failed: {}", program, stderr.trim()))
+	}
+}
+
+#[cfg(windows)]
+mod platform {
+	use std::os::windows::ffi::OsStrExt;
+	use std::path::Path;
+
+	use windows_sys::Win32::UI::WindowsAndMessaging::{
+		SystemParametersInfoW, SPIF_SENDCHANGE, SPIF_UPDATEINIFILE, SPI_SETDESKWALLPAPER,
+	};
+
+	pub fn set_wallpaper(path: &Path) -> Result<(), String> {
+		// `canonicalize` returns a verbatim path, which the wallpaper setting doesn't accept
+		let path = path.to_string_lossy();
+		let path = path.strip_prefix(r"\\?\").unwrap_or(&*path);
+		let mut wide: Vec<u16> = std::ffi::OsStr::new(path).encode_wide().collect();
+		wide.push(0);
+		let succeeded = unsafe {
+			SystemParametersInfoW(
+				SPI_SETDESKWALLPAPER,
+				0,
+				wide.as_mut_ptr().cast(),
+				SPIF_UPDATEINIFILE | SPIF_SENDCHANGE,
+			)
+		};
+		if succeeded != 0 {
+			Ok(())
+		} else {
+			Err(std::io::Error::last_os_error().to_string())
+		}
+	}
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+	use std::path::Path;
+
+	pub fn set_wallpaper(path: &Path) -> Result<(), String> {
+		let path = path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+		let script = format!(
+			"tell application \"System Events\" to tell every desktop to set picture to \"{}\"",
+			path
+		);
+		super::run("osascript", &["-e", script.as_str()])
+	}
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+	use std::path::Path;
+
+	use super::run;
+	use crate::clipboard_handler::file_uri;
+
+	pub fn set_wallpaper(path: &Path) -> Result<(), String> {
+		let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_lowercase();
+		// Some desktops list several names, like "ubuntu:GNOME"
+		let is = |name: &str| desktop.split(':').any(|part| part == name);
+		let uri = file_uri(path);
+		if is("kde") {
+			set_plasma_wallpaper(&uri)
+		} else if is("x-cinnamon") || is("cinnamon") {
+			let uri = format!("'{}'", uri);
+			let schema = "org.cinnamon.desktop.background";
+			run("gsettings", &["set", schema, "picture-uri", uri.as_str()])
+		} else if is("mate") {
+			let path = path.to_string_lossy();
+			run("gsettings", &["set", "org.mate.background", "picture-filename", &*path])
+		} else if is("xfce") {
+			set_xfce_wallpaper(path)
+		} else if ["gnome", "unity", "budgie", "pantheon"].iter().any(|name| is(name)) {
+			let uri = format!("'{}'", uri);
+			let schema = "org.gnome.desktop.background";
+			run("gsettings", &["set", schema, "picture-uri", uri.as_str()])?;
+			// Only exists since GNOME 42, which shows it with the dark style
+			let _ = run("gsettings", &["set", schema, "picture-uri-dark", uri.as_str()]);
+			Ok(())
+		} else {
+			Err(format!("Setting the wallpaper is not supported on {:?}", desktop))
+		}
+	}
+
+	fn set_plasma_wallpaper(uri: &str) -> Result<(), String> {
+		let uri = uri.replace('\\', "\\\\").replace('"', "\\\"");
+		let script = format!(
+			"for (const desktop of desktops()) {{\
+				desktop.wallpaperPlugin = \"org.kde.image\";\
+				desktop.currentConfigGroup = [\"Wallpaper\", \"org.kde.image\", \"General\"];\
+				desktop.writeConfig(\"Image\", \"{}\");\
+			}}",
+			uri
+		);
+		let script_arg = format!("string:{}", script);
+		run(
+			"dbus-send",
+			&[
+				"--session",
+				"--type=method_call",
+				"--dest=org.kde.plasmashell",
+				"/PlasmaShell",
+				"org.kde.PlasmaShell.evaluateScript",
+				script_arg.as_str(),
+			],
+		)
+	}
+
+	/// Sets the image on every monitor and workspace that Xfce has a wallpaper setting for
+	fn set_xfce_wallpaper(path: &Path) -> Result<(), String> {
+		let output = std::process::Command::new("xfconf-query")
+			.args(["--channel", "xfce4-desktop", "--list"])
+			.output()
+			.map_err(|e| format!("Could not run xfconf-query: {}", e))?;
+		let properties = String::from_utf8_lossy(&output.stdout);
+		let mut properties = properties.lines().filter(|p| p.ends_with("/last-image")).peekable();
+		if properties.peek().is_none() {
+			return Err("Xfce has no wallpaper setting to change".into());
+		}
+		let path = path.to_string_lossy();
+		for property in properties {
+			let args = ["--channel", "xfce4-desktop", "--property", property, "--set", &*path];
+			run("xfconf-query", &args)?;
+		}
+		Ok(())
+	}
+}
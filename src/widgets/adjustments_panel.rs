@@ -0,0 +1,173 @@
+//! Sliders that change how the image is drawn, for a quick inspection of dark or washed out
+//! images. The adjustments are applied in the fragment shaders, so the file is never modified.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gelatin::{
+	label::Label,
+	line_layout_container::VerticalLayoutContainer,
+	misc::{Alignment, Length, LogicalVector},
+	picture::Picture,
+	slider::Slider,
+	Widget,
+};
+
+use crate::text_rendering::{render_text, TextStyle};
+
+const SLIDER_WIDTH: f32 = 200.0;
+const SLIDER_HEIGHT: f32 = 20.0;
+const PADDING: f32 = 8.0;
+
+/// The values that the fragment shaders get
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Adjustments {
+	/// In stops
+	pub exposure: f32,
+	pub gamma: f32,
+	pub saturation: f32,
+}
+
+impl Default for Adjustments {
+	fn default() -> Self {
+		Adjustments { exposure: 0.0, gamma: 1.0, saturation: 1.0 }
+	}
+}
+
+/// A slider with a label above it that shows its value
+struct AdjustmentSlider {
+	label: Rc<Label>,
+	slider: Rc<Slider>,
+	min: f32,
+	step: f32,
+	default: f32,
+	format: fn(f32) -> String,
+	/// The height of the rendered label in logical pixels
+	label_height: Cell<f32>,
+}
+
+impl AdjustmentSlider {
+	fn new(min: f32, max: f32, step: f32, default: f32, format: fn(f32) -> String) -> Rc<Self> {
+		let label = Rc::new(Label::new());
+		label.set_horizontal_align(Alignment::Start);
+		let slider = Rc::new(Slider::new());
+		slider.set_width(Length::Fixed(SLIDER_WIDTH));
+		slider.set_height(Length::Fixed(SLIDER_HEIGHT));
+		slider.set_horizontal_align(Alignment::Start);
+		slider.set_shadow_color([0.0, 0.0, 0.0]);
+		let adjustment = Rc::new(AdjustmentSlider {
+			label,
+			slider,
+			min,
+			step,
+			default,
+			format,
+			label_height: Cell::new(0.0),
+		});
+		let steps = ((max - min) / step).round() as u32 + 1;
+		adjustment.slider.set_steps(steps, adjustment.index_of(default));
+		adjustment.render_label();
+		// The slider is owned by the adjustment, so the callback only holds a weak reference
+		let weak = Rc::downgrade(&adjustment);
+		adjustment.slider.set_on_value_change(move || {
+			if let Some(adjustment) = weak.upgrade() {
+				adjustment.render_label();
+			}
+		});
+		adjustment
+	}
+
+	fn value(&self) -> f32 {
+		self.min + self.slider.value() as f32 * self.step
+	}
+
+	fn index_of(&self, value: f32) -> u32 {
+		((value - self.min) / self.step).round() as u32
+	}
+
+	fn reset(&self) {
+		self.slider.set_value(self.index_of(self.default));
+		self.render_label();
+	}
+
+	fn render_label(&self) {
+		let text = (self.format)(self.value());
+		if let Some(rendered) = render_text(&text, &TextStyle::default()) {
+			self.label.set_fixed_size(LogicalVector::new(rendered.width, rendered.height));
+			self.label.set_icon(Some(Rc::new(Picture::from_image(rendered.image))));
+			self.label_height.set(rendered.height);
+		}
+	}
+}
+
+pub struct AdjustmentsPanel {
+	/// Has to be added to the same container as the picture widget, after it
+	pub widget: Rc<VerticalLayoutContainer>,
+	exposure: Rc<AdjustmentSlider>,
+	gamma: Rc<AdjustmentSlider>,
+	saturation: Rc<AdjustmentSlider>,
+}
+
+impl AdjustmentsPanel {
+	pub fn new() -> Self {
+		let exposure = AdjustmentSlider::new(-5.0, 5.0, 0.1, 0.0, |value| {
+			format!("Exposure: {:+.1} EV", value)
+		});
+		let gamma =
+			AdjustmentSlider::new(0.2, 3.0, 0.05, 1.0, |value| format!("Gamma: {:.2}", value));
+		let saturation = AdjustmentSlider::new(0.0, 2.0, 0.05, 1.0, |value| {
+			format!("Saturation: {:.0}%", value * 100.0)
+		});
+
+		let widget = Rc::new(VerticalLayoutContainer::new());
+		widget.set_ignore_layout(true);
+		widget.set_margin_all(8.0);
+		widget.set_horizontal_align(Alignment::End);
+		widget.set_vertical_align(Alignment::Start);
+		widget.set_bg_color([0.08, 0.08, 0.08, 0.82]);
+		widget.set_visible(false);
+		let mut height = PADDING;
+		for adjustment in [&exposure, &gamma, &saturation].iter() {
+			adjustment.label.set_margin_left(PADDING);
+			adjustment.label.set_margin_top(PADDING);
+			adjustment.slider.set_margin_left(PADDING);
+			widget.add_child(adjustment.label.clone());
+			widget.add_child(adjustment.slider.clone());
+			height += PADDING + adjustment.label_height.get() + SLIDER_HEIGHT;
+		}
+		widget.set_width(Length::Fixed(SLIDER_WIDTH + 2.0 * PADDING));
+		widget.set_height(Length::Fixed(height + PADDING));
+
+		AdjustmentsPanel { widget, exposure, gamma, saturation }
+	}
+
+	pub fn adjustments(&self) -> Adjustments {
+		Adjustments {
+			exposure: self.exposure.value(),
+			gamma: self.gamma.value(),
+			saturation: self.saturation.value(),
+		}
+	}
+
+	pub fn reset(&self) {
+		self.exposure.reset();
+		self.gamma.reset();
+		self.saturation.reset();
+	}
+
+	pub fn toggle_visible(&self) {
+		self.widget.set_visible(!self.widget.visible());
+	}
+
+	/// Returns true if the panel is visible and covers `pos`, so that clicks at that position
+	/// are meant for the sliders
+	pub fn covers(&self, pos: LogicalVector) -> bool {
+		self.widget.visible() && self.widget.drawn_bounds().contains(pos)
+	}
+}
+
+impl Default for AdjustmentsPanel {
+	fn default() -> Self {
+		Self::new()
+	}
+}
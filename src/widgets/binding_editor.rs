@@ -0,0 +1,159 @@
+//! Lists the actions with the keys that they are bound to, and binds the selected action to the
+//! next key that is pressed. The bindings are saved into the `[bindings]` section of the config
+//! file, so the user doesn't have to look up the names of the keys.
+
+use gelatin::winit::keyboard::ModifiersState;
+
+use super::command_palette::action_title;
+use crate::configuration::{same_input, Configuration, FolderConfiguration};
+use crate::input_handling::{action_keys, ACTIONS};
+
+/// At most this many actions are listed at once
+const MAX_SHOWN_ACTIONS: usize = 15;
+
+/// Pressing only these doesn't finish the binding, they are held together with the key
+const MODIFIER_KEYS: [&str; 7] = ["control", "shift", "alt", "altgraph", "super", "meta", "hyper"];
+
+/// Asked from the picture widget, which owns the config
+pub enum EditorRequest {
+	Bind {
+		action_name: &'static str,
+		binding: String,
+	},
+	/// Binds the action to its default keys again
+	Reset(&'static str),
+	Close,
+}
+
+#[derive(Default)]
+pub struct BindingEditor {
+	/// An index into `ACTIONS`
+	selection: usize,
+	/// Set while waiting for the new key of the selected action
+	capturing: bool,
+	/// Tells what the last key did, shown under the list
+	message: String,
+}
+
+impl BindingEditor {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn set_message(&mut self, message: String) {
+		self.message = message;
+	}
+
+	pub fn handle_key(
+		&mut self,
+		input_key: &str,
+		modifiers: ModifiersState,
+	) -> Option<EditorRequest> {
+		let action_name = ACTIONS.get(self.selection)?.name;
+		if self.capturing {
+			if input_key.is_empty() || MODIFIER_KEYS.contains(&input_key) {
+				return None;
+			}
+			self.capturing = false;
+			if input_key == "escape" {
+				self.message.clear();
+				return None;
+			}
+			let binding = binding_of(input_key, modifiers);
+			return Some(EditorRequest::Bind { action_name, binding });
+		}
+		self.message.clear();
+		match input_key {
+			"up" => self.move_selection(-1),
+			"down" => self.move_selection(1),
+			"pageup" => self.move_selection(-(MAX_SHOWN_ACTIONS as isize)),
+			"pagedown" => self.move_selection(MAX_SHOWN_ACTIONS as isize),
+			"return" => self.capturing = true,
+			"delete" | "backspace" => return Some(EditorRequest::Reset(action_name)),
+			"escape" => return Some(EditorRequest::Close),
+			_ => (),
+		}
+		None
+	}
+
+	/// Moves the selection by `offset`, stopping at the ends of the list
+	fn move_selection(&mut self, offset: isize) {
+		let last = ACTIONS.len().saturating_sub(1) as isize;
+		self.selection = (self.selection as isize + offset).clamp(0, last) as usize;
+	}
+
+	pub fn text(&self, config: &Configuration) -> String {
+		let mut text = String::from("Key bindings\n");
+		let first = self.selection.saturating_sub(MAX_SHOWN_ACTIONS / 2);
+		let first = first.min(ACTIONS.len().saturating_sub(MAX_SHOWN_ACTIONS));
+		let shown = ACTIONS.iter().enumerate().skip(first).take(MAX_SHOWN_ACTIONS);
+		for (index, action) in shown {
+			let marker = if index == self.selection { ">" } else { " " };
+			let keys = action_keys(config, action.name).join(", ");
+			let keys = if keys.is_empty() { "not bound".to_owned() } else { keys };
+			text.push_str(&format!("\n{} {}: {}", marker, action_title(action.name), keys));
+		}
+		text.push_str(&format!("\n\n{} of {}\n", self.selection + 1, ACTIONS.len()));
+		if !self.message.is_empty() {
+			text.push_str(&format!("{}\n", self.message));
+		}
+		if self.capturing {
+			text.push_str("Press the new key of the selected action, or Escape to keep its keys");
+		} else {
+			text.push_str(
+				"Press Return to bind the selected action to another key, Delete to restore its\n\
+				default keys, or Escape to close the list",
+			);
+		}
+		text
+	}
+}
+
+/// The titles of the other actions and the commands that `binding` triggers too
+pub fn conflicts(
+	config: &Configuration,
+	folder_config: &FolderConfiguration,
+	action_name: &str,
+	binding: &str,
+) -> Vec<String> {
+	let bound = |keys: &[String]| keys.iter().any(|key| same_input(key, binding));
+	let other_actions = ACTIONS.iter().filter(|action| action.name != action_name);
+	let actions = other_actions
+		.filter(|action| bound(&action_keys(config, action.name)))
+		.map(|action| action_title(action.name));
+	let commands = config.folder_commands(folder_config).into_iter();
+	let commands = commands
+		.filter(|command| bound(&command.input))
+		.map(|command| format!("Run {}", command.program));
+	actions.chain(commands).collect()
+}
+
+/// Formats the key as it's written in the config, like "CmdCtrl+Shift+K". The modifier that
+/// most shortcuts use on the platform is written as CmdCtrl, so that the config works on the
+/// other platforms too.
+fn binding_of(input_key: &str, modifiers: ModifiersState) -> String {
+	let (primary, other, other_name) = if cfg!(target_os = "macos") {
+		(modifiers.super_key(), modifiers.control_key(), "Ctrl+")
+	} else {
+		(modifiers.control_key(), modifiers.super_key(), "Logo+")
+	};
+	let mut binding = String::new();
+	if primary {
+		binding.push_str("CmdCtrl+");
+	}
+	if other {
+		binding.push_str(other_name);
+	}
+	if modifiers.alt_key() {
+		binding.push_str("Alt+");
+	}
+	if modifiers.shift_key() {
+		binding.push_str("Shift+");
+	}
+	let mut chars = input_key.chars();
+	if let Some(first) = chars.next() {
+		binding.extend(first.to_uppercase());
+		binding.push_str(chars.as_str());
+	}
+	binding
+}
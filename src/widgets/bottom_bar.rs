@@ -1,13 +1,23 @@
-use super::picture_widget::ScalingMode;
-use crate::{ConfigWindowSection, Configuration, Theme};
+use super::picture_widget::{Channel, ScalingMode};
+use crate::{
+	image_cache::FramePosition,
+	playback_manager::{PlaybackEvent, PlaybackState},
+	text_rendering::{render_text, TextStyle},
+	ConfigWindowSection, Configuration, Theme,
+};
 
 use gelatin::{
 	button::Button,
+	label::Label,
 	line_layout_container::HorizontalLayoutContainer,
-	misc::{Alignment, Length},
+	misc::{Alignment, Length, LogicalVector},
 	picture::Picture,
 	slider::Slider,
+	window::Window,
+	winit::event::WindowEvent,
+	Widget,
 };
+use std::cell::{Cell, RefCell};
 use std::f32;
 use std::rc::Rc;
 
@@ -30,19 +40,46 @@ const ACTIVE_BG_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.5];
 const SMALL_BUTTON_GAP: f32 = 4.0;
 const BIG_BUTTON_GAP: f32 = 32.0;
 const BUTTON_SIZE: f32 = 24.0;
+/// When the bar hides automatically, it's shown while the cursor is closer than this to the
+/// bottom edge of the window, in logical pixels
+const AUTOHIDE_REVEAL_DISTANCE: f32 = 48.0;
 
 pub struct BottomBar {
 	pub widget: Rc<HorizontalLayoutContainer>,
 	pub orig_scale_button: Rc<Button>,
 	pub fit_stretch_button: Rc<Button>,
 	pub fit_best_button: Rc<Button>,
+	/// Shows the zoom level in percent
+	pub zoom_button: Rc<Button>,
+	zoom_text: RefCell<Option<String>>,
 	pub slider: Rc<Slider>,
 	pub theme_button: Rc<Button>,
 	pub help_button: Rc<Button>,
+	/// Shows the interval of the presentation while it's running
+	present_interval_label: Rc<Label>,
+	present_interval: Cell<Option<f32>>,
+	/// Updated by the events of the playback manager
+	presenting: Cell<bool>,
+	interval_secs: Cell<f32>,
+	/// Shows the position of the shown frame while an animation is paused
+	frame_label: Rc<Label>,
+	frame: Cell<Option<FramePosition>>,
+	/// Shows the rating and the tags of the image
+	metadata_label: Rc<Label>,
+	metadata: RefCell<Option<String>>,
+	/// Shows the channel of the image that is isolated
+	channel_label: Rc<Label>,
+	channel: Cell<Channel>,
+	theme: Cell<Theme>,
 
 	/// This is false if the configuration requires this to be invisible
 	// and true otherwise.
 	pub should_show: bool,
+	/// Set while the window is fullscreen
+	hidden: Cell<bool>,
+	/// The bar is only shown while the cursor is near the bottom edge of the window
+	autohide: Cell<bool>,
+	cursor_near_bottom: Cell<bool>,
 
 	question: Rc<Picture>,
 	question_light: Rc<Picture>,
@@ -82,6 +119,12 @@ impl BottomBar {
 		let orig_scale_button = make_icon_button(Alignment::Start);
 		let fit_best_button = make_icon_button(Alignment::Start);
 		let fit_stretch_button = make_icon_button(Alignment::Start);
+		let zoom_button = make_icon_button(Alignment::Start);
+		zoom_button.set_visible(false);
+		let present_interval_label = make_text_label();
+		let frame_label = make_text_label();
+		let metadata_label = make_text_label();
+		let channel_label = make_text_label();
 		let slider = make_slider();
 		let theme_button = make_icon_button(Alignment::End);
 		let help_button = make_icon_button(Alignment::End);
@@ -95,6 +138,11 @@ impl BottomBar {
 		widget.add_child(orig_scale_button.clone());
 		widget.add_child(fit_best_button.clone());
 		widget.add_child(fit_stretch_button.clone());
+		widget.add_child(zoom_button.clone());
+		widget.add_child(present_interval_label.clone());
+		widget.add_child(frame_label.clone());
+		widget.add_child(metadata_label.clone());
+		widget.add_child(channel_label.clone());
 		widget.add_child(slider.clone());
 		widget.add_child(theme_button.clone());
 		widget.add_child(help_button.clone());
@@ -106,16 +154,39 @@ impl BottomBar {
 		} else {
 			should_show = true;
 		}
+		let autohide = matches!(
+			config.window,
+			Some(ConfigWindowSection { autohide_bottom_bar: Some(true), .. })
+		);
+		if autohide {
+			widget.set_visible(false);
+		}
 
 		Self {
 			widget,
 			orig_scale_button,
 			fit_stretch_button,
 			fit_best_button,
+			zoom_button,
+			zoom_text: RefCell::new(None),
 			slider,
 			theme_button,
 			help_button,
+			present_interval_label,
+			present_interval: Cell::new(None),
+			presenting: Cell::new(false),
+			interval_secs: Cell::new(0.0),
+			frame_label,
+			frame: Cell::new(None),
+			metadata_label,
+			metadata: RefCell::new(None),
+			channel_label,
+			channel: Cell::new(Channel::All),
+			theme: Cell::new(Theme::Light),
 			should_show,
+			hidden: Cell::new(false),
+			autohide: Cell::new(autohide),
+			cursor_near_bottom: Cell::new(false),
 
 			question,
 			question_light,
@@ -133,6 +204,12 @@ impl BottomBar {
 	}
 
 	pub fn set_theme(&self, theme: Theme, update_available: bool) {
+		self.theme.set(theme);
+		self.render_present_interval();
+		self.render_frame();
+		self.render_metadata();
+		self.render_channel();
+		self.render_zoom();
 		match theme {
 			Theme::Light => {
 				self.orig_scale_button.set_icon(Some(self.one.clone()));
@@ -165,10 +242,162 @@ impl BottomBar {
 		}
 	}
 
+	/// Keeps the bar up to date with the playback. See `PlaybackManager::subscribe`
+	pub fn handle_playback_event(&self, event: &PlaybackEvent) {
+		match event {
+			PlaybackEvent::PlaybackStateChanged(state) => {
+				let presenting =
+					matches!(state, PlaybackState::Present | PlaybackState::RandomPresent);
+				self.presenting.set(presenting);
+			}
+			PlaybackEvent::PresentIntervalChanged(secs) => self.interval_secs.set(*secs),
+			PlaybackEvent::PausedFrameChanged(frame) => {
+				self.frame.set(*frame);
+				self.render_frame();
+				return;
+			}
+			_ => return,
+		}
+		let secs = if self.presenting.get() { Some(self.interval_secs.get()) } else { None };
+		self.set_present_interval(secs);
+	}
+
+	/// Shows the interval of the presentation in seconds, or hides it if `None`
+	fn set_present_interval(&self, secs: Option<f32>) {
+		if self.present_interval.replace(secs) != secs {
+			self.render_present_interval();
+		}
+	}
+
+	fn render_present_interval(&self) {
+		let text = self.present_interval.get().map(|secs| format!("{} s", secs));
+		self.render_label(&self.present_interval_label, text.as_deref());
+	}
+
+	fn render_frame(&self) {
+		let text = self.frame.get().map(|frame| {
+			let more = if frame.fully_loaded { "" } else { "+" };
+			format!("frame {}/{}{}", frame.index + 1, frame.count, more)
+		});
+		self.render_label(&self.frame_label, text.as_deref());
+	}
+
+	/// Shows the rating and the tags of the image, or hides them if `None`
+	pub fn set_metadata(&self, text: Option<String>) {
+		if *self.metadata.borrow() != text {
+			self.metadata.replace(text);
+			self.render_metadata();
+		}
+	}
+
+	fn render_metadata(&self) {
+		let metadata = self.metadata.borrow();
+		self.render_label(&self.metadata_label, metadata.as_deref());
+	}
+
+	pub fn set_channel(&self, channel: Channel) {
+		if self.channel.replace(channel) != channel {
+			self.render_channel();
+		}
+	}
+
+	fn render_channel(&self) {
+		self.render_label(&self.channel_label, self.channel.get().name());
+	}
+
+	/// Shows the size of an image pixel in physical pixels as a percentage, or hides the zoom
+	/// if `None`
+	pub fn set_zoom(&self, img_texel_size: Option<f32>) {
+		let text = img_texel_size.map(|size| format!("{}%", zoom_percent_text(size)));
+		if *self.zoom_text.borrow() != text {
+			self.zoom_text.replace(text);
+			self.render_zoom();
+		}
+	}
+
+	fn render_zoom(&self) {
+		let text = self.zoom_text.borrow();
+		let rendered = text.as_deref().and_then(|text| render_text(text, &self.text_style()));
+		match rendered {
+			Some(rendered) => {
+				self.zoom_button.set_width(Length::Fixed(rendered.width));
+				self.zoom_button.set_height(Length::Fixed(rendered.height));
+				self.zoom_button.set_icon(Some(Rc::new(Picture::from_image(rendered.image))));
+				self.zoom_button.set_visible(true);
+			}
+			None => self.zoom_button.set_visible(false),
+		}
+	}
+
+	/// Hides the label if `text` is `None`
+	fn render_label(&self, label: &Label, text: Option<&str>) {
+		let text = match text {
+			Some(text) => text,
+			None => {
+				label.set_visible(false);
+				return;
+			}
+		};
+		match render_text(text, &self.text_style()) {
+			Some(rendered) => {
+				label.set_fixed_size(LogicalVector::new(rendered.width, rendered.height));
+				label.set_icon(Some(Rc::new(Picture::from_image(rendered.image))));
+				label.set_visible(true);
+			}
+			None => label.set_visible(false),
+		}
+	}
+
+	fn text_style(&self) -> TextStyle {
+		let color = match self.theme.get() {
+			Theme::Light => [20, 20, 20],
+			Theme::Dark => [235, 235, 235],
+		};
+		TextStyle { font_size: 12.0, color, background: [0, 0, 0, 0] }
+	}
+
 	/// Sets this visible iff both the `visible` parameter is `true` and
-	/// the `should_show` property of this object is `true`
+	/// the `should_show` property of this object is `true`. If the bar hides automatically, it's
+	/// only shown while the cursor is near it.
 	pub fn set_visible_if_should_show(&self, visible: bool) {
-		self.widget.set_visible(visible && self.should_show);
+		self.hidden.set(!visible);
+		self.update_visibility();
+	}
+
+	pub fn autohide(&self) -> bool {
+		self.autohide.get()
+	}
+
+	pub fn set_autohide(&self, autohide: bool) {
+		self.autohide.set(autohide);
+		self.update_visibility();
+	}
+
+	/// Shows the bar when the cursor approaches the bottom edge of the window, if the bar hides
+	/// automatically. Has to be called with every event of the window.
+	pub fn handle_window_event(&self, window: &Window, event: &WindowEvent) {
+		let near_bottom = match event {
+			WindowEvent::CursorMoved { position, .. } => {
+				let height = window.window_mut().inner_size().height as f64;
+				let distance = (height - position.y) / window.scale_factor() as f64;
+				distance <= AUTOHIDE_REVEAL_DISTANCE as f64
+			}
+			WindowEvent::CursorLeft { .. } => false,
+			_ => return,
+		};
+		if self.cursor_near_bottom.replace(near_bottom) != near_bottom {
+			self.update_visibility();
+		}
+	}
+
+	/// Showing or hiding the bar changes the size of the picture area, so the window is laid
+	/// out again
+	fn update_visibility(&self) {
+		let revealed = !self.autohide.get() || self.cursor_near_bottom.get();
+		let visible = self.should_show && !self.hidden.get() && revealed;
+		if self.widget.visible() != visible {
+			self.widget.set_visible(visible);
+		}
 	}
 
 	pub fn set_help_visible(&self, visible: bool) {
@@ -201,6 +430,17 @@ impl BottomBar {
 	}
 }
 
+/// The size of an image pixel in physical pixels as a percentage, without the percent sign.
+/// Small zoom levels get a decimal, so that they don't show up as zero.
+pub fn zoom_percent_text(img_texel_size: f32) -> String {
+	let percent = img_texel_size * 100.0;
+	if percent < 10.0 {
+		format!("{:.1}", percent)
+	} else {
+		format!("{:.0}", percent)
+	}
+}
+
 fn make_icon_button(alignment: Alignment) -> Rc<Button> {
 	let button = Rc::new(Button::new());
 	button.set_margin_top(SMALL_BUTTON_GAP);
@@ -210,13 +450,22 @@ fn make_icon_button(alignment: Alignment) -> Rc<Button> {
 	button
 }
 
+fn make_text_label() -> Rc<Label> {
+	let label = Rc::new(Label::new());
+	label.set_margin_top(SMALL_BUTTON_GAP);
+	label.set_margin_left(BIG_BUTTON_GAP);
+	label.set_horizontal_align(Alignment::Start);
+	label.set_visible(false);
+	label
+}
+
 fn make_slider() -> Rc<Slider> {
 	let slider = Rc::new(Slider::new());
 	slider.set_margin_top(SMALL_BUTTON_GAP);
 	slider.set_margin_left(BIG_BUTTON_GAP);
 	slider.set_margin_right(BIG_BUTTON_GAP);
 	slider.set_height(Length::Fixed(BUTTON_SIZE));
-	slider.set_width(Length::Stretch { min: 0.0, max: std::f32::INFINITY });
+	slider.set_width(Length::Stretch { min: 0.0, max: f32::INFINITY });
 	slider.set_horizontal_align(Alignment::Center);
 	slider.set_steps(6, 1);
 	slider
@@ -0,0 +1,164 @@
+//! Lists the actions and the user commands, filtered by what is typed into it, so that they can
+//! be found without knowing their keys. Typing the characters of a name in order is enough to
+//! find it, for example "tglfs" finds "Toggle fullscreen".
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::rc::Rc;
+
+use gelatin::{text_box::TextBox, Widget};
+
+use super::text_overlay::make_text_box;
+
+/// At most this many entries are listed at once
+const MAX_SHOWN_ENTRIES: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaletteItem {
+	Action(&'static str),
+	/// The index of the user command among the commands that apply to the current folder
+	Command(usize),
+}
+
+pub struct PaletteEntry {
+	pub item: PaletteItem,
+	pub title: String,
+	/// The keys that trigger the entry, listed after the title
+	pub keys: String,
+}
+
+#[derive(Default)]
+struct PaletteState {
+	entries: Vec<PaletteEntry>,
+	/// The text that `matches` were found for
+	query: String,
+	/// The indices of the entries that match the query, the best match first
+	matches: Vec<usize>,
+	/// An index into `matches`
+	selection: usize,
+}
+
+impl PaletteState {
+	fn update_matches(&mut self, query: &str) {
+		if self.query == query && !self.matches.is_empty() {
+			return;
+		}
+		self.query = query.to_owned();
+		let entries = self.entries.iter().enumerate();
+		let mut scored: Vec<_> = entries
+			.filter_map(|(index, entry)| Some((fuzzy_score(query, &entry.title)?, index)))
+			.collect();
+		// The sort is stable, so entries that match equally well stay in their order
+		scored.sort_by_key(|&(score, _)| Reverse(score));
+		self.matches = scored.into_iter().map(|(_, index)| index).collect();
+		self.selection = 0;
+	}
+
+	fn format(&mut self, text: &str, cursor: usize) -> String {
+		self.update_matches(text);
+		let mut result = format!("Run a command:\n{}|{}", &text[..cursor], &text[cursor..]);
+		if self.matches.is_empty() {
+			result.push_str("\n\nNothing matches");
+			return result;
+		}
+		result.push('\n');
+		let first = self.selection.saturating_sub(MAX_SHOWN_ENTRIES - 1);
+		let shown = self.matches.iter().enumerate().skip(first).take(MAX_SHOWN_ENTRIES);
+		for (match_index, &entry_index) in shown {
+			let entry = &self.entries[entry_index];
+			let marker = if match_index == self.selection { ">" } else { " " };
+			result.push_str(&format!("\n{} {}", marker, entry.title));
+			if !entry.keys.is_empty() {
+				result.push_str(&format!("  ({})", entry.keys));
+			}
+		}
+		result
+	}
+}
+
+pub struct CommandPalette {
+	/// Has to be added to the same container as the picture widget, after it
+	pub text_box: Rc<TextBox>,
+	state: Rc<RefCell<PaletteState>>,
+}
+
+impl CommandPalette {
+	pub fn new() -> Self {
+		let state = Rc::new(RefCell::new(PaletteState::default()));
+		let text_box = {
+			let state = state.clone();
+			make_text_box(move |text, cursor| state.borrow_mut().format(text, cursor))
+		};
+		CommandPalette { text_box, state }
+	}
+
+	/// Shows the palette with an empty query, listing all of `entries`
+	pub fn open(&self, entries: Vec<PaletteEntry>) {
+		{
+			let mut state = self.state.borrow_mut();
+			*state = PaletteState { entries, ..PaletteState::default() };
+		}
+		self.text_box.set_text("");
+		self.text_box.set_visible(true);
+	}
+
+	pub fn visible(&self) -> bool {
+		self.text_box.visible()
+	}
+
+	/// Moves the selection down by `offset` entries, or up if it's negative
+	pub fn move_selection(&self, offset: isize) {
+		{
+			let mut state = self.state.borrow_mut();
+			let count = state.matches.len() as isize;
+			if count == 0 {
+				return;
+			}
+			state.selection = (state.selection as isize + offset).rem_euclid(count) as usize;
+		}
+		self.text_box.refresh();
+	}
+
+	pub fn selected(&self) -> Option<PaletteItem> {
+		let state = self.state.borrow();
+		let entry_index = *state.matches.get(state.selection)?;
+		Some(state.entries[entry_index].item)
+	}
+}
+
+impl Default for CommandPalette {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Turns the name of an action like "toggle_fullscreen" into a title like "Toggle fullscreen"
+pub fn action_title(action_name: &str) -> String {
+	let mut title = action_name.replace('_', " ");
+	if let Some(first) = title.get_mut(..1) {
+		first.make_ascii_uppercase();
+	}
+	title
+}
+
+/// Returns `None` if the characters of `query` don't all appear in `text` in the same order.
+/// Characters that follow each other or start a word make the score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+	let text: Vec<char> = text.to_lowercase().chars().collect();
+	let mut score = 0;
+	let mut next = 0;
+	let mut prev = None;
+	for query_ch in query.to_lowercase().chars().filter(|ch| !ch.is_whitespace()) {
+		let index = next + text[next..].iter().position(|&ch| ch == query_ch)?;
+		score += 1;
+		if prev.is_some_and(|prev| prev + 1 == index) {
+			score += 5;
+		}
+		if index == 0 || text[index - 1] == ' ' {
+			score += 3;
+		}
+		prev = Some(index);
+		next = index + 1;
+	}
+	Some(score)
+}
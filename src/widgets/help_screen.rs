@@ -61,6 +61,11 @@ impl HelpScreen {
 		}
 	}
 
+	/// See `Picture::decode_in_background`
+	pub fn decode_in_background(&self) {
+		self.data.borrow().usage_image.decode_in_background();
+	}
+
 	add_common_widget_functions!(data);
 }
 
@@ -135,7 +140,8 @@ impl Widget for HelpScreen {
 				//brighten: if self.hover { 0.15f32 } else { 0.0f32 },
 				brighten: 0.0f32,
 				shadow_color: Into::<[f32; 3]>::into(Vector3::<f32>::new(0.0, 0.0, 0.0)),
-				shadow_offset: 1.0f32
+				shadow_offset: 1.0f32,
+				opacity: 1.0f32,
 			};
 			target
 				.draw(
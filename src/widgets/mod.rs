@@ -1,4 +1,9 @@
+pub mod adjustments_panel;
+pub mod binding_editor;
 pub mod bottom_bar;
-pub mod copy_notification;
+pub mod command_palette;
 pub mod help_screen;
 pub mod picture_widget;
+pub mod text_overlay;
+pub mod toast;
+pub mod touch_gestures;
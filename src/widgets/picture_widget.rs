@@ -1,17 +1,28 @@
 use std::{
-	cell::{Ref, RefCell},
-	path::PathBuf,
+	cell::{Cell, Ref, RefCell},
+	collections::HashMap,
+	ffi::OsStr,
+	mem,
+	path::{Path, PathBuf},
 	rc::{Rc, Weak},
 	sync::{Arc, Mutex},
 	time::{Duration, Instant},
 };
 
+use lazy_static::lazy_static;
+use rand::Rng;
+
 use gelatin::{
-	cgmath::{Matrix4, Vector2, Vector3},
-	glium::{uniform, uniforms::MagnifySamplerFilter, Frame, Program, Surface},
+	cgmath::{Matrix, Matrix4, SquareMatrix, Vector2, Vector3, Vector4},
+	glium::{
+		texture::SrgbTexture2d, uniform, uniforms::MagnifySamplerFilter, Blend, BlendingFunction,
+		Frame, LinearBlendingFactor, Program, Surface,
+	},
+	image::RgbaImage,
 	shaders::ShaderDescriptor,
 	winit::{
-		event::{ElementState, MouseButton},
+		event::{ElementState, MouseButton, TouchPhase, WindowEvent},
+		event_loop::EventLoopProxy,
 		platform::modifier_supplement::KeyEventExtModifierSupplement,
 	},
 };
@@ -26,20 +37,115 @@ use gelatin::{
 };
 
 use crate::{
-	clipboard_handler::ClipboardHandler,
-	configuration::{Antialias, Cache, Configuration},
-	image_cache::{image_loader::Orientation, AnimationFrameTexture},
+	clipboard_handler::{ClipboardHandler, ClipboardOptions, ImageRegion},
+	config_validation::{self, ConfigWarning},
+	config_watcher::ConfigChange,
+	configuration::{
+		Antialias, Background, Cache, Configuration, DisplayTransform, FolderConfiguration,
+		PowerSaving, PresentEnd, ReadingProgress, SortOrder, Theme, ViewZoom, ZoomAnchor,
+		FOLDER_CONFIG_FILE_NAME,
+	},
+	deletion::{Activity, Completion, Deletions},
+	display_profile::DisplayProfileTracker,
+	display_transform::{self, DisplayLut},
+	export::{self, ExportRequest, ExportSource, Exporter},
+	file_operations,
+	image_cache::{
+		decode_limit,
+		file_filter::FileFilter,
+		image_loader::{self, Orientation},
+		orientation_writer,
+		pixel_reader::{linear_to_srgb, PixelReader},
+		sidecar::{self, ImageMetadata, MetadataFilter, MAX_RATING},
+		thumbnail_cache,
+		tiled_image::ImageRect,
+		AnimationFrameTexture,
+	},
 	input_handling::*,
+	onboarding::{Onboarding, OnboardingAnswers},
 	playback_manager::*,
+	power::PowerMonitor,
 	shaders,
-	utils::virtual_keycode_to_string,
+	utils::{
+		format_duration, format_file_size, format_system_time, reveal_in_file_manager,
+		virtual_keycode_to_string,
+	},
+	wallpaper,
 };
 
-use super::{bottom_bar::BottomBar, copy_notification::CopyNotifications, help_screen::HelpScreen};
+#[cfg(feature = "dialogs")]
+use crate::dialogs;
+#[cfg(feature = "layered")]
+use crate::image_cache::image_loader::{file_kind, FileKind};
+#[cfg(feature = "video")]
+use crate::image_cache::video_file;
+
+use super::{
+	adjustments_panel::AdjustmentsPanel,
+	binding_editor::{self, BindingEditor, EditorRequest},
+	bottom_bar::{zoom_percent_text, BottomBar},
+	command_palette::{action_title, PaletteEntry, PaletteItem},
+	help_screen::HelpScreen,
+	text_overlay::PictureOverlays,
+	toast::{ToastId, ToastKind, Toasts},
+	touch_gestures::{Gesture, TouchGestures},
+};
 
 const MIN_ZOOM_FACTOR: f32 = 0.0001;
 const MAX_ZOOM_FACTOR: f32 = 10000.0;
 const AA_TEXEL_SIZE_THRESHOLD: f32 = 4f32;
+/// The sizes of the area that the pixel inspector averages the color over
+const INSPECTOR_SAMPLE_SIZES: [u32; 3] = [1, 3, 5];
+/// Used for `Background::Custom` when `background_color` is not set in the config
+const DEFAULT_BACKGROUND_COLOR: [u8; 3] = [128, 128, 128];
+/// The amount that the presentation interval is changed by, in seconds
+const PRESENT_INTERVAL_STEP: f32 = 1.0;
+const MIN_PRESENT_INTERVAL: f32 = 0.5;
+const MAX_PRESENT_INTERVAL: f32 = 3600.0;
+
+const OPACITY_STEP: f32 = 0.1;
+/// A fully transparent window couldn't be found to click on
+const MIN_OPACITY: f32 = 0.1;
+
+/// In stops
+const EXPOSURE_STEP: f32 = 0.5;
+const MAX_EXPOSURE: f32 = 10.0;
+const DEFAULT_REFERENCE_OPACITY: f32 = 0.5;
+/// The factors that the difference mode can multiply the differences with, so that small ones
+/// become visible
+const DIFFERENCE_AMPLIFICATIONS: &[f32] = &[1.0, 4.0, 16.0, 64.0];
+/// Two clicks that are closer to each other than this are a double click
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(250);
+/// Images whose height is at least this many times their width are considered tall, and the
+/// position of the view is remembered for them
+const TALL_IMAGE_ASPECT: f32 = 2.0;
+/// Images whose width is at least this many times their height are panoramas, which the
+/// panorama tour can be shown for
+const PANORAMA_ASPECT: f32 = 2.0;
+/// In widths of the widget per second
+const DEFAULT_PANORAMA_TOUR_SPEED: f32 = 0.1;
+/// The Ken Burns effect zooms at most this much closer than the zoom that fits the image
+const KEN_BURNS_MAX_ZOOM: f32 = 1.4;
+/// The part of the widget that the page panning actions move the view by
+const PAGE_PAN_FRACTION: f32 = 0.9;
+const DEFAULT_NAV_REPEAT_DELAY_MS: u64 = 400;
+const DEFAULT_NAV_REPEAT_INTERVAL_MS: u64 = 80;
+/// How long the warning about running out of video memory is shown for
+const MEMORY_WARNING_DURATION: Duration = Duration::from_secs(4);
+const DEFAULT_OSD_SECS: f32 = 1.5;
+/// The on-screen display fades out during the end of the time that it's shown for
+const OSD_FADE_DURATION: Duration = Duration::from_millis(400);
+/// How often the cache statistics are refreshed while they are shown
+const CACHE_STATS_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// The recent folders are opened with the number keys, so at most this many are listed
+const MAX_LISTED_RECENT_FOLDERS: usize = 9;
+/// Shown while files are being moved to the trash
+const SPINNER_FRAMES: &[&str] = &["|", "/", "-", "\\"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the progress bar of the presentation is redrawn
+const PRESENT_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+/// The height of the progress bar of the presentation, in logical pixels
+const PRESENT_PROGRESS_HEIGHT: f32 = 3.0;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ScalingMode {
@@ -48,6 +154,43 @@ pub enum ScalingMode {
 	FitMin,
 }
 
+/// The channel of the image that is shown as gray instead of the whole image
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Channel {
+	All,
+	Red,
+	Green,
+	Blue,
+	Alpha,
+	Luminance,
+}
+
+impl Channel {
+	/// The name shown in the bottom bar, `None` if the whole image is shown
+	pub fn name(self) -> Option<&'static str> {
+		match self {
+			Channel::All => None,
+			Channel::Red => Some("Red"),
+			Channel::Green => Some("Green"),
+			Channel::Blue => Some("Blue"),
+			Channel::Alpha => Some("Alpha"),
+			Channel::Luminance => Some("Luminance"),
+		}
+	}
+
+	/// The value of the `channel` uniform of the fragment shaders
+	fn shader_mode(self) -> i32 {
+		match self {
+			Channel::All => 0,
+			Channel::Red => 1,
+			Channel::Green => 2,
+			Channel::Blue => 3,
+			Channel::Alpha => 4,
+			Channel::Luminance => 5,
+		}
+	}
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum MovementDir {
 	None,
@@ -61,12 +204,117 @@ impl MovementDir {
 	}
 }
 
+/// The part of the view that the two picture widgets of the compare mode have in common while
+/// their views are synchronized
+#[derive(Clone, Copy, PartialEq)]
+struct LinkedView {
+	scaling: ScalingMode,
+	/// Only used with `ScalingMode::Fixed`, the fitting modes fit each image on its own
+	img_texel_size: f32,
+	/// The position of the image center relative to the center of the widget
+	img_offset: Vector2<f32>,
+}
+
+/// Shared by the two picture widgets of the compare mode
+#[derive(Default)]
+struct ViewLink {
+	synchronized: bool,
+	view: Option<LinkedView>,
+	/// Incremented whenever one of the widgets changes the view
+	generation: u64,
+}
+
+/// Moving to the next or previous image while the key is held
+#[derive(Clone, Copy)]
+struct NavRepeat {
+	forward: bool,
+	next_time: Instant,
+}
+
+/// A slow zoom and pan from one part of the image to another while it's presented
+#[derive(Clone, Copy)]
+struct KenBurns {
+	start: KenBurnsView,
+	end: KenBurnsView,
+	/// Set once the view was moved to the start, which waits for the image to be shown
+	started: bool,
+	/// The time that the view has been moving for, in seconds. It doesn't include the time
+	/// while the presentation was paused.
+	elapsed: f32,
+	duration: f32,
+}
+
+impl KenBurns {
+	fn view(&self, elapsed: f32) -> KenBurnsView {
+		let t = (elapsed / self.duration.max(0.001)).min(1.0);
+		let (start, end) = (self.start, self.end);
+		KenBurnsView {
+			zoom: start.zoom + (end.zoom - start.zoom) * t,
+			center: start.center + (end.center - start.center) * t,
+		}
+	}
+}
+
+/// A view that doesn't depend on the size of the image or the widget
+#[derive(Clone, Copy)]
+struct KenBurnsView {
+	/// Relative to the zoom that fits the image into the widget
+	zoom: f32,
+	/// The point of the image at the center of the widget, from 0 to 1 along each axis
+	center: Vector2<f32>,
+}
+
+impl KenBurnsView {
+	fn random() -> Self {
+		let mut rng = rand::thread_rng();
+		let zoom = rng.gen_range(1.0..=KEN_BURNS_MAX_ZOOM);
+		// The widget shows `1 / zoom` of the fitted image along each axis
+		let margin = 0.5 / zoom;
+		let mut coordinate = || rng.gen_range(margin..=1.0 - margin);
+		let center = Vector2::new(coordinate(), coordinate());
+		KenBurnsView { zoom, center }
+	}
+}
+
+/// The window options that the reference mode changes, so that they can be restored
+#[derive(Clone, Copy)]
+struct WindowState {
+	always_on_top: bool,
+	borderless: bool,
+	opacity: f32,
+	click_through: bool,
+}
+
+/// What the capture of the rendered view is used for
+#[derive(Clone, Copy)]
+enum ViewCapture {
+	Clipboard,
+	Export,
+}
+
 #[derive(Debug, Clone)]
 enum HoverState {
 	None,
 	ItemHovered { prev_path: LoadedImgPath },
 }
 
+/// Returns the position of the image center along one axis after panning by `pages` pages.
+/// Images that fit into the widget along this axis are not moved.
+fn page_pan_position(center: f32, img_len: f32, widget_len: f32, pages: f32) -> f32 {
+	if img_len <= widget_len {
+		return center;
+	}
+	let center = center - pages * PAGE_PAN_FRACTION * widget_len;
+	center.clamp(widget_len - img_len / 2.0, img_len / 2.0)
+}
+
+/// Returns true if a texel covers a whole number of screen pixels, or a screen pixel covers a
+/// whole number of texels
+fn is_pixel_exact(texel_size: f32) -> bool {
+	let ratio = if texel_size >= 1.0 { texel_size } else { 1.0 / texel_size };
+	(ratio - ratio.round()).abs() < 0.001
+}
+
 fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
 	#[rustfmt::skip]
 	let result = match orientation {
@@ -117,6 +365,13 @@ fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
 	result
 }
 
+/// Called with the answers once the questions of the first launch were answered
+type OnboardingDone = Rc<dyn Fn(&OnboardingAnswers)>;
+
+/// Creates the widget of the compare mode and places it next to the picture widget. Returns
+/// `None` if the window was closed.
+type CompareWidgetFactory = Box<dyn Fn() -> Option<Rc<PictureWidget>>>;
+
 struct PictureWidgetData {
 	placement: WidgetPlacement,
 	drawn_bounds: LogicalRect,
@@ -135,12 +390,54 @@ struct PictureWidgetData {
 	clipboard_request_was_pending: bool,
 
 	program: Program,
+	/// Tone maps the floating point textures of HDR images
+	hdr_program: Program,
+	/// The exposure of HDR images in stops
+	exposure: f32,
+	display_transform: DisplayTransform,
+	/// The identity LUT unless `display_transform` is `Lut`
+	display_lut: DisplayLut,
+	/// Kept when another image is shown, so that the same channel of several textures can be
+	/// compared
+	channel: Channel,
+	/// Shows the difference between the shown image and the pinned one, see
+	/// `PlaybackManager::pin_shown_image`
+	difference: bool,
+	/// An index into `DIFFERENCE_AMPLIFICATIONS`
+	difference_amplification: usize,
+	difference_program: Program,
 	bright_shade: f32,
 	/// Size of an image texel in physical display pixels
 	img_texel_size: f32,
 	scaling: ScalingMode,
 	img_pos: LogicalVector,
+	/// The rotation and flip applied by the user on top of the orientation stored in the file.
+	/// This is reset whenever a different file is shown, unless the file was viewed with a
+	/// different orientation before.
+	view_orientation: Orientation,
+	/// The orientation and zoom of the shown file when it was opened. The view is only
+	/// remembered if it's different from this.
+	opened_view: (Orientation, ViewZoom),
+	/// The zoom of the previous session, which is applied to the first image that's shown
+	session_zoom: Option<ViewZoom>,
+	/// The zoom, position and orientation are kept when a different image is shown
+	view_locked: bool,
+	/// The shown image is progressively uploaded, and has visible tiles that are still missing
+	tiles_pending: bool,
+	reading_progress: ReadingProgress,
+	/// The reading positions of the tall images viewed in this session, if these are not stored
+	/// in the cache
+	reading_positions: HashMap<PathBuf, f32>,
+	wheel_zoom_anchor: ZoomAnchor,
+	invert_scroll_zoom: bool,
+	horizontal_scroll_navigation: bool,
+	/// The horizontal scrolling since the last image change, in lines
+	horizontal_scroll: f32,
 	antialiasing: Antialias,
+	background: Background,
+	/// The color of `Background::Custom`
+	background_color: [f32; 3],
+	present_end: PresentEnd,
 
 	hor_pan_input: MovementDir,
 	ver_pan_input: MovementDir,
@@ -151,8 +448,28 @@ struct PictureWidgetData {
 	ver_pan_vel: f32,
 	/// The velocity of zooming
 	zoom_vel: f32,
+	/// The direction that the panorama tour moves the view in, if the tour is shown. Positive
+	/// moves towards the right end of the image.
+	panorama_tour: Option<MovementDir>,
+	/// In widths of the widget per second
+	panorama_tour_speed: f32,
+	/// Set while the view moves across the presented image
+	ken_burns: Option<KenBurns>,
+	ken_burns_enabled: bool,
+	/// Set while the next or previous image key is held, if the repeating isn't left to the
+	/// operating system
+	nav_repeat: Option<NavRepeat>,
+	/// The window options from before the reference mode was turned on, if it's on
+	reference_mode: Option<WindowState>,
+	nav_repeat_delay: Duration,
+	/// Zero if the key repeat of the operating system is used instead
+	nav_repeat_interval: Duration,
 
 	last_click_time: Instant,
+	last_middle_click_time: Instant,
+	/// The scrolling that hasn't triggered a bound action yet, in lines
+	bound_scroll: f32,
+	touch_gestures: TouchGestures,
 	last_mouse_pos: LogicalVector,
 	panning_2d: bool,
 	panning_vert: bool,
@@ -164,8 +481,90 @@ struct PictureWidgetData {
 	next_update: NextUpdate,
 	bottom_bar: Rc<BottomBar>,
 	left_to_pan_hint: Rc<HelpScreen>,
-	copy_notifications: CopyNotifications,
+	toasts: Toasts,
+	/// The toast that shows the state of the last clipboard request
+	clipboard_toast: Option<ToastId>,
+	overlays: PictureOverlays,
+	power_monitor: PowerMonitor,
+	/// True if the user has requested the info panel to be shown
+	info_panel_requested: bool,
+	cache_stats_requested: bool,
+	/// In selection mode dragging with the left mouse button selects a region of the image
+	/// instead of panning
+	selection_mode: bool,
+	/// The start and end points of the selection as fractions of the displayed image size
+	selection: Option<(Vector2<f32>, Vector2<f32>)>,
+	/// True while the selection is being dragged
+	selecting: bool,
+	/// Set if the window was asked to capture the rendered view, which will be copied to the
+	/// clipboard or exported once the capture is ready
+	view_capture_pending: Option<ViewCapture>,
+	exporter: Exporter,
+	/// The time until which the warning about running out of video memory is shown
+	memory_warning_until: Option<Instant>,
+	/// The time until which the name of the image is shown
+	osd_until: Option<Instant>,
+	/// Read from the folder of the shown image
+	folder_config: FolderConfiguration,
+	/// The folder that `folder_config` was read from
+	folder_config_folder: Option<PathBuf>,
+	/// From the global config
+	sort_order: SortOrder,
+	/// Set with the key. Takes precedence over the config files until the window is closed.
+	cycled_sort_order: Option<SortOrder>,
+	/// Set with the key. Takes precedence over the config file until the window is closed.
+	cycled_min_rating: Option<u8>,
+	/// The rating and the tags of the shown image, read from its sidecar file
+	shown_metadata: Option<(PathBuf, ImageMetadata)>,
+	/// The last viewed images of the folders in the list of recent folders, the most recent
+	/// first. `None` if the list is not shown.
+	recent_folders: Option<Vec<PathBuf>>,
+	/// The selected program while the "Open with" list is shown
+	open_with_selection: Option<usize>,
+	binding_editor: Option<BindingEditor>,
+	confirm_delete: bool,
+	/// The image that is deleted if the user confirms it
+	pending_delete: Option<PathBuf>,
+	deletions: Deletions,
+	/// Set while files are being deleted or restored
+	deletion_spinner_start: Option<Instant>,
+	/// Shows the spinner while files are being deleted or restored
+	deletion_toast: Option<ToastId>,
+	pixel_inspector_enabled: bool,
+	pixel_reader: PixelReader,
+	/// The width and height of the area that the pixel inspector averages over
+	inspector_sample_size: u32,
+	/// If true, the pixel inspector shows linear color values instead of sRGB encoded ones
+	inspector_linear: bool,
+	/// The color under the cursor in the format that clicking copies it to the clipboard
+	inspected_color: Option<String>,
+	dpi_scale: f32,
+	display_profile_tracker: DisplayProfileTracker,
+	/// Set when the window was moved, because it may have been moved to a different monitor
+	window_moved: Rc<Cell<bool>>,
 	window: Weak<Window>,
+	/// Opens a new window, showing the given image if there's one
+	on_new_window: Option<Rc<dyn Fn(Option<PathBuf>)>>,
+	/// The questions of the first launch while they are shown
+	onboarding: Option<Onboarding>,
+	on_onboarding_done: Option<OnboardingDone>,
+	/// The widget that shows the pinned image next to this one in the compare mode. It's only
+	/// created when the compare mode is first shown.
+	compare_widget: Option<Rc<PictureWidget>>,
+	make_compare_widget: Option<CompareWidgetFactory>,
+	/// Shared by the widgets of the compare mode, so that both images are adjusted the same way
+	adjustments_panel: Option<Rc<AdjustmentsPanel>>,
+	/// True if the views are synchronized when the compare mode is shown
+	compare_synchronized: bool,
+	/// True for the widget that shows the pinned image of the compare mode. It doesn't handle
+	/// keyboard input and leaves the window title and the bottom bar to the other widget.
+	pinned: bool,
+	/// Set on both widgets while the compare mode is shown
+	view_link: Option<Rc<RefCell<ViewLink>>>,
+	/// The generation of the linked view that this widget has seen last
+	view_link_generation: u64,
+	/// The view of this widget as of the last synchronization
+	linked_view: Option<LinkedView>,
 }
 impl WidgetData for PictureWidgetData {
 	fn placement(&mut self) -> &mut WidgetPlacement {
@@ -213,7 +612,17 @@ impl PictureWidgetData {
 		}
 	}
 
+	/// The point that stays in place when zooming with the mouse wheel at `cursor_pos`
+	fn wheel_zoom_anchor(&self, cursor_pos: LogicalVector) -> LogicalVector {
+		match self.wheel_zoom_anchor {
+			ZoomAnchor::Cursor => cursor_pos,
+			ZoomAnchor::Center => self.drawn_bounds.size * 0.5f32,
+		}
+	}
+
+	/// `anchor` is the point of the widget that stays in place
 	fn zoom_image(&mut self, anchor: LogicalVector, mut delta: f32) {
+		self.panorama_tour = None;
 		delta = if delta > 0.0 { delta + 1.0 } else { 1.0 / (delta.abs() + 1.0) };
 		let mut image_texel_size = (self.img_texel_size * delta).max(0.0);
 		if (image_texel_size - 1.0).abs() < 0.01 {
@@ -221,13 +630,63 @@ impl PictureWidgetData {
 		} else {
 			image_texel_size = image_texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR)
 		}
-		self.img_pos = (image_texel_size / self.img_texel_size) * (self.img_pos - anchor) + anchor;
-		self.img_texel_size = image_texel_size;
+		self.zoom_image_to(anchor, image_texel_size);
+	}
+
+	/// Sets the size of an image pixel in physical pixels, keeping `anchor` in place
+	fn zoom_image_to(&mut self, anchor: LogicalVector, texel_size: f32) {
+		self.panorama_tour = None;
+		self.ken_burns = None;
+		self.scale_view(anchor, texel_size);
 		self.scaling = ScalingMode::Fixed;
 		self.update_scaling_buttons();
 		self.render_validity.invalidate();
 	}
 
+	/// Like `zoom_image_to`, but keeps the movements of the view going
+	fn scale_view(&mut self, anchor: LogicalVector, texel_size: f32) {
+		self.img_pos = (texel_size / self.img_texel_size) * (self.img_pos - anchor) + anchor;
+		self.img_texel_size = texel_size;
+	}
+
+	/// A fitted image isn't panned by dragging it, so that it can be swiped to move to the next
+	/// or previous image
+	fn apply_touch_gesture(&mut self, gesture: Gesture) {
+		match gesture {
+			Gesture::Pan(delta) => {
+				if self.scaling == ScalingMode::Fixed {
+					self.panorama_tour = None;
+					self.ken_burns = None;
+					self.img_pos += delta;
+					self.render_validity.invalidate();
+				}
+			}
+			Gesture::Pinch { center, pan, factor } => {
+				self.img_pos += pan;
+				let texel_size = self.img_texel_size * factor;
+				self.zoom_image_to(center, texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR));
+			}
+			Gesture::DoubleTap(pos) => {
+				if self.scaling == ScalingMode::Fixed {
+					let stretch = self.cache.lock().unwrap().image.fit_stretches;
+					self.set_img_size_to_fit(stretch);
+				} else {
+					self.zoom_image_to(pos, 1.0);
+				}
+			}
+			Gesture::Swipe(distance) => {
+				if self.scaling != ScalingMode::Fixed {
+					let request = if distance < 0.0 {
+						LoadRequest::LoadNext
+					} else {
+						LoadRequest::LoadPrevious
+					};
+					self.navigate(request);
+				}
+			}
+		}
+	}
+
 	fn update_image_transform(&mut self, dpi_scale: f32) {
 		match self.scaling {
 			ScalingMode::Fixed => {
@@ -293,187 +752,2277 @@ impl PictureWidgetData {
 			self.zoom_image(anchor, self.zoom_vel * dt_sec);
 		}
 		if self.hor_pan_input.moving() || self.ver_pan_input.moving() {
+			self.ken_burns = None;
 			let panning_speed = 400.0 * dpi_scale;
 			let pos_delta = Vector2::new(self.hor_pan_vel, self.ver_pan_vel) * dt_sec;
 			self.scaling = ScalingMode::Fixed;
 			self.update_scaling_buttons();
 			self.img_pos.vec += panning_speed * pos_delta;
 		}
+		if let Some(dir) = self.panorama_tour {
+			self.move_panorama_tour(dir, dt_sec);
+		}
+		if let Some(ken_burns) = self.ken_burns {
+			self.move_ken_burns(ken_burns, dt_sec);
+		}
 	}
 
 	fn camera_movement_will_start(&mut self) {
 		// If there hasn't been any movement in a while, then reset the last update time
 		// to avoid large jumps at the beggining of a move when the delta would be large.
-		if !self.hor_pan_input.moving() && !self.ver_pan_input.moving() && !self.zoom_input.moving()
+		if !self.hor_pan_input.moving()
+			&& !self.ver_pan_input.moving()
+			&& !self.zoom_input.moving()
+			&& self.panorama_tour.is_none()
+			&& !self.ken_burns_moving()
 		{
 			self.last_cam_move_time = Instant::now();
 		}
 	}
 
-	fn set_window_title_filename(
-		&self,
-		window: &Window,
-		playback_state: PlaybackState,
-		file_path: &LoadedImgPath,
-	) {
-		let playback = match playback_state {
-			PlaybackState::Forward => " : Playing",
-			PlaybackState::Present => " : Presenting",
-			PlaybackState::RandomPresent => " : Presenting Shuffled",
-			PlaybackState::Paused => "",
+	/// Fits a wide panorama to the height of the widget and starts moving the view across it
+	/// from the left end, or stops the tour if it's already shown.
+	fn toggle_panorama_tour(&mut self) {
+		if self.panorama_tour.take().is_some() {
+			return;
+		}
+		let (img_w, img_h) = match self.get_texture() {
+			Some(texture) => {
+				let (w, h) = texture.oriented_dimensions();
+				(w as f32, h as f32)
+			}
+			None => return,
 		};
+		let widget_size = self.drawn_bounds.size.vec;
+		let fitted_width = img_w * widget_size.y / img_h;
+		if img_w < img_h * PANORAMA_ASPECT || fitted_width <= widget_size.x {
+			return;
+		}
+		self.camera_movement_will_start();
+		self.img_texel_size = widget_size.y / img_h * self.dpi_scale;
+		self.img_pos = LogicalVector::new(fitted_width / 2.0, widget_size.y / 2.0);
+		self.scaling = ScalingMode::Fixed;
+		self.update_scaling_buttons();
+		self.panorama_tour = Some(MovementDir::Positive);
+		self.render_validity.invalidate();
+	}
 
-		let config = self.configuration.borrow();
-		let title_config = config.title.clone().unwrap_or_default();
-
-		let name = match file_path {
-			LoadedImgPath::NotYetLoaded => "[ none ]".into(),
-			LoadedImgPath::ErrLoading(path) => {
-				format!("[ FAILED TO OPEN ] {}", title_config.format_file_path(path)).into()
-			}
-			LoadedImgPath::Loaded(path) => title_config.format_file_path(path),
+	/// Moves the view of the panorama tour, and turns around at the ends of the image
+	fn move_panorama_tour(&mut self, dir: MovementDir, dt_sec: f32) {
+		let img_size = match self.displayed_image_size() {
+			Some(img_size) => img_size,
+			None => return,
 		};
-		let title = format!("{}{}{}", name, playback, title_config.format_program_name());
-		window.set_title(title);
+		let widget_width = self.drawn_bounds.size.vec.x;
+		// The positions of the image center where the left and the right end of the image is
+		// at the edge of the widget
+		let left_end = img_size.x / 2.0;
+		let right_end = widget_width - img_size.x / 2.0;
+		if right_end >= left_end {
+			// The image doesn't need panning anymore, e.g. because it was rotated
+			self.panorama_tour = None;
+			return;
+		}
+		let step = self.panorama_tour_speed * widget_width * dt_sec;
+		let x = &mut self.img_pos.vec.x;
+		match dir {
+			MovementDir::Positive => {
+				*x -= step;
+				if *x <= right_end {
+					*x = right_end;
+					self.panorama_tour = Some(MovementDir::Negative);
+				}
+			}
+			MovementDir::Negative => {
+				*x += step;
+				if *x >= left_end {
+					*x = left_end;
+					self.panorama_tour = Some(MovementDir::Positive);
+				}
+			}
+			MovementDir::None => (),
+		}
+		self.next_update = NextUpdate::Soonest;
 	}
 
-	fn get_texture(&self) -> Option<AnimationFrameTexture> {
-		self.playback_manager.image_texture()
+	fn presenting(&self) -> bool {
+		let state = self.playback_manager.playback_state();
+		matches!(state, PlaybackState::Present | PlaybackState::RandomPresent)
 	}
 
-	pub fn set_img_size_to_orig(&mut self) {
-		self.img_texel_size = 1.0;
+	/// Starts moving the view across the shown image if it's presented with the Ken Burns
+	/// effect. The view moves from a random part of the image to another one until the next
+	/// image is shown.
+	fn start_ken_burns(&mut self) {
+		self.ken_burns = None;
+		if !self.ken_burns_enabled || !self.presenting() || self.view_locked {
+			return;
+		}
+		self.panorama_tour = None;
+		self.camera_movement_will_start();
+		self.ken_burns = Some(KenBurns {
+			start: KenBurnsView::random(),
+			end: KenBurnsView::random(),
+			started: false,
+			elapsed: 0.0,
+			duration: self.playback_manager.present_interval_secs(),
+		});
 		self.scaling = ScalingMode::Fixed;
 		self.update_scaling_buttons();
 		self.render_validity.invalidate();
 	}
 
-	pub fn set_img_size_to_fit(&mut self, stretch: bool) {
-		{
-			let mut cache = self.cache.lock().unwrap();
-			cache.image.fit_stretches = stretch;
+	/// Called when the presentation is paused or continued. The effect is kept while the
+	/// presentation is paused, so that it continues from the same view.
+	fn resume_ken_burns(&mut self) {
+		if self.ken_burns.is_none() {
+			self.start_ken_burns();
+		} else if self.presenting() {
+			// The time while the presentation was paused isn't part of the movement
+			self.last_cam_move_time = Instant::now();
 		}
-		self.scaling = if stretch { ScalingMode::FitStretch } else { ScalingMode::FitMin };
-		self.update_scaling_buttons();
-		self.render_validity.invalidate();
 	}
 
-	pub fn toggle_antialias(&mut self) {
-		let aa = match self.antialiasing {
-			Antialias::Auto if self.img_texel_size < AA_TEXEL_SIZE_THRESHOLD => Antialias::Never,
-			Antialias::Auto | Antialias::Never => Antialias::Always,
-			Antialias::Always => Antialias::Never,
+	fn ken_burns_moving(&self) -> bool {
+		self.ken_burns.is_some() && self.presenting()
+	}
+
+	/// Moves the view to the start of the effect once the image is shown, and then towards the
+	/// end of it. The view stays where it is while the presentation is paused.
+	fn move_ken_burns(&mut self, mut ken_burns: KenBurns, dt_sec: f32) {
+		let img_size = match self.get_texture() {
+			Some(texture) => {
+				let (w, h) = texture.oriented_dimensions();
+				Vector2::new(w as f32, h as f32)
+			}
+			None => return,
 		};
-		self.antialiasing = aa;
-		self.cache.lock().unwrap().image.antialiasing = aa;
-		self.render_validity.invalidate();
+		if !self.presenting() {
+			return;
+		}
+		let widget_size = self.drawn_bounds.size.vec;
+		let center = LogicalVector::new(widget_size.x * 0.5, widget_size.y * 0.5);
+		let prev_view = ken_burns.view(ken_burns.elapsed);
+		if !ken_burns.started {
+			// In logical pixels
+			let texel_size = (widget_size.x / img_size.x).min(widget_size.y / img_size.y);
+			let texel_size = texel_size * prev_view.zoom;
+			let offset = prev_view.center - Vector2::new(0.5, 0.5);
+			let offset = Vector2::new(offset.x * img_size.x, offset.y * img_size.y) * texel_size;
+			self.img_texel_size = texel_size * self.dpi_scale;
+			self.img_pos.vec = center.vec - offset;
+			ken_burns.started = true;
+		}
+		ken_burns.elapsed += dt_sec;
+		let view = ken_burns.view(ken_burns.elapsed);
+		// Zooming around the center of the widget keeps the point of the image that is there
+		self.scale_view(center, self.img_texel_size * view.zoom / prev_view.zoom);
+		let pan = view.center - prev_view.center;
+		let texel_size = self.img_texel_size / self.dpi_scale;
+		self.img_pos.vec -= Vector2::new(pan.x * img_size.x, pan.y * img_size.y) * texel_size;
+		if ken_burns.elapsed < ken_burns.duration {
+			self.ken_burns = Some(ken_burns);
+			self.next_update = NextUpdate::Soonest;
+		} else {
+			self.ken_burns = None;
+		}
 	}
 
-	pub fn set_automatic_antialias(&mut self) {
-		self.antialiasing = Antialias::Auto;
-		self.cache.lock().unwrap().image.antialiasing = Antialias::Auto;
-		self.render_validity.invalidate();
+	/// Reads the config file of the folder of the shown image, unless it was already read
+	fn update_folder_config(&mut self) {
+		let folder = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.parent().map(|folder| folder.to_owned()),
+			_ => None,
+		};
+		if folder == self.folder_config_folder {
+			return;
+		}
+		self.folder_config = match &folder {
+			Some(folder) => FolderConfiguration::load(folder).unwrap_or_else(|e| {
+				eprintln!("{}", e);
+				Default::default()
+			}),
+			None => Default::default(),
+		};
+		if let (Some(folder), Some(_)) = (&folder, &self.folder_config.commands) {
+			if !self.configuration.borrow().is_folder_trusted(folder) {
+				eprintln!(
+					"The commands of {:?} are ignored, because the folder is not in the \
+					 `trusted_folders` of the config",
+					folder.join(FOLDER_CONFIG_FILE_NAME)
+				);
+				self.folder_config.commands = None;
+			}
+		}
+		self.folder_config_folder = folder;
+		self.apply_sort_order();
+		self.apply_file_filter();
+		self.apply_metadata_filter();
 	}
 
-	/// Ensures that the image is within the widget, or at least touches an edge of the widget
-	fn apply_img_bounds(&mut self, dpi_scale: f32) {
-		if let Some(texture) = self.get_texture() {
-			let (img_phys_w, img_phys_h) = {
-				let (w, h) = texture.oriented_dimensions();
-				(w as f32 * self.img_texel_size, h as f32 * self.img_texel_size)
-			};
-			let img_w = img_phys_w / dpi_scale;
-			let img_h = img_phys_h / dpi_scale;
+	fn apply_file_filter(&mut self) {
+		let file_filter = {
+			let config = self.configuration.borrow();
+			FileFilter::parse(config.file_filter(&self.folder_config).unwrap_or(""))
+		};
+		if let Err(e) = self.playback_manager.set_file_filter(file_filter) {
+			eprintln!("Could not filter the folder: {}", e);
+		}
+	}
 
-			let widget_size = self.drawn_bounds.size.vec;
-			let img_pos = self.img_pos.vec;
+	fn effective_sort_order(&self) -> SortOrder {
+		self.cycled_sort_order.or(self.folder_config.sort_order).unwrap_or(self.sort_order)
+	}
 
-			if img_pos.x < -img_w / 2.0 {
-				self.img_pos.vec.x = -img_w / 2.0;
-			}
-			if img_pos.y < -img_h / 2.0 {
-				self.img_pos.vec.y = -img_h / 2.0;
-			}
+	fn apply_sort_order(&mut self) {
+		let sort_order = self.effective_sort_order();
+		if let Err(e) = self.playback_manager.set_sort_order(sort_order) {
+			eprintln!("Could not sort the folder: {}", e);
+		}
+	}
 
-			if img_pos.x > widget_size.x + img_w / 2.0 {
-				self.img_pos.vec.x = (widget_size.x + img_w / 2.0).ceil();
-			}
-			if img_pos.y > widget_size.y + img_h / 2.0 {
-				self.img_pos.vec.y = (widget_size.y + img_h / 2.0).ceil();
-			}
+	fn cycle_sort_order(&mut self) {
+		let sort_order = self.effective_sort_order().next();
+		self.cycled_sort_order = Some(sort_order);
+		self.apply_sort_order();
+		let text = match sort_order {
+			SortOrder::Name => "Sorted by name",
+			SortOrder::ModifiedTime => "Sorted by modification time",
+			SortOrder::FileSize => "Sorted by file size",
+			SortOrder::Random => "Sorted randomly",
+		};
+		self.show_notice(text);
+	}
+
+	fn apply_metadata_filter(&mut self) {
+		let filter = metadata_filter(&self.configuration.borrow(), self.cycled_min_rating);
+		if let Err(e) = self.playback_manager.set_metadata_filter(filter) {
+			eprintln!("Could not filter the folder: {}", e);
 		}
 	}
 
-	fn update_scaling_buttons(&mut self) {
-		self.bottom_bar.update_scaling_buttons(self.scaling, self.img_texel_size);
+	/// Shows only the images with at least one more star than before, or all of them again
+	/// after five stars
+	fn cycle_rating_filter(&mut self) {
+		let config = self.configuration.borrow();
+		let min_rating = metadata_filter(&config, self.cycled_min_rating).min_rating;
+		drop(config);
+		let min_rating = (min_rating + 1) % (MAX_RATING + 1);
+		self.cycled_min_rating = Some(min_rating);
+		self.apply_metadata_filter();
+		let text = match min_rating {
+			0 => "Showing images with any rating".to_owned(),
+			1 => "Showing images with at least 1 star".to_owned(),
+			_ => format!("Showing images with at least {} stars", min_rating),
+		};
+		self.show_notice(&text);
 	}
-}
 
-pub struct PictureWidget {
-	data: RefCell<PictureWidgetData>,
-}
-impl PictureWidget {
-	pub fn new(
-		display: &Display,
-		window: &Rc<Window>,
-		bottom_bar: Rc<BottomBar>,
-		left_to_pan_hint: Rc<HelpScreen>,
-		copy_notifications: CopyNotifications,
-		configuration: Rc<RefCell<Configuration>>,
-		cache: Arc<Mutex<Cache>>,
-	) -> PictureWidget {
-		// let program = program!(display,
-		// 	140 => {
-		// 		vertex: shaders::VERTEX_140,
-		// 		fragment: shaders::FRAGMENT_140
-		// 	},
-		// 	110 => {
-		// 		vertex: shaders::VERTEX_110,
-		// 		fragment: shaders::FRAGMENT_110
-		// 	},
-		// )
-		// .unwrap();
+	/// Reads the rating and the tags of the shown image and shows them on the bottom bar,
+	/// unless they were already read
+	fn update_shown_metadata(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => Some(path),
+			_ => None,
+		};
+		if self.shown_metadata.as_ref().map(|(shown, _)| shown) == path {
+			return;
+		}
+		self.shown_metadata = path.map(|path| (path.clone(), sidecar::read(path)));
+		let text = self.shown_metadata.as_ref().map(|(_, metadata)| metadata.describe());
+		self.bottom_bar.set_metadata(text.filter(|text| !text.is_empty()));
+	}
 
-		let program = gelatin::shaders::shader_from_source(
-			display,
-			ShaderDescriptor {
-				vertex_shader: shaders::VERTEX_140,
-				fragment_shader: shaders::FRAGMENT_140,
-				outputs_srgb: false,
-				..Default::default()
-			},
-		)
-		.unwrap();
+	/// Changes the rating or the tags of the shown image and saves them in its sidecar file.
+	/// Returns false if they couldn't be saved.
+	fn edit_metadata<F: FnOnce(&mut ImageMetadata)>(&mut self, edit: F) -> bool {
+		let (path, mut metadata) = match self.shown_metadata.clone() {
+			Some(shown) => shown,
+			None => return false,
+		};
+		edit(&mut metadata);
+		if let Err(e) = sidecar::write(&path, &metadata) {
+			self.show_error(&format!("Could not save the rating and tags: {}", e));
+			return false;
+		}
+		let text = metadata.describe();
+		self.bottom_bar.set_metadata(if text.is_empty() { None } else { Some(text) });
+		self.shown_metadata = Some((path, metadata));
+		true
+	}
 
-		let scaling;
-		{
-			let cache = cache.lock().unwrap();
-			if cache.image.fit_stretches {
-				scaling = ScalingMode::FitStretch;
-			} else {
-				scaling = ScalingMode::FitMin;
-			}
+	fn rate(&mut self, rating: u8) {
+		if self.edit_metadata(|metadata| metadata.rating = rating) {
+			let text = match rating {
+				0 => "Rating removed".to_owned(),
+				1 => "Rated 1 star".to_owned(),
+				_ => format!("Rated {} stars", rating),
+			};
+			self.show_notice(&text);
 		}
+	}
 
-		let antialiasing = configuration
-			.borrow()
-			.image
-			.as_ref()
-			.and_then(|s| s.antialiasing.clone())
-			.unwrap_or_else(|| "auto".into());
+	fn toggle_tag(&mut self, tag: &str) {
+		let mut added = false;
+		let saved = self.edit_metadata(|metadata| {
+			metadata.toggle_tag(tag);
+			added = metadata.tags.iter().any(|t| t == tag);
+		});
+		if saved {
+			let text = if added { format!("Tagged {}", tag) } else { format!("Untagged {}", tag) };
+			self.show_notice(&text);
+		}
+	}
 
-		let antialiasing = match antialiasing.as_str() {
-			"auto" => Antialias::Auto,
-			"always" => Antialias::Always,
-			"never" => Antialias::Never,
-			"previous" => cache.lock().unwrap().image.antialiasing,
-			val => {
-				eprintln!("Illegal configuration value {:?} for antialiasing!", val);
-				eprintln!(r#"Allowed values are "auto", "always", "never" and "previous"."#);
-				Antialias::default()
-			}
+	fn change_opacity(&mut self, delta: f32) {
+		let window = match self.window.upgrade() {
+			Some(window) => window,
+			None => return,
 		};
-
+		if !window.supports_opacity() {
+			self.show_notice("Set `opacity` in the [window] section of the config to enable this");
+			return;
+		}
+		let opacity = (window.opacity() + delta).clamp(MIN_OPACITY, 1.0);
+		window.set_opacity(opacity);
+		self.show_notice(&format!("Opacity: {:.0}%", opacity * 100.0));
+	}
+
+	/// Only affects HDR images. The exposure is kept when another image is shown, so that a
+	/// series of renders can be compared.
+	fn set_exposure(&mut self, exposure: f32) {
+		self.exposure = exposure.clamp(-MAX_EXPOSURE, MAX_EXPOSURE);
+		self.show_notice(&format!("Exposure: {:+.1} EV", self.exposure));
+	}
+
+	/// Shows only `channel` of the image, or the whole image if `channel` is already shown
+	fn toggle_channel(&mut self, channel: Channel) {
+		self.channel = if self.channel == channel { Channel::All } else { channel };
+		self.bottom_bar.set_channel(self.channel);
+		self.render_validity.invalidate();
+	}
+
+	fn pin_difference_image(&mut self) {
+		if !self.playback_manager.pin_shown_image() {
+			return;
+		}
+		let name = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.file_name().map(|n| n.to_string_lossy().into()),
+			_ => None,
+		};
+		let name = name.unwrap_or_else(|| "the image".to_string());
+		self.show_notice(&format!("Pinned {} for the difference mode", name));
+		self.render_validity.invalidate();
+	}
+
+	fn toggle_difference(&mut self) {
+		if self.playback_manager.pinned_image().is_none() {
+			let keys = {
+				let config = self.configuration.borrow();
+				action_keys(&config, PIN_DIFFERENCE_IMAGE_NAME).join(" or ")
+			};
+			self.show_notice(&format!("Press {} to pin an image to compare with", keys));
+			return;
+		}
+		self.difference = !self.difference;
+		let comparable = self.get_texture().is_none_or(|tex| self.difference_to(&tex).is_some());
+		let text = match (self.difference, comparable) {
+			(false, _) => "Difference mode off",
+			(true, true) => "Difference mode on",
+			(true, false) => "Difference mode on, but the images differ in size or bit depth",
+		};
+		self.show_notice(text);
+	}
+
+	fn cycle_difference_amplification(&mut self) {
+		self.difference_amplification =
+			(self.difference_amplification + 1) % DIFFERENCE_AMPLIFICATIONS.len();
+		let amplification = DIFFERENCE_AMPLIFICATIONS[self.difference_amplification];
+		self.show_notice(&format!("Difference amplification: {}x", amplification));
+	}
+
+	/// The pinned image if the difference mode is on and the pinned image can be compared with
+	/// `texture`. That needs the images to be the same size, so that their textures are split
+	/// the same way. Floating point textures and placeholders aren't compared.
+	fn difference_to(&self, texture: &AnimationFrameTexture) -> Option<AnimationFrameTexture> {
+		if !self.difference {
+			return None;
+		}
+		let pinned = self.playback_manager.pinned_image()?;
+		let same_kind = pinned.float.is_none()
+			&& texture.float.is_none()
+			&& texture.placeholder.is_none()
+			&& pinned.tiled.is_some() == texture.tiled.is_some();
+		let same_size = (pinned.w, pinned.h) == (texture.w, texture.h)
+			&& pinned.cell_step_size == texture.cell_step_size;
+		if same_kind && same_size {
+			Some(pinned)
+		} else {
+			None
+		}
+	}
+
+	/// Makes the window borderless, translucent and kept above the other windows, so that it
+	/// can be used as a reference while working in another program. Turning it off restores
+	/// the previous state of the window.
+	fn toggle_reference_mode(&mut self) {
+		let window = match self.window.upgrade() {
+			Some(window) => window,
+			None => return,
+		};
+		if let Some(state) = self.reference_mode.take() {
+			window.set_always_on_top(state.always_on_top);
+			window.set_borderless(state.borderless);
+			window.set_opacity(state.opacity);
+			if state.click_through {
+				window.set_click_through(false);
+			}
+			self.show_notice("Reference mode: off");
+			return;
+		}
+		let config = self.configuration.borrow();
+		let window_cfg = config.window.as_ref();
+		let opacity = window_cfg.and_then(|cfg| cfg.reference_opacity);
+		let click_through = window_cfg.and_then(|cfg| cfg.reference_click_through);
+		drop(config);
+		let opacity = opacity.unwrap_or(DEFAULT_REFERENCE_OPACITY).clamp(MIN_OPACITY, 1.0);
+		let click_through = click_through.unwrap_or(false) && window.set_click_through(true);
+		self.reference_mode = Some(WindowState {
+			always_on_top: window.always_on_top(),
+			borderless: window.borderless(),
+			opacity: window.opacity(),
+			click_through,
+		});
+		window.set_always_on_top(true);
+		window.set_borderless(true);
+		window.set_opacity(opacity);
+		if click_through {
+			// The clicks go to the window below, so only the keys can turn the mode off
+			self.show_notice("Reference mode: on. Switch back to this window to turn it off");
+		} else {
+			self.show_notice("Reference mode: on");
+		}
+	}
+
+	fn show_notice(&mut self, text: &str) {
+		self.toasts.push(ToastKind::Info, text);
+		self.render_validity.invalidate();
+	}
+
+	fn show_error(&mut self, text: &str) {
+		self.toasts.push(ToastKind::Error, text);
+		self.render_validity.invalidate();
+	}
+
+	/// Shows the state of the clipboard request in the same toast until it's finished
+	fn show_clipboard_pending(&mut self) {
+		let kind = ToastKind::Pending;
+		self.clipboard_toast = Some(self.toasts.replace(self.clipboard_toast, kind, "Copying"));
+		self.render_validity.invalidate();
+	}
+
+	fn show_clipboard_result(&mut self, succeeded: bool) {
+		let (kind, text) = if succeeded {
+			(ToastKind::Info, "Copied to the clipboard")
+		} else {
+			(ToastKind::Error, "Could not copy to the clipboard")
+		};
+		self.clipboard_toast = Some(self.toasts.replace(self.clipboard_toast, kind, text));
+		self.render_validity.invalidate();
+	}
+
+	/// Displays another mip level or layer of texture files
+	#[cfg(feature = "textures")]
+	fn step_texture_selection(&mut self, level_step: i32, layer_step: i32) {
+		use crate::image_cache::texture_file::{self, Selection};
+
+		let texture = self.playback_manager.image_texture().and_then(|t| t.info);
+		let texture = match texture.as_ref().and_then(|info| info.texture) {
+			Some(texture) => texture,
+			None => return,
+		};
+		let step = |index: u32, step: i32, count: u32| {
+			(index as i64 + step as i64).rem_euclid(count as i64) as u32
+		};
+		let level = step(texture.level, level_step, texture.level_count);
+		let layer = step(texture.layer, layer_step, texture.layer_count);
+		texture_file::set_selection(Selection { level, layer });
+		self.playback_manager.reload_images();
+		let text = format!(
+			"Mip level {} of {}\nLayer {} of {}",
+			level + 1,
+			texture.level_count,
+			layer + 1,
+			texture.layer_count
+		);
+		self.show_notice(&text);
+	}
+
+	/// Shows or hides the list of the recently viewed folders, other than the current one
+	fn toggle_recent_folders(&mut self) {
+		self.render_validity.invalidate();
+		if self.recent_folders.take().is_some() {
+			self.overlays.recent_folders.hide();
+			return;
+		}
+		let current_folder = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.parent().map(|folder| folder.to_owned()),
+			_ => None,
+		};
+		let files: Vec<_> = {
+			let cache = self.cache.lock().unwrap();
+			let files = cache.recent_folders.iter().rev().map(|entry| PathBuf::from(&entry.file));
+			let files = files.filter(|file| file.parent() != current_folder.as_deref());
+			files.take(MAX_LISTED_RECENT_FOLDERS).collect()
+		};
+		let mut text = String::from("Recent folders\n");
+		if files.is_empty() {
+			text.push_str("\nNo other folders were viewed yet");
+		}
+		for (i, file) in files.iter().enumerate() {
+			let folder = file.parent().unwrap_or(file);
+			text.push_str(&format!("\n{}   {}", i + 1, folder.to_string_lossy()));
+		}
+		text.push_str("\n\nPress the number of a folder to open it");
+		self.overlays.recent_folders.show_text(&text);
+		self.recent_folders = Some(files);
+	}
+
+	/// Opens the image that was viewed last in the folder at `index` in the list of recent
+	/// folders
+	fn open_recent_folder(&mut self, index: usize) {
+		let file = self.recent_folders.take().and_then(|files| files.into_iter().nth(index));
+		self.overlays.recent_folders.hide();
+		self.render_validity.invalidate();
+		let file = match file {
+			Some(file) => file,
+			None => return,
+		};
+		// If the image was deleted since, the folder is opened at its first image
+		let path = match file.parent() {
+			Some(folder) if !file.exists() => folder.to_owned(),
+			_ => file,
+		};
+		self.playback_manager.request_load(LoadRequest::FilePath(path));
+	}
+
+	/// Shows or hides the list of the programs that the image can be opened with. The file
+	/// manager is always the last one.
+	fn toggle_open_with(&mut self) {
+		self.render_validity.invalidate();
+		if self.open_with_selection.take().is_some() {
+			self.overlays.open_with.hide();
+			return;
+		}
+		if !matches!(self.playback_manager.shown_file_path(), LoadedImgPath::Loaded(_)) {
+			return;
+		}
+		self.open_with_selection = Some(0);
+		self.update_open_with_list();
+	}
+
+	/// Moves the selection by `offset`, wrapping around at the ends of the list
+	fn move_open_with_selection(&mut self, offset: isize) {
+		let program_count = self.configuration.borrow().open_with.as_ref().map_or(0, |p| p.len());
+		let count = program_count + 1;
+		if let Some(selected) = self.open_with_selection {
+			let selected = (selected as isize + offset).rem_euclid(count as isize) as usize;
+			self.open_with_selection = Some(selected);
+			self.update_open_with_list();
+		}
+	}
+
+	fn update_open_with_list(&mut self) {
+		let selected = match self.open_with_selection {
+			Some(selected) => selected,
+			None => return,
+		};
+		let mut text = String::from("Open with\n");
+		let config = self.configuration.borrow();
+		let programs = config.open_with.iter().flatten().map(|program| program.name.as_str());
+		for (i, name) in programs.chain(std::iter::once("File manager")).enumerate() {
+			let marker = if i == selected { ">" } else { " " };
+			text.push_str(&format!("\n{} {}   {}", marker, i + 1, name));
+		}
+		drop(config);
+		text.push_str("\n\nSelect a program with the arrow keys and press Return to open it");
+		self.overlays.open_with.show_text(&text);
+		self.render_validity.invalidate();
+	}
+
+	fn toggle_binding_editor(&mut self) {
+		self.render_validity.invalidate();
+		if self.binding_editor.take().is_some() {
+			self.overlays.binding_editor.hide();
+			return;
+		}
+		self.binding_editor = Some(BindingEditor::new());
+		self.update_binding_editor();
+	}
+
+	fn update_binding_editor(&mut self) {
+		let text = match &self.binding_editor {
+			Some(editor) => editor.text(&self.configuration.borrow()),
+			None => return,
+		};
+		self.overlays.binding_editor.show_text(&text);
+		self.render_validity.invalidate();
+	}
+
+	/// The binding editor takes every key while it's shown
+	fn binding_editor_input(&mut self, input_key: &str, modifiers: ModifiersState) {
+		let request = match &mut self.binding_editor {
+			Some(editor) => editor.handle_key(input_key, modifiers),
+			None => return,
+		};
+		let message = match request {
+			Some(EditorRequest::Bind { action_name, binding }) => {
+				let conflicts = binding_editor::conflicts(
+					&self.configuration.borrow(),
+					&self.folder_config,
+					action_name,
+					&binding,
+				);
+				let title = action_title(action_name);
+				let saved = self.save_binding(action_name, Some(vec![binding.clone()]));
+				match (saved, conflicts.is_empty()) {
+					(false, _) => None,
+					(true, true) => Some(format!("{} is bound to {}", title, binding)),
+					(true, false) => {
+						Some(format!("{} is also bound to {}", binding, conflicts.join(", ")))
+					}
+				}
+			}
+			Some(EditorRequest::Reset(action_name)) => {
+				let saved = self.save_binding(action_name, None);
+				let title = action_title(action_name);
+				saved.then(|| format!("{} is bound to its default keys", title))
+			}
+			Some(EditorRequest::Close) => {
+				self.toggle_binding_editor();
+				return;
+			}
+			None => None,
+		};
+		if let (Some(editor), Some(message)) = (&mut self.binding_editor, message) {
+			editor.set_message(message);
+		}
+		self.update_binding_editor();
+	}
+
+	/// Returns false if the config file couldn't be written
+	fn save_binding(&mut self, action_name: &str, keys: Option<Vec<String>>) -> bool {
+		let result = self.configuration.borrow_mut().save_binding(action_name, keys);
+		if let Err(e) = &result {
+			self.show_error(&format!("Could not save the binding: {}", e));
+		}
+		result.is_ok()
+	}
+
+	/// Opens the shown image with the program at `index` in the "Open with" list
+	fn open_with(&mut self, index: usize) {
+		self.open_with_selection = None;
+		self.overlays.open_with.hide();
+		self.render_validity.invalidate();
+		let config = self.configuration.borrow();
+		let program_count = config.open_with.as_ref().map_or(0, |p| p.len());
+		let program = config.open_with.iter().flatten().nth(index).cloned();
+		drop(config);
+		if index == program_count {
+			self.open_containing_folder();
+			return;
+		}
+		let (program, path) = match (program, self.playback_manager.shown_file_path()) {
+			(Some(program), LoadedImgPath::Loaded(path)) => (program, path.clone()),
+			_ => return,
+		};
+		let folder = path.parent().unwrap_or_else(|| Path::new(""));
+		let (img_path, folder_path) = match (path.to_str(), folder.to_str()) {
+			(Some(img_path), Some(folder_path)) => (img_path, folder_path),
+			_ => {
+				self.show_error("The path of the image is not valid UTF-8");
+				return;
+			}
+		};
+		match open_with(&program, img_path, folder_path) {
+			Ok(()) => self.show_notice(&format!("Opened with {}", program.name)),
+			Err(e) => self.show_error(&format!("Could not start {}: {}", program.name, e)),
+		}
+	}
+
+	/// Answers the current question of the first launch with the number key. Returns the
+	/// answers after the last question.
+	fn answer_onboarding(&mut self, input_key: &str, skip: bool) -> Option<OnboardingAnswers> {
+		let mut onboarding = self.onboarding.take()?;
+		self.render_validity.invalidate();
+		if !skip {
+			if let Some(number) = input_key.parse::<usize>().ok().filter(|&n| n >= 1) {
+				onboarding.answer(number - 1);
+			}
+			if !onboarding.is_finished() {
+				self.overlays.onboarding.show_text(&onboarding.text());
+				self.onboarding = Some(onboarding);
+				return None;
+			}
+		}
+		self.overlays.onboarding.hide();
+		if skip {
+			return None;
+		}
+		let answers = onboarding.answers();
+		self.set_img_size_to_fit(answers.fit_stretches);
+		Some(answers)
+	}
+
+	/// Deletes the shown image, or asks for confirmation first if that's configured
+	fn request_delete(&mut self) {
+		let path = match self.pending_delete.take() {
+			Some(path) => {
+				self.overlays.delete_prompt.hide();
+				path
+			}
+			None => {
+				let path = match self.playback_manager.shown_file_path() {
+					LoadedImgPath::Loaded(path) => path.clone(),
+					_ => return,
+				};
+				if self.confirm_delete {
+					let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+					let text = format!(
+						"Move {} to the trash?\n\n\
+						Press the delete key again to confirm, or any other key to cancel",
+						file_name
+					);
+					self.overlays.delete_prompt.show_text(&text);
+					self.pending_delete = Some(path);
+					self.render_validity.invalidate();
+					return;
+				}
+				path
+			}
+		};
+		// The folder is updated when the file is gone
+		self.deletions.delete(&path);
+		self.render_validity.invalidate();
+	}
+
+	/// Shows the text box for renaming the current file
+	fn start_rename(&mut self) {
+		let file_name = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.file_name().unwrap_or_default().to_string_lossy(),
+			_ => return,
+		};
+		// The cursor is placed before the extension, so that the name can be edited right away
+		let cursor = match file_name.rfind('.') {
+			Some(dot) if dot > 0 => dot,
+			_ => file_name.len(),
+		};
+		let rename_box = &self.overlays.rename_box;
+		rename_box.set_text(&file_name);
+		rename_box.set_cursor(cursor);
+		rename_box.set_visible(true);
+		self.render_validity.invalidate();
+	}
+
+	fn cancel_delete(&mut self) {
+		if self.pending_delete.take().is_some() {
+			self.overlays.delete_prompt.hide();
+			self.render_validity.invalidate();
+		}
+	}
+
+	/// Moves or copies the shown image into the folder, and shows the next image
+	fn send_to_folder(&mut self, folder: &Path, copy: bool) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		let folder_name = folder.file_name().unwrap_or(folder.as_os_str()).to_string_lossy();
+		let result = if copy {
+			file_operations::copy_to_folder(&path, folder)
+		} else {
+			file_operations::move_to_folder(&path, folder)
+		};
+		match result {
+			Ok(_) if copy => {
+				self.show_notice(&format!("Copied to {}", folder_name));
+				let config = self.configuration.borrow();
+				let advance = config.quick_sort.as_ref().and_then(|s| s.advance_after_copy);
+				drop(config);
+				if advance.unwrap_or(true) {
+					self.playback_manager.request_load(LoadRequest::LoadNext);
+				}
+			}
+			Ok(_) => {
+				self.show_notice(&format!("Moved to {}", folder_name));
+				// The image at the same index is the next one now
+				if let Err(e) = self.playback_manager.update_directory() {
+					eprintln!("Error while updating directory {:?}", e);
+				}
+			}
+			Err(e) => {
+				self.show_error(&format!("Could not send the file to {}: {}", folder_name, e));
+			}
+		}
+		self.render_validity.invalidate();
+	}
+
+	/// Restores the last deleted image and shows it
+	fn undo_delete(&mut self) {
+		self.deletions.undo();
+		self.render_validity.invalidate();
+	}
+
+	/// Handles the deletions that finished in the background, and shows a spinner while
+	/// there are unfinished ones
+	fn update_deletions(&mut self, now: Instant) {
+		for completion in self.deletions.poll() {
+			match completion {
+				Completion::Deleted(path) => {
+					if let Err(e) = self.playback_manager.update_directory() {
+						eprintln!("Error while updating directory {:?}", e);
+					}
+					let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+					self.show_notice(&format!("Moved {} to the trash", file_name));
+				}
+				Completion::DeleteFailed(path, e) => {
+					eprintln!("Error while moving file '{:?}' to trash: {}", path, e);
+					let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+					self.show_error(&format!("Could not move {} to the trash", file_name));
+				}
+				Completion::Restored(path) => {
+					if let Err(e) = self.playback_manager.update_directory() {
+						eprintln!("Error while updating directory {:?}", e);
+					}
+					self.playback_manager.request_load(LoadRequest::FilePath(path));
+					self.show_notice("Restored the deleted file");
+				}
+				Completion::RestoreFailed(e) => {
+					self.show_error(&format!("Could not restore the deleted file: {}", e));
+				}
+			}
+		}
+		let activity = match self.deletions.activity() {
+			Some(activity) => activity,
+			None => {
+				self.deletion_spinner_start = None;
+				if let Some(toast) = self.deletion_toast.take() {
+					self.toasts.dismiss(toast);
+				}
+				return;
+			}
+		};
+		let start = *self.deletion_spinner_start.get_or_insert(now);
+		let elapsed = now.duration_since(start);
+		let frame = (elapsed.as_millis() / SPINNER_INTERVAL.as_millis()) as usize;
+		let text = match activity {
+			Activity::Deleting => "Moving to the trash",
+			Activity::Restoring => "Restoring from the trash",
+		};
+		let spinner = SPINNER_FRAMES[frame % SPINNER_FRAMES.len()];
+		let text = format!("{} {}", text, spinner);
+		let toast = self.toasts.replace(self.deletion_toast, ToastKind::Pending, &text);
+		self.deletion_toast = Some(toast);
+		let spinner_update = NextUpdate::WaitUntil(now + SPINNER_INTERVAL);
+		self.next_update = self.next_update.aggregate(spinner_update);
+	}
+
+	fn set_window_title_filename(
+		&self,
+		window: &Window,
+		playback_state: PlaybackState,
+		file_path: &LoadedImgPath,
+	) {
+		let playback = match playback_state {
+			PlaybackState::Forward => " : Playing",
+			PlaybackState::Present => " : Presenting",
+			PlaybackState::RandomPresent => " : Presenting Shuffled",
+			PlaybackState::Paused => "",
+		};
+
+		let title_config = self.configuration.borrow().title_section();
+
+		let name = match file_path {
+			LoadedImgPath::NotYetLoaded => "[ none ]".into(),
+			LoadedImgPath::ErrLoading(path) => {
+				format!("[ FAILED TO OPEN ] {}", title_config.format_file_path(path)).into()
+			}
+			#[cfg(feature = "layered")]
+			LoadedImgPath::Loaded(path) if file_kind(path) == Some(FileKind::Layered) => {
+				format!("{} (flattened preview)", title_config.format_file_path(path)).into()
+			}
+			LoadedImgPath::Loaded(path) => title_config.format_file_path(path),
+			LoadedImgPath::Memory => "[ stdin ]".into(),
+		};
+		let title = format!("{}{}{}", name, playback, title_config.format_program_name());
+		window.set_title(title);
+	}
+
+	fn get_texture(&self) -> Option<AnimationFrameTexture> {
+		self.playback_manager.image_texture().map(|mut texture| {
+			texture.orientation = texture.orientation.then(self.view_orientation);
+			texture
+		})
+	}
+
+	fn view_zoom(&self) -> ViewZoom {
+		match self.scaling {
+			ScalingMode::Fixed => ViewZoom::Fixed(self.img_texel_size),
+			ScalingMode::FitStretch => ViewZoom::FitStretch,
+			ScalingMode::FitMin => ViewZoom::Fit,
+		}
+	}
+
+	/// The vertical position of the view in a tall image, as the fraction of the image height
+	/// that's at the center of the widget. Returns `None` if the image is not tall, or if it's
+	/// not zoomed in enough to be panned vertically.
+	fn reading_position(&self) -> Option<f32> {
+		let img_size = self.displayed_image_size()?;
+		let widget_height = self.drawn_bounds.size.vec.y;
+		let tall = img_size.y >= img_size.x * TALL_IMAGE_ASPECT && img_size.y > widget_height;
+		if self.scaling != ScalingMode::Fixed || !tall {
+			return None;
+		}
+		let position = (widget_height / 2.0 - self.img_pos.vec.y) / img_size.y + 0.5;
+		Some(position.clamp(0.0, 1.0))
+	}
+
+	fn set_reading_position(&mut self, position: f32) {
+		if let Some(img_size) = self.displayed_image_size() {
+			let widget_height = self.drawn_bounds.size.vec.y;
+			self.img_pos.vec.y = widget_height / 2.0 - (position - 0.5) * img_size.y;
+		}
+	}
+
+	/// Stores the orientation and zoom of the image at `path` in the cache if the user changed
+	/// them since the image was opened. `reading_position` is remembered as configured by
+	/// `reading_progress`.
+	fn remember_view(&mut self, path: &LoadedImgPath, reading_position: Option<f32>) {
+		let path = match path {
+			LoadedImgPath::Loaded(path) => path,
+			_ => return,
+		};
+		if self.reading_progress == ReadingProgress::Session {
+			match reading_position {
+				Some(position) => self.reading_positions.insert(path.clone(), position),
+				None => self.reading_positions.remove(path),
+			};
+		}
+		let cached_position =
+			reading_position.filter(|_| self.reading_progress == ReadingProgress::Cache);
+		let zoom = self.view_zoom();
+		if (self.view_orientation, zoom) != self.opened_view || cached_position.is_some() {
+			let orientation = self.view_orientation.to_exif();
+			self.cache.lock().unwrap().set_view(path, orientation, zoom, cached_position);
+		}
+	}
+
+	/// Applies the orientation, zoom and reading position that the shown image was last viewed
+	/// with
+	fn restore_view(&mut self) {
+		self.view_orientation = Orientation::Deg0;
+		if let LoadedImgPath::Loaded(path) = self.playback_manager.shown_file_path() {
+			let view = self.cache.lock().unwrap().view(path).cloned();
+			let reading_position = match self.reading_progress {
+				ReadingProgress::Off => None,
+				ReadingProgress::Session => self.reading_positions.get(path).copied(),
+				ReadingProgress::Cache => view.as_ref().and_then(|view| view.reading_position),
+			};
+			if let Some(view) = view {
+				self.view_orientation =
+					Orientation::from_exif(view.orientation).unwrap_or(Orientation::Deg0);
+				self.apply_view_zoom(view.zoom);
+			}
+			if let Some(zoom) = self.session_zoom.take() {
+				self.apply_view_zoom(zoom);
+			}
+			if let (Some(position), ScalingMode::Fixed) = (reading_position, self.scaling) {
+				self.set_reading_position(position);
+			}
+		}
+		self.opened_view = (self.view_orientation, self.view_zoom());
+	}
+
+	fn apply_view_zoom(&mut self, zoom: ViewZoom) {
+		match zoom {
+			ViewZoom::Fit => self.scaling = ScalingMode::FitMin,
+			ViewZoom::FitStretch => self.scaling = ScalingMode::FitStretch,
+			ViewZoom::Fixed(texel_size) => {
+				self.scaling = ScalingMode::Fixed;
+				self.img_texel_size = texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+				self.img_pos = self.drawn_bounds.size * 0.5f32;
+			}
+		}
+		self.update_scaling_buttons();
+	}
+
+	fn set_view_orientation(&mut self, orientation: Orientation) {
+		self.view_orientation = orientation;
+		// The selection would cover a different part of the image after rotating it
+		self.selection = None;
+		self.render_validity.invalidate();
+	}
+
+	/// Converts a point in window coordinates to a position relative to the displayed image,
+	/// where (0, 0) is the top left and (1, 1) is the bottom right corner of the image. The
+	/// result is clamped to the image.
+	fn to_image_fraction(&self, point: LogicalVector) -> Option<Vector2<f32>> {
+		let position = self.image_position(point)?;
+		Some(Vector2::new(position.x.clamp(0.0, 1.0), position.y.clamp(0.0, 1.0)))
+	}
+
+	/// Same as `to_image_fraction` but the result is not clamped to the image.
+	fn image_position(&self, point: LogicalVector) -> Option<Vector2<f32>> {
+		let texture = self.get_texture()?;
+		let (w, h) = texture.oriented_dimensions();
+		let img_size = Vector2::new(w as f32, h as f32) * (self.img_texel_size / self.dpi_scale);
+		if img_size.x <= 0.0 || img_size.y <= 0.0 {
+			return None;
+		}
+		let corner = self.img_pos.vec - img_size * 0.5;
+		let local = point.vec - self.drawn_bounds.pos.vec - corner;
+		Some(Vector2::new(local.x / img_size.x, local.y / img_size.y))
+	}
+
+	/// Returns the coordinates of the texel that is displayed at the given point. The
+	/// coordinates are in the texture, before the orientation is applied to it.
+	fn texel_at(&self, point: LogicalVector) -> Option<(u32, u32)> {
+		let texture = self.get_texture()?;
+		let position = self.image_position(point)?;
+		let (w, h) = texture.oriented_dimensions();
+		let displayed =
+			Vector4::new((position.x - 0.5) * w as f32, (position.y - 0.5) * h as f32, 0.0, 0.0);
+		// The orientation matrices are orthogonal, so their inverse is their transpose
+		let source = orientation_to_matrix(texture.orientation).transpose() * displayed;
+		let x = source.x + texture.w as f32 * 0.5;
+		let y = source.y + texture.h as f32 * 0.5;
+		if x < 0.0 || y < 0.0 || x >= texture.w as f32 || y >= texture.h as f32 {
+			return None;
+		}
+		Some((x as u32, y as u32))
+	}
+
+	fn update_pixel_inspector(&mut self) {
+		self.inspected_color = None;
+		if !self.pixel_inspector_enabled {
+			self.overlays.pixel_inspector.hide();
+			return;
+		}
+		let texture = match self.get_texture() {
+			Some(texture) => texture,
+			None => {
+				self.overlays.pixel_inspector.hide();
+				return;
+			}
+		};
+		let size = self.inspector_sample_size;
+		let sample = self.texel_at(self.last_mouse_pos).and_then(|(x, y)| {
+			let color = self.pixel_reader.average_linear(&texture, x, y, size)?;
+			Some((x, y, color))
+		});
+		let text = match sample {
+			Some((x, y, color)) => {
+				let mut text = format!("X: {}, Y: {}", x, y);
+				if size > 1 {
+					text.push_str(&format!(" ({}x{} average)", size, size));
+				}
+				let [r, g, b, a] = color;
+				let hex = format!(
+					"#{:02x}{:02x}{:02x}{:02x}",
+					linear_to_srgb(r),
+					linear_to_srgb(g),
+					linear_to_srgb(b),
+					(a * 255.0).round() as u8
+				);
+				if self.inspector_linear {
+					text.push_str(&format!(
+						"\nR: {:.3}  G: {:.3}  B: {:.3}  A: {:.3} (linear)",
+						r, g, b, a
+					));
+					self.inspected_color = Some(format!("{:.3}, {:.3}, {:.3}, {:.3}", r, g, b, a));
+				} else {
+					let (r, g, b) = (linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b));
+					let a = (a * 255.0).round() as u8;
+					text.push_str(&format!("\nR: {}  G: {}  B: {}  A: {} (sRGB)", r, g, b, a));
+					self.inspected_color = Some(hex.clone());
+				}
+				text.push_str(&format!("\nHex: {}", hex));
+				text
+			}
+			None => "Move the cursor over the image to inspect its colors\n\
+				Click to copy the color under the cursor"
+				.to_owned(),
+		};
+		self.overlays.pixel_inspector.show_text(&text);
+	}
+
+	fn selected_region(&self) -> Option<ImageRegion> {
+		let (start, end) = self.selection?;
+		let region = ImageRegion::from_corners(start, end);
+		if region.right > region.left && region.bottom > region.top {
+			Some(region)
+		} else {
+			None
+		}
+	}
+
+	fn set_selection_mode(&mut self, enabled: bool) {
+		self.selection_mode = enabled;
+		self.selection = None;
+		self.selecting = false;
+		if enabled {
+			let text = {
+				let config = self.configuration.borrow();
+				let copy_keys = action_keys(&config, COPY_SELECTION_NAME).join(" or ");
+				let exit_keys = action_keys(&config, ESCAPE_NAME).join(" or ");
+				format!(
+					"Selection mode: drag with the left mouse button to select a region.\n\
+					Press {copy_keys} to copy it or {exit_keys} to leave selection mode."
+				)
+			};
+			self.overlays.selection_hint.show_text(&text);
+		} else {
+			self.overlays.selection_hint.hide();
+		}
+		self.render_validity.invalidate();
+	}
+
+	/// Asks the window to capture the rendered view. It's used once the capture is ready.
+	fn request_view_capture(&mut self, view_capture: ViewCapture) {
+		if let Some(window) = self.window.upgrade() {
+			window.request_capture(self.drawn_bounds);
+			self.view_capture_pending = Some(view_capture);
+		}
+	}
+
+	/// Saves the shown image into a new file, with the orientation that it's displayed with
+	fn export_image(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		let config = self.configuration.borrow();
+		let section = config.export.as_ref();
+		let pattern = section.and_then(|s| s.image_path.clone());
+		drop(config);
+		let pattern = pattern.as_deref().unwrap_or(export::DEFAULT_IMAGE_PATTERN);
+		let target = export::target_path(pattern, &path);
+		let view_orientation = self.view_orientation;
+		self.start_export(ExportSource::File { path, view_orientation }, target);
+	}
+
+	/// Saves a capture of the rendered view into a new file named after the shown image. The
+	/// capture is `None` if reading back the framebuffer failed.
+	fn export_view(&mut self, capture: Option<RgbaImage>) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		let image = match capture {
+			Some(image) => image,
+			None => {
+				self.show_error("Could not capture the view");
+				return;
+			}
+		};
+		let config = self.configuration.borrow();
+		let pattern = config.export.as_ref().and_then(|s| s.view_path.clone());
+		drop(config);
+		let pattern = pattern.as_deref().unwrap_or(export::DEFAULT_VIEW_PATTERN);
+		let target = export::target_path(pattern, &path);
+		self.start_export(ExportSource::Capture(image), target);
+	}
+
+	fn start_export(&mut self, source: ExportSource, target: PathBuf) {
+		let config = self.configuration.borrow();
+		let jpeg_quality = config.export.as_ref().and_then(|s| s.jpeg_quality);
+		drop(config);
+		let jpeg_quality = jpeg_quality.unwrap_or(export::DEFAULT_JPEG_QUALITY);
+		if self.exporter.export(ExportRequest { source, target, jpeg_quality }) {
+			self.show_notice("Exporting");
+		} else {
+			self.show_notice("The previous export hasn't finished yet");
+		}
+	}
+
+	/// Shows the outcome of the export once it's finished
+	fn update_export(&mut self, now: Instant) {
+		match self.exporter.poll() {
+			Some(Ok(path)) => {
+				let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+				self.show_notice(&format!("Exported to {}", file_name));
+			}
+			Some(Err(e)) => self.show_error(&format!("Could not export: {}", e)),
+			None if self.exporter.is_busy() => {
+				let poll_update = NextUpdate::WaitUntil(now + SPINNER_INTERVAL);
+				self.next_update = self.next_update.aggregate(poll_update);
+			}
+			None => (),
+		}
+	}
+
+	/// Copies a capture of the rendered view to the clipboard. The capture is `None` if
+	/// reading back the framebuffer failed.
+	fn copy_view_to_clipboard(&mut self, capture: Option<RgbaImage>) {
+		if let Some(clipboard_handler) = &mut self.clipboard_handler {
+			match capture {
+				Some(image) => {
+					clipboard_handler.request_copy_image(image);
+					self.clipboard_request_was_pending = true;
+					self.show_clipboard_pending();
+				}
+				None => self.show_clipboard_result(false),
+			}
+		}
+	}
+
+	/// Copies the color under the cursor as shown by the pixel inspector
+	fn copy_inspected_color(&mut self) {
+		let text = match self.inspected_color.clone() {
+			Some(text) => text,
+			None => return,
+		};
+		if let Some(clipboard_handler) = &mut self.clipboard_handler {
+			clipboard_handler.request_copy_text(text);
+			self.clipboard_request_was_pending = true;
+			self.show_clipboard_pending();
+		}
+	}
+
+	/// Copies the path of the shown file to the clipboard as text, or the file itself if
+	/// `as_file` is true, so that it can be pasted in file managers and other applications
+	fn copy_path_to_clipboard(&mut self, as_file: bool) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		if let Some(clipboard_handler) = &mut self.clipboard_handler {
+			if as_file {
+				clipboard_handler.request_copy_file(&path);
+			} else {
+				clipboard_handler.request_copy_path(&path);
+			}
+			self.clipboard_request_was_pending = true;
+			self.show_clipboard_pending();
+		}
+	}
+
+	/// Copies the shown image to the clipboard, or just the given region of it.
+	fn copy_to_clipboard(&mut self, region: Option<ImageRegion>) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		let view_orientation = self.view_orientation;
+		if let Some(clipboard_handler) = &mut self.clipboard_handler {
+			match region {
+				Some(region) => {
+					clipboard_handler.request_copy_region(path, view_orientation, region)
+				}
+				None => clipboard_handler.request_copy(path),
+			};
+			self.clipboard_request_was_pending = true;
+			self.show_clipboard_pending();
+		}
+	}
+
+	/// Writes the currently displayed orientation into the Exif data of the shown file.
+	fn save_orientation(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		let orientation = match self.get_texture() {
+			Some(texture) => texture.orientation,
+			None => return,
+		};
+		if let Err(e) = orientation_writer::save_orientation(&path, orientation) {
+			eprintln!("Could not save the orientation of '{:?}': {}", path, e);
+			self.show_error(&format!("Could not save the orientation: {}", e));
+			return;
+		}
+		// The remembered orientation was relative to the old orientation of the file
+		self.view_orientation = Orientation::Deg0;
+		self.opened_view.0 = Orientation::Deg0;
+		self.cache.lock().unwrap().forget_view(&path);
+		// This makes the image cache reload the file with the new orientation
+		if let Err(e) = self.playback_manager.update_directory() {
+			eprintln!("Error while updating directory {:?}", e);
+		}
+		self.show_notice("Saved the orientation");
+	}
+
+	/// Shows the shown file in the file manager
+	fn open_containing_folder(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) | LoadedImgPath::ErrLoading(path) => path.clone(),
+			_ => return,
+		};
+		if let Err(e) = reveal_in_file_manager(&path) {
+			self.show_error(&format!("Could not open the file manager: {}", e));
+		}
+	}
+
+	/// Whether the keys are typed into one of the text boxes
+	fn text_input_active(&self) -> bool {
+		let overlays = &self.overlays;
+		overlays.rename_box.visible()
+			|| overlays.zoom_box.visible()
+			|| overlays.command_palette.visible()
+	}
+
+	/// The paths of the shown image and its folder, which the user commands get as arguments
+	fn command_paths(&self) -> Option<(String, String)> {
+		let img_path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(img_path) => img_path,
+			_ => return None,
+		};
+		let folder_path = match img_path.parent() {
+			Some(folder_path) => folder_path,
+			None => {
+				log::error!("Could not get parent folder for the image path {:?}", img_path);
+				return None;
+			}
+		};
+		match (img_path.to_str(), folder_path.to_str()) {
+			(Some(img), Some(folder)) => Some((img.to_owned(), folder.to_owned())),
+			_ => {
+				log::error!("Could not convert the image path to utf8. Path: '{:?}'", img_path);
+				None
+			}
+		}
+	}
+
+	/// Lists the actions and the commands of the folder in the command palette
+	fn open_command_palette(&mut self) {
+		let mut entries = Vec::new();
+		{
+			let config = self.configuration.borrow();
+			for action in ACTIONS.iter().filter(|action| !action.held) {
+				let title = action_title(action.name);
+				let keys = action_keys(&config, action.name).join(", ");
+				entries.push(PaletteEntry { item: PaletteItem::Action(action.name), title, keys });
+			}
+			let commands = config.folder_commands(&self.folder_config);
+			for (index, command) in commands.into_iter().enumerate() {
+				let mut title = format!("Run {}", command.program);
+				for arg in command.args.iter().flatten() {
+					title.push(' ');
+					title.push_str(arg);
+				}
+				let keys = command.input.join(", ");
+				entries.push(PaletteEntry { item: PaletteItem::Command(index), title, keys });
+			}
+		}
+		self.overlays.command_palette.open(entries);
+		self.render_validity.invalidate();
+	}
+
+	fn set_wallpaper(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		match wallpaper::set_wallpaper(&path) {
+			Ok(()) => self.show_notice("Set as wallpaper"),
+			Err(e) => self.show_error(&format!("Could not set the wallpaper: {}", e)),
+		}
+	}
+
+	pub fn set_img_size_to_orig(&mut self) {
+		self.panorama_tour = None;
+		self.ken_burns = None;
+		self.img_texel_size = 1.0;
+		self.scaling = ScalingMode::Fixed;
+		self.update_scaling_buttons();
+		self.render_validity.invalidate();
+	}
+
+	/// Keeps the current view for the next images, for comparing the same part of similar
+	/// images
+	fn toggle_view_lock(&mut self) {
+		self.view_locked = !self.view_locked;
+		if self.view_locked {
+			// A fitted zoom would follow the size of the next image
+			self.panorama_tour = None;
+			self.ken_burns = None;
+			self.scaling = ScalingMode::Fixed;
+			self.update_scaling_buttons();
+			self.show_notice("View locked");
+		} else {
+			self.show_notice("View unlocked");
+		}
+	}
+
+	/// Sets the size of an image pixel in physical pixels, keeping the center of the view in
+	/// place
+	fn set_zoom(&mut self, texel_size: f32) {
+		let texel_size = texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+		let center = self.drawn_bounds.size * 0.5f32;
+		if self.img_texel_size > 0.0 {
+			self.img_pos = (texel_size / self.img_texel_size) * (self.img_pos - center) + center;
+		} else {
+			self.img_pos = center;
+		}
+		self.panorama_tour = None;
+		self.ken_burns = None;
+		self.img_texel_size = texel_size;
+		self.scaling = ScalingMode::Fixed;
+		self.update_scaling_buttons();
+		self.render_validity.invalidate();
+	}
+
+	pub fn set_img_size_to_fit(&mut self, stretch: bool) {
+		{
+			let mut cache = self.cache.lock().unwrap();
+			cache.image.fit_stretches = stretch;
+		}
+		self.panorama_tour = None;
+		self.ken_burns = None;
+		self.scaling = if stretch { ScalingMode::FitStretch } else { ScalingMode::FitMin };
+		self.update_scaling_buttons();
+		self.render_validity.invalidate();
+	}
+
+	pub fn toggle_antialias(&mut self) {
+		let aa = match self.antialiasing {
+			Antialias::Auto if self.img_texel_size < AA_TEXEL_SIZE_THRESHOLD => Antialias::Never,
+			Antialias::Auto | Antialias::Never => Antialias::Always,
+			Antialias::Always => Antialias::Never,
+		};
+		self.antialiasing = aa;
+		self.cache.lock().unwrap().image.antialiasing = aa;
+		self.render_validity.invalidate();
+	}
+
+	pub fn set_automatic_antialias(&mut self) {
+		self.antialiasing = Antialias::Auto;
+		self.cache.lock().unwrap().image.antialiasing = Antialias::Auto;
+		self.render_validity.invalidate();
+	}
+
+	/// Ensures that the image is within the widget, or at least touches an edge of the widget
+	fn apply_img_bounds(&mut self, dpi_scale: f32) {
+		if let Some(texture) = self.get_texture() {
+			let (img_phys_w, img_phys_h) = {
+				let (w, h) = texture.oriented_dimensions();
+				(w as f32 * self.img_texel_size, h as f32 * self.img_texel_size)
+			};
+			let img_w = img_phys_w / dpi_scale;
+			let img_h = img_phys_h / dpi_scale;
+
+			let widget_size = self.drawn_bounds.size.vec;
+			let img_pos = self.img_pos.vec;
+
+			if img_pos.x < -img_w / 2.0 {
+				self.img_pos.vec.x = -img_w / 2.0;
+			}
+			if img_pos.y < -img_h / 2.0 {
+				self.img_pos.vec.y = -img_h / 2.0;
+			}
+
+			if img_pos.x > widget_size.x + img_w / 2.0 {
+				self.img_pos.vec.x = (widget_size.x + img_w / 2.0).ceil();
+			}
+			if img_pos.y > widget_size.y + img_h / 2.0 {
+				self.img_pos.vec.y = (widget_size.y + img_h / 2.0).ceil();
+			}
+		}
+	}
+
+	/// The size of the displayed image in logical pixels
+	fn displayed_image_size(&self) -> Option<Vector2<f32>> {
+		let texture = self.get_texture()?;
+		let (w, h) = texture.oriented_dimensions();
+		let scale = self.img_texel_size / self.dpi_scale;
+		Some(Vector2::new(w as f32 * scale, h as f32 * scale))
+	}
+
+	/// Moves the view by the given number of pages along each axis, where a page is most of the
+	/// widget. The view never moves past the edges of the image.
+	fn pan_by_pages(&mut self, x_pages: f32, y_pages: f32) {
+		let img_size = match self.displayed_image_size() {
+			Some(img_size) => img_size,
+			None => return,
+		};
+		let widget_size = self.drawn_bounds.size.vec;
+		let pos = &mut self.img_pos.vec;
+		pos.x = page_pan_position(pos.x, img_size.x, widget_size.x, x_pages);
+		pos.y = page_pan_position(pos.y, img_size.y, widget_size.y, y_pages);
+		self.ken_burns = None;
+		self.scaling = ScalingMode::Fixed;
+		self.update_scaling_buttons();
+		self.render_validity.invalidate();
+	}
+
+	/// Moves the view to the top left corner of the image, or to the bottom right one if `end`
+	/// is true. The image is centered along the axes on which it fits into the widget.
+	fn pan_to_corner(&mut self, end: bool) {
+		let img_size = match self.displayed_image_size() {
+			Some(img_size) => img_size,
+			None => return,
+		};
+		let widget_size = self.drawn_bounds.size.vec;
+		let corner_position = |img_len: f32, widget_len: f32| {
+			if img_len <= widget_len {
+				widget_len / 2.0
+			} else if end {
+				widget_len - img_len / 2.0
+			} else {
+				img_len / 2.0
+			}
+		};
+		self.img_pos.vec.x = corner_position(img_size.x, widget_size.x);
+		self.img_pos.vec.y = corner_position(img_size.y, widget_size.y);
+		self.ken_burns = None;
+		self.scaling = ScalingMode::Fixed;
+		self.update_scaling_buttons();
+		self.render_validity.invalidate();
+	}
+
+	fn linked_view(&self) -> LinkedView {
+		match self.scaling {
+			ScalingMode::Fixed => LinkedView {
+				scaling: self.scaling,
+				img_texel_size: self.img_texel_size,
+				img_offset: (self.img_pos - self.prev_draw_size * 0.5f32).vec,
+			},
+			scaling => {
+				LinkedView { scaling, img_texel_size: 0.0, img_offset: Vector2::new(0.0, 0.0) }
+			}
+		}
+	}
+
+	/// Returns true if the other widget of the compare mode changed the view since this widget
+	/// last synchronized with it
+	fn linked_view_changed(&self) -> bool {
+		match &self.view_link {
+			Some(link) => {
+				let link = link.borrow();
+				link.synchronized && link.generation != self.view_link_generation
+			}
+			None => false,
+		}
+	}
+
+	/// Applies the view of the other widget of the compare mode if it changed. This has to be
+	/// called before the image transform is updated.
+	fn adopt_linked_view(&mut self) {
+		let link = match &self.view_link {
+			Some(link) if self.linked_view_changed() => link.clone(),
+			_ => return,
+		};
+		let link = link.borrow();
+		self.view_link_generation = link.generation;
+		if let Some(view) = link.view {
+			self.scaling = view.scaling;
+			if view.scaling == ScalingMode::Fixed {
+				self.img_texel_size = view.img_texel_size;
+				// Relative to the previous size, because updating the image transform keeps the
+				// image at the same place relative to the center when the widget is resized
+				let center = self.prev_draw_size * 0.5f32;
+				self.img_pos = center + LogicalVector::from(view.img_offset);
+			}
+			self.update_scaling_buttons();
+		}
+		self.linked_view = Some(self.linked_view());
+	}
+
+	/// Shares the view of this widget with the other widget of the compare mode if it changed
+	/// since the last synchronization
+	fn publish_linked_view(&mut self) {
+		let link = match &self.view_link {
+			Some(link) => link.clone(),
+			None => return,
+		};
+		let mut link = link.borrow_mut();
+		let view = self.linked_view();
+		if !link.synchronized || self.linked_view == Some(view) {
+			return;
+		}
+		link.view = Some(view);
+		link.generation += 1;
+		self.view_link_generation = link.generation;
+		self.linked_view = Some(view);
+		// The other widget may have been drawn already in this frame
+		self.next_update = self.next_update.aggregate(NextUpdate::Soonest);
+	}
+
+	/// The adjustments panel covers a part of the widget, and the clicks there are meant for its
+	/// sliders
+	fn is_over_picture(&self, pos: LogicalVector) -> bool {
+		let panel = self.adjustments_panel.as_ref();
+		self.drawn_bounds.contains(pos) && !panel.is_some_and(|panel| panel.covers(pos))
+	}
+
+	fn toggle_adjustments(&mut self) {
+		if let Some(panel) = &self.adjustments_panel {
+			panel.toggle_visible();
+		}
+	}
+
+	fn reset_adjustments(&mut self) {
+		if let Some(panel) = &self.adjustments_panel {
+			panel.reset();
+			self.show_notice("The adjustments were reset");
+		}
+	}
+
+	/// Returns true if the cursor is over this widget or the other widget of the compare mode
+	fn picture_area_hovered(&self) -> bool {
+		let compare_widget = self.compare_widget.as_ref();
+		self.hover || compare_widget.is_some_and(|widget| widget.data.borrow().hover)
+	}
+
+	/// Shows or hides the compare mode. Showing it pins the current image next to this widget.
+	fn toggle_compare(&mut self) {
+		let compare_widget = match self.compare_widget() {
+			Some(compare_widget) => compare_widget,
+			None => return,
+		};
+		if compare_widget.visible() {
+			compare_widget.set_visible(false);
+			compare_widget.data.borrow_mut().view_link = None;
+			self.view_link = None;
+		} else {
+			let path = match self.playback_manager.shown_file_path() {
+				LoadedImgPath::Loaded(path) => path.clone(),
+				_ => return,
+			};
+			let link = ViewLink { synchronized: self.compare_synchronized, ..Default::default() };
+			self.view_link = Some(Rc::new(RefCell::new(link)));
+			self.linked_view = None;
+			self.publish_linked_view();
+			compare_widget.data.borrow_mut().pin(self, path);
+			compare_widget.set_visible(true);
+		}
+		self.render_validity.invalidate();
+	}
+
+	/// Returns the widget of the compare mode, and creates it the first time
+	fn compare_widget(&mut self) -> Option<Rc<PictureWidget>> {
+		if self.compare_widget.is_none() {
+			let compare_widget = (self.make_compare_widget.as_ref()?)()?;
+			{
+				let mut compare_data = compare_widget.data.borrow_mut();
+				compare_data.pinned = true;
+				compare_data.bright_shade = self.bright_shade;
+				compare_data.background = self.background;
+				compare_data.adjustments_panel = self.adjustments_panel.clone();
+			}
+			compare_widget.set_visible(false);
+			self.compare_widget = Some(compare_widget);
+		}
+		self.compare_widget.clone()
+	}
+
+	fn toggle_compare_sync(&mut self) {
+		self.compare_synchronized = !self.compare_synchronized;
+		if let Some(link) = &self.view_link {
+			link.borrow_mut().synchronized = self.compare_synchronized;
+			// Makes the other widget take over the view of this one
+			self.linked_view = None;
+			self.publish_linked_view();
+			self.render_validity.invalidate();
+		}
+	}
+
+	/// Makes this widget show the image at `path` with the same view as `other`, for the compare
+	/// mode
+	fn pin(&mut self, other: &PictureWidgetData, path: PathBuf) {
+		self.playback_manager.request_load(LoadRequest::FilePath(path));
+		self.view_link = other.view_link.clone();
+		self.view_link_generation = 0;
+		self.linked_view = None;
+		self.view_orientation = other.view_orientation;
+		self.scaling = other.scaling;
+		self.img_texel_size = other.img_texel_size;
+		self.img_pos = other.img_pos;
+		self.prev_draw_size = other.prev_draw_size;
+		self.bright_shade = other.bright_shade;
+		self.background = other.background;
+		self.background_color = other.background_color;
+		self.antialiasing = other.antialiasing;
+		self.render_validity.invalidate();
+	}
+
+	/// Keeps the zoom levels at which the image pixels map to whole screen pixels, like the
+	/// original size, pixel-exact on the new monitor. Other zoom levels keep the size of the
+	/// image on the screen.
+	fn set_dpi_scale(&mut self, dpi_scale: f32) {
+		let prev_dpi_scale = self.dpi_scale;
+		self.dpi_scale = dpi_scale;
+		if self.scaling == ScalingMode::Fixed && !is_pixel_exact(self.img_texel_size) {
+			let texel_size = self.img_texel_size * dpi_scale / prev_dpi_scale;
+			self.img_texel_size = texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+			self.update_scaling_buttons();
+		}
+		self.render_validity.invalidate();
+	}
+
+	fn update_scaling_buttons(&mut self) {
+		if !self.pinned {
+			self.bottom_bar.update_scaling_buttons(self.scaling, self.img_texel_size);
+		}
+	}
+
+	/// Returns the path and the dimensions of the shown image if it wasn't opened because it
+	/// exceeds the decode limit.
+	fn oversized_image(&self) -> Option<(PathBuf, (u32, u32))> {
+		match self.playback_manager.shown_file_path() {
+			LoadedImgPath::ErrLoading(path) => {
+				decode_limit::oversized_dimensions(path).map(|dims| (path.clone(), dims))
+			}
+			_ => None,
+		}
+	}
+
+	/// Shows another image of the folder. A presentation is paused, so that it doesn't move on
+	/// from the image that was navigated to.
+	fn navigate(&mut self, request: LoadRequest) {
+		let state = self.playback_manager.playback_state();
+		if matches!(state, PlaybackState::Present | PlaybackState::RandomPresent) {
+			self.playback_manager.pause_playback();
+			self.show_notice("Paused the presentation");
+		}
+		self.playback_manager.request_load(request);
+		self.render_validity.invalidate();
+	}
+
+	fn adjust_present_interval(&mut self, delta_secs: f32) {
+		let secs = self.playback_manager.present_interval_secs() + delta_secs;
+		let secs = secs.clamp(MIN_PRESENT_INTERVAL, MAX_PRESENT_INTERVAL);
+		self.playback_manager.set_present_interval_secs(secs);
+		self.render_validity.invalidate();
+	}
+
+	fn update_large_image_prompt(&mut self) {
+		let (w, h) = match self.oversized_image() {
+			Some((_, dims)) => dims,
+			None => {
+				self.overlays.large_image_prompt.hide();
+				return;
+			}
+		};
+		let text = {
+			let config = self.configuration.borrow();
+			let open_keys = action_keys(&config, OPEN_DOWNSCALED_NAME).join(" or ");
+			let always_keys = action_keys(&config, ALWAYS_DOWNSCALE_NAME).join(" or ");
+			let megapixels = (w as u64 * h as u64) as f64 / 1_000_000.0;
+			let limit = decode_limit::max_megapixels().unwrap_or(0);
+			format!(
+				"This image is {w} x {h} pixels ({megapixels:.0} megapixels)\n\
+				which is more than the limit of {limit} megapixels.\n\n\
+				Press {open_keys} to open a downscaled version of it\n\
+				or {always_keys} to always downscale large images until Emulsion is closed."
+			)
+		};
+		self.overlays.large_image_prompt.show_text(&text);
+	}
+
+	/// Shows why the file couldn't be opened in place of the image. Oversized images have their
+	/// own prompt.
+	fn update_load_error_panel(&mut self) {
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::ErrLoading(path) if self.oversized_image().is_none() => path,
+			_ => {
+				self.overlays.load_error.hide();
+				return;
+			}
+		};
+		let reason = self.playback_manager.load_error().unwrap_or("The file could not be read");
+		let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+		let retry_keys = action_keys(&self.configuration.borrow(), RETRY_LOAD_NAME).join(" or ");
+		let text = format!(
+			"Could not open {file_name}\n\n\
+			{reason}\n\n\
+			Press {retry_keys} to try again."
+		);
+		self.overlays.load_error.show_text(&text);
+	}
+
+	/// Shows the name and the index of the image for a while, if `show_osd` is set in the
+	/// config. This is useful in fullscreen, where neither the title nor the bottom bar is shown.
+	fn show_osd(&mut self, now: Instant) {
+		let secs = {
+			let config = self.configuration.borrow();
+			match &config.window {
+				Some(window) if window.show_osd == Some(true) => {
+					window.osd_secs.unwrap_or(DEFAULT_OSD_SECS).max(0.0)
+				}
+				_ => return,
+			}
+		};
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) | LoadedImgPath::ErrLoading(path) => path,
+			_ => {
+				self.overlays.osd.hide();
+				self.osd_until = None;
+				return;
+			}
+		};
+		let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+		let index = self.playback_manager.current_file_index();
+		let text = match (index, self.playback_manager.current_dir_len()) {
+			(Some(index), Some(len)) => format!("{} ({}/{})", name, index + 1, len),
+			_ => name,
+		};
+		self.overlays.osd.set_opacity(1.0);
+		self.overlays.osd.show_text(&text);
+		self.osd_until = Some(now + Duration::from_secs_f32(secs));
+	}
+
+	/// Fades the on-screen display out at the end of the time that it's shown for
+	fn update_osd(&mut self, now: Instant) {
+		let osd_until = match self.osd_until {
+			Some(osd_until) => osd_until,
+			None => return,
+		};
+		if now >= osd_until {
+			self.overlays.osd.hide();
+			self.osd_until = None;
+			return;
+		}
+		let remaining = osd_until - now;
+		if remaining < OSD_FADE_DURATION {
+			let opacity = remaining.as_secs_f32() / OSD_FADE_DURATION.as_secs_f32();
+			self.overlays.osd.set_opacity(opacity);
+			self.next_update = NextUpdate::Soonest;
+		} else {
+			let fade_start = NextUpdate::WaitUntil(osd_until - OSD_FADE_DURATION);
+			self.next_update = self.next_update.aggregate(fade_start);
+		}
+	}
+
+	/// Tells the user how to open an image when there's nothing to show, for example because
+	/// the folder of the image was deleted.
+	fn update_open_prompt(&mut self) {
+		let message = match self.playback_manager.unavailable_folder() {
+			Some(folder) => format!("The folder {} is no longer available.\n\n", folder.display()),
+			// An empty window doesn't need explanation if images can only be opened by dropping
+			None if cfg!(feature = "dialogs") && self.playback_manager.is_empty() => String::new(),
+			None => {
+				self.overlays.open_prompt.hide();
+				return;
+			}
+		};
+		let text = {
+			let config = self.configuration.borrow();
+			let close_keys = action_keys(&config, ESCAPE_NAME).join(" or ");
+			#[cfg(feature = "dialogs")]
+			let options = {
+				let file_keys = action_keys(&config, OPEN_FILE_NAME).join(" or ");
+				let folder_keys = action_keys(&config, OPEN_FOLDER_NAME).join(" or ");
+				format!(
+					"Press {file_keys} to open an image,\n\
+					{folder_keys} to open a folder\n\
+					or {close_keys} to close Emulsion."
+				)
+			};
+			#[cfg(not(feature = "dialogs"))]
+			let options = format!(
+				"Drop an image here to open it\n\
+				or press {close_keys} to close Emulsion."
+			);
+			message + &options
+		};
+		self.overlays.open_prompt.show_text(&text);
+	}
+
+	/// Applies the options of the `[playback]` section that were changed in the config file.
+	/// The ones that weren't changed keep the values that were set with the keys.
+	fn apply_playback_config(&mut self, previous: &Configuration, config: &Configuration) {
+		let present_interval = |config: &Configuration| {
+			let playback = config.playback.as_ref();
+			playback.and_then(|s| s.present_interval_secs)
+		};
+		if present_interval(config) != present_interval(previous) {
+			let secs = present_interval(config).unwrap_or(DEFAULT_PRESENT_INTERVAL_SECS);
+			let secs = secs.clamp(MIN_PRESENT_INTERVAL, MAX_PRESENT_INTERVAL);
+			self.playback_manager.set_present_interval_secs(secs);
+		}
+		if config.present_end() != previous.present_end() {
+			self.present_end = config.present_end();
+			self.playback_manager.set_loop_presentation(self.present_end == PresentEnd::Loop);
+		}
+		if config.folder_end() != previous.folder_end() {
+			self.playback_manager.set_folder_end(config.folder_end());
+		}
+		self.ken_burns_enabled = ken_burns_enabled(config);
+		if !self.ken_burns_enabled {
+			self.ken_burns = None;
+		}
+	}
+
+	fn presentation_finished(&mut self) {
+		match self.present_end {
+			PresentEnd::Loop | PresentEnd::Stop => (),
+			PresentEnd::Exit => request_exit(),
+			PresentEnd::Summary => {
+				let (count, elapsed) = self.playback_manager.presentation_stats();
+				let images = if count == 1 { "image" } else { "images" };
+				let text = format!(
+					"End of the presentation\n{} {} in {}",
+					count,
+					images,
+					format_duration(elapsed)
+				);
+				self.overlays.present_summary.show_text(&text);
+			}
+		}
+	}
+
+	/// Shows a dialog for opening an image. `folder` selects between opening a single image and
+	/// opening the first image of a folder.
+	#[cfg(feature = "dialogs")]
+	fn open_with_dialog(&mut self, folder: bool) {
+		let start_dir = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) | LoadedImgPath::ErrLoading(path) => {
+				path.parent().map(|p| p.to_owned())
+			}
+			LoadedImgPath::NotYetLoaded | LoadedImgPath::Memory => None,
+		};
+		let path = if folder {
+			dialogs::pick_folder(start_dir.as_deref())
+		} else {
+			dialogs::pick_image(start_dir.as_deref())
+		};
+		if let Some(path) = path {
+			self.playback_manager.request_load(LoadRequest::FilePath(path));
+			self.render_validity.invalidate();
+		}
+	}
+
+	fn update_cache_stats(&mut self) {
+		if !self.cache_stats_requested {
+			self.overlays.cache_stats.hide();
+			return;
+		}
+		let stats = self.playback_manager.cache_stats();
+		let text = format!(
+			"Image cache\n{} of {} used\n{} images cached\n{} loading\n{} prefetched and {} \
+			 priority results queued\n{} loader threads",
+			format_file_size(stats.used.max(0) as u64),
+			format_file_size(stats.capacity.max(0) as u64),
+			stats.cached_images,
+			stats.pending_requests,
+			stats.queued_results.prefetched,
+			stats.queued_results.priority,
+			stats.active_threads,
+		);
+		self.overlays.cache_stats.show_text(&text);
+	}
+
+	fn update_info_panel(&mut self) {
+		if !self.info_panel_requested {
+			self.overlays.info_panel.hide();
+			return;
+		}
+		let path = match self.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => {
+				self.overlays.info_panel.hide();
+				return;
+			}
+		};
+		let mut text = String::new();
+		if let Some(name) = path.file_name() {
+			text.push_str(&name.to_string_lossy());
+			text.push('\n');
+		}
+		if let Some(texture) = self.playback_manager.image_texture() {
+			text.push_str(&format!("{} x {} pixels\n", texture.w, texture.h));
+			if let Some(info) = texture.info {
+				text.push_str(&format!("File size: {}\n", format_file_size(info.file_size)));
+				if let Some(modified) = info.modified {
+					text.push_str(&format!("Modified: {}\n", format_system_time(modified)));
+				}
+				#[cfg(feature = "textures")]
+				if let Some(texture) = info.texture {
+					text.push_str(&format!(
+						"Mip level: {} of {}\nLayer: {} of {}\n",
+						texture.level + 1,
+						texture.level_count,
+						texture.layer + 1,
+						texture.layer_count
+					));
+				}
+				for (label, value) in info.exif_fields.iter() {
+					text.push_str(&format!("{}: {}\n", label, value));
+				}
+				if !info.gps_fields.is_empty() {
+					text.push_str("\nLocation\n");
+					for (label, value) in info.gps_fields.iter() {
+						text.push_str(&format!("{}: {}\n", label, value));
+					}
+				}
+			}
+		}
+		self.overlays.info_panel.show_text(text.trim_end());
+	}
+
+	/// Opens the shown image again, but now allowing it to be downscaled.
+	fn open_downscaled(&mut self, always: bool) {
+		if let Some((path, _)) = self.oversized_image() {
+			if always {
+				decode_limit::set_always_downscale();
+			} else {
+				decode_limit::allow_downscale(&path);
+			}
+			self.load_again(path);
+		}
+	}
+
+	/// Tries to open the shown file again if it couldn't be loaded, for example because it was
+	/// still being written.
+	fn retry_load(&mut self) {
+		if let LoadedImgPath::ErrLoading(path) = self.playback_manager.shown_file_path() {
+			let path = path.clone();
+			self.load_again(path);
+		}
+	}
+
+	fn load_again(&mut self, path: PathBuf) {
+		// Updating the directory makes the cache forget that this image failed to load
+		if let Err(e) = self.playback_manager.update_directory() {
+			eprintln!("Error while updating directory {:?}", e);
+		}
+		self.playback_manager.request_load(LoadRequest::FilePath(path));
+		self.render_validity.invalidate();
+	}
+}
+
+pub struct PictureWidget {
+	data: RefCell<PictureWidgetData>,
+}
+impl PictureWidget {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		display: &Display,
+		window: &Rc<Window>,
+		bottom_bar: Rc<BottomBar>,
+		left_to_pan_hint: Rc<HelpScreen>,
+		toasts: Toasts,
+		overlays: PictureOverlays,
+		configuration: Rc<RefCell<Configuration>>,
+		cache: Arc<Mutex<Cache>>,
+		proxy: EventLoopProxy<()>,
+	) -> PictureWidget {
+		// Checks that every action has a handler when the first window is opened, instead of
+		// when the action is used
+		lazy_static::initialize(&ACTION_HANDLERS);
+
+		// let program = program!(display,
+		// 	140 => {
+		// 		vertex: shaders::VERTEX_140,
+		// 		fragment: shaders::FRAGMENT_140
+		// 	},
+		// 	110 => {
+		// 		vertex: shaders::VERTEX_110,
+		// 		fragment: shaders::FRAGMENT_110
+		// 	},
+		// )
+		// .unwrap();
+
+		let program = gelatin::shaders::shader_from_source(
+			display,
+			ShaderDescriptor {
+				vertex_shader: shaders::VERTEX_140,
+				fragment_shader: &shaders::with_common_functions(shaders::FRAGMENT_140),
+				outputs_srgb: false,
+				..Default::default()
+			},
+		)
+		.unwrap();
+		let hdr_program = gelatin::shaders::shader_from_source(
+			display,
+			ShaderDescriptor {
+				vertex_shader: shaders::VERTEX_140,
+				fragment_shader: &shaders::with_common_functions(shaders::FRAGMENT_HDR_140),
+				outputs_srgb: false,
+				..Default::default()
+			},
+		)
+		.unwrap();
+		let difference_program = gelatin::shaders::shader_from_source(
+			display,
+			ShaderDescriptor {
+				vertex_shader: shaders::VERTEX_140,
+				fragment_shader: &shaders::with_common_functions(shaders::DIFFERENCE_140),
+				outputs_srgb: false,
+				..Default::default()
+			},
+		)
+		.unwrap();
+
+		let (display_transform, display_lut) = {
+			let config = configuration.borrow();
+			let image_config = config.image.as_ref();
+			let transform = image_config.and_then(|s| s.display_transform).unwrap_or_default();
+			let lut_path = image_config.and_then(|s| s.display_lut.as_deref());
+			display_transform::load(display, transform, lut_path)
+		};
+
+		let scaling;
+		{
+			let cache = cache.lock().unwrap();
+			let fit_stretches = configuration.borrow().image.as_ref().and_then(|s| s.fit_stretches);
+			if fit_stretches.unwrap_or(cache.image.fit_stretches) {
+				scaling = ScalingMode::FitStretch;
+			} else {
+				scaling = ScalingMode::FitMin;
+			}
+		}
+
+		let antialiasing = configuration
+			.borrow()
+			.image
+			.as_ref()
+			.and_then(|s| s.antialiasing.clone())
+			.unwrap_or_else(|| "auto".into());
+
+		let antialiasing = match antialiasing.as_str() {
+			"auto" => Antialias::Auto,
+			"always" => Antialias::Always,
+			"never" => Antialias::Never,
+			"previous" => cache.lock().unwrap().image.antialiasing,
+			val => {
+				eprintln!("Illegal configuration value {:?} for antialiasing!", val);
+				eprintln!(r#"Allowed values are "auto", "always", "never" and "previous"."#);
+				Antialias::default()
+			}
+		};
+
+		let (background, background_color) = {
+			let config = configuration.borrow();
+			let image = config.image.as_ref();
+			let background = image.and_then(|s| s.background).unwrap_or_default();
+			let color = image.and_then(|s| s.background_color).unwrap_or(DEFAULT_BACKGROUND_COLOR);
+			(background, color.map(|c| c as f32 / 255.0))
+		};
+
+		let present_interval = configuration
+			.borrow()
+			.playback
+			.as_ref()
+			.and_then(|s| s.present_interval_secs)
+			.unwrap_or(DEFAULT_PRESENT_INTERVAL_SECS)
+			.clamp(MIN_PRESENT_INTERVAL, MAX_PRESENT_INTERVAL);
+		let present_end = configuration.borrow().present_end();
+		let reading_progress = configuration
+			.borrow()
+			.image
+			.as_ref()
+			.and_then(|s| s.reading_progress)
+			.unwrap_or_default();
+		let wheel_zoom_anchor = configuration
+			.borrow()
+			.image
+			.as_ref()
+			.and_then(|s| s.wheel_zoom_anchor)
+			.unwrap_or_default();
+		let (invert_scroll_zoom, horizontal_scroll_navigation) = {
+			let config = configuration.borrow();
+			let mouse = config.mouse.as_ref();
+			(
+				mouse.and_then(|s| s.invert_scroll_zoom).unwrap_or(false),
+				mouse.and_then(|s| s.horizontal_scroll_navigation).unwrap_or(false),
+			)
+		};
+		let panorama_tour_speed = configuration
+			.borrow()
+			.playback
+			.as_ref()
+			.and_then(|s| s.panorama_tour_speed)
+			.unwrap_or(DEFAULT_PANORAMA_TOUR_SPEED)
+			.max(0.0);
+		let ken_burns_enabled = ken_burns_enabled(&configuration.borrow());
+		let (nav_repeat_delay, nav_repeat_interval) = {
+			let config = configuration.borrow();
+			let playback = config.playback.as_ref();
+			(
+				playback.and_then(|s| s.nav_repeat_delay_ms).unwrap_or(DEFAULT_NAV_REPEAT_DELAY_MS),
+				playback
+					.and_then(|s| s.nav_repeat_interval_ms)
+					.unwrap_or(DEFAULT_NAV_REPEAT_INTERVAL_MS),
+			)
+		};
+		let sort_order =
+			configuration.borrow().playback.as_ref().and_then(|s| s.sort_order).unwrap_or_default();
+		let confirm_delete =
+			configuration.borrow().files.as_ref().and_then(|s| s.confirm_delete).unwrap_or(false);
+		let clipboard_handler = ClipboardHandler::new();
+		if let Some(clipboard) = configuration.borrow().clipboard.as_ref() {
+			clipboard_handler.set_options(ClipboardOptions {
+				max_image_size: clipboard.max_image_size,
+				offer_jpeg: clipboard.jpeg.unwrap_or(false),
+			});
+		}
+		let cache_config = configuration.borrow().cache.clone().unwrap_or_default();
+		let mut playback_manager = PlaybackManager::new(display, &cache_config, proxy);
+		playback_manager.set_present_interval_secs(present_interval);
+		// No folder is open yet, so these can't fail
+		let _ = playback_manager.set_sort_order(sort_order);
+		let file_filter = {
+			let config = configuration.borrow();
+			FileFilter::parse(config.file_filter(&FolderConfiguration::default()).unwrap_or(""))
+		};
+		let _ = playback_manager.set_file_filter(file_filter);
+		let filter = metadata_filter(&configuration.borrow(), None);
+		let _ = playback_manager.set_metadata_filter(filter);
+		playback_manager.set_loop_presentation(present_end == PresentEnd::Loop);
+		playback_manager.set_folder_end(configuration.borrow().folder_end());
+
+		let max_megapixels = configuration
+			.borrow()
+			.image
+			.as_ref()
+			.and_then(|s| s.max_decode_megapixels)
+			.unwrap_or(decode_limit::DEFAULT_MAX_MEGAPIXELS);
+		decode_limit::set_max_megapixels(max_megapixels);
+		thumbnail_cache::set_enabled(cache_config.save_thumbnails.unwrap_or(false));
+		if let Some(extra_extensions) =
+			configuration.borrow().navigation.as_ref().and_then(|s| s.extra_extensions.as_ref())
+		{
+			image_loader::set_extra_extensions(extra_extensions);
+		}
+		#[cfg(feature = "video")]
+		{
+			let video = configuration.borrow().video.clone().unwrap_or_default();
+			let mut ffmpeg = video_file::Ffmpeg::default();
+			if let Some(path) = video.ffmpeg_path {
+				ffmpeg.ffmpeg = path;
+			}
+			if let Some(path) = video.ffprobe_path {
+				ffmpeg.ffprobe = path;
+			}
+			let max_loop_seconds =
+				video.max_loop_seconds.unwrap_or(video_file::DEFAULT_MAX_LOOP_SECONDS);
+			let cache_capacity = playback_manager.cache_stats().capacity.max(0) as u64;
+			video_file::configure(Box::new(ffmpeg), max_loop_seconds, cache_capacity);
+		}
+
+		let power_saving = configuration
+			.borrow()
+			.power
+			.as_ref()
+			.and_then(|s| s.power_saving)
+			.unwrap_or(PowerSaving::Auto);
+
+		// Images are converted to the color space of the monitor, so its profile is found out
+		// before loading any image
+		let display_profile_tracker = DisplayProfileTracker::new(&window.window_mut());
+		let window_moved = Rc::new(Cell::new(false));
+		{
+			let window_moved = window_moved.clone();
+			window.add_global_event_handler(move |window, event| {
+				if let WindowEvent::Moved(_) = event {
+					window_moved.set(true);
+					window.request_redraw();
+				}
+			});
+		}
+
 		let mut data = PictureWidgetData {
 			placement: Default::default(),
 			drawn_bounds: Default::default(),
@@ -483,24 +3032,57 @@ impl PictureWidget {
 			hover: false,
 			configuration,
 			cache,
-			playback_manager: PlaybackManager::new(),
-			clipboard_handler: Some(ClipboardHandler::new()),
+			playback_manager,
+			clipboard_handler: Some(clipboard_handler),
 			clipboard_request_was_pending: false,
 			render_validity: Default::default(),
 
 			program,
+			hdr_program,
+			exposure: 0.0,
+			display_transform,
+			display_lut,
+			channel: Channel::All,
+			difference: false,
+			difference_amplification: 0,
+			difference_program,
 			bright_shade: 0.95,
 			img_texel_size: 0.0,
 			scaling,
 			img_pos: Default::default(),
+			view_orientation: Orientation::Deg0,
+			opened_view: (Orientation::Deg0, ViewZoom::Fit),
+			session_zoom: None,
+			view_locked: false,
+			tiles_pending: false,
+			reading_progress,
+			reading_positions: HashMap::new(),
+			wheel_zoom_anchor,
+			invert_scroll_zoom,
+			horizontal_scroll_navigation,
+			horizontal_scroll: 0.0,
 			antialiasing,
+			background,
+			background_color,
+			present_end,
 			hor_pan_input: MovementDir::None,
 			ver_pan_input: MovementDir::None,
 			zoom_input: MovementDir::None,
 			hor_pan_vel: 0.0,
 			ver_pan_vel: 0.0,
 			zoom_vel: 0.0,
+			panorama_tour: None,
+			panorama_tour_speed,
+			ken_burns: None,
+			ken_burns_enabled,
+			nav_repeat: None,
+			reference_mode: None,
+			nav_repeat_delay: Duration::from_millis(nav_repeat_delay),
+			nav_repeat_interval: Duration::from_millis(nav_repeat_interval),
 			last_click_time: Instant::now() - Duration::from_secs(10),
+			last_middle_click_time: Instant::now() - Duration::from_secs(10),
+			bound_scroll: 0.0,
+			touch_gestures: TouchGestures::default(),
 			last_mouse_pos: Default::default(),
 			panning_2d: false,
 			panning_vert: false,
@@ -511,156 +3093,744 @@ impl PictureWidget {
 			next_update: NextUpdate::Latest,
 			bottom_bar,
 			left_to_pan_hint,
-			copy_notifications,
+			toasts,
+			clipboard_toast: None,
+			overlays,
+			power_monitor: PowerMonitor::new(power_saving),
+			info_panel_requested: false,
+			cache_stats_requested: false,
+			selection_mode: false,
+			selection: None,
+			selecting: false,
+			view_capture_pending: None,
+			exporter: Exporter::new(),
+			memory_warning_until: None,
+			osd_until: None,
+			folder_config: Default::default(),
+			folder_config_folder: None,
+			sort_order,
+			cycled_sort_order: None,
+			cycled_min_rating: None,
+			shown_metadata: None,
+			recent_folders: None,
+			open_with_selection: None,
+			binding_editor: None,
+			confirm_delete,
+			pending_delete: None,
+			deletions: Deletions::new(),
+			deletion_spinner_start: None,
+			deletion_toast: None,
+			pixel_inspector_enabled: false,
+			pixel_reader: PixelReader::new(),
+			inspector_sample_size: INSPECTOR_SAMPLE_SIZES[0],
+			inspector_linear: false,
+			inspected_color: None,
+			dpi_scale: 1.0,
+			display_profile_tracker,
+			window_moved,
 			window: Rc::downgrade(window),
+			on_new_window: None,
+			onboarding: None,
+			on_onboarding_done: None,
+			compare_widget: None,
+			make_compare_widget: None,
+			adjustments_panel: None,
+			compare_synchronized: true,
+			pinned: false,
+			view_link: None,
+			view_link_generation: 0,
+			linked_view: None,
 		};
 		data.update_scaling_buttons();
 		PictureWidget { data: RefCell::new(data) }
 	}
 
-	add_common_widget_functions!(data);
-
-	pub fn set_bright_shade(&self, shade: f32) {
+	add_common_widget_functions!(data);
+
+	pub fn set_toast_theme(&self, theme: Theme) {
+		self.data.borrow_mut().toasts.set_theme(theme);
+	}
+
+	pub fn set_bright_shade(&self, shade: f32) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.bright_shade = shade;
+		if let Some(compare_widget) = &borrowed.compare_widget {
+			compare_widget.set_bright_shade(shade);
+		}
+		borrowed.render_validity.invalidate();
+	}
+
+	pub fn set_img_size_to_orig(&self) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.set_img_size_to_orig();
+	}
+
+	pub fn set_img_size_to_fit(&self, stretch: bool) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.set_img_size_to_fit(stretch);
+	}
+
+	/// Shows the text box for typing the zoom level in percent
+	pub fn start_zoom_input(&self) {
+		let borrowed = self.data.borrow();
+		if borrowed.get_texture().is_none() {
+			return;
+		}
+		let zoom_box = &borrowed.overlays.zoom_box;
+		zoom_box.set_text(&zoom_percent_text(borrowed.img_texel_size));
+		zoom_box.set_visible(true);
+		borrowed.render_validity.invalidate();
+	}
+
+	/// Sets the zoom level from the text of the zoom box, which is a percentage
+	pub fn set_zoom_from_text(&self, text: &str) {
+		let mut borrowed = self.data.borrow_mut();
+		let percent = text.trim().trim_end_matches('%').trim().parse::<f32>();
+		match percent {
+			Ok(percent) if percent > 0.0 => borrowed.set_zoom(percent / 100.0),
+			_ => borrowed.show_error(&format!("{:?} is not a valid zoom level", text)),
+		}
+	}
+
+	/// Sets how the widget that shows the pinned image of the compare mode is made. It's called
+	/// when the compare mode is first shown, and the widget has to be placed next to this one.
+	pub fn set_compare_widget_factory<F>(&self, make_compare_widget: F)
+	where
+		F: Fn() -> Option<Rc<PictureWidget>> + 'static,
+	{
+		self.data.borrow_mut().make_compare_widget = Some(Box::new(make_compare_widget));
+	}
+
+	/// Sets the panel whose adjustments are applied when the image is drawn. It has to be placed
+	/// over this widget.
+	pub fn set_adjustments_panel(&self, panel: Rc<AdjustmentsPanel>) {
+		self.data.borrow_mut().adjustments_panel = Some(panel);
+	}
+
+	/// See `PlaybackManager::subscribe`
+	pub fn subscribe_playback<F: FnMut(&PlaybackEvent) + 'static>(&self, callback: F) {
+		self.data.borrow_mut().playback_manager.subscribe(callback);
+	}
+
+	/// Shows the mistakes that were found in the config file in a toast, if there are any
+	pub fn show_config_warnings(&self, warnings: &[ConfigWarning]) {
+		if let Some(summary) = config_validation::summary(warnings) {
+			self.data.borrow_mut().show_error(&summary);
+		}
+	}
+
+	/// The bindings and the title follow the config without this
+	pub fn apply_config_change(&self, change: &ConfigChange) {
+		let mut borrowed = self.data.borrow_mut();
+		match change {
+			ConfigChange::Reloaded { previous, config, warnings } => {
+				borrowed.apply_playback_config(previous, config);
+				// The trusted folders may have changed
+				borrowed.folder_config_folder = None;
+				borrowed.update_binding_editor();
+				match config_validation::summary(warnings) {
+					Some(summary) => borrowed.show_error(&summary),
+					None => borrowed.show_notice("Reloaded the config"),
+				}
+			}
+			ConfigChange::Failed(e) => {
+				borrowed.show_error(&format!("Could not reload the config: {}", e));
+			}
+		}
+	}
+
+	pub fn set_on_new_window<F: Fn(Option<PathBuf>) + 'static>(&self, on_new_window: F) {
+		self.data.borrow_mut().on_new_window = Some(Rc::new(on_new_window));
+	}
+
+	/// Asks the questions of the first launch. `on_done` is called with the answers after the
+	/// last one, but not if the questions are skipped.
+	pub fn start_onboarding<F: Fn(&OnboardingAnswers) + 'static>(&self, on_done: F) {
+		let mut borrowed = self.data.borrow_mut();
+		let onboarding = Onboarding::new();
+		borrowed.overlays.onboarding.show_text(&onboarding.text());
+		borrowed.onboarding = Some(onboarding);
+		borrowed.on_onboarding_done = Some(Rc::new(on_done));
+		borrowed.render_validity.invalidate();
+	}
+
+	/// Shows the image at `index` in the folder, or the frame at `index` while the slider shows
+	/// the loop of an animation
+	pub fn jump_to_index(&self, index: u32) {
+		let mut borrowed = self.data.borrow_mut();
+		if borrowed.playback_manager.loop_range().is_some() {
+			borrowed.playback_manager.jump_to_frame(index as usize);
+		} else {
+			borrowed.navigate(LoadRequest::LoadAtIndex(index as usize));
+		}
+		borrowed.render_validity.invalidate();
+	}
+
+	/// Renames the shown file, keeping it shown
+	pub fn rename_current_file(&self, new_name: &str) {
+		let mut borrowed = self.data.borrow_mut();
+		let path = match borrowed.playback_manager.shown_file_path() {
+			LoadedImgPath::Loaded(path) => path.clone(),
+			_ => return,
+		};
+		if path.file_name() == Some(OsStr::new(new_name)) {
+			return;
+		}
+		if let Err(e) = borrowed.playback_manager.rename_file(&path, OsStr::new(new_name)) {
+			borrowed.show_error(&format!("Could not rename the file: {}", e));
+		}
+		borrowed.render_validity.invalidate();
+	}
+
+	pub fn jump_to_path<P: Into<PathBuf>>(&self, path: P) {
+		self.request_load(LoadRequest::FilePath(path.into()));
+	}
+
+	pub fn request_load(&self, request: LoadRequest) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.playback_manager.request_load(request);
+		borrowed.render_validity.invalidate();
+	}
+
+	/// See `PlaybackManager::dispatch_load_request`
+	pub fn dispatch_load_request(&self) {
+		let mut borrowed = self.data.borrow_mut();
+		if let Some(window) = borrowed.window.upgrade() {
+			borrowed.playback_manager.dispatch_load_request(&window);
+		}
+	}
+
+	/// Stores the view of the shown image in the cache, so that it's restored when the image is
+	/// opened again
+	pub fn remember_view(&self) {
+		let mut data = self.data.borrow_mut();
+		let path = data.playback_manager.shown_file_path().clone();
+		let reading_position = data.reading_position();
+		data.remember_view(&path, reading_position);
+	}
+
+	/// Stores the shown image and its zoom in the cache, so that the next session can start
+	/// with them
+	pub fn remember_session(&self) {
+		let data = self.data.borrow();
+		if let LoadedImgPath::Loaded(path) = data.playback_manager.shown_file_path() {
+			let mut cache = data.cache.lock().unwrap();
+			cache.session.last_file = Some(path.to_string_lossy().into_owned());
+			cache.session.zoom = Some(data.view_zoom());
+		}
+	}
+
+	/// Opens the image of the previous session, with its zoom if `zoom` is set
+	pub fn restore_session<P: Into<PathBuf>>(&self, path: P, zoom: Option<ViewZoom>) {
+		self.data.borrow_mut().session_zoom = zoom;
+		self.jump_to_path(path);
+	}
+
+	/// See `PlaybackManager::show_memory_image`
+	pub fn show_memory_image(&self, image: RgbaImage, orientation: Orientation) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.playback_manager.show_memory_image(image, orientation);
+		borrowed.render_validity.invalidate();
+	}
+
+	pub fn shown_file_path(&self) -> LoadedImgPath {
+		self.data.borrow().playback_manager.shown_file_path().clone()
+	}
+
+	pub fn shown_texture(&self) -> Option<AnimationFrameTexture> {
+		self.data.borrow().playback_manager.image_texture()
+	}
+
+	/// Size of an image texel in physical display pixels
+	pub fn img_texel_size(&self) -> f32 {
+		self.data.borrow().img_texel_size
+	}
+
+	/// Zooms in (positive `delta`) or out (negative `delta`) around the center of the widget
+	pub fn zoom_at_center(&self, delta: f32) {
 		let mut borrowed = self.data.borrow_mut();
-		borrowed.bright_shade = shade;
-		borrowed.render_validity.invalidate();
+		let anchor = borrowed.drawn_bounds.size * 0.5f32;
+		borrowed.zoom_image(anchor, delta);
 	}
 
-	pub fn set_img_size_to_orig(&self) {
-		let mut borrowed = self.data.borrow_mut();
-		borrowed.set_img_size_to_orig();
+	pub fn set_cache_capacity(&self, capacity: isize) {
+		self.data.borrow_mut().playback_manager.set_cache_capacity(capacity);
 	}
 
-	pub fn set_img_size_to_fit(&self, stretch: bool) {
-		let mut borrowed = self.data.borrow_mut();
-		borrowed.set_img_size_to_fit(stretch);
+	pub fn cached_image_count(&self) -> usize {
+		self.data.borrow().playback_manager.cached_image_count()
 	}
 
-	pub fn jump_to_index(&self, index: u32) {
-		let mut borrowed = self.data.borrow_mut();
-		borrowed.playback_manager.request_load(LoadRequest::LoadAtIndex(index as usize));
-		borrowed.render_validity.invalidate();
+	fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
+		self.handle_input(input_key, modifiers, None);
 	}
 
-	pub fn jump_to_path<P: Into<PathBuf>>(&self, path: P) {
-		let mut borrowed = self.data.borrow_mut();
-		borrowed.playback_manager.request_load(LoadRequest::FilePath(path.into()));
-		borrowed.render_validity.invalidate();
+	/// Does what the key of the action would do
+	fn trigger_action(&self, action_name: &str) {
+		self.handle_input("", ModifiersState::empty(), Some(action_name));
 	}
 
-	fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
+	/// Triggers `action` if it's given, otherwise the actions and commands that are bound to the
+	/// input
+	fn handle_input(&self, input_key: &str, modifiers: ModifiersState, action: Option<&str>) {
 		let mut borrowed = self.data.borrow_mut();
 		macro_rules! triggered {
-			($action_name:ident) => {
-				action_triggered(&borrowed.configuration, $action_name, input_key, modifiers)
-			};
+			($action_name:ident) => {{
+				let action_name: &str = $action_name;
+				match action {
+					Some(action) => action == action_name,
+					None => {
+						action_triggered(&borrowed.configuration, action_name, input_key, modifiers)
+					}
+				}
+			}};
 		}
-		if triggered!(TOGGLE_FULLSCREEN_NAME) {
-			if let Some(window) = borrowed.window.upgrade() {
-				let fullscreen = !window.fullscreen();
-				window.set_fullscreen(fullscreen);
-				borrowed.bottom_bar.set_visible_if_should_show(!fullscreen);
+		if borrowed.overlays.command_palette.visible() {
+			// The text box of the palette handles the other keys
+			match input_key {
+				"up" => borrowed.overlays.command_palette.move_selection(-1),
+				"down" => borrowed.overlays.command_palette.move_selection(1),
+				_ => (),
 			}
+			return;
 		}
-		if triggered!(ESCAPE_NAME) {
-			if let Some(window) = borrowed.window.upgrade() {
-				if window.fullscreen() {
-					window.set_fullscreen(false);
-					borrowed.bottom_bar.set_visible_if_should_show(true);
-				} else {
-					request_exit();
-				}
+		if borrowed.text_input_active() {
+			// The text boxes handle the keys while they are shown
+			return;
+		}
+		if borrowed.binding_editor.is_some() {
+			borrowed.binding_editor_input(input_key, modifiers);
+			return;
+		}
+		if borrowed.onboarding.is_some() {
+			// The questions take every key until they are answered
+			let skip = triggered!(ESCAPE_NAME);
+			let answers = borrowed.answer_onboarding(input_key, skip);
+			if let (Some(answers), Some(on_done)) = (answers, borrowed.on_onboarding_done.take()) {
+				// The callback may use the widget too
+				drop(borrowed);
+				on_done(&answers);
 			}
+			return;
 		}
-		if triggered!(PLAY_ANIM_NAME) {
-			match borrowed.playback_manager.playback_state() {
-				PlaybackState::Forward => borrowed.playback_manager.pause_playback(),
-				_ => borrowed.playback_manager.start_playback_forward(),
+		if borrowed.pending_delete.is_some() {
+			if triggered!(IMG_DEL_NAME) {
+				borrowed.request_delete();
+			} else {
+				borrowed.cancel_delete();
 			}
+			return;
 		}
-		if triggered!(IMG_PREV_NAME) {
-			borrowed.playback_manager.request_load(LoadRequest::LoadPrevious);
-			borrowed.render_validity.invalidate();
+		if borrowed.recent_folders.is_some() {
+			// The number keys open the listed folders instead of triggering their actions
+			if let Ok(number @ 1..=MAX_LISTED_RECENT_FOLDERS) = input_key.parse::<usize>() {
+				borrowed.open_recent_folder(number - 1);
+				return;
+			}
 		}
-		if triggered!(IMG_NEXT_NAME) {
-			borrowed.playback_manager.request_load(LoadRequest::LoadNext);
-			borrowed.render_validity.invalidate();
+		if let Some(selected) = borrowed.open_with_selection {
+			// The list is navigated with the arrow keys, and the number keys pick a program too
+			let picked = match input_key {
+				"up" => {
+					borrowed.move_open_with_selection(-1);
+					return;
+				}
+				"down" => {
+					borrowed.move_open_with_selection(1);
+					return;
+				}
+				"return" => Some(selected),
+				_ => input_key.parse::<usize>().ok().filter(|&n| n >= 1).map(|n| n - 1),
+			};
+			if let Some(index) = picked {
+				borrowed.open_with(index);
+				return;
+			}
 		}
-		if triggered!(IMG_FIT_NAME) {
-			borrowed.set_img_size_to_fit(true);
+		let modifiers = {
+			let config = borrowed.configuration.borrow();
+			binding_modifiers(&config, &borrowed.folder_config, input_key, modifiers)
+		};
+		let quick_sort_target =
+			quick_sort_target(&borrowed.configuration.borrow(), input_key, modifiers);
+		if let Some((folder, copy)) = quick_sort_target {
+			borrowed.send_to_folder(&folder, copy);
+			return;
 		}
-		if triggered!(IMG_FIT_BEST_NAME) {
-			borrowed.set_img_size_to_fit(false);
+		let tag = tag_key_target(&borrowed.configuration.borrow(), input_key, modifiers);
+		if let Some(tag) = tag {
+			borrowed.toggle_tag(&tag);
+			return;
 		}
-		if triggered!(IMG_ORIG_NAME) {
-			borrowed.set_img_size_to_orig();
+		for &(action_name, handler) in ACTION_HANDLERS.iter() {
+			if triggered!(action_name) {
+				handler(&mut borrowed);
+			}
 		}
-		if triggered!(TOGGLE_ANTIALIAS_NAME) {
-			borrowed.toggle_antialias();
+		if action.is_some() {
+			return;
 		}
-		if triggered!(SET_AUTOMATIC_ANTIALIAS_NAME) {
-			borrowed.set_automatic_antialias();
+		if let Some((img_path, folder_path)) = borrowed.command_paths() {
+			execute_triggered_commands(
+				borrowed.configuration.clone(),
+				&borrowed.folder_config,
+				input_key,
+				modifiers,
+				&img_path,
+				&folder_path,
+			);
 		}
-		if triggered!(PLAY_PRESENT_NAME) {
-			match borrowed.playback_manager.playback_state() {
-				PlaybackState::Present => borrowed.playback_manager.pause_playback(),
-				_ => borrowed.playback_manager.start_presentation(),
+	}
+
+	/// Triggers the action or runs the command that is selected in the command palette
+	pub fn run_palette_selection(&self) {
+		let selected = self.data.borrow().overlays.command_palette.selected();
+		match selected {
+			Some(PaletteItem::Action(action_name)) => self.trigger_action(action_name),
+			Some(PaletteItem::Command(index)) => {
+				let borrowed = self.data.borrow();
+				let config = borrowed.configuration.borrow();
+				let commands = config.folder_commands(&borrowed.folder_config);
+				let command = commands.get(index);
+				if let (Some(command), Some((img_path, folder_path))) =
+					(command, borrowed.command_paths())
+				{
+					execute_command(command, &img_path, &folder_path);
+				}
 			}
-			borrowed.render_validity.invalidate();
+			None => (),
 		}
-		if triggered!(PLAY_PRESENT_RND_NAME) {
-			match borrowed.playback_manager.playback_state() {
-				PlaybackState::RandomPresent => borrowed.playback_manager.pause_playback(),
-				_ => borrowed.playback_manager.start_random_presentation(),
+	}
+
+	/// Triggers the actions bound to the mouse button
+	fn handle_mouse_button_input(&self, button: MouseButton, modifiers: ModifiersState) {
+		let double_click = {
+			let mut borrowed = self.data.borrow_mut();
+			if borrowed.pinned || !borrowed.picture_area_hovered() {
+				return;
 			}
-			borrowed.render_validity.invalidate();
+			let now = Instant::now();
+			let double_click = button == MouseButton::Middle
+				&& now.duration_since(borrowed.last_middle_click_time) < DOUBLE_CLICK_INTERVAL;
+			if button == MouseButton::Middle {
+				borrowed.last_middle_click_time = now;
+			}
+			double_click
+		};
+		if let Some(input_key) = mouse_button_to_input_key(button, false) {
+			self.handle_key_input(input_key, modifiers);
 		}
-		if triggered!(IMG_DEL_NAME) {
-			if let LoadedImgPath::Loaded(path) = borrowed.playback_manager.shown_file_path() {
-				if let Err(e) = trash::delete(path) {
-					eprintln!("Error while moving file '{:?}' to trash: {:?}", path, e);
-				}
-				if let Err(e) = borrowed.playback_manager.update_directory() {
-					eprintln!("Error while updating directory {:?}", e);
-				}
-				borrowed.render_validity.invalidate();
+		if let (true, Some(input_key)) = (double_click, mouse_button_to_input_key(button, true)) {
+			self.handle_key_input(input_key, modifiers);
+		}
+	}
+
+	/// Triggers the actions bound to scrolling. Touchpads scroll in small steps, so these are
+	/// added up to a whole line before an action is triggered.
+	fn handle_scroll_input(&self, delta: f32, modifiers: ModifiersState) {
+		let input_key = {
+			let mut borrowed = self.data.borrow_mut();
+			if borrowed.pinned || !borrowed.picture_area_hovered() {
+				return;
+			}
+			if borrowed.bound_scroll.signum() != delta.signum() {
+				borrowed.bound_scroll = 0.0;
+			}
+			borrowed.bound_scroll += delta;
+			if borrowed.bound_scroll.abs() < 1.0 {
+				return;
+			}
+			borrowed.bound_scroll = 0.0;
+			if delta > 0.0 {
+				SCROLL_UP_INPUT
+			} else {
+				SCROLL_DOWN_INPUT
 			}
+		};
+		self.handle_key_input(input_key, modifiers);
+	}
+
+	/// Moves to the next image after scrolling right by a line, or to the previous one after
+	/// scrolling left
+	fn handle_horizontal_scroll(&self, delta: f32) {
+		let mut borrowed = self.data.borrow_mut();
+		if borrowed.pinned || !borrowed.picture_area_hovered() {
+			return;
 		}
-		if triggered!(IMG_COPY_NAME) {
-			if let LoadedImgPath::Loaded(path) = borrowed.playback_manager.shown_file_path().clone()
-			{
-				let request_started;
-				if let Some(clipboard_handler) = &mut borrowed.clipboard_handler {
-					request_started = true;
-					clipboard_handler.request_copy(path);
-					borrowed.copy_notifications.set_started();
+		if borrowed.horizontal_scroll.signum() != delta.signum() {
+			borrowed.horizontal_scroll = 0.0;
+		}
+		borrowed.horizontal_scroll += delta;
+		if borrowed.horizontal_scroll.abs() < 1.0 {
+			return;
+		}
+		borrowed.horizontal_scroll = 0.0;
+		let request = if delta > 0.0 { LoadRequest::LoadNext } else { LoadRequest::LoadPrevious };
+		borrowed.navigate(request);
+	}
+}
+
+/// Does what the action does when its key is pressed
+type ActionHandler = fn(&mut PictureWidgetData);
+
+lazy_static! {
+	/// The actions are triggered in this order if the same input is bound to more of them. The
+	/// ones that last while their key is held are handled with the key events instead.
+	static ref ACTION_HANDLERS: Vec<(&'static str, ActionHandler)> = {
+		let mut handlers = Vec::new();
+		let mut add = |action_name: &'static str, handler: ActionHandler| {
+			handlers.push((action_name, handler));
+		};
+		add(TOGGLE_FULLSCREEN_NAME, |data| {
+			if let Some(window) = data.window.upgrade() {
+				let fullscreen = !window.fullscreen();
+				window.set_fullscreen(fullscreen);
+				data.bottom_bar.set_visible_if_should_show(!fullscreen);
+			}
+		});
+		add(TOGGLE_BOTTOM_BAR_AUTOHIDE_NAME, |data| {
+			let autohide = !data.bottom_bar.autohide();
+			data.bottom_bar.set_autohide(autohide);
+			let state = if autohide { "on" } else { "off" };
+			data.show_notice(&format!("Hiding the bottom bar automatically: {}", state));
+		});
+		add(TOGGLE_ALWAYS_ON_TOP_NAME, |data| {
+			if let Some(window) = data.window.upgrade() {
+				let always_on_top = !window.always_on_top();
+				window.set_always_on_top(always_on_top);
+				let state = if always_on_top { "on" } else { "off" };
+				data.show_notice(&format!("Always on top: {}", state));
+			}
+		});
+		add(TOGGLE_BORDERLESS_NAME, |data| {
+			if let Some(window) = data.window.upgrade() {
+				window.set_borderless(!window.borderless());
+			}
+		});
+		add(TOGGLE_REFERENCE_MODE_NAME, |data| data.toggle_reference_mode());
+		add(OPACITY_INC_NAME, |data| data.change_opacity(OPACITY_STEP));
+		add(OPACITY_DEC_NAME, |data| data.change_opacity(-OPACITY_STEP));
+		add(EXPOSURE_INC_NAME, |data| data.set_exposure(data.exposure + EXPOSURE_STEP));
+		add(EXPOSURE_DEC_NAME, |data| data.set_exposure(data.exposure - EXPOSURE_STEP));
+		add(EXPOSURE_RESET_NAME, |data| data.set_exposure(0.0));
+		add(SHOW_RED_CHANNEL_NAME, |data| data.toggle_channel(Channel::Red));
+		add(SHOW_GREEN_CHANNEL_NAME, |data| data.toggle_channel(Channel::Green));
+		add(SHOW_BLUE_CHANNEL_NAME, |data| data.toggle_channel(Channel::Blue));
+		add(SHOW_ALPHA_CHANNEL_NAME, |data| data.toggle_channel(Channel::Alpha));
+		add(SHOW_LUMINANCE_NAME, |data| data.toggle_channel(Channel::Luminance));
+		add(TOGGLE_ADJUSTMENTS_NAME, |data| data.toggle_adjustments());
+		add(RESET_ADJUSTMENTS_NAME, |data| data.reset_adjustments());
+		add(PIN_DIFFERENCE_IMAGE_NAME, |data| data.pin_difference_image());
+		add(TOGGLE_DIFFERENCE_NAME, |data| data.toggle_difference());
+		add(CYCLE_DIFFERENCE_AMPLIFICATION_NAME, |data| data.cycle_difference_amplification());
+		add(ESCAPE_NAME, |data| {
+			if data.recent_folders.is_some() {
+				data.toggle_recent_folders();
+			} else if data.open_with_selection.is_some() {
+				data.toggle_open_with();
+			} else if data.selection_mode {
+				data.set_selection_mode(false);
+			} else if let Some(window) = data.window.upgrade() {
+				if window.fullscreen() {
+					window.set_fullscreen(false);
+					data.bottom_bar.set_visible_if_should_show(true);
 				} else {
-					request_started = false;
-				}
-				if request_started {
-					borrowed.clipboard_request_was_pending = true;
+					request_exit();
 				}
 			}
+		});
+		add(PLAY_ANIM_NAME, |data| {
+			match data.playback_manager.playback_state() {
+				PlaybackState::Forward => data.playback_manager.pause_playback(),
+				_ => data.playback_manager.start_playback_forward(),
+			}
+		});
+		add(TOGGLE_ANIMATION_NAME, |data| {
+			data.playback_manager.toggle_animation();
+			data.render_validity.invalidate();
+		});
+		add(STEP_FRAME_FORWARD_NAME, |data| {
+			data.playback_manager.step_frame(1);
+			data.render_validity.invalidate();
+		});
+		add(STEP_FRAME_BACKWARD_NAME, |data| {
+			data.playback_manager.step_frame(-1);
+			data.render_validity.invalidate();
+		});
+		add(SET_LOOP_START_NAME, |data| {
+			if let Some(index) = data.playback_manager.set_loop_start() {
+				data.show_notice(&format!("The loop starts at frame {}", index + 1));
+			}
+		});
+		add(SET_LOOP_END_NAME, |data| {
+			if let Some(index) = data.playback_manager.set_loop_end() {
+				data.show_notice(&format!("The loop ends at frame {}", index + 1));
+			}
+		});
+		add(CLEAR_LOOP_NAME, |data| {
+			if data.playback_manager.loop_range().is_some() {
+				data.playback_manager.clear_loop_range();
+				data.show_notice("Playing the whole animation");
+			}
+		});
+		add(IMG_PREV_NAME, |data| data.navigate(LoadRequest::LoadPrevious));
+		add(IMG_NEXT_NAME, |data| data.navigate(LoadRequest::LoadNext));
+		add(IMG_FIT_NAME, |data| data.set_img_size_to_fit(true));
+		add(IMG_FIT_BEST_NAME, |data| data.set_img_size_to_fit(false));
+		add(IMG_ORIG_NAME, |data| data.set_img_size_to_orig());
+		add(TOGGLE_VIEW_LOCK_NAME, |data| data.toggle_view_lock());
+		add(ZOOM_25_NAME, |data| data.set_zoom(0.25));
+		add(ZOOM_50_NAME, |data| data.set_zoom(0.5));
+		add(ZOOM_100_NAME, |data| data.set_zoom(1.0));
+		add(ZOOM_200_NAME, |data| data.set_zoom(2.0));
+		add(ZOOM_400_NAME, |data| data.set_zoom(4.0));
+		add(TOGGLE_ANTIALIAS_NAME, |data| data.toggle_antialias());
+		add(SET_AUTOMATIC_ANTIALIAS_NAME, |data| data.set_automatic_antialias());
+		#[cfg(feature = "dialogs")]
+		{
+			add(OPEN_FOLDER_NAME, |data| data.open_with_dialog(true));
+			add(OPEN_FILE_NAME, |data| data.open_with_dialog(false));
+		}
+		add(NEW_WINDOW_NAME, |data| {
+			if let Some(on_new_window) = data.on_new_window.clone() {
+				let path = match data.playback_manager.shown_file_path() {
+					LoadedImgPath::Loaded(path) => Some(path.clone()),
+					_ => None,
+				};
+				on_new_window(path);
+			}
+		});
+		add(CYCLE_BACKGROUND_NAME, |data| {
+			data.background = data.background.next();
+			if let Some(compare_widget) = &data.compare_widget {
+				compare_widget.data.borrow_mut().background = data.background;
+			}
+			data.render_validity.invalidate();
+		});
+		add(PLAY_PRESENT_NAME, |data| {
+			match data.playback_manager.playback_state() {
+				PlaybackState::Present => data.playback_manager.pause_playback(),
+				_ => data.playback_manager.start_presentation(),
+			}
+			data.resume_ken_burns();
+			data.render_validity.invalidate();
+		});
+		add(PLAY_PRESENT_RND_NAME, |data| {
+			match data.playback_manager.playback_state() {
+				PlaybackState::RandomPresent => data.playback_manager.pause_playback(),
+				_ => data.playback_manager.start_random_presentation(),
+			}
+			data.resume_ken_burns();
+			data.render_validity.invalidate();
+		});
+		add(PRESENT_INTERVAL_INC_NAME, |data| data.adjust_present_interval(PRESENT_INTERVAL_STEP));
+		add(PRESENT_INTERVAL_DEC_NAME, |data| data.adjust_present_interval(-PRESENT_INTERVAL_STEP));
+		add(TOGGLE_PANORAMA_TOUR_NAME, |data| data.toggle_panorama_tour());
+		add(CYCLE_SORT_ORDER_NAME, |data| data.cycle_sort_order());
+		add(RECENT_FOLDERS_NAME, |data| data.toggle_recent_folders());
+		add(OPEN_WITH_NAME, |data| data.toggle_open_with());
+		add(RENAME_NAME, |data| data.start_rename());
+		add(RATE_0_NAME, |data| data.rate(0));
+		add(RATE_1_NAME, |data| data.rate(1));
+		add(RATE_2_NAME, |data| data.rate(2));
+		add(RATE_3_NAME, |data| data.rate(3));
+		add(RATE_4_NAME, |data| data.rate(4));
+		add(RATE_5_NAME, |data| data.rate(5));
+		add(CYCLE_RATING_FILTER_NAME, |data| data.cycle_rating_filter());
+		#[cfg(feature = "textures")]
+		{
+			add(NEXT_MIP_LEVEL_NAME, |data| data.step_texture_selection(1, 0));
+			add(PREV_MIP_LEVEL_NAME, |data| data.step_texture_selection(-1, 0));
+			add(NEXT_TEXTURE_LAYER_NAME, |data| data.step_texture_selection(0, 1));
+			add(PREV_TEXTURE_LAYER_NAME, |data| data.step_texture_selection(0, -1));
 		}
-		if let LoadedImgPath::Loaded(img_path) = borrowed.playback_manager.shown_file_path() {
-			if let Some(folder_path) = img_path.parent() {
-				let img_and_folder = (img_path.to_str(), folder_path.to_str());
-				if let (Some(img_path), Some(folder_path)) = img_and_folder {
-					execute_triggered_commands(
-						borrowed.configuration.clone(),
-						input_key,
-						modifiers,
-						img_path,
-						folder_path,
-					);
-				} else {
-					log::error!("Could not convert the image path to utf8. Path: '{:?}'", img_path);
-				}
-			} else {
-				log::error!("Could not get parent folder for the image path {:?}", img_path);
+		add(TOGGLE_COMPARE_NAME, |data| data.toggle_compare());
+		add(TOGGLE_COMPARE_SYNC_NAME, |data| data.toggle_compare_sync());
+		add(PAN_PAGE_UP_NAME, |data| data.pan_by_pages(0.0, -1.0));
+		add(PAN_PAGE_DOWN_NAME, |data| data.pan_by_pages(0.0, 1.0));
+		add(PAN_PAGE_LEFT_NAME, |data| data.pan_by_pages(-1.0, 0.0));
+		add(PAN_PAGE_RIGHT_NAME, |data| data.pan_by_pages(1.0, 0.0));
+		add(PAN_HOME_NAME, |data| data.pan_to_corner(false));
+		add(PAN_END_NAME, |data| data.pan_to_corner(true));
+		add(ROTATE_LEFT_NAME, |data| {
+			let orientation = data.view_orientation.rotated_left();
+			data.set_view_orientation(orientation);
+		});
+		add(ROTATE_RIGHT_NAME, |data| {
+			let orientation = data.view_orientation.rotated_right();
+			data.set_view_orientation(orientation);
+		});
+		add(FLIP_HOR_NAME, |data| {
+			let orientation = data.view_orientation.flipped_horizontally();
+			data.set_view_orientation(orientation);
+		});
+		add(FLIP_VERT_NAME, |data| {
+			let orientation = data.view_orientation.flipped_vertically();
+			data.set_view_orientation(orientation);
+		});
+		add(SAVE_ROTATION_NAME, |data| data.save_orientation());
+		add(SET_WALLPAPER_NAME, |data| data.set_wallpaper());
+		add(TOGGLE_INFO_NAME, |data| {
+			data.info_panel_requested = !data.info_panel_requested;
+			data.update_info_panel();
+		});
+		add(TOGGLE_CACHE_STATS_NAME, |data| {
+			data.cache_stats_requested = !data.cache_stats_requested;
+			data.update_cache_stats();
+		});
+		add(TOGGLE_SELECTION_NAME, |data| {
+			let enabled = !data.selection_mode;
+			data.set_selection_mode(enabled);
+		});
+		add(COPY_SELECTION_NAME, |data| {
+			if let Some(region) = data.selected_region() {
+				data.copy_to_clipboard(Some(region));
 			}
+		});
+		add(COPY_VIEW_NAME, |data| {
+			if data.clipboard_handler.is_some() {
+				data.request_view_capture(ViewCapture::Clipboard);
+			}
+		});
+		add(EXPORT_IMAGE_NAME, |data| data.export_image());
+		add(EXPORT_VIEW_NAME, |data| data.request_view_capture(ViewCapture::Export));
+		add(TOGGLE_INSPECTOR_NAME, |data| {
+			data.pixel_inspector_enabled = !data.pixel_inspector_enabled;
+			data.update_pixel_inspector();
+		});
+		add(INSPECTOR_SAMPLE_SIZE_NAME, |data| {
+			let curr_size = data.inspector_sample_size;
+			let index = INSPECTOR_SAMPLE_SIZES.iter().position(|&s| s == curr_size).unwrap_or(0);
+			data.inspector_sample_size =
+				INSPECTOR_SAMPLE_SIZES[(index + 1) % INSPECTOR_SAMPLE_SIZES.len()];
+			data.update_pixel_inspector();
+		});
+		add(INSPECTOR_LINEAR_NAME, |data| {
+			data.inspector_linear = !data.inspector_linear;
+			data.update_pixel_inspector();
+		});
+		add(OPEN_CONTAINING_FOLDER_NAME, |data| data.open_containing_folder());
+		add(RETRY_LOAD_NAME, |data| data.retry_load());
+		add(OPEN_DOWNSCALED_NAME, |data| data.open_downscaled(false));
+		add(ALWAYS_DOWNSCALE_NAME, |data| data.open_downscaled(true));
+		add(IMG_DEL_NAME, |data| data.request_delete());
+		add(UNDO_DELETE_NAME, |data| data.undo_delete());
+		add(IMG_COPY_NAME, |data| data.copy_to_clipboard(None));
+		add(COPY_PATH_NAME, |data| data.copy_path_to_clipboard(false));
+		add(COPY_FILE_NAME, |data| data.copy_path_to_clipboard(true));
+		add(COMMAND_PALETTE_NAME, |data| data.open_command_palette());
+		add(EDIT_BINDINGS_NAME, |data| data.toggle_binding_editor());
+
+		for action in ACTIONS.iter().filter(|action| !action.held) {
+			let handled = handlers.iter().any(|&(name, _)| name == action.name);
+			assert!(handled, "The {} action has no handler", action.name);
 		}
-	}
+		for &(name, _) in handlers.iter() {
+			let pressed = find_action(name).is_some_and(|action| !action.held);
+			assert!(pressed, "{} is not an action that happens when its key is pressed", name);
+		}
+		handlers
+	};
 }
 
 impl Widget for PictureWidget {
@@ -669,46 +3839,167 @@ impl Widget for PictureWidget {
 		if !data.visible {
 			return NextUpdate::Latest;
 		}
-		if data.first_draw {
+		let first_draw = mem::take(&mut data.first_draw);
+		// Small images are loaded before the first draw, so that the window doesn't show up
+		// without the image
+		let sync_load = first_draw && data.playback_manager.requested_file_is_small();
+		if first_draw && !sync_load {
 			// Don't block on the main thread and
 			// wait on the image to be loaded on the first draw,
 			// instead let the ui draw itself first and then we can wait.
-			data.first_draw = false;
 			data.next_update = NextUpdate::Soonest;
 			return data.next_update;
 		}
 		let now = Instant::now();
 		let prev_texture = data.playback_manager.image_texture();
-		data.next_update = data.playback_manager.update_image(window);
+		let prev_path = data.playback_manager.shown_file_path().clone();
+		// This depends on the size of the image, so it has to be taken before a different image
+		// is shown
+		let prev_reading_position = data.reading_position();
+		if let Some(nav_repeat) = data.nav_repeat {
+			if now >= nav_repeat.next_time {
+				// If the previously requested image hasn't been loaded yet, this moves on
+				// without showing it
+				let request = if nav_repeat.forward {
+					LoadRequest::LoadNext
+				} else {
+					LoadRequest::LoadPrevious
+				};
+				data.playback_manager.request_load(request);
+				let next_time = now + data.nav_repeat_interval;
+				data.nav_repeat = Some(NavRepeat { next_time, ..nav_repeat });
+			}
+		}
+		match data.playback_manager.update_directory_if_changed() {
+			Ok(true) => data.render_validity.invalidate(),
+			Ok(false) => (),
+			Err(e) => eprintln!("{}", e),
+		}
+		data.next_update = if sync_load {
+			data.playback_manager.update_image_until_loaded(window)
+		} else {
+			data.playback_manager.update_image(window)
+		};
+		data.update_deletions(now);
+		if let Some(nav_repeat) = data.nav_repeat {
+			let repeat_update = NextUpdate::WaitUntil(nav_repeat.next_time);
+			data.next_update = data.next_update.aggregate(repeat_update);
+		}
+		if data.playback_manager.presentation_progress().is_some() {
+			// Moves the progress bar
+			data.render_validity.invalidate();
+			let progress_update = NextUpdate::WaitUntil(now + PRESENT_PROGRESS_INTERVAL);
+			data.next_update = data.next_update.aggregate(progress_update);
+		}
 		let new_texture = data.playback_manager.image_texture();
+		if *data.playback_manager.shown_file_path() != prev_path {
+			data.update_folder_config();
+		}
+		// The pinned image of the compare mode is shown with the view of the other widget
+		if *data.playback_manager.shown_file_path() != prev_path && !data.pinned {
+			data.remember_view(&prev_path, prev_reading_position);
+			if data.view_locked {
+				data.opened_view = (data.view_orientation, data.view_zoom());
+			} else {
+				data.restore_view();
+			}
+			if let LoadedImgPath::Loaded(path) = data.playback_manager.shown_file_path() {
+				data.cache.lock().unwrap().set_recent_folder(path);
+			}
+			data.overlays.present_summary.hide();
+			data.panorama_tour = None;
+			data.start_ken_burns();
+			data.selection = None;
+			data.update_large_image_prompt();
+			data.show_osd(now);
+		}
+		data.update_osd(now);
+		data.update_open_prompt();
+		data.update_load_error_panel();
 		let curr_file_index = data.playback_manager.current_file_index();
 		let curr_dir_len = data.playback_manager.current_dir_len();
-		if let (Some(curr_file_index), Some(curr_dir_len)) = (curr_file_index, curr_dir_len) {
+		let loop_range = data.playback_manager.loop_range();
+		let frame = data.playback_manager.current_frame();
+		if let (Some((start, end)), Some(frame), false) = (loop_range, frame, data.pinned) {
+			// The slider shows the frames of the animation while it loops
+			let last = frame.count.saturating_sub(1);
+			let slider = &data.bottom_bar.slider;
+			slider.set_steps(frame.count as u32, frame.index as u32);
+			slider.set_range(Some((start.min(last) as u32, end.min(last) as u32)));
+		} else if let (Some(curr_file_index), Some(curr_dir_len), false) =
+			(curr_file_index, curr_dir_len, data.pinned)
+		{
 			// dbg!(curr_file_index);
 			// dbg!(curr_dir_len);
 			data.bottom_bar.slider.set_steps(curr_dir_len as u32, curr_file_index as u32);
+			data.bottom_bar.slider.set_range(None);
 		}
 		//data.slider.set_step_bg(data.playback_manager.cached_from_dir());
+		if data.playback_manager.take_presentation_finished() {
+			data.presentation_finished();
+		}
+		if data.playback_manager.take_video_memory_low() {
+			let text = "Not enough video memory\nImages may be shown at a lower quality";
+			data.overlays.memory_warning.show_text(text);
+			data.memory_warning_until = Some(now + MEMORY_WARNING_DURATION);
+		}
+		if data.cache_stats_requested {
+			data.update_cache_stats();
+			let next_update = now + CACHE_STATS_UPDATE_INTERVAL;
+			data.next_update = data.next_update.aggregate(NextUpdate::WaitUntil(next_update));
+		}
+		if let Some(warning_until) = data.memory_warning_until {
+			if now >= warning_until {
+				data.overlays.memory_warning.hide();
+				data.memory_warning_until = None;
+			} else {
+				data.next_update = data.next_update.aggregate(NextUpdate::WaitUntil(warning_until));
+			}
+		}
 		let playback_state = data.playback_manager.playback_state();
-		data.set_window_title_filename(
-			window,
-			playback_state,
-			data.playback_manager.shown_file_path(),
-		);
-		if prev_texture.is_none() != new_texture.is_none() {
+		if playback_state != PlaybackState::Paused {
+			data.overlays.present_summary.hide();
+		}
+		if !data.pinned {
+			data.update_shown_metadata();
+			data.set_window_title_filename(
+				window,
+				playback_state,
+				data.playback_manager.shown_file_path(),
+			);
+		}
+		if data.linked_view_changed() {
+			data.render_validity.invalidate();
+		}
+		let mut texture_changed = prev_texture.is_none() != new_texture.is_none();
+		if let (Some(prev_tex), Some(new_tex)) = (prev_texture, new_texture) {
+			texture_changed = !Rc::ptr_eq(&prev_tex.tex_grid, &new_tex.tex_grid);
+		}
+		if texture_changed {
+			data.update_info_panel();
+			data.update_pixel_inspector();
 			data.render_validity.invalidate();
-		} else if let (Some(prev_tex), Some(new_tex)) = (prev_texture, new_texture) {
-			if !Rc::ptr_eq(&prev_tex.tex_grid, &new_tex.tex_grid) {
-				data.render_validity.invalidate();
+		}
+		if let Some(view_capture) = data.view_capture_pending {
+			if window.capture_pending() {
+				data.next_update = data.next_update.aggregate(NextUpdate::Soonest);
+			} else {
+				data.view_capture_pending = None;
+				let capture = window.take_captured_image();
+				match view_capture {
+					ViewCapture::Clipboard => data.copy_view_to_clipboard(capture),
+					ViewCapture::Export => data.export_view(capture),
+				}
 			}
 		}
+		data.update_export(now);
 		if let Some(clipboard_handler) = &data.clipboard_handler {
 			let clipboard_result = clipboard_handler.try_get_result();
 			let request_pending = clipboard_result.is_none();
 			if data.clipboard_request_was_pending != request_pending {
 				match clipboard_result {
-					Some(succeeded) => data.copy_notifications.set_finished(succeeded),
-					None => data.copy_notifications.set_started(),
+					Some(succeeded) => data.show_clipboard_result(succeeded),
+					None => data.show_clipboard_pending(),
 				}
 				data.clipboard_request_was_pending = request_pending;
 			} else if request_pending {
@@ -716,12 +4007,40 @@ impl Widget for PictureWidget {
 				data.next_update = data.next_update.aggregate(NextUpdate::WaitUntil(next_update));
 			}
 		}
-		if data.zoom_input.moving() || data.hor_pan_input.moving() || data.ver_pan_input.moving() {
+		let camera_moving =
+			data.zoom_input.moving() || data.hor_pan_input.moving() || data.ver_pan_input.moving();
+		if camera_moving || data.panorama_tour.is_some() || data.ken_burns_moving() {
+			data.render_validity.invalidate();
+			data.next_update = NextUpdate::Soonest;
+		}
+		let next_toast_update = data.toasts.update();
+		data.next_update = data.next_update.aggregate(next_toast_update);
+		if data.window_moved.replace(false) {
+			data.display_profile_tracker.update(&window.window_mut());
+		}
+		if data.display_profile_tracker.receive_profile() {
+			data.playback_manager.reload_images();
+			data.render_validity.invalidate();
+		}
+		if let Some(next_check) = data.display_profile_tracker.next_check_time() {
+			data.next_update = data.next_update.aggregate(NextUpdate::WaitUntil(next_check));
+		}
+		if data.power_monitor.update() {
+			let saving_power = data.power_monitor.saving_power();
+			data.playback_manager.set_power_saving(saving_power);
+			if saving_power {
+				data.overlays.power_indicator.show_text("Power saving");
+			} else {
+				data.overlays.power_indicator.hide();
+			}
+		}
+		if let Some(next_check) = data.power_monitor.next_check_time() {
+			data.next_update = data.next_update.aggregate(NextUpdate::WaitUntil(next_check));
+		}
+		if data.tiles_pending {
 			data.render_validity.invalidate();
 			data.next_update = NextUpdate::Soonest;
 		}
-		let next_copy_noti_update = data.copy_notifications.update();
-		data.next_update = data.next_update.aggregate(next_copy_noti_update);
 		data.next_update
 	}
 
@@ -732,14 +4051,24 @@ impl Widget for PictureWidget {
 			if !data.visible {
 				return Ok(data.next_update);
 			}
+			data.dpi_scale = context.dpi_scale_factor;
+			data.adopt_linked_view();
 			data.update_image_transform(context.dpi_scale_factor);
 			data.apply_camera_movement(context.dpi_scale_factor);
+			data.publish_linked_view();
+			if !data.pinned {
+				let zoom = data.get_texture().map(|_| data.img_texel_size);
+				data.bottom_bar.set_zoom(zoom);
+			}
 			texture = data.get_texture();
 		}
 		if let Some(texture) = texture {
 			let data = self.data.borrow();
-			draw_tex_grid(data, target, context, texture);
+			let tiles_pending = draw_tex_grid(data, target, context, texture);
+			self.data.borrow_mut().tiles_pending = tiles_pending;
 		}
+		draw_selection(self.data.borrow(), target, context);
+		draw_present_progress(self.data.borrow(), target, context);
 		let borrowed = self.data.borrow();
 		Ok(borrowed.next_update)
 	}
@@ -747,17 +4076,39 @@ impl Widget for PictureWidget {
 	fn layout(&self, available_space: LogicalRect) {
 		let mut borrowed = self.data.borrow_mut();
 		borrowed.default_layout(available_space);
-		borrowed.hover = borrowed.drawn_bounds.contains(borrowed.last_mouse_pos);
+		borrowed.hover = borrowed.is_over_picture(borrowed.last_mouse_pos);
 	}
 
 	fn handle_event(&self, event: &Event) {
-		if !self.data.borrow().visible {
-			return;
+		{
+			let borrowed = self.data.borrow();
+			if !borrowed.visible {
+				return;
+			}
+			// The pinned image of the compare mode is controlled through the other widget
+			let other_widget_event = matches!(
+				event.kind,
+				EventKind::KeyInput { .. }
+					| EventKind::DroppedFile(_)
+					| EventKind::HoveredFile(_)
+					| EventKind::HoveredFileCancelled
+			);
+			if borrowed.pinned && other_widget_event {
+				return;
+			}
 		}
 		match event.kind {
 			EventKind::MouseMove => {
 				let mut borrowed = self.data.borrow_mut();
-				borrowed.hover = borrowed.drawn_bounds.contains(event.cursor_pos);
+				borrowed.hover = borrowed.is_over_picture(event.cursor_pos);
+				if borrowed.selecting {
+					if let (Some((start, _)), Some(end)) =
+						(borrowed.selection, borrowed.to_image_fraction(event.cursor_pos))
+					{
+						borrowed.selection = Some((start, end));
+						borrowed.render_validity.invalidate();
+					}
+				}
 				if borrowed.panning_2d || borrowed.panning_hor || borrowed.panning_vert {
 					let mut delta = event.cursor_pos - borrowed.last_mouse_pos;
 					if !borrowed.panning_2d {
@@ -770,30 +4121,47 @@ impl Widget for PictureWidget {
 							delta.vec.y = 0.0;
 						}
 					}
+					borrowed.ken_burns = None;
 					borrowed.scaling = ScalingMode::Fixed;
 					borrowed.update_scaling_buttons();
 					borrowed.img_pos += delta;
 					borrowed.render_validity.invalidate();
 				}
 				borrowed.last_mouse_pos = event.cursor_pos;
+				if borrowed.pixel_inspector_enabled {
+					borrowed.update_pixel_inspector();
+				}
 			}
 			EventKind::MouseButton { state, button, .. } => match button {
 				MouseButton::Left => {
 					let mut borrowed = self.data.borrow_mut();
 					if state == ElementState::Pressed {
-						if borrowed.hover {
+						if borrowed.hover && borrowed.selection_mode {
+							let start = borrowed.to_image_fraction(event.cursor_pos);
+							borrowed.selection = start.map(|start| (start, start));
+							borrowed.selecting = start.is_some();
+						} else if borrowed.hover && borrowed.pixel_inspector_enabled {
+							// The image is not panned in this mode so that the color that was
+							// copied stays under the cursor
+							borrowed.copy_inspected_color();
+						} else if borrowed.hover {
 							borrowed.click = true;
 							borrowed.panning_2d = true
 						}
+					} else if borrowed.selecting {
+						borrowed.selecting = false;
+						if borrowed.selected_region().is_none() {
+							borrowed.selection = None;
+						}
 					} else {
 						borrowed.panning_2d = false;
 						borrowed.click = false;
-						if borrowed.hover {
+						if borrowed.hover && !borrowed.pixel_inspector_enabled {
 							let now = Instant::now();
 							let duration_since_last_click =
 								now.duration_since(borrowed.last_click_time);
 							borrowed.last_click_time = now;
-							if duration_since_last_click < Duration::from_millis(250) {
+							if duration_since_last_click < DOUBLE_CLICK_INTERVAL {
 								match borrowed.window.upgrade() {
 									Some(window) => {
 										let fullscreen = !window.fullscreen();
@@ -812,12 +4180,47 @@ impl Widget for PictureWidget {
 					let pressed = state == ElementState::Pressed;
 					borrowed.left_to_pan_hint.set_visible(pressed);
 				}
-				_ => {}
+				button => {
+					if state == ElementState::Pressed {
+						self.handle_mouse_button_input(button, event.modifiers);
+					}
+				}
 			},
 			EventKind::MouseScroll { delta } => {
+				let horizontal_scroll_navigation = self.data.borrow().horizontal_scroll_navigation;
+				if horizontal_scroll_navigation && delta.vec.x != 0.0 {
+					self.handle_horizontal_scroll(delta.vec.x);
+				}
+				if delta.vec.y == 0.0 {
+					return;
+				}
+				let scroll_input =
+					if delta.vec.y > 0.0 { SCROLL_UP_INPUT } else { SCROLL_DOWN_INPUT };
+				let scroll_bound = {
+					let borrowed = self.data.borrow();
+					let config = borrowed.configuration.borrow();
+					let folder_config = &borrowed.folder_config;
+					let modifiers =
+						binding_modifiers(&config, folder_config, scroll_input, event.modifiers);
+					input_bound(&config, folder_config, scroll_input, modifiers)
+				};
+				if scroll_bound {
+					self.handle_scroll_input(delta.vec.y, event.modifiers);
+					return;
+				}
 				let mut borrowed = self.data.borrow_mut();
-				let delta = delta.vec.y * 0.375;
-				borrowed.zoom_image(event.cursor_pos, delta);
+				// In the compare mode only the image under the cursor is zoomed, the other one
+				// follows it if the views are synchronized
+				if borrowed.view_link.is_some() && !borrowed.hover {
+					return;
+				}
+				let direction = if borrowed.invert_scroll_zoom { -1.0 } else { 1.0 };
+				let delta = direction * delta.vec.y * 0.375;
+				let anchor = borrowed.wheel_zoom_anchor(event.cursor_pos);
+				borrowed.zoom_image(anchor, delta);
+				if borrowed.pixel_inspector_enabled {
+					borrowed.update_pixel_inspector();
+				}
 			}
 			EventKind::KeyInput { ref input } => {
 				let key = input.key_without_modifiers();
@@ -825,16 +4228,51 @@ impl Widget for PictureWidget {
 				//println!("Got input for {:?}", key);
 				let input_key_str = virtual_keycode_to_string(&key).to_lowercase();
 				let input_key_str = char_to_input_key(&input_key_str);
-				if is_pressed {
+				// The bindings are matched against these, but `handle_key_input` gets the held
+				// modifiers, because the overlays need those
+				let modifiers = {
+					let borrowed = self.data.borrow();
+					let config = borrowed.configuration.borrow();
+					let folder_config = &borrowed.folder_config;
+					binding_modifiers(&config, folder_config, &input_key_str, event.modifiers)
+				};
+				// When the navigation is repeated by `before_draw`, the repeated key events of
+				// the operating system are ignored
+				let nav_forward = {
+					let borrowed = self.data.borrow();
+					let config = &borrowed.configuration;
+					let key = input_key_str.as_str();
+					let overlay_input =
+						borrowed.text_input_active() || borrowed.binding_editor.is_some();
+					if borrowed.nav_repeat_interval.is_zero() || overlay_input {
+						None
+					} else if action_triggered(config, IMG_NEXT_NAME, key, modifiers) {
+						Some(true)
+					} else if action_triggered(config, IMG_PREV_NAME, key, modifiers) {
+						Some(false)
+					} else {
+						None
+					}
+				};
+				if is_pressed && !(input.repeat && nav_forward.is_some()) {
 					self.handle_key_input(input_key_str.as_str(), event.modifiers);
 				}
-				// Panning is a special snowflake
 				let mut borrowed = self.data.borrow_mut();
+				if let Some(forward) = nav_forward {
+					if is_pressed && !input.repeat {
+						let next_time = Instant::now() + borrowed.nav_repeat_delay;
+						borrowed.nav_repeat = Some(NavRepeat { forward, next_time });
+						borrowed.render_validity.invalidate();
+					} else if !is_pressed {
+						borrowed.nav_repeat = None;
+					}
+				}
+				// Panning is a special snowflake
 				if action_triggered(
 					&borrowed.configuration,
 					PAN_NAME,
 					input_key_str.as_str(),
-					event.modifiers,
+					modifiers,
 				) {
 					borrowed.panning_2d = is_pressed;
 				}
@@ -842,7 +4280,7 @@ impl Widget for PictureWidget {
 					&borrowed.configuration,
 					PAN_VERT_NAME,
 					input_key_str.as_str(),
-					event.modifiers,
+					modifiers,
 				) {
 					borrowed.panning_vert = is_pressed;
 				}
@@ -850,7 +4288,7 @@ impl Widget for PictureWidget {
 					&borrowed.configuration,
 					PAN_HOR_NAME,
 					input_key_str.as_str(),
-					event.modifiers,
+					modifiers,
 				) {
 					borrowed.panning_hor = is_pressed;
 				}
@@ -861,7 +4299,7 @@ impl Widget for PictureWidget {
 							&borrowed.configuration,
 							$name,
 							input_key_str.as_str(),
-							event.modifiers,
+							modifiers,
 						) {
 							if $input == $dir && !is_pressed {
 								$input = MovementDir::None;
@@ -950,14 +4388,57 @@ impl Widget for PictureWidget {
 				borrowed.render_validity.invalidate();
 			}
 			EventKind::Focused(focused) => {
+				// The key may be released while the window isn't focused
+				self.data.borrow_mut().nav_repeat = None;
 				if focused {
 					let mut borrowed = self.data.borrow_mut();
 					if let Err(e) = borrowed.playback_manager.update_directory() {
 						eprintln!("{}", e);
 					}
+					// The config of the folder may have been edited in the meantime
+					borrowed.folder_config_folder = None;
+					borrowed.update_folder_config();
+					// The sidecar files may also have been edited
+					if !borrowed.pinned {
+						borrowed.shown_metadata = None;
+						borrowed.update_shown_metadata();
+					}
 					borrowed.render_validity.invalidate();
 				}
 			}
+			EventKind::ScaleFactorChanged(scale_factor) => {
+				self.data.borrow_mut().set_dpi_scale(scale_factor);
+			}
+			EventKind::Touch { id, phase } => {
+				let mut borrowed = self.data.borrow_mut();
+				if borrowed.pinned {
+					return;
+				}
+				// Touches that start outside of the picture belong to the other widgets
+				if phase == TouchPhase::Started && !borrowed.is_over_picture(event.cursor_pos) {
+					return;
+				}
+				let gesture = borrowed.touch_gestures.handle_touch(id, phase, event.cursor_pos);
+				if let Some(gesture) = gesture {
+					borrowed.apply_touch_gesture(gesture);
+				}
+			}
+			EventKind::Magnify { delta } => {
+				let mut borrowed = self.data.borrow_mut();
+				// Like with the wheel, only the image under the cursor is zoomed in the compare mode
+				if borrowed.view_link.is_some() && !borrowed.hover {
+					return;
+				}
+				// The scale changes by a small amount with each event, so unlike `zoom_image`
+				// this doesn't snap to 100%, which would get stuck there
+				let texel_size = borrowed.img_texel_size * (1.0 + delta).max(0.01);
+				let texel_size = texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+				let anchor = borrowed.wheel_zoom_anchor(event.cursor_pos);
+				borrowed.zoom_image_to(anchor, texel_size);
+				if borrowed.pixel_inspector_enabled {
+					borrowed.update_pixel_inspector();
+				}
+			}
 			EventKind::CloseRequested => {
 				let mut borrowed = self.data.borrow_mut();
 				// Just let it drop.
@@ -990,12 +4471,26 @@ impl Drop for PictureWidget {
 	}
 }
 
+/// The images to show based on their rating and tags. `cycled_min_rating` takes precedence
+/// over the config.
+fn metadata_filter(config: &Configuration, cycled_min_rating: Option<u8>) -> MetadataFilter {
+	let section = config.metadata.as_ref();
+	let min_rating = cycled_min_rating.or_else(|| section.and_then(|s| s.min_rating));
+	let tags = section.and_then(|s| s.required_tags.clone()).unwrap_or_default();
+	MetadataFilter { min_rating: min_rating.unwrap_or(0).min(MAX_RATING), tags }
+}
+
+fn ken_burns_enabled(config: &Configuration) -> bool {
+	config.playback.as_ref().and_then(|s| s.ken_burns).unwrap_or(false)
+}
+
+/// Returns true if there are tiles of a progressively shown image left to upload
 fn draw_tex_grid(
 	data: Ref<PictureWidgetData>,
 	target: &mut Frame,
 	context: &DrawContext,
 	texture: AnimationFrameTexture,
-) {
+) -> bool {
 	let size = data.drawn_bounds.size.vec;
 	let projection_transform = gelatin::cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0);
 
@@ -1043,57 +4538,170 @@ fn draw_tex_grid(
 	let img_translation =
 		Matrix4::from_translation(Vector3::new(img_logical_corner_x, img_logical_corner_y, 0.0));
 
-	// let img_logical_w = img_w / context.dpi_scale_factor;
-	// let img_logical_h = img_h / context.dpi_scale_factor;
-	let cell_phy_step = texture.cell_step_size;
-	for cell_tex in texture.tex_grid.iter() {
-		let (cell_phys_w, cell_phys_h) = cell_tex.tex.dimensions();
-
-		let cell_phy_offset_x = cell_phy_step * cell_tex.col;
-		let cell_phy_offset_y = cell_phy_step * cell_tex.row;
-		// let cell_logical_offset_x = cell_phy_offset_x as f32 / context.dpi_scale_factor;
-		// let cell_logical_offset_y = cell_phy_offset_y as f32 / context.dpi_scale_factor;
+	let image_transform = img_translation * orientation * img_scaling;
+	let filter = match data.antialiasing {
+		// The placeholder is meant to look blurry
+		_ if texture.placeholder.is_some() => MagnifySamplerFilter::Linear,
+		Antialias::Auto if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD => {
+			MagnifySamplerFilter::Linear
+		}
+		Antialias::Auto | Antialias::Never => MagnifySamplerFilter::Nearest,
+		Antialias::Always => MagnifySamplerFilter::Linear,
+	};
+	let bg_color = match data.background {
+		Background::Custom => data.background_color,
+		_ => [data.bright_shade; 3],
+	};
+	// The transform of a texture that covers the rectangle of the image given in image pixels
+	let cell_transform = |(cell_w, cell_h): (u32, u32), x: u32, y: u32, texel_size: f32| {
+		let cell_phys_w = cell_w as f32 * texel_size / data.img_texel_size;
+		let cell_phys_h = cell_h as f32 * texel_size / data.img_texel_size;
 
 		// The grid is constructed so that it is exactly of size (1, 1) and is located at (0, 0)
 		// This allows to leave most of the image transformation logic unchanged.
-		let cell_scaling = Matrix4::from_nonuniform_scale(
-			cell_phys_w as f32 / img_phys_w,
-			cell_phys_h as f32 / img_phys_h,
-			1.0,
-		);
+		let cell_scaling =
+			Matrix4::from_nonuniform_scale(cell_phys_w / img_phys_w, cell_phys_h / img_phys_h, 1.0);
 		let cell_translation = Matrix4::from_translation(Vector3::new(
-			cell_phy_offset_x as f32 / img_phys_w,
-			cell_phy_offset_y as f32 / img_phys_h,
+			x as f32 / img_phys_w,
+			y as f32 / img_phys_h,
 			0.0,
 		));
 
-		let transform =
-			img_translation * orientation * img_scaling * cell_translation * cell_scaling;
+		let transform = image_transform * cell_translation * cell_scaling;
 		// Projection tranform
-		let transform = projection_transform * transform;
+		Into::<[[f32; 4]; 4]>::into(projection_transform * transform)
+	};
+	let lod_level = |texel_size: f32| ((1.0 / texel_size).log2().max(0.0) + 0.125).floor();
+	let checkerboard = data.background == Background::Checkerboard;
+	let display_transform = display_transform::shader_mode(data.display_transform);
+	let channel = data.channel.shader_mode();
+	let adjustments = data.adjustments_panel.as_ref().map(|p| p.adjustments()).unwrap_or_default();
+	let adjust_exposure = 2f32.powf(adjustments.exposure);
+	let lut = &data.display_lut;
+	let lut_sampler = lut
+		.texture
+		.sampled()
+		.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::Linear)
+		.magnify_filter(MagnifySamplerFilter::Linear)
+		.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp);
 
-		let sampler = cell_tex
-			.tex
+	if let Some(float) = &texture.float {
+		let tex = &float.tex;
+		let sampler = tex
 			.sampled()
 			.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
-			.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp);
+			.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp)
+			.magnify_filter(filter);
+		let uniforms = uniform! {
+			matrix: cell_transform(tex.dimensions(), 0, 0, data.img_texel_size),
+			bright_shade: data.bright_shade,
+			checkerboard: checkerboard,
+			bg_color: bg_color,
+			tex: sampler,
+			lod_level: lod_level(data.img_texel_size),
+			exposure: 2f32.powf(data.exposure),
+			tone_mapping: float.tone_map,
+			display_transform: display_transform,
+			channel: channel,
+			adjust_exposure: adjust_exposure,
+			adjust_gamma: adjustments.gamma,
+			adjust_saturation: adjustments.saturation,
+			lut: lut_sampler,
+			lut_size: lut.size,
+			lut_domain_min: lut.domain_min,
+			lut_domain_max: lut.domain_max,
+		};
+		target
+			.draw(
+				context.unit_quad_vertices,
+				context.unit_quad_indices,
+				&data.hdr_program,
+				&uniforms,
+				&image_draw_params,
+			)
+			.unwrap();
+		return false;
+	}
 
-		let filter = match data.antialiasing {
-			Antialias::Auto if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD => {
-				MagnifySamplerFilter::Linear
+	if let Some(pinned) = data.difference_to(&texture) {
+		// The textures of both images cover the same rectangles, because the images have the
+		// same size. Very large images are compared through their previews.
+		let mut cells = Vec::new();
+		match (&texture.tiled, &pinned.tiled) {
+			(Some(tiled), Some(pinned_tiled)) => {
+				let texel_size = data.img_texel_size * tiled.preview_scale();
+				cells.push((&tiled.preview, &pinned_tiled.preview, 0, 0, texel_size));
 			}
-			Antialias::Auto | Antialias::Never => MagnifySamplerFilter::Nearest,
-			Antialias::Always => MagnifySamplerFilter::Linear,
-		};
-		let sampler = sampler.magnify_filter(filter);
+			_ => {
+				let step = texture.cell_step_size;
+				for (cell, pinned_cell) in texture.tex_grid.iter().zip(pinned.tex_grid.iter()) {
+					let (x, y) = (step * cell.col, step * cell.row);
+					cells.push((&cell.tex, &pinned_cell.tex, x, y, data.img_texel_size));
+				}
+			}
+		}
+		for (tex, pinned_tex, x, y, texel_size) in cells {
+			let sampler = tex
+				.sampled()
+				.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
+				.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp)
+				.magnify_filter(filter);
+			let pinned_sampler = pinned_tex
+				.sampled()
+				.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
+				.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp)
+				.magnify_filter(filter);
+			let uniforms = uniform! {
+				matrix: cell_transform(tex.dimensions(), x, y, texel_size),
+				tex: sampler,
+				pinned_tex: pinned_sampler,
+				lod_level: lod_level(texel_size),
+				amplification: DIFFERENCE_AMPLIFICATIONS[data.difference_amplification],
+				display_transform: display_transform,
+				channel: channel,
+				lut: lut_sampler,
+				lut_size: lut.size,
+				lut_domain_min: lut.domain_min,
+				lut_domain_max: lut.domain_max,
+			};
+			target
+				.draw(
+					context.unit_quad_vertices,
+					context.unit_quad_indices,
+					&data.difference_program,
+					&uniforms,
+					&image_draw_params,
+				)
+				.unwrap();
+		}
+		return false;
+	}
+
+	// Draws a texture that covers the rectangle of the image given in image pixels
+	let mut draw_cell = |tex: &SrgbTexture2d, x: u32, y: u32, texel_size: f32| {
+		let sampler = tex
+			.sampled()
+			.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
+			.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp)
+			.magnify_filter(filter);
 
 		// building the uniforms
-		let lod_level = ((1.0 / data.img_texel_size).log2().max(0.0) + 0.125).floor();
 		let uniforms = uniform! {
-			matrix: Into::<[[f32; 4]; 4]>::into(transform),
+			matrix: cell_transform(tex.dimensions(), x, y, texel_size),
 			bright_shade: data.bright_shade,
+			checkerboard: checkerboard,
+			bg_color: bg_color,
 			tex: sampler,
-			lod_level: lod_level,
+			lod_level: lod_level(texel_size),
+			display_transform: display_transform,
+			channel: channel,
+			adjust_exposure: adjust_exposure,
+			adjust_gamma: adjustments.gamma,
+			adjust_saturation: adjustments.saturation,
+			lut: lut_sampler,
+			lut_size: lut.size,
+			lut_domain_min: lut.domain_min,
+			lut_domain_max: lut.domain_max,
 		};
 		target
 			.draw(
@@ -1104,5 +4712,132 @@ fn draw_tex_grid(
 				&image_draw_params,
 			)
 			.unwrap();
+	};
+
+	if let Some(placeholder) = &texture.placeholder {
+		let texel_size = data.img_texel_size * texture.w as f32 / placeholder.width() as f32;
+		draw_cell(placeholder, 0, 0, texel_size);
+		return false;
+	}
+
+	let tiled = match &texture.tiled {
+		Some(tiled) => tiled,
+		None => {
+			let cell_phy_step = texture.cell_step_size;
+			for cell_tex in texture.tex_grid.iter() {
+				let cell_phy_offset_x = cell_phy_step * cell_tex.col;
+				let cell_phy_offset_y = cell_phy_step * cell_tex.row;
+				draw_cell(&cell_tex.tex, cell_phy_offset_x, cell_phy_offset_y, data.img_texel_size);
+			}
+			return false;
+		}
+	};
+	// The preview is drawn first so that there's something in place of the missing tiles
+	let preview_texel_size = data.img_texel_size * tiled.preview_scale();
+	draw_cell(&tiled.preview, 0, 0, preview_texel_size);
+	let visible = if tiled.needs_tiles(data.img_texel_size) {
+		visible_image_rect(image_transform, size)
+	} else {
+		None
+	};
+	let tiles_pending = tiled.update(context.display, visible);
+	tiled.for_each_tile(|tex, x, y| draw_cell(tex, x, y, data.img_texel_size));
+	tiles_pending
+}
+
+/// Returns the part of the image that is in the widget of the given size. `image_transform`
+/// maps the image onto the widget, from the 0 to 1 range of the image width and height.
+fn visible_image_rect(image_transform: Matrix4<f32>, size: Vector2<f32>) -> Option<ImageRect> {
+	let inverse = image_transform.invert()?;
+	let mut rect = ImageRect { min: [f32::MAX; 2], max: [f32::MIN; 2] };
+	for &(x, y) in [(0.0, 0.0), (size.x, 0.0), (0.0, size.y), (size.x, size.y)].iter() {
+		let corner = inverse * Vector4::new(x, y, 0.0, 1.0);
+		rect.min = [rect.min[0].min(corner.x), rect.min[1].min(corner.y)];
+		rect.max = [rect.max[0].max(corner.x), rect.max[1].max(corner.y)];
 	}
+	Some(rect)
+}
+
+/// Draws the selected region with a translucent fill and a border.
+fn draw_selection(data: Ref<PictureWidgetData>, target: &mut Frame, context: &DrawContext) {
+	let (start, end) = match data.selection {
+		Some(selection) => selection,
+		None => return,
+	};
+	let texture = match data.get_texture() {
+		Some(texture) => texture,
+		None => return,
+	};
+	let (img_w, img_h) = texture.oriented_dimensions();
+	let img_scale = data.img_texel_size / context.dpi_scale_factor;
+	let img_size = Vector2::new(img_w as f32 * img_scale, img_h as f32 * img_scale);
+	let img_corner = data.drawn_bounds.pos.vec + data.img_pos.vec - img_size * 0.5;
+	let region = ImageRegion::from_corners(start, end);
+	let pos = img_corner + Vector2::new(region.left * img_size.x, region.top * img_size.y);
+	let size = Vector2::new(
+		(region.right - region.left) * img_size.x,
+		(region.bottom - region.top) * img_size.y,
+	);
+
+	let mut draw_rect = |pos: Vector2<f32>, size: Vector2<f32>, color: [f32; 4]| {
+		draw_colored_rect(target, context, pos, size, color);
+	};
+	let fill_color = [0.3, 0.55, 0.9, 0.25];
+	let border_color = [0.3, 0.55, 0.9, 0.9];
+	let border = 1.0 / context.dpi_scale_factor;
+	draw_rect(pos, size, fill_color);
+	draw_rect(pos, Vector2::new(size.x, border), border_color);
+	draw_rect(pos + Vector2::new(0.0, size.y - border), Vector2::new(size.x, border), border_color);
+	draw_rect(pos, Vector2::new(border, size.y), border_color);
+	draw_rect(pos + Vector2::new(size.x - border, 0.0), Vector2::new(border, size.y), border_color);
+}
+
+/// Draws a thin bar along the bottom of the picture that fills up until the next image of the
+/// presentation is shown.
+fn draw_present_progress(data: Ref<PictureWidgetData>, target: &mut Frame, context: &DrawContext) {
+	let progress = match data.playback_manager.presentation_progress() {
+		Some(progress) => progress,
+		None => return,
+	};
+	let bounds = &data.drawn_bounds;
+	let pos = bounds.pos.vec + Vector2::new(0.0, bounds.size.vec.y - PRESENT_PROGRESS_HEIGHT);
+	let size = Vector2::new(bounds.size.vec.x * progress, PRESENT_PROGRESS_HEIGHT);
+	draw_colored_rect(target, context, pos, size, [0.5, 0.5, 0.5, 0.6]);
+}
+
+/// `pos` and `size` are in logical pixels
+fn draw_colored_rect(
+	target: &mut Frame,
+	context: &DrawContext,
+	pos: Vector2<f32>,
+	size: Vector2<f32>,
+	color: [f32; 4],
+) {
+	let draw_params = gelatin::glium::DrawParameters {
+		viewport: Some(*context.viewport),
+		blend: Blend {
+			color: BlendingFunction::Addition {
+				source: LinearBlendingFactor::SourceAlpha,
+				destination: LinearBlendingFactor::OneMinusSourceAlpha,
+			},
+			..Default::default()
+		},
+		..Default::default()
+	};
+	let transform = Matrix4::from_nonuniform_scale(size.x, size.y, 1.0);
+	let transform = Matrix4::from_translation(pos.extend(0.0)) * transform;
+	let transform = context.projection_transform * transform;
+	let uniforms = uniform! {
+		matrix: Into::<[[f32; 4]; 4]>::into(transform),
+		color: color,
+	};
+	target
+		.draw(
+			context.unit_quad_vertices,
+			context.unit_quad_indices,
+			context.colored_program,
+			&uniforms,
+			&draw_params,
+		)
+		.unwrap();
 }
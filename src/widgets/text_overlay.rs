@@ -0,0 +1,200 @@
+use std::rc::{Rc, Weak};
+
+use gelatin::{label::Label, misc::*, picture::Picture, text_box::TextBox, Widget};
+
+use super::command_palette::CommandPalette;
+use crate::text_rendering::{render_text, render_text_with_icon, TextStyle};
+
+/// A red triangle with an exclamation mark, see `render_text_with_icon`
+const ERROR_ICON: &str = concat!(
+	r#"<path d="M12 1.5 L23 21.5 H1 Z" fill="rgb(210,55,45)"/>"#,
+	r#"<rect x="11" y="8" width="2" height="7.5" fill="white"/>"#,
+	r#"<rect x="11" y="17" width="2" height="2" fill="white"/>"#,
+);
+
+/// Displays a block of text over the picture, using a `Label` as the surface.
+#[derive(Clone)]
+pub struct TextOverlay {
+	pub widget: Weak<Label>,
+	text: String,
+	/// Drawn above the text
+	icon: Option<&'static str>,
+}
+
+impl TextOverlay {
+	pub fn new(widget: &Rc<Label>, horizontal_align: Alignment, vertical_align: Alignment) -> Self {
+		widget.set_icon(None);
+		widget.set_ignore_layout(true);
+		widget.set_margin_all(8.0);
+		widget.set_horizontal_align(horizontal_align);
+		widget.set_vertical_align(vertical_align);
+		widget.set_visible(false);
+		TextOverlay { widget: Rc::downgrade(widget), text: String::new(), icon: None }
+	}
+
+	pub fn with_icon(mut self, icon: &'static str) -> Self {
+		self.icon = Some(icon);
+		self
+	}
+
+	/// Shows the overlay with the given text. The text is only rendered again if it changed.
+	pub fn show_text(&mut self, text: &str) {
+		let widget = match self.widget.upgrade() {
+			Some(widget) => widget,
+			None => return,
+		};
+		if self.text != text {
+			self.text = text.to_owned();
+			let rendered = match self.icon {
+				Some(icon) => render_text_with_icon(icon, text, &TextStyle::default()),
+				None => render_text(text, &TextStyle::default()),
+			};
+			match rendered {
+				Some(rendered) => {
+					widget.set_fixed_size(LogicalVector::new(rendered.width, rendered.height));
+					widget.set_icon(Some(Rc::new(Picture::from_image(rendered.image))));
+				}
+				None => widget.set_icon(None),
+			}
+		}
+		widget.set_visible(true);
+	}
+
+	/// Between 0 and 1
+	pub fn set_opacity(&self, opacity: f32) {
+		if let Some(widget) = self.widget.upgrade() {
+			widget.set_opacity(opacity);
+		}
+	}
+
+	pub fn hide(&mut self) {
+		if let Some(widget) = self.widget.upgrade() {
+			if widget.visible() {
+				widget.set_visible(false);
+			}
+		}
+	}
+}
+
+/// The text overlays that are displayed on top of the picture widget.
+pub struct PictureOverlays {
+	pub large_image_prompt: TextOverlay,
+	/// Tells why the shown file couldn't be opened
+	pub load_error: TextOverlay,
+	pub power_indicator: TextOverlay,
+	pub info_panel: TextOverlay,
+	/// Shows how much memory the image cache uses
+	pub cache_stats: TextOverlay,
+	pub selection_hint: TextOverlay,
+	pub pixel_inspector: TextOverlay,
+	pub open_prompt: TextOverlay,
+	pub present_summary: TextOverlay,
+	pub memory_warning: TextOverlay,
+	/// Shows the name and the index of the image for a while after it changes
+	pub osd: TextOverlay,
+	pub recent_folders: TextOverlay,
+	pub open_with: TextOverlay,
+	pub binding_editor: TextOverlay,
+	pub delete_prompt: TextOverlay,
+	/// Asks the questions of the first launch
+	pub onboarding: TextOverlay,
+	/// Edits the name of the current file
+	pub rename_box: Rc<TextBox>,
+	/// Edits the zoom level in percent
+	pub zoom_box: Rc<TextBox>,
+	pub command_palette: CommandPalette,
+	widgets: Vec<Rc<Label>>,
+}
+
+impl PictureOverlays {
+	pub fn new() -> Self {
+		let mut widgets = Vec::new();
+		let mut make_overlay = |horizontal_align, vertical_align| {
+			let widget = Rc::new(Label::new());
+			let overlay = TextOverlay::new(&widget, horizontal_align, vertical_align);
+			widgets.push(widget);
+			overlay
+		};
+		let large_image_prompt = make_overlay(Alignment::Center, Alignment::Center);
+		let load_error = make_overlay(Alignment::Center, Alignment::Center).with_icon(ERROR_ICON);
+		let power_indicator = make_overlay(Alignment::End, Alignment::Start);
+		let info_panel = make_overlay(Alignment::Start, Alignment::Start);
+		let cache_stats = make_overlay(Alignment::End, Alignment::Center);
+		let selection_hint = make_overlay(Alignment::Center, Alignment::End);
+		let pixel_inspector = make_overlay(Alignment::Start, Alignment::End);
+		let open_prompt = make_overlay(Alignment::Center, Alignment::Center);
+		let present_summary = make_overlay(Alignment::Center, Alignment::Center);
+		let memory_warning = make_overlay(Alignment::Center, Alignment::Start);
+		let osd = make_overlay(Alignment::End, Alignment::Start);
+		let recent_folders = make_overlay(Alignment::Center, Alignment::Center);
+		let open_with = make_overlay(Alignment::Center, Alignment::Center);
+		let binding_editor = make_overlay(Alignment::Center, Alignment::Center);
+		let delete_prompt = make_overlay(Alignment::Center, Alignment::Center);
+		let onboarding = make_overlay(Alignment::Center, Alignment::Center);
+		let rename_box = make_text_box(|text, cursor| {
+			format!("Rename to:\n{}|{}", &text[..cursor], &text[cursor..])
+		});
+		let zoom_box = make_text_box(|text, cursor| {
+			format!(
+				"Zoom in percent, like 25, 50, 100, 200 or 400:\n{}|{}%",
+				&text[..cursor],
+				&text[cursor..]
+			)
+		});
+		PictureOverlays {
+			large_image_prompt,
+			load_error,
+			power_indicator,
+			info_panel,
+			cache_stats,
+			selection_hint,
+			pixel_inspector,
+			open_prompt,
+			present_summary,
+			memory_warning,
+			osd,
+			recent_folders,
+			open_with,
+			binding_editor,
+			delete_prompt,
+			onboarding,
+			rename_box,
+			zoom_box,
+			command_palette: CommandPalette::new(),
+			widgets,
+		}
+	}
+
+	/// These have to be added to the same container as the picture widget, after it. The
+	/// `rename_box`, the `zoom_box` and the text box of the `command_palette` have to be added
+	/// too.
+	pub fn widgets(&self) -> Vec<Rc<Label>> {
+		self.widgets.clone()
+	}
+}
+
+impl Default for PictureOverlays {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// `format_text` receives the text and the position of the cursor, and returns the text to
+/// display
+pub fn make_text_box<F>(format_text: F) -> Rc<TextBox>
+where
+	F: Fn(&str, usize) -> String + 'static,
+{
+	let text_box = Rc::new(TextBox::new());
+	text_box.set_ignore_layout(true);
+	text_box.set_margin_all(8.0);
+	text_box.set_horizontal_align(Alignment::Center);
+	text_box.set_vertical_align(Alignment::Center);
+	text_box.set_visible(false);
+	text_box.set_text_renderer(move |text, cursor| {
+		let rendered = render_text(&format_text(text, cursor), &TextStyle::default())?;
+		let size = LogicalVector::new(rendered.width, rendered.height);
+		Some((Picture::from_image(rendered.image), size))
+	});
+	text_box
+}
@@ -0,0 +1,208 @@
+//! Short messages that are stacked in the corner of the picture area and disappear after a
+//! while. The clipboard, the file operations and the error reports all push their messages here.
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gelatin::{
+	label::Label,
+	line_layout_container::VerticalLayoutContainer,
+	misc::{Alignment, Length, LogicalVector},
+	picture::Picture,
+	NextUpdate,
+};
+
+use crate::configuration::Theme;
+use crate::text_rendering::{render_text, TextStyle};
+
+/// The oldest toasts are removed when there would be more than this many
+const MAX_TOASTS: usize = 4;
+const INFO_DURATION: Duration = Duration::from_secs(2);
+const ERROR_DURATION: Duration = Duration::from_secs(4);
+/// The space between the stacked toasts in logical pixels
+const GAP: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToastKind {
+	Info,
+	Error,
+	/// Stays until it's replaced, for example while something is copied to the clipboard
+	Pending,
+}
+
+impl ToastKind {
+	fn duration(self) -> Option<Duration> {
+		match self {
+			ToastKind::Info => Some(INFO_DURATION),
+			ToastKind::Error => Some(ERROR_DURATION),
+			ToastKind::Pending => None,
+		}
+	}
+
+	fn text_style(self, theme: Theme) -> TextStyle {
+		let (color, background) = match (self, theme) {
+			(ToastKind::Error, _) => ([250, 250, 250], [170, 30, 30, 225]),
+			(_, Theme::Dark) => ([235, 235, 235], [20, 20, 20, 210]),
+			(_, Theme::Light) => ([20, 20, 20], [245, 245, 245, 225]),
+		};
+		TextStyle { color, background, ..TextStyle::default() }
+	}
+}
+
+/// Identifies a toast, so that it can be replaced once the thing that it's about is done
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ToastId(u64);
+
+struct Toast {
+	id: ToastId,
+	kind: ToastKind,
+	text: String,
+	/// `None` while the toast is pending
+	until: Option<Instant>,
+	/// The text and its size in logical pixels, rendered when the toast is first shown
+	rendered: Option<(Rc<Picture>, LogicalVector)>,
+}
+
+impl Toast {
+	fn start_timer(&mut self) {
+		self.until = self.kind.duration().map(|duration| Instant::now() + duration);
+	}
+
+	fn render(&mut self, theme: Theme) {
+		let rendered = render_text(&self.text, &self.kind.text_style(theme));
+		self.rendered = rendered.map(|rendered| {
+			let size = LogicalVector::new(rendered.width, rendered.height);
+			(Rc::new(Picture::from_image(rendered.image)), size)
+		});
+	}
+}
+
+pub struct Toasts {
+	/// Has to be added to the same container as the picture widget, after it
+	pub widget: Rc<VerticalLayoutContainer>,
+	/// A label for each toast that can be shown at once
+	labels: Vec<Rc<Label>>,
+	/// The oldest one first, which is shown at the top
+	toasts: Vec<Toast>,
+	next_id: u64,
+	theme: Theme,
+}
+
+impl Toasts {
+	pub fn new() -> Self {
+		let widget = Rc::new(VerticalLayoutContainer::new());
+		widget.set_ignore_layout(true);
+		widget.set_margin_all(8.0);
+		widget.set_horizontal_align(Alignment::End);
+		widget.set_vertical_align(Alignment::End);
+		widget.set_visible(false);
+		let labels: Vec<_> = (0..MAX_TOASTS).map(|_| Rc::new(Label::new())).collect();
+		for label in labels.iter() {
+			label.set_horizontal_align(Alignment::End);
+			label.set_margin_top(GAP);
+			label.set_visible(false);
+			widget.add_child(label.clone());
+		}
+		Toasts { widget, labels, toasts: Vec::new(), next_id: 0, theme: Theme::Dark }
+	}
+
+	/// Shows the message under the other toasts
+	pub fn push(&mut self, kind: ToastKind, text: &str) -> ToastId {
+		let id = ToastId(self.next_id);
+		self.next_id += 1;
+		let mut toast = Toast { id, kind, text: text.to_owned(), until: None, rendered: None };
+		toast.start_timer();
+		self.toasts.push(toast);
+		if self.toasts.len() > MAX_TOASTS {
+			self.toasts.remove(0);
+		}
+		self.layout();
+		id
+	}
+
+	/// Shows the message in place of the toast `id` and starts its timer again. A new toast is
+	/// shown if `id` is `None` or that toast has disappeared already.
+	pub fn replace(&mut self, id: Option<ToastId>, kind: ToastKind, text: &str) -> ToastId {
+		let index = self.toasts.iter().position(|toast| Some(toast.id) == id);
+		let index = match index {
+			Some(index) => index,
+			None => return self.push(kind, text),
+		};
+		let mut toast = self.toasts.remove(index);
+		if toast.kind != kind || toast.text != text {
+			toast.kind = kind;
+			toast.text = text.to_owned();
+			toast.rendered = None;
+		}
+		toast.start_timer();
+		let id = toast.id;
+		self.toasts.insert(index, toast);
+		self.layout();
+		id
+	}
+
+	/// Removes the toast right away
+	pub fn dismiss(&mut self, id: ToastId) {
+		self.toasts.retain(|toast| toast.id != id);
+		self.layout();
+	}
+
+	pub fn set_theme(&mut self, theme: Theme) {
+		if self.theme != theme {
+			self.theme = theme;
+			self.toasts.iter_mut().for_each(|toast| toast.rendered = None);
+			self.layout();
+		}
+	}
+
+	/// Removes the expired toasts, and returns when the next one expires
+	pub fn update(&mut self) -> NextUpdate {
+		let now = Instant::now();
+		let count = self.toasts.len();
+		self.toasts.retain(|toast| toast.until.is_none_or(|until| until > now));
+		if self.toasts.len() != count {
+			self.layout();
+		}
+		match self.toasts.iter().filter_map(|toast| toast.until).min() {
+			Some(until) => NextUpdate::WaitUntil(until),
+			None => NextUpdate::Latest,
+		}
+	}
+
+	/// Assigns the toasts to the labels, and sizes the container to fit them
+	fn layout(&mut self) {
+		let theme = self.theme;
+		let mut size = LogicalVector::default();
+		let mut labels = self.labels.iter();
+		for toast in self.toasts.iter_mut() {
+			if toast.rendered.is_none() {
+				toast.render(theme);
+			}
+			let (picture, toast_size) = match &toast.rendered {
+				Some(rendered) => rendered,
+				None => continue,
+			};
+			let label = match labels.next() {
+				Some(label) => label,
+				None => break,
+			};
+			label.set_fixed_size(*toast_size);
+			label.set_icon(Some(picture.clone()));
+			label.set_visible(true);
+			size.vec.x = size.vec.x.max(toast_size.vec.x);
+			size.vec.y += GAP + toast_size.vec.y;
+		}
+		for label in labels {
+			label.set_visible(false);
+		}
+		self.widget.set_width(Length::Fixed(size.vec.x));
+		self.widget.set_height(Length::Fixed(size.vec.y));
+		self.widget.set_visible(!self.toasts.is_empty());
+	}
+}
+
+impl Default for Toasts {
+	fn default() -> Self {
+		Self::new()
+	}
+}
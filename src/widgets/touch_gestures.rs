@@ -0,0 +1,155 @@
+//! Recognizes the gestures of touchscreens from the touch events of the window: dragging with a
+//! finger, pinching with two, tapping twice and swiping sideways.
+
+use std::time::{Duration, Instant};
+
+use gelatin::{cgmath::InnerSpace, misc::LogicalVector, winit::event::TouchPhase};
+
+/// A finger has to move farther than this, in logical pixels, for the touch to not be a tap
+const TAP_SLOP: f32 = 10.0;
+/// Two taps that are closer to each other than this are a double tap
+const DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(300);
+/// The second tap of a double tap has to be this close to the first one, in logical pixels
+const DOUBLE_TAP_DISTANCE: f32 = 30.0;
+/// A swipe has to move the finger at least this far sideways, in logical pixels
+const SWIPE_DISTANCE: f32 = 80.0;
+/// Slower movements are drags rather than swipes
+const SWIPE_DURATION: Duration = Duration::from_millis(500);
+
+pub enum Gesture {
+	/// Moves the view by the given amount of logical pixels
+	Pan(LogicalVector),
+	/// Moves the view by `pan`, then scales it by `factor` keeping `center` in place
+	Pinch {
+		center: LogicalVector,
+		pan: LogicalVector,
+		factor: f32,
+	},
+	DoubleTap(LogicalVector),
+	/// Contains the distance that the finger moved to the right, which is negative when swiping
+	/// to the left
+	Swipe(f32),
+}
+
+struct Finger {
+	id: u64,
+	pos: LogicalVector,
+}
+
+/// Starts when the first finger touches the screen and ends when the last one is lifted
+struct Touch {
+	start_pos: LogicalVector,
+	start_time: Instant,
+	/// Set once a finger moved farther than a tap could
+	moved: bool,
+	/// Set if a second finger touched the screen at any point
+	pinched: bool,
+}
+
+#[derive(Default)]
+pub struct TouchGestures {
+	/// In the order that they touched the screen. Only the first two are used for pinching.
+	fingers: Vec<Finger>,
+	touch: Option<Touch>,
+	last_tap: Option<(Instant, LogicalVector)>,
+}
+
+impl TouchGestures {
+	/// `pos` is the position of the finger. Returns the gesture that the event completed or
+	/// continued, if any.
+	pub fn handle_touch(
+		&mut self,
+		id: u64,
+		phase: TouchPhase,
+		pos: LogicalVector,
+	) -> Option<Gesture> {
+		match phase {
+			TouchPhase::Started => {
+				self.finger_down(id, pos);
+				None
+			}
+			TouchPhase::Moved => self.finger_moved(id, pos),
+			TouchPhase::Ended => self.finger_up(id, true),
+			TouchPhase::Cancelled => self.finger_up(id, false),
+		}
+	}
+
+	fn finger_down(&mut self, id: u64, pos: LogicalVector) {
+		if self.fingers.is_empty() {
+			let start_time = Instant::now();
+			self.touch = Some(Touch { start_pos: pos, start_time, moved: false, pinched: false });
+		} else if let Some(touch) = &mut self.touch {
+			touch.pinched = true;
+		}
+		self.fingers.push(Finger { id, pos });
+	}
+
+	fn finger_moved(&mut self, id: u64, pos: LogicalVector) -> Option<Gesture> {
+		let index = self.fingers.iter().position(|finger| finger.id == id)?;
+		let touch = self.touch.as_mut()?;
+		let old_pos = self.fingers[index].pos;
+		self.fingers[index].pos = pos;
+		let was_moved = touch.moved;
+		touch.moved |= (pos - touch.start_pos).vec.magnitude() > TAP_SLOP;
+		if !touch.moved {
+			return None;
+		}
+		match self.fingers.as_slice() {
+			[_] => {
+				// The first few pixels were held back in case this is a tap
+				let from = if was_moved { old_pos } else { touch.start_pos };
+				Some(Gesture::Pan(pos - from))
+			}
+			[first, second, ..] if index < 2 => {
+				let other = if index == 0 { second.pos } else { first.pos };
+				let old_center = (old_pos + other) * 0.5f32;
+				let center = (pos + other) * 0.5f32;
+				let old_distance = (old_pos - other).vec.magnitude();
+				let distance = (pos - other).vec.magnitude();
+				if old_distance <= 0.0 || distance <= 0.0 {
+					return None;
+				}
+				let factor = distance / old_distance;
+				Some(Gesture::Pinch { center, pan: center - old_center, factor })
+			}
+			_ => None,
+		}
+	}
+
+	fn finger_up(&mut self, id: u64, ended: bool) -> Option<Gesture> {
+		let index = self.fingers.iter().position(|finger| finger.id == id)?;
+		let finger = self.fingers.remove(index);
+		if !self.fingers.is_empty() {
+			return None;
+		}
+		let touch = self.touch.take()?;
+		if !ended || touch.pinched {
+			self.last_tap = None;
+			return None;
+		}
+		let now = Instant::now();
+		if !touch.moved {
+			let double_tap = match self.last_tap {
+				Some((time, pos)) => {
+					now.duration_since(time) < DOUBLE_TAP_INTERVAL
+						&& (finger.pos - pos).vec.magnitude() < DOUBLE_TAP_DISTANCE
+				}
+				None => false,
+			};
+			if double_tap {
+				self.last_tap = None;
+				return Some(Gesture::DoubleTap(finger.pos));
+			}
+			self.last_tap = Some((now, finger.pos));
+			return None;
+		}
+		self.last_tap = None;
+		let offset = (finger.pos - touch.start_pos).vec;
+		let quick = now.duration_since(touch.start_time) < SWIPE_DURATION;
+		if quick && offset.x.abs() >= SWIPE_DISTANCE && offset.x.abs() > 2.0 * offset.y.abs() {
+			Some(Gesture::Swipe(offset.x))
+		} else {
+			None
+		}
+	}
+}
@@ -1,4 +1,5 @@
 use std::{
+	cell::RefCell,
 	collections::hash_map::HashMap,
 	fmt::Debug,
 	rc::Rc,
@@ -12,15 +13,32 @@ use winit::{
 	window::WindowId,
 };
 
-use crate::{window::Window, NextUpdate};
+use crate::{
+	window::{Window, WindowDescriptor},
+	NextUpdate,
+};
 
 // const MAX_SLEEP_DURATION: std::time::Duration = std::time::Duration::from_millis(4);
 static EXIT_REQUESTED: AtomicBool = AtomicBool::new(false);
 
+type WindowCreatedCallback = Box<dyn FnOnce(Rc<Window>)>;
+
+thread_local! {
+	/// The windows that were requested while the event loop is running
+	static WINDOW_REQUESTS: RefCell<Vec<(WindowDescriptor, WindowCreatedCallback)>> =
+		RefCell::new(Vec::new());
+}
+
 pub fn request_exit() {
 	EXIT_REQUESTED.store(true, Ordering::Relaxed);
 }
 
+/// Creates a window while the event loop is running. The window is created at the next
+/// iteration of the event loop, and then it's passed to `on_created`.
+pub fn create_window<F: FnOnce(Rc<Window>) + 'static>(desc: WindowDescriptor, on_created: F) {
+	WINDOW_REQUESTS.with(|requests| requests.borrow_mut().push((desc, Box::new(on_created))));
+}
+
 fn set_control_flow<E>(event_loop: &EventLoopWindowTarget<E>, control_flow: ControlFlow) {
 	if let ControlFlow::WaitUntil(time) = control_flow {
 		let very_short_time_from_now = Instant::now() + Duration::from_micros(100);
@@ -43,11 +61,9 @@ fn aggregate_control_flow<E>(event_loop: &EventLoopWindowTarget<E>, new: Control
 			return true;
 		}
 		ControlFlow::WaitUntil(new_time) => match original {
-			ControlFlow::WaitUntil(orig_time) => {
-				if new_time < orig_time {
-					set_control_flow(event_loop, new);
-					return true;
-				}
+			ControlFlow::WaitUntil(orig_time) if new_time < orig_time => {
+				set_control_flow(event_loop, new);
+				return true;
 			}
 			ControlFlow::Wait => {
 				set_control_flow(event_loop, new);
@@ -162,13 +178,25 @@ where
 						}
 					}
 					Event::WindowEvent { event, window_id } => {
+						// Events may still arrive for windows that were already closed
+						let window = match windows.get(&window_id) {
+							Some(window) => window.clone(),
+							None => return,
+						};
 						if let WindowEvent::RedrawRequested = event {
-							let new_control_flow = windows.get(&window_id).unwrap().redraw().into();
+							let new_control_flow = window.redraw().into();
 							aggregate_control_flow(event_loop, new_control_flow);
 							#[cfg(feature = "benchmark")]
 							update_draw_dt();
 						}
 						if let WindowEvent::CloseRequested = event {
+							if windows.len() > 1 {
+								// The window is hidden in case something still holds a
+								// reference to it
+								window.window_mut().set_visible(false);
+								windows.remove(&window_id);
+								return;
+							}
 							// This actually wouldn't be okay for a general pupose ui toolkit,
 							// but gelatin is specifically made for emulsion so this is fine hehe
 							request_exit();
@@ -179,7 +207,7 @@ where
 						} else {
 							destroyed = false;
 						}
-						windows.get(&window_id).unwrap().process_event(event, event_loop);
+						window.process_event(event, event_loop);
 						if destroyed {
 							windows.remove(&window_id);
 						}
@@ -189,6 +217,12 @@ where
 							event_loop.exit();
 							return;
 						}
+						let requests = WINDOW_REQUESTS.with(|requests| requests.take());
+						for (desc, on_created) in requests {
+							let window = Window::build(event_loop, desc);
+							windows.insert(window.get_id(), window.clone());
+							on_created(window);
+						}
 						for window in windows.values() {
 							window.main_events_cleared();
 							if window.redraw_needed() {
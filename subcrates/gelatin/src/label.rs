@@ -18,6 +18,7 @@ struct LabelData {
 
 	shadow_size: f32,
 	icon: Option<Rc<Picture>>,
+	opacity: f32,
 
 	render_validity: RenderValidity,
 }
@@ -46,6 +47,7 @@ impl Label {
 				visible: true,
 				shadow_size: 0.0,
 				icon: None,
+				opacity: 1.0,
 				render_validity: Default::default(),
 			}),
 		}
@@ -64,6 +66,15 @@ impl Label {
 		borrowed.shadow_size = shadow_size;
 		borrowed.render_validity.invalidate();
 	}
+
+	/// Between 0 and 1. Only applies to the icon.
+	pub fn set_opacity(&self, opacity: f32) {
+		let mut borrowed = self.data.borrow_mut();
+		if borrowed.opacity != opacity {
+			borrowed.opacity = opacity;
+			borrowed.render_validity.invalidate();
+		}
+	}
 }
 
 impl Default for Label {
@@ -122,6 +133,7 @@ impl Widget for Label {
 					brighten: 0.0f32,
 					shadow_color: Into::<[f32; 3]>::into(Vector3::<f32>::new(0.0, 0.0, 0.0)),
 					shadow_offset: 1.0 - borrowed.shadow_size,
+					opacity: borrowed.opacity,
 				};
 				target
 					.draw(
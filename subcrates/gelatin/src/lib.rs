@@ -10,7 +10,7 @@ use std::{
 };
 
 use winit::{
-	event::{ElementState, KeyEvent, MouseButton},
+	event::{ElementState, KeyEvent, MouseButton, TouchPhase},
 	event_loop::ControlFlow,
 	keyboard::ModifiersState,
 };
@@ -31,6 +31,7 @@ pub mod misc;
 pub mod picture;
 pub mod shaders;
 pub mod slider;
+pub mod text_box;
 pub mod window;
 
 pub type Display = glium::Display<WindowSurface>;
@@ -320,6 +321,10 @@ macro_rules! add_common_widget_functions {
 			borrowed.visible = visible;
 			borrowed.render_validity.invalidate();
 		}
+		/// The area that the widget occupied when it was laid out the last time
+		pub fn drawn_bounds(&self) -> $crate::misc::LogicalRect {
+			self.$data_field.borrow().drawn_bounds
+		}
 	};
 }
 
@@ -332,13 +337,34 @@ pub struct Event {
 }
 pub enum EventKind {
 	MouseMove,
-	MouseButton { state: ElementState, button: MouseButton },
-	MouseScroll { delta: LogicalVector },
-	KeyInput { input: KeyEvent },
+	MouseButton {
+		state: ElementState,
+		button: MouseButton,
+	},
+	MouseScroll {
+		delta: LogicalVector,
+	},
+	KeyInput {
+		input: KeyEvent,
+	},
 	DroppedFile(PathBuf),
 	HoveredFile(PathBuf),
 	HoveredFileCancelled,
 	Focused(bool),
+	/// The window was moved to a monitor with a different scale factor. Contains the new
+	/// scale factor.
+	ScaleFactorChanged(f32),
+	/// A finger touched, moved on or left the touchscreen. The `cursor_pos` of the event is the
+	/// position of the finger, and `id` tells the fingers apart.
+	Touch {
+		id: u64,
+		phase: TouchPhase,
+	},
+	/// A pinch gesture on a touchpad. Contains the change of the scale, which is positive when
+	/// the fingers move apart.
+	Magnify {
+		delta: f32,
+	},
 	CloseRequested,
 }
 
@@ -8,6 +8,7 @@ use std::{
 	cell::{Ref, RefCell},
 	ops::Deref,
 	path,
+	thread::JoinHandle,
 };
 
 pub struct PictureTextureRef<'a> {
@@ -27,6 +28,8 @@ impl<'a> Deref for PictureTextureRef<'a> {
 pub enum PictureData {
 	Path(path::PathBuf),
 	EncodedBytes(&'static [u8]),
+	/// The encoded bytes are being decoded on a separate thread
+	Decoding(JoinHandle<Result<RgbaImage, ImageError>>),
 	Cpu(RgbaImage),
 	Gpu(SrgbTexture2d),
 }
@@ -53,6 +56,23 @@ impl Picture {
 		Picture { data: RefCell::new(PictureData::Cpu(img)) }
 	}
 
+	/// Starts decoding the image on a separate thread if it was created from encoded bytes, so
+	/// that the image is ready by the time it's first drawn. Otherwise this does nothing.
+	pub fn decode_in_background(&self) {
+		let mut borrowed = self.data.borrow_mut();
+		if let PictureData::EncodedBytes(bytes) = *borrowed {
+			let decode = move || image::load_from_memory(bytes).map(|img| img.into_rgba8());
+			*borrowed = PictureData::Decoding(std::thread::spawn(decode));
+		}
+	}
+
+	/// Waits for the decoding thread to finish
+	fn join_decoding(
+		handle: JoinHandle<Result<RgbaImage, ImageError>>,
+	) -> Result<RgbaImage, ImageError> {
+		handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+	}
+
 	/// This function loads the entire image from file it it hasn't been loaded yet
 	pub fn get_metadata(&self) -> Result<PictureMetadata, ImageError> {
 		let mut borrowed = self.data.borrow_mut();
@@ -72,6 +92,11 @@ impl Picture {
 				dimensions = rgba.dimensions();
 				*borrowed = PictureData::Cpu(rgba);
 			}
+			PictureData::Decoding(handle) => {
+				let rgba = Self::join_decoding(handle)?;
+				dimensions = rgba.dimensions();
+				*borrowed = PictureData::Cpu(rgba);
+			}
 			PictureData::Cpu(img) => {
 				dimensions = img.dimensions();
 				*borrowed = PictureData::Cpu(img);
@@ -86,7 +111,7 @@ impl Picture {
 		Ok(PictureMetadata { width: dimensions.0, height: dimensions.1 })
 	}
 
-	pub fn texture<F: Facade>(&self, facade: &F) -> Result<PictureTextureRef, ImageError> {
+	pub fn texture<F: Facade>(&self, facade: &F) -> Result<PictureTextureRef<'_>, ImageError> {
 		self.upload_to_texture(facade)?;
 		if let PictureData::Gpu(_) = &*self.data.borrow() {
 			Ok(PictureTextureRef { pic_data: self.data.borrow() })
@@ -110,6 +135,10 @@ impl Picture {
 				let rgba = img.into_rgba8();
 				*borrowed = PictureData::Gpu(Self::cpu_to_texture(rgba, facade));
 			}
+			PictureData::Decoding(handle) => {
+				let rgba = Self::join_decoding(handle)?;
+				*borrowed = PictureData::Gpu(Self::cpu_to_texture(rgba, facade));
+			}
 			PictureData::Cpu(img) => {
 				*borrowed = PictureData::Gpu(Self::cpu_to_texture(img, facade));
 			}
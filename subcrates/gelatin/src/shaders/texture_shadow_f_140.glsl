@@ -5,6 +5,7 @@ uniform float brighten;
 uniform vec3 shadow_color;
 uniform vec4 bg_color;
 uniform float shadow_offset;
+uniform float opacity;
 in vec2 v_tex_coords;
 out vec4 f_color;
 
@@ -23,4 +24,5 @@ void main() {
     color = mix(bg_color, color, color.a);
     float shadow = shadow_along_axes.x + shadow_along_axes.y;
     f_color = vec4(mix(color.rgb, shadow_color, min(1.0, 4.0*shadow)), mix(color.a, 1.0, shadow));
+    f_color.a *= opacity;
 }
@@ -17,6 +17,8 @@ struct SliderData {
 
 	steps: u32,
 	value: u32,
+	/// The first and the last step of the highlighted range
+	range: Option<(u32, u32)>,
 	click: bool,
 	hover: bool,
 	on_value_change: Option<Rc<dyn Fn()>>,
@@ -50,6 +52,7 @@ impl Slider {
 				visible: true,
 				steps: 1,
 				value: 0,
+				range: None,
 				click: false,
 				hover: false,
 				on_value_change: None,
@@ -87,6 +90,15 @@ impl Slider {
 		borrowed.render_validity.invalidate();
 	}
 
+	/// Highlights the steps from the first to the last one of `range`, or nothing if `None`
+	pub fn set_range(&self, range: Option<(u32, u32)>) {
+		let mut borrowed = self.data.borrow_mut();
+		if borrowed.range != range {
+			borrowed.range = range;
+			borrowed.render_validity.invalidate();
+		}
+	}
+
 	/// Feel free to use `RefCell`s within the callback to satisfy the apparent constnes
 	/// of the callback.
 	pub fn set_on_value_change<T: Fn() + 'static>(&self, callback: T) {
@@ -127,6 +139,32 @@ impl Widget for Slider {
 				..Default::default()
 			};
 
+			// -----------------------
+			// Draw the highlighted range under the line of the value
+			if let Some((start, end)) = borrowed.range {
+				let steps = borrowed.steps.max(1) as f32;
+				let start_x = start.min(end) as f32 / steps * size.x;
+				let end_x = (end.max(start) + 1).min(borrowed.steps) as f32 / steps * size.x;
+				let range_pos = Vector3::new(position.x + start_x, position.y, 0.0);
+				let mut transform =
+					Matrix4::from_nonuniform_scale((end_x - start_x).max(1.0), size.y, 1.0);
+				transform = Matrix4::from_translation(range_pos) * transform;
+				transform = context.projection_transform * transform;
+				let uniforms = uniform! {
+					matrix: Into::<[[f32; 4]; 4]>::into(transform),
+					color: [0.35, 0.55, 0.85, 0.4f32],
+				};
+				target
+					.draw(
+						context.unit_quad_vertices,
+						context.unit_quad_indices,
+						context.colored_program,
+						&uniforms,
+						&image_draw_params,
+					)
+					.unwrap();
+			}
+
 			// -----------------------
 			// Draw vertical line at slider value
 			// Do this before the shadow so the shadow we draw later will cover this line as well
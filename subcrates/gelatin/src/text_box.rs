@@ -0,0 +1,301 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath::{Matrix4, Vector3};
+use glium::{uniform, Frame, Surface};
+use winit::event::ElementState;
+use winit::keyboard::{Key, NamedKey};
+
+use crate::add_common_widget_functions;
+use crate::misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement};
+use crate::picture::Picture;
+use crate::window::RenderValidity;
+use crate::NextUpdate;
+use crate::{DrawContext, Event, EventKind, Widget, WidgetData, WidgetError};
+
+/// Receives the text and the byte position of the cursor within it. Returns the picture to be
+/// displayed and its size in logical pixels.
+type TextRenderer = dyn Fn(&str, usize) -> Option<(Picture, LogicalVector)>;
+
+struct TextBoxData {
+	placement: WidgetPlacement,
+	drawn_bounds: LogicalRect,
+	visible: bool,
+
+	text: String,
+	/// Byte index into `text`, always at a character boundary
+	cursor: usize,
+	rendered: Option<Rc<Picture>>,
+	text_renderer: Option<Rc<TextRenderer>>,
+	on_submit: Option<Rc<dyn Fn(String)>>,
+	on_cancel: Option<Rc<dyn Fn()>>,
+
+	render_validity: RenderValidity,
+}
+impl WidgetData for TextBoxData {
+	fn placement(&mut self) -> &mut WidgetPlacement {
+		&mut self.placement
+	}
+	fn drawn_bounds(&mut self) -> &mut LogicalRect {
+		&mut self.drawn_bounds
+	}
+	fn visible(&mut self) -> &mut bool {
+		&mut self.visible
+	}
+}
+impl TextBoxData {
+	fn render(&mut self) {
+		let rendered = match &self.text_renderer {
+			Some(text_renderer) => text_renderer(&self.text, self.cursor),
+			None => None,
+		};
+		match rendered {
+			Some((picture, size)) => {
+				self.placement.width = Length::Fixed(size.vec.x);
+				self.placement.height = Length::Fixed(size.vec.y);
+				self.rendered = Some(Rc::new(picture));
+			}
+			None => self.rendered = None,
+		}
+		self.render_validity.invalidate();
+	}
+
+	fn prev_boundary(&self) -> usize {
+		self.text[..self.cursor].char_indices().next_back().map_or(0, |(i, _)| i)
+	}
+
+	fn next_boundary(&self) -> usize {
+		match self.text[self.cursor..].chars().next() {
+			Some(ch) => self.cursor + ch.len_utf8(),
+			None => self.cursor,
+		}
+	}
+}
+
+/// A single line of editable text. Gelatin can't draw text, so the text is turned into a
+/// picture by the function given to `set_text_renderer`.
+///
+/// The text box handles the keyboard input while it's visible. It hides itself when Enter or
+/// Escape is pressed.
+pub struct TextBox {
+	data: RefCell<TextBoxData>,
+}
+
+impl TextBox {
+	pub fn new() -> TextBox {
+		TextBox {
+			data: RefCell::new(TextBoxData {
+				placement: Default::default(),
+				drawn_bounds: Default::default(),
+				visible: true,
+				text: String::new(),
+				cursor: 0,
+				rendered: None,
+				text_renderer: None,
+				on_submit: None,
+				on_cancel: None,
+				render_validity: Default::default(),
+			}),
+		}
+	}
+
+	add_common_widget_functions!(data);
+
+	pub fn set_text_renderer<T>(&self, text_renderer: T)
+	where
+		T: Fn(&str, usize) -> Option<(Picture, LogicalVector)> + 'static,
+	{
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.text_renderer = Some(Rc::new(text_renderer));
+		borrowed.render();
+	}
+
+	/// Replaces the text and moves the cursor to its end
+	pub fn set_text(&self, text: &str) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.text = text.to_owned();
+		borrowed.cursor = text.len();
+		borrowed.render();
+	}
+
+	pub fn text(&self) -> String {
+		self.data.borrow().text.clone()
+	}
+
+	/// Moves the cursor to the given byte position, or to the end if it's past the end or not at
+	/// a character boundary
+	pub fn set_cursor(&self, cursor: usize) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.cursor =
+			if borrowed.text.is_char_boundary(cursor) { cursor } else { borrowed.text.len() };
+		borrowed.render();
+	}
+
+	/// Renders the text again, for when the text renderer would display it differently
+	pub fn refresh(&self) {
+		self.data.borrow_mut().render();
+	}
+
+	/// Called with the text when Enter is pressed
+	pub fn set_on_submit<T: Fn(String) + 'static>(&self, callback: T) {
+		self.data.borrow_mut().on_submit = Some(Rc::new(callback));
+	}
+
+	/// Called when Escape is pressed
+	pub fn set_on_cancel<T: Fn() + 'static>(&self, callback: T) {
+		self.data.borrow_mut().on_cancel = Some(Rc::new(callback));
+	}
+}
+
+impl Default for TextBox {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Widget for TextBox {
+	fn draw(&self, target: &mut Frame, context: &DrawContext) -> Result<NextUpdate, WidgetError> {
+		use glium::{Blend, BlendingFunction, LinearBlendingFactor};
+
+		let borrowed = self.data.borrow();
+		let rendered = match &borrowed.rendered {
+			Some(rendered) if borrowed.visible => rendered,
+			_ => return Ok(NextUpdate::Latest),
+		};
+		let aligned_bounds = borrowed.drawn_bounds.align_to_pixels(context.dpi_scale_factor);
+		let img_w = aligned_bounds.size.vec.x;
+		let img_h = aligned_bounds.size.vec.y;
+
+		// Model tranform
+		let transform = Matrix4::from_nonuniform_scale(img_w, img_h, 1.0);
+		let transform = Matrix4::from_translation(aligned_bounds.pos.vec.extend(0.0)) * transform;
+		// Projection
+		let transform = context.projection_transform * transform;
+
+		let image_draw_params = glium::DrawParameters {
+			viewport: Some(*context.viewport),
+			blend: Blend {
+				color: BlendingFunction::Addition {
+					source: LinearBlendingFactor::SourceAlpha,
+					destination: LinearBlendingFactor::OneMinusSourceAlpha,
+				},
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let texture = rendered.texture(context.display)?;
+		let sampler = texture
+			.sampled()
+			.wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+			.minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+			.magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
+		let uniforms = uniform! {
+			matrix: Into::<[[f32; 4]; 4]>::into(transform),
+			tex: sampler,
+			color: [1.0f32, 0.1, 0.5, 0.5],
+			bg_color: [0.0f32, 0.0, 0.0, 0.0],
+			texture_size: [img_w, img_h],
+			brighten: 0.0f32,
+			shadow_color: Into::<[f32; 3]>::into(Vector3::<f32>::new(0.0, 0.0, 0.0)),
+			shadow_offset: 1.0f32,
+			opacity: 1.0f32,
+		};
+		target
+			.draw(
+				context.unit_quad_vertices,
+				context.unit_quad_indices,
+				context.textured_program,
+				&uniforms,
+				&image_draw_params,
+			)
+			.unwrap();
+		Ok(NextUpdate::Latest)
+	}
+
+	fn layout(&self, available_space: LogicalRect) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.default_layout(available_space);
+	}
+
+	fn handle_event(&self, event: &Event) {
+		let input = match &event.kind {
+			EventKind::KeyInput { input } if input.state == ElementState::Pressed => input,
+			_ => return,
+		};
+		let mut borrowed = self.data.borrow_mut();
+		if !borrowed.visible {
+			return;
+		}
+		match &input.logical_key {
+			Key::Named(NamedKey::Enter) => {
+				borrowed.visible = false;
+				borrowed.render_validity.invalidate();
+				let on_submit = borrowed.on_submit.clone();
+				let text = borrowed.text.clone();
+				drop(borrowed);
+				if let Some(callback) = on_submit {
+					callback(text);
+				}
+				return;
+			}
+			Key::Named(NamedKey::Escape) => {
+				borrowed.visible = false;
+				borrowed.render_validity.invalidate();
+				let on_cancel = borrowed.on_cancel.clone();
+				drop(borrowed);
+				if let Some(callback) = on_cancel {
+					callback();
+				}
+				return;
+			}
+			Key::Named(NamedKey::Backspace) => {
+				let start = borrowed.prev_boundary();
+				let end = borrowed.cursor;
+				borrowed.text.replace_range(start..end, "");
+				borrowed.cursor = start;
+			}
+			Key::Named(NamedKey::Delete) => {
+				let start = borrowed.cursor;
+				let end = borrowed.next_boundary();
+				borrowed.text.replace_range(start..end, "");
+			}
+			Key::Named(NamedKey::ArrowLeft) => borrowed.cursor = borrowed.prev_boundary(),
+			Key::Named(NamedKey::ArrowRight) => borrowed.cursor = borrowed.next_boundary(),
+			Key::Named(NamedKey::Home) => borrowed.cursor = 0,
+			Key::Named(NamedKey::End) => borrowed.cursor = borrowed.text.len(),
+			_ => {
+				// Shortcuts don't type anything. Ctrl+Alt is AltGr on Windows.
+				let modifiers = event.modifiers;
+				if modifiers.super_key() || (modifiers.control_key() && !modifiers.alt_key()) {
+					return;
+				}
+				let text = match &input.text {
+					Some(text) => text.chars().filter(|ch| !ch.is_control()).collect::<String>(),
+					None => return,
+				};
+				if text.is_empty() {
+					return;
+				}
+				let cursor = borrowed.cursor;
+				borrowed.text.insert_str(cursor, &text);
+				borrowed.cursor += text.len();
+			}
+		}
+		borrowed.render();
+	}
+
+	// No children for a text box
+	fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+	fn placement(&self) -> WidgetPlacement {
+		self.data.borrow().placement
+	}
+
+	fn visible(&self) -> bool {
+		self.data.borrow().visible
+	}
+
+	fn set_valid_ref(&self, render_validity: RenderValidity) {
+		self.data.borrow_mut().render_validity = render_validity;
+	}
+}
@@ -2,22 +2,26 @@ use cgmath::{Matrix4, Vector3};
 use glium::{
 	glutin::{
 		self,
-		config::{Api, ConfigSurfaceTypes, GlConfig},
+		config::{Api, ColorBufferType, Config, ConfigSurfaceTypes, GlConfig},
 		context::{ContextApi, GlProfile, NotCurrentGlContext, Version},
 		display::{GetGlDisplay, GlDisplay},
 		surface::{GlSurface, WindowSurface},
 	},
-	uniform, Blend, BlendingFunction, Display, Frame, IndexBuffer, Program, Rect, Surface,
-	VertexBuffer,
+	texture::{MipmapsOption, RawImage2d, UncompressedFloatFormat},
+	uniform,
+	uniforms::MagnifySamplerFilter,
+	Blend, BlendingFunction, BlitTarget, Display, Frame, IndexBuffer, LinearBlendingFactor,
+	Program, Rect, Surface, Texture2d, VertexBuffer,
 };
+use image::RgbaImage;
 use log::{debug, error, warn};
 use raw_window_handle::HasRawWindowHandle;
 use winit::{
 	dpi::{PhysicalPosition, PhysicalSize},
 	event::WindowEvent,
-	event_loop::{EventLoop, EventLoopWindowTarget},
+	event_loop::EventLoopWindowTarget,
 	keyboard::ModifiersState,
-	window::{CursorIcon, Fullscreen, Icon, WindowBuilder, WindowId},
+	window::{CursorIcon, Fullscreen, Icon, WindowBuilder, WindowId, WindowLevel},
 };
 
 #[cfg(not(any(target_os = "macos", windows)))]
@@ -29,11 +33,13 @@ use winit::platform::{
 use std::{
 	cell::{Cell, RefCell, RefMut},
 	cmp::Eq,
+	ffi::{c_void, CStr},
 	fmt::Debug,
 	hash::{Hash, Hasher},
 	num::NonZeroU32,
 	ops::{Deref, DerefMut},
 	rc::Rc,
+	sync::atomic::{AtomicPtr, Ordering},
 };
 
 use cgmath::ortho;
@@ -51,6 +57,54 @@ use crate::{
 
 const EVENT_UPDATE_DELTA: std::time::Duration = std::time::Duration::from_millis(2);
 
+const GL_NO_ERROR: u32 = 0;
+const GL_OUT_OF_MEMORY: u32 = 0x0505;
+
+/// There's one error flag for each kind of error, so this many calls clear all of them
+const MAX_GL_ERROR_FLAGS: usize = 8;
+
+type GetErrorFn = unsafe extern "system" fn() -> u32;
+
+/// `glGetError`, loaded when the first window is created
+static GL_GET_ERROR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Returns true if the GL driver ran out of memory since the last call to this function,
+/// according to `glGetError`. The other errors are cleared too.
+///
+/// Texture creation may seemingly succeed even when there's not enough video memory,
+/// so callers that upload large textures should call this before the upload to clear the
+/// earlier errors, and check it right after the upload.
+pub fn take_gpu_out_of_memory(display: &Display<WindowSurface>) -> bool {
+	let get_error = GL_GET_ERROR.load(Ordering::Relaxed);
+	if get_error.is_null() {
+		return false;
+	}
+	// Safe, because the pointer was returned by the GL library for `glGetError`
+	let get_error: GetErrorFn = unsafe { std::mem::transmute(get_error) };
+	let mut out_of_memory = false;
+	// Safe, because `glGetError` doesn't change the state that glium keeps track of
+	unsafe {
+		display.exec_in_context(|| {
+			for _ in 0..MAX_GL_ERROR_FLAGS {
+				match get_error() {
+					GL_NO_ERROR => break,
+					GL_OUT_OF_MEMORY => out_of_memory = true,
+					_ => (),
+				}
+			}
+		});
+	}
+	out_of_memory
+}
+
+/// The smallest number of bits of the red, green and blue channels
+fn color_bits(config: &Config) -> u8 {
+	match config.color_buffer_type() {
+		Some(ColorBufferType::Rgb { r_size, g_size, b_size }) => r_size.min(g_size).min(b_size),
+		_ => 0,
+	}
+}
+
 /// Returns true if and only if of the give window postion is within the boundaries of the display.
 fn is_in_bounds(
 	display_pos: PhysicalPosition<i32>,
@@ -143,6 +197,22 @@ pub struct WindowDescriptor {
 	#[builder(default)]
 	#[allow(dead_code)]
 	app_id: Option<String>,
+
+	/// Renders on the CPU instead of the GPU, for systems with broken graphics drivers. Uses
+	/// Mesa's llvmpipe on Linux and the BSDs, and the Apple software renderer on macOS. It's not
+	/// available on Windows.
+	#[builder(default)]
+	software_rendering: bool,
+
+	/// Allows making the window translucent with `set_opacity`. The compositor has to support
+	/// transparent windows, otherwise the window stays opaque.
+	#[builder(default)]
+	transparent: bool,
+
+	/// Prefers a framebuffer with 10 bits per color channel, so that smooth gradients don't
+	/// band. Only used if the display supports it, see `Window::color_bits`.
+	#[builder(default)]
+	deep_color: bool,
 }
 
 pub type EventHandler = dyn FnMut(&Window, &WindowEvent);
@@ -153,6 +223,14 @@ struct WindowData {
 
 	size_before_fullscreen: PhysicalSize<u32>,
 	fullscreen: bool,
+	always_on_top: bool,
+	transparent: bool,
+	/// The number of bits per color channel of the framebuffer
+	color_bits: u8,
+	/// The alpha value that the whole window is drawn with
+	opacity: f32,
+	/// Multiplies the scale factor of the monitor
+	ui_scale: f32,
 	last_mouse_move_update_time: std::time::Instant,
 	unprocessed_move_event: Option<Event>,
 	last_event_invalidated: bool,
@@ -164,6 +242,9 @@ struct WindowData {
 	modifiers: ModifiersState,
 	root_widget: Rc<dyn Widget>,
 	bg_color: [f32; 4],
+	/// The area of the window that should be copied into `captured_image` after the next draw
+	capture_request: Option<LogicalRect>,
+	captured_image: Option<RgbaImage>,
 
 	global_event_handlers: Vec<Box<EventHandler>>,
 
@@ -193,6 +274,17 @@ impl Eq for Window {}
 impl Window {
 	pub fn new<UserEvent: Debug>(
 		application: &mut Application<UserEvent>,
+		desc: WindowDescriptor,
+	) -> Rc<Self> {
+		let window = Self::build(&application.event_loop, desc);
+		application.register_window(window.clone());
+		window
+	}
+
+	/// Creates the window without registering it to the application. Use `Window::new` before
+	/// the event loop is started, and `application::create_window` afterwards.
+	pub(crate) fn build<UserEvent>(
+		event_loop: &EventLoopWindowTarget<UserEvent>,
 		mut desc: WindowDescriptor,
 	) -> Rc<Self> {
 		//use glium::glutin::window::Icon;
@@ -212,7 +304,8 @@ impl Window {
 			.with_title("Loading")
 			.with_fullscreen(None)
 			.with_window_icon(desc.icon)
-			.with_maximized(desc.maximized);
+			.with_maximized(desc.maximized)
+			.with_transparent(desc.transparent);
 
 		if !desc.maximized {
 			window_builder = window_builder.with_inner_size(desc.size);
@@ -220,7 +313,7 @@ impl Window {
 				// Check if the window would be placed outside of the screen
 				// (This can happen when using two displays, then disconnecting
 				// one of the displays and starting up emulsion)
-				let in_bounds = application.event_loop.available_monitors().any(|monitor| {
+				let in_bounds = event_loop.available_monitors().any(|monitor| {
 					debug!("Monitor pos: {:?}", monitor.position());
 					debug!("Monitor size: {:?}", monitor.size());
 					is_in_bounds(monitor.position(), monitor.size(), window_pos)
@@ -234,7 +327,7 @@ impl Window {
 		#[cfg(not(any(target_os = "macos", windows)))]
 		let window_builder = if let Some(app_id) = desc.app_id {
 			let is_wayland = std::env::var("XDG_SESSION_TYPE")
-				.map_or(false, |var| var.to_lowercase().contains("wayland"));
+				.is_ok_and(|var| var.to_lowercase().contains("wayland"));
 			if is_wayland {
 				WindowBuilderExtWayland::with_name(window_builder, &app_id, app_id.to_lowercase())
 			} else {
@@ -245,7 +338,13 @@ impl Window {
 		};
 
 		// let window = window.build(&application.event_loop).unwrap();
-		let (window, display) = Self::build_winit_window(window_builder, &application.event_loop);
+		let (window, display, color_bits) = Self::build_winit_window(
+			window_builder,
+			event_loop,
+			desc.software_rendering,
+			desc.transparent,
+			desc.deep_color,
+		);
 
 		window.set_cursor_icon(CursorIcon::Default);
 
@@ -302,12 +401,17 @@ impl Window {
 		)
 		.unwrap();
 
-		let resulting_window = Rc::new(Window {
+		Rc::new(Window {
 			data: RefCell::new(WindowData {
 				display,
 				window,
 				size_before_fullscreen: desc.size,
 				fullscreen: false,
+				always_on_top: false,
+				transparent: desc.transparent,
+				color_bits,
+				opacity: 1.0,
+				ui_scale: 1.0,
 				last_mouse_move_update_time: std::time::Instant::now(),
 				unprocessed_move_event: None,
 				last_event_invalidated: true,
@@ -317,6 +421,8 @@ impl Window {
 				render_validity: RenderValidity { validity: Rc::new(Cell::new(false)) },
 				root_widget: Rc::new(crate::line_layout_container::VerticalLayoutContainer::new()),
 				bg_color: [0.85, 0.85, 0.85, 1.0],
+				capture_request: None,
+				captured_image: None,
 
 				global_event_handlers: Vec::new(),
 
@@ -326,41 +432,48 @@ impl Window {
 				colored_shadowed_program,
 				colored_program,
 			}),
-		});
-
-		application.register_window(resulting_window.clone());
-		resulting_window
+		})
 	}
 
 	/// This is mostly copy-pasted from `glutin::SimpleWindowBuilder::build`
 	/// but I use some custom configuration settings here
 	fn build_winit_window<UserEvent>(
 		builder: WindowBuilder,
-		event_loop: &EventLoop<UserEvent>,
-	) -> (winit::window::Window, Display<WindowSurface>) {
+		event_loop: &EventLoopWindowTarget<UserEvent>,
+		software_rendering: bool,
+		transparent: bool,
+		deep_color: bool,
+	) -> (winit::window::Window, Display<WindowSurface>, u8) {
 		// let is_maximized = builder.m
 		// First we start by opening a new Window
 		let display_builder =
 			glutin_winit::DisplayBuilder::new().with_window_builder(Some(builder));
 
+		// Mesa's software renderer is chosen through environment variables by the application,
+		// and its configs may not be marked as either accelerated or not
+		let hardware_accelerated = match software_rendering {
+			false => Some(true),
+			true if cfg!(target_os = "macos") => Some(false),
+			true => None,
+		};
 		let config_template_builder = glutin::config::ConfigTemplateBuilder::new()
-			.prefer_hardware_accelerated(Some(true))
+			.prefer_hardware_accelerated(hardware_accelerated)
 			.with_surface_type(ConfigSurfaceTypes::WINDOW)
+			.with_transparency(transparent)
 			.with_api(Api::OPENGL);
 		let (window, gl_config) = display_builder
-			.build(event_loop, config_template_builder, |mut configs| {
-				// Pick the first srgb capable config
-				let mut target = configs.next().unwrap();
-				for cfg in configs {
-					if target.srgb_capable() {
-						break;
-					}
-					target = cfg;
-				}
-				target
+			.build(event_loop, config_template_builder, |configs| {
+				// Pick the first srgb capable config, preferring the ones with 10 bits per
+				// channel if deep color was requested
+				let score =
+					|cfg: &Config| (cfg.srgb_capable(), deep_color && color_bits(cfg) >= 10);
+				configs
+					.reduce(|best, cfg| if score(&cfg) > score(&best) { cfg } else { best })
+					.unwrap()
 			})
 			.unwrap();
 		let window = window.unwrap();
+		let color_bits = color_bits(&gl_config);
 
 		// Now we get the window size to use as the initial size of the Surface
 		let (width, height): (u32, u32) = window.inner_size().into();
@@ -397,9 +510,12 @@ impl Window {
 			error!("Failed to set vsync, error returned by set_swap_interval: {}", error);
 		}
 
+		let get_error_name = CStr::from_bytes_with_nul(b"glGetError\0").unwrap();
+		let get_error = gl_config.display().get_proc_address(get_error_name);
+		GL_GET_ERROR.store(get_error as *mut c_void, Ordering::Relaxed);
 		let display = Display::from_context_surface(current_context, surface).unwrap();
 
-		(window, display)
+		(window, display, color_bits)
 	}
 
 	pub fn add_global_event_handler<F: FnMut(&Window, &WindowEvent) + 'static>(&self, fun: F) {
@@ -475,7 +591,7 @@ impl Window {
 				WindowEvent::CursorMoved { position, .. } => {
 					let logical_pos;
 					{
-						let scaling = borrowed.window.scale_factor() as f32;
+						let scaling = borrowed.window.scale_factor() as f32 * borrowed.ui_scale;
 
 						logical_pos = LogicalVector::from_physical(position, scaling);
 						//logical_pos.vec.y = logical_dimensions.vec.y - logical_pos.vec.y;
@@ -544,6 +660,30 @@ impl Window {
 						kind: EventKind::Focused(focused),
 					});
 				}
+				WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+					borrowed.render_validity.invalidate();
+					let scale_factor = scale_factor as f32 * borrowed.ui_scale;
+					event = Some(Event {
+						cursor_pos: borrowed.cursor_pos,
+						modifiers: borrowed.modifiers,
+						kind: EventKind::ScaleFactorChanged(scale_factor),
+					});
+				}
+				WindowEvent::Touch(touch) => {
+					let scaling = borrowed.window.scale_factor() as f32 * borrowed.ui_scale;
+					event = Some(Event {
+						cursor_pos: LogicalVector::from_physical(touch.location, scaling),
+						modifiers: borrowed.modifiers,
+						kind: EventKind::Touch { id: touch.id, phase: touch.phase },
+					});
+				}
+				WindowEvent::TouchpadMagnify { delta, .. } => {
+					event = Some(Event {
+						cursor_pos: borrowed.cursor_pos,
+						modifiers: borrowed.modifiers,
+						kind: EventKind::Magnify { delta: delta as f32 },
+					});
+				}
 				WindowEvent::ModifiersChanged(modifiers) => {
 					borrowed.modifiers = modifiers.state();
 					event = None;
@@ -571,6 +711,25 @@ impl Window {
 		borrowed.render_validity.invalidate();
 	}
 
+	/// Requests the given area of the window to be copied into an image the next time the
+	/// window is drawn. The image can be retrieved with `take_captured_image`.
+	pub fn request_capture(&self, rect: LogicalRect) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.capture_request = Some(rect);
+		borrowed.captured_image = None;
+		borrowed.render_validity.invalidate();
+	}
+
+	/// Returns true if a capture was requested but the window hasn't been drawn since.
+	pub fn capture_pending(&self) -> bool {
+		self.data.borrow().capture_request.is_some()
+	}
+
+	/// Returns `None` if no capture was made or if reading back the framebuffer failed.
+	pub fn take_captured_image(&self) -> Option<RgbaImage> {
+		self.data.borrow_mut().captured_image.take()
+	}
+
 	pub fn display_mut(&self) -> WindowDisplayRefMut<'_> {
 		WindowDisplayRefMut { window_ref: self.data.borrow_mut() }
 	}
@@ -612,7 +771,7 @@ impl Window {
 			borrowed.last_event_invalidated = false;
 		}
 		// this way self.data is not borrowed while before draw is running.
-		let dpi_scaling = self.data.borrow().window.scale_factor();
+		let dpi_scaling = self.scale_factor();
 		let mut target = self.data.borrow().display.draw();
 
 		// Can't change the window during drawing phase. Deal with it.
@@ -621,7 +780,7 @@ impl Window {
 		let phys_dimensions = PhysicalSize::new(dimensions.0 as f32, dimensions.1 as f32);
 		let phys_width = phys_dimensions.width;
 		let phys_height = phys_dimensions.height;
-		let logical_dimensions = LogicalVector::from_physical(phys_dimensions, dpi_scaling as f32);
+		let logical_dimensions = LogicalVector::from_physical(phys_dimensions, dpi_scaling);
 
 		// Invoke the layout functions
 		let available_widget_space =
@@ -643,7 +802,7 @@ impl Window {
 
 		let draw_context = DrawContext {
 			display: &borrowed.display,
-			dpi_scale_factor: dpi_scaling as f32,
+			dpi_scale_factor: dpi_scaling,
 			unit_quad_vertices: &borrowed.unit_quad_vertices,
 			unit_quad_indices: &borrowed.unit_quad_indices,
 			textured_program: &borrowed.textured_program,
@@ -667,10 +826,25 @@ impl Window {
 		// This is required on Wayland because the Wayland compositor very kindly takes
 		// the alpha values into account and blends the framebuffer set by applications
 		// with the rest of the desktop.
-		self.set_alpha_to_1(&mut target, &draw_context);
+		self.set_alpha(&mut target, &draw_context, 1.0);
+
+		let capture_request = borrowed.capture_request;
+		let captured_image =
+			capture_request.and_then(|rect| capture_frame(&target, &draw_context, &rect));
+
+		// The capture is taken before this, so that it isn't translucent
+		if borrowed.opacity < 1.0 {
+			self.set_alpha(&mut target, &draw_context, borrowed.opacity);
+		}
 
 		target.finish().unwrap();
 		borrowed.render_validity.make_valid();
+		drop(borrowed);
+		if capture_request.is_some() {
+			let mut borrowed = self.data.borrow_mut();
+			borrowed.capture_request = None;
+			borrowed.captured_image = captured_image;
+		}
 		next_update
 	}
 
@@ -698,22 +872,91 @@ impl Window {
 		self.data.borrow_mut().window.set_maximized(maximized);
 	}
 
-	/// Sets the alpha values by drawing a quad covering the entire framebuffer
-	/// with a blending mode set to max and a shader that draws (0,0,0,1) values
-	fn set_alpha_to_1(&self, target: &mut Frame, context: &DrawContext) {
+	pub fn always_on_top(&self) -> bool {
+		self.data.borrow().always_on_top
+	}
+
+	pub fn set_always_on_top(&self, always_on_top: bool) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.always_on_top = always_on_top;
+		let level = if always_on_top { WindowLevel::AlwaysOnTop } else { WindowLevel::Normal };
+		borrowed.window.set_window_level(level);
+	}
+
+	/// Returns true if the window has no title bar and borders
+	pub fn borderless(&self) -> bool {
+		!self.data.borrow().window.is_decorated()
+	}
+
+	pub fn set_borderless(&self, borderless: bool) {
+		self.data.borrow().window.set_decorations(!borderless);
+	}
+
+	/// Lets the mouse events go through the window to the windows below it. Returns false if
+	/// this is not supported on the platform.
+	pub fn set_click_through(&self, click_through: bool) -> bool {
+		self.data.borrow().window.set_cursor_hittest(!click_through).is_ok()
+	}
+
+	/// The number of bits per color channel of the framebuffer. At least 10 if the window was
+	/// created with `deep_color` and the display supports it.
+	pub fn color_bits(&self) -> u8 {
+		self.data.borrow().color_bits
+	}
+
+	/// Returns true if the window was created with `transparent`, so that its opacity can be
+	/// changed
+	pub fn supports_opacity(&self) -> bool {
+		self.data.borrow().transparent
+	}
+
+	pub fn opacity(&self) -> f32 {
+		self.data.borrow().opacity
+	}
+
+	/// The number of physical pixels per logical pixel. This is the scale factor of the monitor
+	/// multiplied by the UI scale.
+	pub fn scale_factor(&self) -> f32 {
+		let borrowed = self.data.borrow();
+		borrowed.window.scale_factor() as f32 * borrowed.ui_scale
+	}
+
+	/// Draws every widget larger or smaller than the scale factor of the monitor would
+	pub fn set_ui_scale(&self, ui_scale: f32) {
+		let mut borrowed = self.data.borrow_mut();
+		borrowed.ui_scale = ui_scale;
+		borrowed.render_validity.invalidate();
+	}
+
+	/// Has no effect unless the window was created with `transparent`
+	pub fn set_opacity(&self, opacity: f32) {
+		let mut borrowed = self.data.borrow_mut();
+		if borrowed.transparent {
+			borrowed.opacity = opacity.clamp(0.0, 1.0);
+			borrowed.render_validity.invalidate();
+		}
+	}
+
+	/// Sets the alpha values by drawing a quad covering the entire framebuffer with a shader
+	/// that draws (0,0,0,alpha) values. The colors are multiplied by the alpha, because the
+	/// compositors expect premultiplied alpha.
+	fn set_alpha(&self, target: &mut Frame, context: &DrawContext, alpha: f32) {
 		let transform = Matrix4::from_scale(2.0);
 		let transform = Matrix4::from_translation(Vector3::new(-1.0, -1.0, 0.0)) * transform;
 		let image_draw_params = glium::DrawParameters {
 			blend: Blend {
-				color: BlendingFunction::Max,
-				alpha: BlendingFunction::Max,
+				color: BlendingFunction::Addition {
+					source: LinearBlendingFactor::Zero,
+					destination: LinearBlendingFactor::SourceAlpha,
+				},
+				alpha: BlendingFunction::AlwaysReplace,
 				..Default::default()
 			},
 			..Default::default()
 		};
 		let uniforms = uniform! {
 			matrix: Into::<[[f32; 4]; 4]>::into(transform),
-			color: [0.0f32, 0.0, 0.0, 1.0],
+			color: [0.0f32, 0.0, 0.0, alpha],
 		};
 		target
 			.draw(
@@ -726,3 +969,42 @@ impl Window {
 			.unwrap();
 	}
 }
+
+/// Reads back the pixels of the given area from the framebuffer.
+fn capture_frame(target: &Frame, context: &DrawContext, rect: &LogicalRect) -> Option<RgbaImage> {
+	let source_rect = context.logical_rect_to_viewport(rect);
+	if source_rect.width == 0 || source_rect.height == 0 {
+		return None;
+	}
+	let texture = Texture2d::empty_with_format(
+		context.display,
+		UncompressedFloatFormat::U8U8U8U8,
+		MipmapsOption::NoMipmap,
+		source_rect.width,
+		source_rect.height,
+	);
+	let texture = match texture {
+		Ok(texture) => texture,
+		Err(e) => {
+			error!("Could not create a texture for capturing the window: {}", e);
+			return None;
+		}
+	};
+	let target_rect = BlitTarget {
+		left: 0,
+		bottom: 0,
+		width: source_rect.width as i32,
+		height: source_rect.height as i32,
+	};
+	target.blit_color(
+		&source_rect,
+		&texture.as_surface(),
+		&target_rect,
+		MagnifySamplerFilter::Nearest,
+	);
+	let raw: RawImage2d<u8> = texture.read();
+	let mut image = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())?;
+	// OpenGL stores the rows from the bottom to the top
+	image::imageops::flip_vertical_in_place(&mut image);
+	Some(image)
+}